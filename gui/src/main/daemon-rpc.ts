@@ -993,6 +993,8 @@ function convertFromParameterError(
       return TunnelParameterError.noWireguardKey;
     case grpcTypes.ErrorState.GenerationError.CUSTOM_TUNNEL_HOST_RESOLUTION_ERROR:
       return TunnelParameterError.customTunnelHostResolutionError;
+    case grpcTypes.ErrorState.GenerationError.NO_DIVERSE_MULTIHOP_RELAYS:
+      return TunnelParameterError.noDiverseMultihopRelays;
   }
 }
 
@@ -1046,6 +1048,7 @@ function convertFromObfuscationEndpoint(
 ): IObfuscationEndpoint {
   const obfuscationTypes: Record<grpcTypes.ObfuscationType, EndpointObfuscationType> = {
     [grpcTypes.ObfuscationType.UDP2TCP]: 'udp2tcp',
+    [grpcTypes.ObfuscationType.SHADOWSOCKS_UDP]: 'shadowsocks',
   };
 
   return {
@@ -184,6 +184,7 @@ function getTunnelParameterMessage(error: TunnelParameterError): string {
     /// TODO: once bridge constraints can be set, add a more descriptive error message
     case TunnelParameterError.noMatchingBridgeRelay:
     case TunnelParameterError.noMatchingRelay:
+    case TunnelParameterError.noDiverseMultihopRelays:
       return messages.pgettext(
         'notifications',
         'No servers match your settings, try changing server or other settings.',
@@ -1,4 +1,4 @@
-//! Fetches and prints the full relay list in JSON.
+//! Fetches and prints the full relay list in JSON, along with its signature.
 //! Used by the installer artifact packer to bundle the latest available
 //! relay list at the time of creating the installer.
 
@@ -21,17 +21,20 @@ pub struct RelayListProxy {
 
 const RELAY_LIST_TIMEOUT: Duration = Duration::from_secs(15);
 
+/// Response header containing the hex-encoded ed25519 signature of the relay list.
+const SIGNATURE_HEADER: &str = "x-relay-list-signature";
+
 impl RelayListProxy {
     /// Construct a new relay list rest client
     pub fn new(handle: rest::MullvadRestHandle) -> Self {
         Self { handle }
     }
 
-    /// Fetch the relay list
+    /// Fetch the relay list along with its signature. The signature is not verified.
     pub fn relay_list(
         &self,
         etag: Option<String>,
-    ) -> impl Future<Output = Result<Option<relay_list::RelayList>, rest::Error>> {
+    ) -> impl Future<Output = Result<Option<SignedRelayList>, rest::Error>> {
         let service = self.handle.service.clone();
         let request = self.handle.factory.request("app/v1/relays", Method::GET);
 
@@ -62,15 +65,45 @@ impl RelayListProxy {
                     }
                 });
 
-            Ok(Some(
-                rest::deserialize_body::<ServerRelayList>(response)
-                    .await?
-                    .into_relay_list(etag),
-            ))
+            let signature = response
+                .headers()
+                .get(SIGNATURE_HEADER)
+                .and_then(|signature| signature.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+
+            let content = String::from_utf8(rest::read_body(response).await?)
+                .map_err(|error| rest::Error::DeserializeError(serde::de::Error::custom(error)))?;
+
+            Ok(Some(SignedRelayList {
+                content,
+                signature,
+                etag,
+            }))
         }
     }
 }
 
+/// A relay list exactly as served by the API, together with its detached signature. This is
+/// also the format in which relay lists are stored on disk, so that their signatures can be
+/// verified when they are loaded.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SignedRelayList {
+    /// The relay list in the format used by the API.
+    pub content: String,
+    /// Hex-encoded ed25519 signature of `content`.
+    pub signature: String,
+    pub etag: Option<String>,
+}
+
+impl SignedRelayList {
+    /// Parses the relay list. This does not verify the signature.
+    pub fn parse(&self) -> Result<relay_list::RelayList, serde_json::Error> {
+        serde_json::from_str::<ServerRelayList>(&self.content)
+            .map(|relay_list| relay_list.into_relay_list(self.etag.clone()))
+    }
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct ServerRelayList {
     locations: BTreeMap<String, Location>,
@@ -21,8 +21,8 @@ use mullvad_types::account::AccountToken;
 use std::{
     future::Future,
     str::FromStr,
-    sync::{Arc, Weak},
-    time::Duration,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
 };
 use talpid_types::ErrorExt;
 
@@ -43,6 +43,35 @@ const API_IP_CHECK_ERROR_INTERVAL: Duration = Duration::from_secs(15 * 60);
 pub type Result<T> = std::result::Result<T, Error>;
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// Upper bounds, in seconds, of the buckets that request latencies are counted in.
+pub const LATENCY_BUCKETS: [f64; 8] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Latencies of the requests that have received a response or failed. Requests that time out or
+/// are dropped are not included.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RequestLatencies {
+    /// The number of requests that took at most the corresponding number of seconds in
+    /// [`LATENCY_BUCKETS`]. Like in a Prometheus histogram, the counts are cumulative.
+    pub buckets: [u64; LATENCY_BUCKETS.len()],
+    /// The total number of requests.
+    pub count: u64,
+    /// The sum of the latencies of all requests.
+    pub sum: Duration,
+}
+
+impl RequestLatencies {
+    fn record(&mut self, latency: Duration) {
+        let seconds = latency.as_secs_f64();
+        for (bucket, upper_bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= upper_bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += latency;
+    }
+}
+
 /// Describes all the ways a REST request can fail
 #[derive(err_derive::Error, Debug)]
 pub enum Error {
@@ -125,6 +154,7 @@ pub(crate) struct RequestService<
     new_address_callback: F,
     address_cache: AddressCache,
     api_availability: ApiAvailabilityHandle,
+    latencies: Arc<Mutex<RequestLatencies>>,
 }
 
 impl<
@@ -163,6 +193,7 @@ impl<
         let client = Client::builder().build(connector);
 
         let command_tx = Arc::new(command_tx);
+        let latencies = Arc::new(Mutex::new(RequestLatencies::default()));
 
         let service = Self {
             command_tx: Arc::downgrade(&command_tx),
@@ -173,8 +204,12 @@ impl<
             new_address_callback,
             address_cache,
             api_availability,
+            latencies: latencies.clone(),
+        };
+        let handle = RequestServiceHandle {
+            tx: command_tx,
+            latencies,
         };
-        let handle = RequestServiceHandle { tx: command_tx };
         tokio::spawn(service.into_future());
         handle
     }
@@ -190,10 +225,14 @@ impl<
                 let api_availability = self.api_availability.clone();
                 let suspend_fut = api_availability.wait_for_unsuspend();
                 let request_fut = self.client.request(hyper_request).map_err(Error::from);
+                let latencies = self.latencies.clone();
 
                 let request_future = async move {
                     let _ = suspend_fut.await;
-                    request_fut.await
+                    let start = Instant::now();
+                    let response = request_fut.await;
+                    latencies.lock().unwrap().record(start.elapsed());
+                    response
                 };
 
                 let future = async move {
@@ -256,6 +295,7 @@ impl<
 /// A handle to interact with a spawned `RequestService`.
 pub struct RequestServiceHandle {
     tx: Arc<mpsc::UnboundedSender<RequestCommand>>,
+    latencies: Arc<Mutex<RequestLatencies>>,
 }
 
 impl RequestServiceHandle {
@@ -273,6 +313,11 @@ impl RequestServiceHandle {
         completion_rx.await.map_err(|_| Error::ReceiveError)?
     }
 
+    /// Returns the latencies of all requests executed by the service so far.
+    pub fn latencies(&self) -> RequestLatencies {
+        self.latencies.lock().unwrap().clone()
+    }
+
     /// Forcibly update the connection mode.
     pub fn next_api_endpoint(&self) -> Result<()> {
         self.tx
@@ -555,15 +600,25 @@ pub async fn deserialize_body<T: serde::de::DeserializeOwned>(response: Response
 }
 
 async fn deserialize_body_inner<T: serde::de::DeserializeOwned>(
-    mut response: Response,
+    response: Response,
     body_length: usize,
 ) -> Result<T> {
+    let body = read_body_inner(response, body_length).await?;
+    serde_json::from_slice(&body).map_err(Error::DeserializeError)
+}
+
+/// Reads the entire body of a response.
+pub async fn read_body(response: Response) -> Result<Vec<u8>> {
+    let body_length = get_body_length(&response);
+    read_body_inner(response, body_length).await
+}
+
+async fn read_body_inner(mut response: Response, body_length: usize) -> Result<Vec<u8>> {
     let mut body: Vec<u8> = Vec::with_capacity(body_length);
     while let Some(chunk) = response.body_mut().next().await {
         body.extend(&chunk?);
     }
-
-    serde_json::from_slice(&body).map_err(Error::DeserializeError)
+    Ok(body)
 }
 
 fn get_body_length(response: &Response) -> usize {
@@ -706,3 +761,22 @@ fn flatten_result<T, E>(
         Err(err) => Err(err),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{RequestLatencies, LATENCY_BUCKETS};
+    use std::time::Duration;
+
+    #[test]
+    fn test_record_latencies() {
+        let mut latencies = RequestLatencies::default();
+        latencies.record(Duration::from_millis(200));
+        latencies.record(Duration::from_secs(3));
+        latencies.record(Duration::from_secs(60));
+
+        assert_eq!(latencies.buckets, [0, 1, 1, 1, 1, 2, 2, 2]);
+        assert_eq!(latencies.buckets.len(), LATENCY_BUCKETS.len());
+        assert_eq!(latencies.count, 3);
+        assert_eq!(latencies.sum, Duration::from_millis(63_200));
+    }
+}
@@ -1,10 +1,15 @@
-use crate::{new_rpc_client, Command, Error, Result};
+use crate::{format, new_rpc_client, Command, Error, Result};
+use chrono::{DateTime, Utc};
 use itertools::Itertools;
 use mullvad_management_interface::{
     types::{self, Timestamp},
     Code, ManagementServiceClient, Status,
 };
-use mullvad_types::{account::AccountToken, device::Device};
+use mullvad_types::{
+    account::AccountToken,
+    device::{Device, DeviceState},
+};
+use serde::Serialize;
 use std::io::{self, Write};
 
 const NOT_LOGGED_IN_MESSAGE: &str = "Not logged in on any account";
@@ -18,6 +23,13 @@ const ALREADY_LOGGED_IN_ERROR: &str =
 
 pub struct Account;
 
+#[derive(Serialize)]
+struct AccountOutput {
+    device_state: DeviceState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expiry: Option<DateTime<Utc>>,
+}
+
 #[mullvad_management_interface::async_trait]
 impl Command for Account {
     fn name(&self) -> &'static str {
@@ -95,6 +107,9 @@ impl Command for Account {
         } else if let Some(_matches) = matches.subcommand_matches("logout") {
             self.logout().await
         } else if let Some(set_matches) = matches.subcommand_matches("get") {
+            if set_matches.is_present("json") {
+                return self.get_json().await;
+            }
             let verbose = set_matches.is_present("verbose");
             self.get(verbose).await
         } else if let Some(set_matches) = matches.subcommand_matches("list-devices") {
@@ -185,6 +200,38 @@ impl Account {
         Ok(())
     }
 
+    async fn get_json(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+
+        let _ = rpc.update_device(()).await;
+
+        let state = rpc
+            .get_device(())
+            .await
+            .map_err(map_device_error)?
+            .into_inner();
+        let device_state = DeviceState::try_from(state)?;
+
+        let expiry = match &device_state {
+            DeviceState::LoggedIn(device) => {
+                let account_data = rpc
+                    .get_account_data(device.account_token.clone())
+                    .await
+                    .map_err(|error| Error::RpcFailedExt("Failed to fetch account data", error))?
+                    .into_inner();
+                account_data
+                    .expiry
+                    .map(|expiry| Self::expiry_to_utc(&expiry))
+            }
+            DeviceState::LoggedOut | DeviceState::Revoked => None,
+        };
+
+        format::print_json(&AccountOutput {
+            device_state,
+            expiry,
+        })
+    }
+
     async fn list_devices(&self, matches: &clap::ArgMatches) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         let token = self.parse_account_else_current(&mut rpc, matches).await?;
@@ -196,10 +243,20 @@ impl Account {
 
         let verbose = matches.is_present("verbose");
 
-        println!("Devices on the account:");
         device_list
             .devices
             .sort_unstable_by_key(|dev| dev.created.as_ref().map(|dt| dt.seconds).unwrap_or(0));
+
+        if matches.is_present("json") {
+            let devices = device_list
+                .devices
+                .into_iter()
+                .map(Device::try_from)
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            return format::print_json(&devices);
+        }
+
+        println!("Devices on the account:");
         for device in device_list.devices {
             let device = Device::try_from(device.clone()).unwrap();
             if verbose {
@@ -317,9 +374,14 @@ impl Account {
     }
 
     fn format_expiry(expiry: &Timestamp) -> String {
+        Self::expiry_to_utc(expiry)
+            .with_timezone(&chrono::Local)
+            .to_string()
+    }
+
+    fn expiry_to_utc(expiry: &Timestamp) -> DateTime<Utc> {
         let ndt = chrono::NaiveDateTime::from_timestamp(expiry.seconds, expiry.nanos as u32);
-        let utc = chrono::DateTime::<chrono::Utc>::from_utc(ndt, chrono::Utc);
-        utc.with_timezone(&chrono::Local).to_string()
+        DateTime::<Utc>::from_utc(ndt, Utc)
     }
 }
 
@@ -1,4 +1,13 @@
+#[cfg(target_os = "linux")]
+use crate::Error;
 use crate::{new_rpc_client, Command, Result};
+#[cfg(target_os = "linux")]
+use mullvad_management_interface::{types, Code, ManagementServiceClient};
+#[cfg(target_os = "linux")]
+use mullvad_types::network_rules::{
+    MacAddress, NetworkInfo, NetworkMatch, NetworkRule, NetworkRuleError, NetworkRulesSettings,
+    NetworkTrust,
+};
 
 pub struct AutoConnect;
 
@@ -9,7 +18,7 @@ impl Command for AutoConnect {
     }
 
     fn clap_subcommand(&self) -> clap::App<'static> {
-        clap::App::new(self.name())
+        let subcommand = clap::App::new(self.name())
             .about("Control the daemon auto-connect setting")
             .setting(clap::AppSettings::SubcommandRequiredElseHelp)
             .subcommand(
@@ -21,7 +30,10 @@ impl Command for AutoConnect {
                             .possible_values(["on", "off"]),
                     ),
             )
-            .subcommand(clap::App::new("get").about("Display the current auto-connect setting"))
+            .subcommand(clap::App::new("get").about("Display the current auto-connect setting"));
+        #[cfg(target_os = "linux")]
+        let subcommand = subcommand.subcommand(create_rule_subcommand());
+        subcommand
     }
 
     async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
@@ -31,11 +43,99 @@ impl Command for AutoConnect {
         } else if let Some(_matches) = matches.subcommand_matches("get") {
             self.get().await
         } else {
+            #[cfg(target_os = "linux")]
+            if let Some(rule_matches) = matches.subcommand_matches("rule") {
+                return Self::handle_rule(rule_matches).await;
+            }
             unreachable!("No auto-connect command given");
         }
     }
 }
 
+#[cfg(target_os = "linux")]
+fn create_rule_subcommand() -> clap::App<'static> {
+    clap::App::new("rule")
+        .about(
+            "Manage rules that connect or disconnect the tunnel when joining specific networks. \
+            The tunnel is disconnected when joining a trusted network, and traffic is not blocked \
+            there even if 'always require VPN' is enabled. It is connected when joining an \
+            untrusted network. Since anyone can broadcast an SSID, a trusted network must also be \
+            matched by its connection or gateway MAC address",
+        )
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::App::new("list").about("Show the network rules and the current network"))
+        .subcommand(
+            clap::App::new("add")
+                .about(
+                    "Add a rule. Rules are evaluated in order and the first match applies. A rule \
+                    with several network properties only matches networks that have all of them",
+                )
+                .arg(trust_arg())
+                .arg(
+                    clap::Arg::new("ssid")
+                        .help("Match Wi-Fi networks with this SSID")
+                        .long("ssid")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::new("connection")
+                        .help("Match the NetworkManager connection with this UUID")
+                        .long("connection")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::new("gateway-mac")
+                        .help("Match networks whose default gateway has this MAC address")
+                        .long("gateway-mac")
+                        .takes_value(true)
+                        .validator(str::parse::<MacAddress>),
+                )
+                .group(
+                    clap::ArgGroup::new("network")
+                        .args(&["ssid", "connection", "gateway-mac"])
+                        .multiple(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            clap::App::new("remove").about("Remove a rule").arg(
+                clap::Arg::new("index")
+                    .help("Position of the rule, as shown by 'list'")
+                    .required(true),
+            ),
+        )
+        .subcommand(
+            clap::App::new("unknown-networks")
+                .about("Set how to treat networks that no rule matches")
+                .arg(
+                    clap::Arg::new("trust")
+                        .help(
+                            "Whether networks without a matching rule are trusted, or if joining \
+                            them should leave the tunnel unchanged",
+                        )
+                        .required(true)
+                        .possible_values(["trusted", "untrusted", "unchanged"]),
+                ),
+        )
+}
+
+#[cfg(target_os = "linux")]
+fn trust_arg() -> clap::Arg<'static> {
+    clap::Arg::new("trust")
+        .help("Whether the network is trusted")
+        .required(true)
+        .possible_values(["trusted", "untrusted"])
+}
+
+#[cfg(target_os = "linux")]
+fn parse_trust(trust: &str) -> Option<NetworkTrust> {
+    match trust {
+        "trusted" => Some(NetworkTrust::Trusted),
+        "untrusted" => Some(NetworkTrust::Untrusted),
+        _ => None,
+    }
+}
+
 impl AutoConnect {
     async fn set(&self, auto_connect: bool) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
@@ -50,4 +150,129 @@ impl AutoConnect {
         println!("Autoconnect: {}", if auto_connect { "on" } else { "off" });
         Ok(())
     }
+
+    #[cfg(target_os = "linux")]
+    async fn handle_rule(matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("list", _)) => Self::list_rules().await,
+            Some(("add", matches)) => {
+                let trust = parse_trust(matches.value_of("trust").unwrap()).unwrap();
+                let mut networks = vec![];
+                if let Some(ssid) = matches.value_of("ssid") {
+                    networks.push(NetworkMatch::Ssid(ssid.to_owned()));
+                }
+                if let Some(uuid) = matches.value_of("connection") {
+                    networks.push(NetworkMatch::ConnectionUuid(uuid.to_owned()));
+                }
+                if matches.is_present("gateway-mac") {
+                    networks.push(NetworkMatch::GatewayMac(
+                        matches.value_of_t_or_exit("gateway-mac"),
+                    ));
+                }
+                let rule = NetworkRule { networks, trust };
+                if rule.validate() == Err(NetworkRuleError::TrustedBySsidOnly) {
+                    return Err(Error::InvalidCommand(
+                        "A trusted network must also be matched by --connection or --gateway-mac",
+                    ));
+                }
+                Self::update_rules(|rules| {
+                    rules.rules.push(rule);
+                    Ok(())
+                })
+                .await
+            }
+            Some(("remove", matches)) => {
+                let index: usize = matches.value_of_t_or_exit("index");
+                Self::update_rules(|rules| {
+                    if index == 0 || index > rules.rules.len() {
+                        return Err(Error::InvalidCommand("There is no rule at that position"));
+                    }
+                    rules.rules.remove(index - 1);
+                    Ok(())
+                })
+                .await
+            }
+            Some(("unknown-networks", matches)) => {
+                let trust = parse_trust(matches.value_of("trust").unwrap());
+                Self::update_rules(|rules| {
+                    rules.unknown_network_trust = trust;
+                    Ok(())
+                })
+                .await
+            }
+            _ => unreachable!("unhandled command"),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn list_rules() -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let rules = Self::get_rules(&mut rpc).await?;
+        let current_network = match rpc.get_current_network(()).await {
+            Ok(network) => Some(
+                NetworkInfo::try_from(network.into_inner())
+                    .expect("failed to parse current network"),
+            ),
+            Err(status) if status.code() == Code::NotFound => None,
+            Err(status) => {
+                return Err(Error::RpcFailedExt(
+                    "Failed to obtain the current network",
+                    status,
+                ))
+            }
+        };
+
+        if rules.rules.is_empty() {
+            println!("No network rules");
+        }
+        for (index, rule) in rules.rules.iter().enumerate() {
+            println!("{}. {}", index + 1, rule);
+        }
+        match rules.unknown_network_trust {
+            Some(trust) => println!("Other networks are {trust}"),
+            None => println!("Other networks leave the tunnel unchanged"),
+        }
+
+        println!();
+        match current_network {
+            Some(network) => {
+                println!("Current network: {}", network.connection_name);
+                println!("\tConnection: {}", network.connection_uuid);
+                if let Some(ssid) = &network.ssid {
+                    println!("\tSSID: {ssid}");
+                }
+                if let Some(mac) = &network.gateway_mac {
+                    println!("\tGateway MAC: {mac}");
+                }
+                match rules.evaluate(&network) {
+                    Some(trust) => println!("\tTrust: {trust}"),
+                    None => println!("\tTrust: no rule applies"),
+                }
+            }
+            None => println!("Not connected to any network"),
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn update_rules(
+        update: impl FnOnce(&mut NetworkRulesSettings) -> Result<()>,
+    ) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let mut rules = Self::get_rules(&mut rpc).await?;
+        update(&mut rules)?;
+        rpc.set_network_rules(types::NetworkRules::from(&rules))
+            .await?;
+        println!("Updated network rules");
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn get_rules(rpc: &mut ManagementServiceClient) -> Result<NetworkRulesSettings> {
+        let settings = rpc.get_settings(()).await?.into_inner();
+        Ok(
+            NetworkRulesSettings::try_from(settings.network_rules.expect("No network rules"))
+                .expect("failed to parse network rules"),
+        )
+    }
 }
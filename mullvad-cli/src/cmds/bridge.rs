@@ -1,15 +1,22 @@
-use crate::{location, new_rpc_client, Command, Error, Result};
+use crate::{format, location, new_rpc_client, Command, Error, Result};
 
 use mullvad_management_interface::types;
 use mullvad_types::relay_constraints::{
     BridgeConstraints, BridgeSettings, BridgeState, Constraint, LocationConstraint,
 };
+use serde::Serialize;
 use talpid_types::net::openvpn::{self, SHADOWSOCKS_CIPHERS};
 
 use std::{convert::TryFrom, net::SocketAddr};
 
 pub struct Bridge;
 
+#[derive(Serialize)]
+struct BridgeOutput {
+    bridge_state: BridgeState,
+    bridge_settings: BridgeSettings,
+}
+
 #[mullvad_management_interface::async_trait]
 impl Command for Bridge {
     fn name(&self) -> &'static str {
@@ -33,7 +40,7 @@ impl Command for Bridge {
     async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
         match matches.subcommand() {
             Some(("set", set_matches)) => Self::handle_set(set_matches).await,
-            Some(("get", _)) => Self::handle_get().await,
+            Some(("get", get_matches)) => Self::handle_get(get_matches.is_present("json")).await,
             Some(("list", _)) => Self::list_bridge_relays().await,
             _ => unreachable!("unhandled command"),
         }
@@ -210,14 +217,28 @@ impl Bridge {
         }
     }
 
-    async fn handle_get() -> Result<()> {
+    async fn handle_get(json: bool) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         let settings = rpc.get_settings(()).await?.into_inner();
-        let bridge_settings = BridgeSettings::try_from(settings.bridge_settings.unwrap()).unwrap();
-        println!(
-            "Bridge state: {}",
-            BridgeState::try_from(settings.bridge_state.unwrap()).unwrap()
-        );
+        let bridge_settings = BridgeSettings::try_from(
+            settings
+                .bridge_settings
+                .ok_or(Error::InvalidDaemonResponse("missing bridge settings"))?,
+        )?;
+        let bridge_state = BridgeState::try_from(
+            settings
+                .bridge_state
+                .ok_or(Error::InvalidDaemonResponse("missing bridge state"))?,
+        )?;
+
+        if json {
+            return format::print_json(&BridgeOutput {
+                bridge_state,
+                bridge_settings,
+            });
+        }
+
+        println!("Bridge state: {bridge_state}");
         match bridge_settings {
             BridgeSettings::Custom(proxy) => match proxy {
                 openvpn::ProxySettings::Local(local_proxy) => Self::print_local_proxy(&local_proxy),
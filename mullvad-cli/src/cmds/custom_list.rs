@@ -0,0 +1,160 @@
+use crate::{location, new_rpc_client, Command, Error, Result};
+use mullvad_management_interface::{types, ManagementServiceClient};
+use mullvad_types::relay_constraints::{Constraint, LocationConstraint};
+
+pub struct CustomList;
+
+#[mullvad_management_interface::async_trait]
+impl Command for CustomList {
+    fn name(&self) -> &'static str {
+        "custom-list"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about("Manage named lists of locations that can be used as relay constraints")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(clap::App::new("list").about("Show all custom lists and their locations"))
+            .subcommand(
+                clap::App::new("new")
+                    .about("Create a new, empty custom list")
+                    .arg(name_arg()),
+            )
+            .subcommand(
+                clap::App::new("delete")
+                    .about("Delete a custom list")
+                    .arg(name_arg()),
+            )
+            .subcommand(location_subcommand("add").about("Add a location to a custom list"))
+            .subcommand(location_subcommand("remove").about("Remove a location from a custom list"))
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("list", _)) => Self::list().await,
+            Some(("new", matches)) => Self::create(matches.value_of("name").unwrap()).await,
+            Some(("delete", matches)) => Self::delete(matches.value_of("name").unwrap()).await,
+            Some(("add", matches)) => Self::add_location(matches).await,
+            Some(("remove", matches)) => Self::remove_location(matches).await,
+            _ => unreachable!("unhandled command"),
+        }
+    }
+}
+
+fn name_arg() -> clap::Arg<'static> {
+    clap::Arg::new("name")
+        .help("Name of the custom list")
+        .required(true)
+}
+
+fn location_subcommand(name: &'static str) -> clap::App<'static> {
+    clap::App::new(name)
+        .arg(name_arg().index(1))
+        .arg(
+            clap::Arg::new("country")
+                .help("The two letter country code")
+                .required(true)
+                .index(2)
+                .validator(location::country_code_validator),
+        )
+        .arg(
+            clap::Arg::new("city")
+                .help("The three letter city code")
+                .index(3)
+                .validator(location::city_code_validator),
+        )
+        .arg(clap::Arg::new("hostname").help("The hostname").index(4))
+}
+
+impl CustomList {
+    async fn list() -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let custom_lists = Self::get_custom_lists(&mut rpc).await?;
+        if custom_lists.is_empty() {
+            println!("No custom lists");
+        }
+        for list in custom_lists {
+            println!("{}", list.name);
+            for location in list.locations {
+                println!("\t{}", Constraint::<LocationConstraint>::from(location));
+            }
+        }
+        Ok(())
+    }
+
+    async fn create(name: &str) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.create_custom_list(name.to_owned()).await?;
+        println!("Created custom list {name}");
+        Ok(())
+    }
+
+    async fn delete(name: &str) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.delete_custom_list(name.to_owned()).await?;
+        println!("Deleted custom list {name}");
+        Ok(())
+    }
+
+    async fn add_location(matches: &clap::ArgMatches) -> Result<()> {
+        let location = Self::get_location_from_args(matches)?;
+        let mut list = Self::find_custom_list(matches.value_of("name").unwrap()).await?;
+        if list.locations.contains(&location) {
+            return Err(Error::CommandFailed("The location is already in the list"));
+        }
+        list.locations.push(location);
+        Self::update_custom_list(list).await
+    }
+
+    async fn remove_location(matches: &clap::ArgMatches) -> Result<()> {
+        let location = Self::get_location_from_args(matches)?;
+        let mut list = Self::find_custom_list(matches.value_of("name").unwrap()).await?;
+        let num_locations = list.locations.len();
+        list.locations.retain(|existing| existing != &location);
+        if list.locations.len() == num_locations {
+            return Err(Error::CommandFailed("The location is not in the list"));
+        }
+        Self::update_custom_list(list).await
+    }
+
+    fn get_location_from_args(matches: &clap::ArgMatches) -> Result<types::RelayLocation> {
+        let location = location::get_constraint(
+            matches.value_of("country").unwrap(),
+            matches.value_of("city"),
+            matches.value_of("hostname"),
+        );
+        if location.country.is_empty() {
+            return Err(Error::InvalidCommand(
+                "Custom lists cannot contain the 'any' location",
+            ));
+        }
+        Ok(location)
+    }
+
+    async fn find_custom_list(name: &str) -> Result<types::CustomList> {
+        let mut rpc = new_rpc_client().await?;
+        Self::get_custom_lists(&mut rpc)
+            .await?
+            .into_iter()
+            .find(|list| list.name == name)
+            .ok_or(Error::CommandFailed("No custom list with that name exists"))
+    }
+
+    async fn update_custom_list(list: types::CustomList) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let name = list.name.clone();
+        rpc.update_custom_list(list).await?;
+        println!("Updated custom list {name}");
+        Ok(())
+    }
+
+    async fn get_custom_lists(rpc: &mut ManagementServiceClient) -> Result<Vec<types::CustomList>> {
+        Ok(rpc
+            .get_settings(())
+            .await?
+            .into_inner()
+            .custom_lists
+            .unwrap_or_default()
+            .custom_lists)
+    }
+}
@@ -0,0 +1,584 @@
+//! Probes that try to send traffic that the firewall should block in the current tunnel state.
+//!
+//! The probes use ordinary, unprivileged sockets without the firewall mark that the daemon uses
+//! for its own traffic outside the tunnel, so they are subject to the same rules as any other
+//! application. A probe counts as having got through if the kernel accepts the packet, since the
+//! firewall drops or rejects blocked packets in the output path and the send fails with an error.
+
+use ipnetwork::IpNetwork;
+use mullvad_types::{
+    settings::{DnsOptions, DnsState, Settings},
+    states::TunnelState,
+};
+use serde::Serialize;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::{
+    fmt, fs, io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+use talpid_types::net::{AllowListEndpoint, TransportProtocol};
+
+/// Public resolvers that the DNS probe is sent to, unless the user has configured them.
+const PUBLIC_RESOLVERS: [IpAddr; 3] = [
+    IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)),
+    IpAddr::V4(Ipv4Addr::new(9, 9, 9, 9)),
+    IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)),
+];
+const DEFAULT_IPV4_TARGET: Ipv4Addr = Ipv4Addr::new(1, 1, 1, 1);
+const DEFAULT_IPV6_TARGET: Ipv6Addr = Ipv6Addr::new(0x2606, 0x4700, 0x4700, 0, 0, 0, 0, 0x1111);
+
+const PROBE_PORT: u16 = 443;
+const DNS_PORT: u16 = 53;
+/// The discard port, so that a probe that gets through is ignored by the receiver.
+const LAN_PROBE_PORT: u16 = 9;
+const TCP_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+const PROBE_PAYLOAD: &[u8] = b"mullvad leak test";
+/// A query for the A record of `mullvad.net`.
+const DNS_QUERY: &[u8] = &[
+    0x4d, 0x56, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, b'm', b'u', b'l',
+    b'l', b'v', b'a', b'd', 0x03, b'n', b'e', b't', 0x00, 0x00, 0x01, 0x00, 0x01,
+];
+/// An ICMP echo request. The kernel fills in the identifier and checksum for ping sockets.
+const ICMP_ECHO_REQUEST: &[u8] = &[8, 0, 0, 0, 0, 0, 0, 1];
+
+/// The kind of traffic that a probe sends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeKind {
+    /// UDP bound to the physical interface.
+    Udp,
+    /// TCP bound to the physical interface.
+    Tcp,
+    /// ICMP echo request bound to the physical interface.
+    Icmp,
+    /// A DNS query to a server that is not configured.
+    Dns,
+    /// UDP over IPv6.
+    Ipv6,
+    /// UDP to an address on the local network.
+    Lan,
+}
+
+impl ProbeKind {
+    fn protocol(self) -> Option<TransportProtocol> {
+        match self {
+            ProbeKind::Udp | ProbeKind::Dns | ProbeKind::Ipv6 | ProbeKind::Lan => {
+                Some(TransportProtocol::Udp)
+            }
+            ProbeKind::Tcp => Some(TransportProtocol::Tcp),
+            ProbeKind::Icmp => None,
+        }
+    }
+}
+
+/// The traffic that the firewall is expected to block, as derived from the firewall policy of
+/// the tunnel state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Enforcement {
+    pub outside_tunnel: bool,
+    pub dns: bool,
+    pub ipv6: bool,
+    pub lan: bool,
+    /// Networks that are reachable despite the above, since local network sharing is enabled.
+    pub lan_networks: Vec<IpNetwork>,
+    /// Endpoints that are reachable despite the above.
+    pub allow_list: Vec<AllowListEndpoint>,
+}
+
+impl Enforcement {
+    /// Returns what the firewall is expected to block in `state`, or `None` if the firewall is
+    /// about to change policy.
+    pub fn new(state: &TunnelState, settings: &Settings) -> Option<Self> {
+        let blocked = Enforcement {
+            outside_tunnel: true,
+            dns: true,
+            ipv6: true,
+            lan: true,
+            lan_networks: if settings.allow_lan {
+                settings.lan_networks.clone()
+            } else {
+                vec![]
+            },
+            allow_list: settings.firewall_allow_list.clone(),
+        };
+        let unrestricted = Enforcement {
+            outside_tunnel: false,
+            dns: false,
+            ipv6: false,
+            lan: false,
+            lan_networks: vec![],
+            allow_list: vec![],
+        };
+
+        match state {
+            TunnelState::Disconnected if settings.block_when_disconnected => Some(blocked),
+            TunnelState::Disconnected => Some(unrestricted),
+            TunnelState::Connecting { .. } => Some(blocked),
+            TunnelState::Connected { .. } => Some(Enforcement {
+                ipv6: !settings.tunnel_options.generic.enable_ipv6,
+                ..blocked
+            }),
+            TunnelState::Error(error_state) if error_state.is_blocking() => Some(blocked),
+            TunnelState::Error(_) => Some(unrestricted),
+            TunnelState::Disconnecting(_) => None,
+        }
+    }
+
+    /// Returns whether a probe to `address` and `port` is expected to be blocked. The allow list
+    /// is applied before DNS is blocked, but the LAN networks are not.
+    fn blocks(&self, probe: ProbeKind, address: Option<IpAddr>, port: Option<u16>) -> bool {
+        let blocked = match probe {
+            ProbeKind::Udp | ProbeKind::Tcp | ProbeKind::Icmp => self.outside_tunnel,
+            ProbeKind::Dns => self.dns,
+            ProbeKind::Ipv6 => self.ipv6,
+            ProbeKind::Lan => self.lan,
+        };
+        let address = match address {
+            Some(address) => address,
+            None => return blocked,
+        };
+        let allow_listed = match (probe.protocol(), port) {
+            (Some(protocol), Some(port)) => self.allow_list.iter().any(|endpoint| {
+                endpoint.protocol == protocol
+                    && endpoint.port == port
+                    && endpoint.network.contains(address)
+            }),
+            _ => false,
+        };
+        let in_lan = probe != ProbeKind::Dns
+            && self
+                .lan_networks
+                .iter()
+                .any(|network| network.contains(address));
+        blocked && !allow_listed && !in_lan
+    }
+}
+
+/// Where the probes are sent.
+#[derive(Debug, Clone)]
+pub struct ProbeTargets {
+    /// Interface that probes outside the tunnel are bound to.
+    pub interface: Option<String>,
+    pub ipv4: IpAddr,
+    pub ipv6: IpAddr,
+    pub dns: Option<IpAddr>,
+    pub lan: Option<IpAddr>,
+}
+
+impl ProbeTargets {
+    pub fn new(interface: Option<String>) -> Self {
+        ProbeTargets {
+            interface,
+            ipv4: IpAddr::V4(DEFAULT_IPV4_TARGET),
+            ipv6: IpAddr::V6(DEFAULT_IPV6_TARGET),
+            dns: None,
+            lan: None,
+        }
+    }
+}
+
+/// What happened to a probe.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", content = "reason", rename_all = "snake_case")]
+pub enum Outcome {
+    /// The probe left the host.
+    Reached,
+    /// The probe was not let through.
+    Blocked(String),
+    /// The probe was sent, but it is not known whether it left the host.
+    Inconclusive(String),
+    /// The probe could not be sent.
+    Skipped(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Reached => write!(f, "reached"),
+            Outcome::Blocked(reason) => write!(f, "blocked ({reason})"),
+            Outcome::Inconclusive(reason) => write!(f, "inconclusive ({reason})"),
+            Outcome::Skipped(reason) => write!(f, "skipped ({reason})"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeResult {
+    pub probe: ProbeKind,
+    pub address: Option<IpAddr>,
+    pub port: Option<u16>,
+    pub interface: Option<String>,
+    pub expect_blocked: bool,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+impl ProbeResult {
+    /// Returns whether the probe got through even though it should have been blocked.
+    pub fn is_leak(&self) -> bool {
+        self.expect_blocked && self.outcome == Outcome::Reached
+    }
+
+    /// Returns whether it is unknown if the probe got through even though it should have been
+    /// blocked.
+    pub fn is_inconclusive(&self) -> bool {
+        self.expect_blocked && matches!(self.outcome, Outcome::Inconclusive(_))
+    }
+
+    /// Returns a description of where the probe was sent.
+    pub fn target(&self) -> String {
+        let mut target = match (self.address, self.port) {
+            (Some(address), Some(port)) => SocketAddr::new(address, port).to_string(),
+            (Some(address), None) => address.to_string(),
+            (None, _) => "-".to_owned(),
+        };
+        if let Some(ref interface) = self.interface {
+            target.push_str(&format!(" via {interface}"));
+        }
+        target
+    }
+}
+
+/// Sends all probes and returns their results.
+pub fn run(targets: &ProbeTargets, enforcement: &Enforcement) -> Vec<ProbeResult> {
+    let ipv4_target = SocketAddr::new(targets.ipv4, PROBE_PORT);
+    // Probes that are not bound to the physical interface are routed into the tunnel
+    let outside_tunnel = |probe: &dyn Fn(Option<&str>) -> Outcome| match targets.interface {
+        Some(ref interface) => probe(Some(interface)),
+        None => Outcome::Skipped("no physical interface found".to_owned()),
+    };
+
+    let mut results = vec![
+        ProbeResult {
+            probe: ProbeKind::Udp,
+            address: Some(ipv4_target.ip()),
+            port: Some(ipv4_target.port()),
+            interface: targets.interface.clone(),
+            expect_blocked: enforcement.blocks(
+                ProbeKind::Udp,
+                Some(ipv4_target.ip()),
+                Some(ipv4_target.port()),
+            ),
+            outcome: outside_tunnel(&|interface| send_udp(ipv4_target, interface, PROBE_PAYLOAD)),
+        },
+        ProbeResult {
+            probe: ProbeKind::Tcp,
+            address: Some(ipv4_target.ip()),
+            port: Some(ipv4_target.port()),
+            interface: targets.interface.clone(),
+            expect_blocked: enforcement.blocks(
+                ProbeKind::Tcp,
+                Some(ipv4_target.ip()),
+                Some(ipv4_target.port()),
+            ),
+            outcome: outside_tunnel(&|interface| connect_tcp(ipv4_target, interface)),
+        },
+        ProbeResult {
+            probe: ProbeKind::Icmp,
+            address: Some(targets.ipv4),
+            port: None,
+            interface: targets.interface.clone(),
+            expect_blocked: enforcement.blocks(ProbeKind::Icmp, Some(targets.ipv4), None),
+            outcome: outside_tunnel(&|interface| send_ping(targets.ipv4, interface)),
+        },
+    ];
+
+    let dns_target = targets.dns.map(|ip| SocketAddr::new(ip, DNS_PORT));
+    results.push(ProbeResult {
+        probe: ProbeKind::Dns,
+        address: dns_target.map(|addr| addr.ip()),
+        port: dns_target.map(|addr| addr.port()),
+        interface: None,
+        expect_blocked: enforcement.blocks(
+            ProbeKind::Dns,
+            dns_target.map(|addr| addr.ip()),
+            dns_target.map(|addr| addr.port()),
+        ),
+        outcome: match dns_target {
+            Some(target) => send_udp(target, None, DNS_QUERY),
+            None => Outcome::Skipped("all public resolvers are configured".to_owned()),
+        },
+    });
+
+    let ipv6_target = SocketAddr::new(targets.ipv6, PROBE_PORT);
+    results.push(ProbeResult {
+        probe: ProbeKind::Ipv6,
+        address: Some(ipv6_target.ip()),
+        port: Some(ipv6_target.port()),
+        interface: None,
+        expect_blocked: enforcement.blocks(
+            ProbeKind::Ipv6,
+            Some(ipv6_target.ip()),
+            Some(ipv6_target.port()),
+        ),
+        outcome: send_udp(ipv6_target, None, PROBE_PAYLOAD),
+    });
+
+    let lan_target = targets.lan.map(|ip| SocketAddr::new(ip, LAN_PROBE_PORT));
+    results.push(ProbeResult {
+        probe: ProbeKind::Lan,
+        address: lan_target.map(|addr| addr.ip()),
+        port: lan_target.map(|addr| addr.port()),
+        interface: None,
+        expect_blocked: enforcement.blocks(
+            ProbeKind::Lan,
+            lan_target.map(|addr| addr.ip()),
+            lan_target.map(|addr| addr.port()),
+        ),
+        outcome: match lan_target {
+            Some(target) => send_udp(target, None, PROBE_PAYLOAD),
+            None => Outcome::Skipped("no default gateway found".to_owned()),
+        },
+    });
+
+    results
+}
+
+fn send_udp(target: SocketAddr, interface: Option<&str>, payload: &[u8]) -> Outcome {
+    let socket = match open_socket(
+        Domain::for_address(target),
+        Type::DGRAM,
+        Protocol::UDP,
+        interface,
+    ) {
+        Ok(socket) => socket,
+        Err(outcome) => return outcome,
+    };
+    match socket.send_to(payload, &SockAddr::from(target)) {
+        Ok(_) => Outcome::Reached,
+        Err(error) => Outcome::Blocked(error.to_string()),
+    }
+}
+
+/// Connects to `target`. A refused connection counts as blocked, since the firewall rejects
+/// blocked packets in the output path. A timeout is inconclusive, since it can also mean that the
+/// connection attempt left the host and was not answered.
+fn connect_tcp(target: SocketAddr, interface: Option<&str>) -> Outcome {
+    let socket = match open_socket(
+        Domain::for_address(target),
+        Type::STREAM,
+        Protocol::TCP,
+        interface,
+    ) {
+        Ok(socket) => socket,
+        Err(outcome) => return outcome,
+    };
+    match socket.connect_timeout(&SockAddr::from(target), TCP_CONNECT_TIMEOUT) {
+        Ok(()) => Outcome::Reached,
+        Err(error) => connect_error_outcome(error),
+    }
+}
+
+fn connect_error_outcome(error: io::Error) -> Outcome {
+    match error.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+            Outcome::Inconclusive(error.to_string())
+        }
+        _ => Outcome::Blocked(error.to_string()),
+    }
+}
+
+/// Sends an ICMP echo request from an unprivileged ping socket.
+fn send_ping(target: IpAddr, interface: Option<&str>) -> Outcome {
+    let (domain, protocol) = match target {
+        IpAddr::V4(_) => (Domain::IPV4, Protocol::ICMPV4),
+        IpAddr::V6(_) => (Domain::IPV6, Protocol::ICMPV6),
+    };
+    let socket = match open_socket(domain, Type::DGRAM, protocol, interface) {
+        Ok(socket) => socket,
+        Err(outcome) => return outcome,
+    };
+    match socket.send_to(
+        ICMP_ECHO_REQUEST,
+        &SockAddr::from(SocketAddr::new(target, 0)),
+    ) {
+        Ok(_) => Outcome::Reached,
+        Err(error) => Outcome::Blocked(error.to_string()),
+    }
+}
+
+fn open_socket(
+    domain: Domain,
+    ty: Type,
+    protocol: Protocol,
+    interface: Option<&str>,
+) -> Result<Socket, Outcome> {
+    let socket = Socket::new(domain, ty, Some(protocol))
+        .map_err(|error| Outcome::Skipped(format!("failed to create socket: {error}")))?;
+    if let Some(interface) = interface {
+        // Binding to an interface only requires `CAP_NET_RAW` before Linux 5.7
+        socket
+            .bind_device(Some(interface.as_bytes()))
+            .map_err(|error| Outcome::Skipped(format!("failed to bind to {interface}: {error}")))?;
+    }
+    Ok(socket)
+}
+
+/// Returns the first public resolver that is not one of the DNS servers that the firewall allows
+/// queries to.
+pub fn unconfigured_resolver(options: &DnsOptions) -> Option<IpAddr> {
+    let mut configured: Vec<IpAddr> = options
+        .split_dns_rules
+        .iter()
+        .flat_map(|rule| rule.resolvers.iter().copied())
+        .collect();
+    if options.state == DnsState::Custom {
+        configured.extend(options.custom_options.addresses.iter().copied());
+    }
+    PUBLIC_RESOLVERS
+        .into_iter()
+        .find(|resolver| !configured.contains(resolver))
+}
+
+/// An IPv4 default route in the main routing table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefaultRoute {
+    pub interface: String,
+    pub gateway: Ipv4Addr,
+}
+
+/// Returns the default route with the lowest metric in the main routing table. The tunnel routes
+/// are kept in a separate table, so this is the route through the physical interface.
+pub fn default_route() -> Option<DefaultRoute> {
+    let routes = fs::read_to_string("/proc/net/route").ok()?;
+    parse_default_route(&routes)
+}
+
+fn parse_default_route(routes: &str) -> Option<DefaultRoute> {
+    const RTF_UP: u16 = 0x1;
+    const RTF_GATEWAY: u16 = 0x2;
+
+    let parse_addr = |hex: &str| -> Option<Ipv4Addr> {
+        Some(u32::from_str_radix(hex, 16).ok()?.to_ne_bytes().into())
+    };
+
+    routes
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (interface, destination, gateway, flags, metric, mask) = (
+                *fields.first()?,
+                parse_addr(fields.get(1)?)?,
+                parse_addr(fields.get(2)?)?,
+                u16::from_str_radix(fields.get(3)?, 16).ok()?,
+                fields.get(6)?.parse::<u32>().ok()?,
+                parse_addr(fields.get(7)?)?,
+            );
+            let is_default = destination.is_unspecified() && mask.is_unspecified();
+            let is_usable = flags & (RTF_UP | RTF_GATEWAY) == RTF_UP | RTF_GATEWAY;
+            (is_default && is_usable).then(|| {
+                (
+                    metric,
+                    DefaultRoute {
+                        interface: interface.to_owned(),
+                        gateway,
+                    },
+                )
+            })
+        })
+        .min_by_key(|(metric, _)| *metric)
+        .map(|(_, route)| route)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::{TcpListener, UdpSocket};
+    use talpid_types::tunnel::{ErrorState, ErrorStateCause};
+
+    #[test]
+    fn test_enforcement() {
+        let mut settings = Settings::default();
+        settings.allow_lan = false;
+        settings.block_when_disconnected = false;
+        settings.tunnel_options.generic.enable_ipv6 = false;
+        let lan_target = Some(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)));
+
+        let disconnected = Enforcement::new(&TunnelState::Disconnected, &settings).unwrap();
+        assert!(!disconnected.blocks(ProbeKind::Udp, None, None));
+        assert!(!disconnected.blocks(ProbeKind::Lan, lan_target, Some(LAN_PROBE_PORT)));
+
+        let error = TunnelState::Error(ErrorState::new(ErrorStateCause::IsOffline, None));
+        let error = Enforcement::new(&error, &settings).unwrap();
+        assert!(error.blocks(ProbeKind::Icmp, None, None));
+        assert!(error.blocks(ProbeKind::Ipv6, None, None));
+        assert!(error.blocks(ProbeKind::Lan, lan_target, Some(LAN_PROBE_PORT)));
+
+        settings.allow_lan = true;
+        settings.block_when_disconnected = true;
+        let lockdown = Enforcement::new(&TunnelState::Disconnected, &settings).unwrap();
+        assert!(lockdown.blocks(ProbeKind::Tcp, None, None));
+        assert!(lockdown.blocks(ProbeKind::Dns, None, None));
+        assert!(!lockdown.blocks(ProbeKind::Lan, lan_target, Some(LAN_PROBE_PORT)));
+        assert!(lockdown.blocks(ProbeKind::Dns, lan_target, Some(DNS_PORT)));
+
+        settings.lan_networks = vec!["10.0.0.0/8".parse().unwrap()];
+        let lockdown = Enforcement::new(&TunnelState::Disconnected, &settings).unwrap();
+        assert!(lockdown.blocks(ProbeKind::Lan, lan_target, Some(LAN_PROBE_PORT)));
+    }
+
+    #[test]
+    fn test_enforcement_allow_list() {
+        let mut settings = Settings::default();
+        settings.block_when_disconnected = true;
+        settings.firewall_allow_list = vec!["1.1.1.0/24:443/tcp".parse().unwrap()];
+        let enforcement = Enforcement::new(&TunnelState::Disconnected, &settings).unwrap();
+
+        let target = Some(IpAddr::V4(DEFAULT_IPV4_TARGET));
+        assert!(!enforcement.blocks(ProbeKind::Tcp, target, Some(PROBE_PORT)));
+        assert!(enforcement.blocks(ProbeKind::Udp, target, Some(PROBE_PORT)));
+        assert!(enforcement.blocks(ProbeKind::Tcp, target, Some(80)));
+        assert!(enforcement.blocks(ProbeKind::Icmp, target, None));
+    }
+
+    #[test]
+    fn test_connect_timeout_is_inconclusive() {
+        let timeout = io::Error::from(io::ErrorKind::TimedOut);
+        assert!(matches!(
+            connect_error_outcome(timeout),
+            Outcome::Inconclusive(_)
+        ));
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+        assert!(matches!(
+            connect_error_outcome(refused),
+            Outcome::Blocked(_)
+        ));
+    }
+
+    #[test]
+    fn test_parse_default_route() {
+        let routes = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+wlan0\t00000000\t0100000A\t0003\t0\t0\t600\t00000000\t0\t0\t0
+eth0\t00000000\t0101A8C0\t0003\t0\t0\t100\t00000000\t0\t0\t0
+eth0\t0001A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0
+";
+        let route = parse_default_route(routes).unwrap();
+        assert_eq!(route.interface, "eth0");
+        assert_eq!(
+            route.gateway,
+            Ipv4Addr::from(u32::from_str_radix("0101A8C0", 16).unwrap().to_ne_bytes())
+        );
+
+        assert_eq!(parse_default_route(routes.lines().next().unwrap()), None);
+    }
+
+    #[test]
+    fn test_probes_reach_loopback() {
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let udp_target = udp.local_addr().unwrap();
+        assert_eq!(send_udp(udp_target, None, PROBE_PAYLOAD), Outcome::Reached);
+        let mut buf = [0u8; 64];
+        let (len, _) = udp.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], PROBE_PAYLOAD);
+
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let tcp_target = tcp.local_addr().unwrap();
+        assert_eq!(connect_tcp(tcp_target, None), Outcome::Reached);
+        drop(tcp);
+        assert!(matches!(connect_tcp(tcp_target, None), Outcome::Blocked(_)));
+    }
+}
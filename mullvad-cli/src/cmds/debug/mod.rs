@@ -0,0 +1,173 @@
+use crate::{format, new_rpc_client, Command, Error, Result};
+use mullvad_types::{settings::Settings, states::TunnelState};
+use std::net::IpAddr;
+
+mod leak_test;
+use leak_test::{Enforcement, ProbeKind, ProbeResult, ProbeTargets};
+
+pub struct Debug;
+
+#[mullvad_management_interface::async_trait]
+impl Command for Debug {
+    fn name(&self) -> &'static str {
+        "debug"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about("Debugging and troubleshooting tools")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(create_leak_test_subcommand())
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("leak-test", leak_test_matches)) => Self::leak_test(leak_test_matches).await,
+            _ => unreachable!("unhandled command"),
+        }
+    }
+}
+
+fn create_leak_test_subcommand() -> clap::App<'static> {
+    clap::App::new("leak-test")
+        .about("Check that the firewall blocks traffic that should not leave the tunnel")
+        .long_about(
+            "Check that the firewall blocks traffic that should not leave the tunnel. \
+            Probes are sent from ordinary sockets, outside the tunnel, over IPv6 and to the \
+            local network. Any probe that gets through when the current tunnel state and \
+            settings say it should be blocked is reported as a leak.",
+        )
+        .arg(
+            clap::Arg::new("interface")
+                .long("interface")
+                .takes_value(true)
+                .help(
+                    "Interface to send probes outside the tunnel through. Defaults to the \
+                    interface of the default route",
+                ),
+        )
+        .arg(
+            clap::Arg::new("ipv4-target")
+                .long("ipv4-target")
+                .takes_value(true)
+                .help("IPv4 address to send probes outside the tunnel to"),
+        )
+        .arg(
+            clap::Arg::new("ipv6-target")
+                .long("ipv6-target")
+                .takes_value(true)
+                .help("IPv6 address to send the IPv6 probe to"),
+        )
+        .arg(
+            clap::Arg::new("dns-target")
+                .long("dns-target")
+                .takes_value(true)
+                .help(
+                    "DNS server to send the DNS probe to. Defaults to a public resolver that \
+                    is not configured",
+                ),
+        )
+        .arg(
+            clap::Arg::new("lan-target")
+                .long("lan-target")
+                .takes_value(true)
+                .help(
+                    "Local network address to send the LAN probe to. Defaults to the default \
+                    gateway",
+                ),
+        )
+}
+
+impl Debug {
+    async fn leak_test(matches: &clap::ArgMatches) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let state = TunnelState::try_from(rpc.get_tunnel_state(()).await?.into_inner())?;
+        let settings = Settings::try_from(rpc.get_settings(()).await?.into_inner())?;
+
+        let enforcement = Enforcement::new(&state, &settings).ok_or(Error::CommandFailed(
+            "The tunnel state is changing. Try again when it has settled",
+        ))?;
+
+        let default_route = leak_test::default_route();
+        let interface = match matches.value_of("interface") {
+            Some(interface) => Some(interface.to_owned()),
+            None => default_route.as_ref().map(|route| route.interface.clone()),
+        };
+        let mut targets = ProbeTargets::new(interface);
+        if let Some(target) = parse_ip_arg(matches, "ipv4-target") {
+            targets.ipv4 = target;
+        }
+        if let Some(target) = parse_ip_arg(matches, "ipv6-target") {
+            targets.ipv6 = target;
+        }
+        targets.dns = match parse_ip_arg(matches, "dns-target") {
+            Some(target) => Some(target),
+            None => leak_test::unconfigured_resolver(&settings.tunnel_options.dns_options),
+        };
+        targets.lan = match parse_ip_arg(matches, "lan-target") {
+            Some(target) => Some(target),
+            None => default_route.map(|route| IpAddr::V4(route.gateway)),
+        };
+
+        let results = tokio::task::spawn_blocking(move || leak_test::run(&targets, &enforcement))
+            .await
+            .expect("leak test panicked");
+        let leaked = results.iter().any(ProbeResult::is_leak);
+
+        if matches.is_present("json") {
+            format::print_json(&results)?;
+        } else {
+            print_leak_test_results(&state, &results);
+        }
+
+        if leaked {
+            return Err(Error::CommandFailed(
+                "Traffic that should be blocked was not blocked",
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn parse_ip_arg(matches: &clap::ArgMatches, name: &str) -> Option<IpAddr> {
+    matches
+        .is_present(name)
+        .then(|| matches.value_of_t_or_exit(name))
+}
+
+fn print_leak_test_results(state: &TunnelState, results: &[ProbeResult]) {
+    print!("Tunnel state: ");
+    format::print_state(state, false);
+    println!();
+
+    for result in results {
+        let description = match result.probe {
+            ProbeKind::Udp => "UDP outside tunnel",
+            ProbeKind::Tcp => "TCP outside tunnel",
+            ProbeKind::Icmp => "ICMP outside tunnel",
+            ProbeKind::Dns => "DNS",
+            ProbeKind::Ipv6 => "IPv6",
+            ProbeKind::Lan => "LAN",
+        };
+        let expected = if result.expect_blocked {
+            "blocked"
+        } else {
+            "allowed"
+        };
+        let leak = if result.is_leak() { "  LEAK" } else { "" };
+        println!(
+            "{description:<20} {:<40} expected {expected:<8} {}{leak}",
+            result.target(),
+            result.outcome,
+        );
+    }
+
+    println!();
+    if results.iter().any(ProbeResult::is_leak) {
+        println!("Some probes got through the firewall");
+    } else if results.iter().any(ProbeResult::is_inconclusive) {
+        println!("No leaks found, but some probes were inconclusive");
+    } else {
+        println!("No leaks found");
+    }
+}
@@ -1,7 +1,8 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{format, new_rpc_client, Command, Error, Result};
 use mullvad_management_interface::types;
 use mullvad_types::settings::{DnsOptions, DnsState};
-use std::{convert::TryInto, net::IpAddr};
+use std::{convert::TryInto, net::IpAddr, path::Path};
+use talpid_types::net::dns::{DnsBlocklistStats, EncryptedDnsServer};
 
 pub struct Dns;
 
@@ -60,11 +61,19 @@ impl Command for Dns {
                             .arg(
                                 clap::Arg::new("servers")
                                     .multiple_occurrences(true)
-                                    .help("One or more IP addresses pointing to DNS resolvers.")
+                                    .help(
+                                        "One or more IP addresses pointing to DNS resolvers, or \
+                                        one or more DNS-over-HTTPS or DNS-over-TLS servers, \
+                                        given as https://<name>@<ip>[:<port>]/dns-query or \
+                                        tls://<name>@<ip>[:<port>]. <name> is the name that \
+                                        the certificate of the server must be valid for.",
+                                    )
                                     .required(true),
                             ),
                     ),
             )
+            .subcommand(create_blocklist_subcommand())
+            .subcommand(create_split_subcommand())
     }
 
     async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
@@ -81,23 +90,119 @@ impl Command for Dns {
                     .await
                 }
                 Some(("custom", matches)) => {
-                    let servers = match matches.values_of_t::<IpAddr>("servers") {
-                        Ok(servers) => Some(servers),
-                        Err(e) => match e.kind {
-                            clap::ErrorKind::ArgumentNotFound => None,
-                            _ => e.exit(),
-                        },
-                    };
-                    self.set_custom(servers).await
+                    let values = matches.values_of("servers").into_iter().flatten();
+                    let (encrypted, plain): (Vec<_>, Vec<_>) =
+                        values.partition(|value| value.contains("://"));
+                    if !encrypted.is_empty() && !plain.is_empty() {
+                        clap::Error::raw(
+                            clap::ErrorKind::ArgumentConflict,
+                            "Encrypted DNS servers cannot be combined with plain IP addresses",
+                        )
+                        .exit();
+                    }
+                    let addresses = plain
+                        .into_iter()
+                        .map(|value| value.parse::<IpAddr>().map_err(|e| e.to_string()))
+                        .collect::<std::result::Result<Vec<_>, _>>();
+                    let encrypted_servers = encrypted
+                        .into_iter()
+                        .map(|value| {
+                            value
+                                .parse::<EncryptedDnsServer>()
+                                .map_err(|e| format!("{value}: {e}"))
+                        })
+                        .collect::<std::result::Result<Vec<_>, _>>();
+                    match (addresses, encrypted_servers) {
+                        (Ok(addresses), Ok(encrypted_servers)) => {
+                            self.set_custom(addresses, encrypted_servers).await
+                        }
+                        (Err(error), _) | (_, Err(error)) => {
+                            clap::Error::raw(clap::ErrorKind::ValueValidation, error).exit()
+                        }
+                    }
                 }
                 _ => unreachable!("No custom-dns server command given"),
             },
-            Some(("get", _)) => self.get().await,
+            Some(("get", matches)) => self.get(matches.is_present("json")).await,
+            Some(("blocklist", matches)) => self.handle_blocklist_cmd(matches).await,
+            Some(("split", matches)) => self.handle_split_cmd(matches).await,
             _ => unreachable!("No custom-dns command given"),
         }
     }
 }
 
+fn create_blocklist_subcommand() -> clap::App<'static> {
+    clap::App::new("blocklist")
+        .about(
+            "Manage blocklists that are enforced locally. Queries for blocked domains are answered \
+            with 0.0.0.0 or NXDOMAIN, and all other queries are forwarded to the DNS servers in use",
+        )
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            clap::App::new("list")
+                .about("Display the blocklists and the allowlist"),
+        )
+        .subcommand(
+            clap::App::new("add")
+                .about("Add a hosts file or a file with one domain per line")
+                .arg(clap::Arg::new("path").required(true)),
+        )
+        .subcommand(
+            clap::App::new("remove")
+                .about("Remove a blocklist")
+                .arg(clap::Arg::new("path").required(true)),
+        )
+        .subcommand(
+            clap::App::new("stats")
+                .about("Display the number of domains in and queries blocked by each blocklist"),
+        )
+        .subcommand(
+            clap::App::new("allowlist")
+                .about("Manage domains that are never blocked, including their subdomains")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    clap::App::new("add")
+                        .about("Add a domain to the allowlist")
+                        .arg(clap::Arg::new("domain").required(true)),
+                )
+                .subcommand(
+                    clap::App::new("remove")
+                        .about("Remove a domain from the allowlist")
+                        .arg(clap::Arg::new("domain").required(true)),
+                ),
+        )
+}
+
+fn create_split_subcommand() -> clap::App<'static> {
+    clap::App::new("split")
+        .about(
+            "Manage split DNS rules. Queries for a domain and its subdomains are sent to the \
+            resolvers of its rule instead of the DNS servers in use",
+        )
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::App::new("list").about("Display the split DNS rules"))
+        .subcommand(
+            clap::App::new("add")
+                .about("Add or replace the rule for a domain")
+                .arg(
+                    clap::Arg::new("domain")
+                        .help("Domain suffix, such as corp.internal")
+                        .required(true),
+                )
+                .arg(
+                    clap::Arg::new("resolvers")
+                        .help("One or more IP addresses of resolvers to use for the domain")
+                        .multiple_occurrences(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            clap::App::new("remove")
+                .about("Remove the rule for a domain")
+                .arg(clap::Arg::new("domain").required(true)),
+        )
+}
+
 impl Dns {
     async fn set_default(
         &self,
@@ -125,16 +230,20 @@ impl Dns {
         Ok(())
     }
 
-    async fn set_custom(&self, servers: Option<Vec<IpAddr>>) -> Result<()> {
+    async fn set_custom(
+        &self,
+        addresses: Vec<IpAddr>,
+        encrypted_servers: Vec<EncryptedDnsServer>,
+    ) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         let settings = rpc.get_settings(()).await?.into_inner();
         rpc.set_dns_options(types::DnsOptions {
             state: types::dns_options::DnsState::Custom as i32,
             custom_options: Some(types::CustomDnsOptions {
-                addresses: servers
-                    .unwrap_or_default()
+                addresses: addresses.into_iter().map(|a| a.to_string()).collect(),
+                encrypted_servers: encrypted_servers
                     .into_iter()
-                    .map(|a| a.to_string())
+                    .map(|server| server.to_string())
                     .collect(),
             }),
             ..settings.tunnel_options.unwrap().dns_options.unwrap()
@@ -144,7 +253,7 @@ impl Dns {
         Ok(())
     }
 
-    async fn get(&self) -> Result<()> {
+    async fn get(&self, json: bool) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         let options: DnsOptions = rpc
             .get_settings(())
@@ -157,6 +266,10 @@ impl Dns {
             .try_into()
             .unwrap();
 
+        if json {
+            return format::print_json(&options);
+        }
+
         match options.state {
             DnsState::Default => {
                 println!("Custom DNS: no");
@@ -171,12 +284,268 @@ impl Dns {
             }
             DnsState::Custom => {
                 println!("Custom DNS: yes\nServers:");
-                for server in &options.custom_options.addresses {
+                for server in &options.custom_options.encrypted_servers {
                     println!("{server}");
                 }
+                if options.custom_options.encrypted_servers.is_empty() {
+                    for server in &options.custom_options.addresses {
+                        println!("{server}");
+                    }
+                }
             }
         }
 
         Ok(())
     }
+
+    async fn handle_blocklist_cmd(&self, matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("list", matches)) => self.list_blocklists(matches.is_present("json")).await,
+            Some(("add", matches)) => {
+                let path = Path::new(matches.value_of("path").unwrap());
+                let path = std::fs::canonicalize(path).map_err(Error::ReadBlocklist)?;
+                let path = path
+                    .to_str()
+                    .ok_or(Error::InvalidCommand("The path must be valid UTF-8"))?
+                    .to_owned();
+                self.update_blocklist_options(|options| {
+                    if options.lists.contains(&path) {
+                        return Err(Error::CommandFailed("The blocklist has already been added"));
+                    }
+                    options.lists.push(path);
+                    Ok(())
+                })
+                .await
+            }
+            Some(("remove", matches)) => {
+                let path = matches.value_of("path").unwrap();
+                // Also accept relative paths to lists that still exist
+                let canonical_path = std::fs::canonicalize(path)
+                    .ok()
+                    .and_then(|path| path.to_str().map(str::to_owned));
+                self.update_blocklist_options(|options| {
+                    let old_len = options.lists.len();
+                    options
+                        .lists
+                        .retain(|list| list != path && Some(list) != canonical_path.as_ref());
+                    if options.lists.len() == old_len {
+                        return Err(Error::CommandFailed("There is no such blocklist"));
+                    }
+                    Ok(())
+                })
+                .await
+            }
+            Some(("stats", matches)) => self.blocklist_stats(matches.is_present("json")).await,
+            Some(("allowlist", matches)) => match matches.subcommand() {
+                Some(("add", matches)) => {
+                    let domain = matches.value_of("domain").unwrap().to_owned();
+                    self.update_blocklist_options(|options| {
+                        if !options.allowlist.contains(&domain) {
+                            options.allowlist.push(domain);
+                        }
+                        Ok(())
+                    })
+                    .await
+                }
+                Some(("remove", matches)) => {
+                    let domain = matches.value_of("domain").unwrap();
+                    self.update_blocklist_options(|options| {
+                        let old_len = options.allowlist.len();
+                        options.allowlist.retain(|allowed| allowed != domain);
+                        if options.allowlist.len() == old_len {
+                            return Err(Error::CommandFailed("The domain is not in the allowlist"));
+                        }
+                        Ok(())
+                    })
+                    .await
+                }
+                _ => unreachable!("No allowlist command given"),
+            },
+            _ => unreachable!("No blocklist command given"),
+        }
+    }
+
+    async fn update_blocklist_options(
+        &self,
+        update: impl FnOnce(&mut types::DnsBlocklistOptions) -> Result<()>,
+    ) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let mut options = rpc
+            .get_settings(())
+            .await?
+            .into_inner()
+            .tunnel_options
+            .unwrap()
+            .dns_options
+            .unwrap();
+        update(
+            options
+                .blocklist_options
+                .get_or_insert_with(Default::default),
+        )?;
+        rpc.set_dns_options(options).await?;
+        println!("Updated DNS settings");
+        Ok(())
+    }
+
+    async fn list_blocklists(&self, json: bool) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let options: DnsOptions = rpc
+            .get_settings(())
+            .await?
+            .into_inner()
+            .tunnel_options
+            .unwrap()
+            .dns_options
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let options = options.blocklist_options;
+
+        if json {
+            return format::print_json(&options);
+        }
+
+        println!("Blocklists:");
+        for path in &options.lists {
+            println!("{}", path.display());
+        }
+        println!("Allowlist:");
+        for domain in &options.allowlist {
+            println!("{domain}");
+        }
+        Ok(())
+    }
+
+    async fn blocklist_stats(&self, json: bool) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let stats: Vec<DnsBlocklistStats> = rpc
+            .get_dns_blocklist_stats(())
+            .await?
+            .into_inner()
+            .lists
+            .into_iter()
+            .map(DnsBlocklistStats::from)
+            .collect();
+
+        if json {
+            return format::print_json(&stats);
+        }
+
+        if stats.is_empty() {
+            println!("No blocklists are loaded");
+        }
+        for list in &stats {
+            println!(
+                "{}: {} domains, {} blocked queries",
+                list.path.display(),
+                list.entries,
+                list.hits
+            );
+        }
+        Ok(())
+    }
+
+    async fn handle_split_cmd(&self, matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("list", matches)) => self.list_split_dns_rules(matches.is_present("json")).await,
+            Some(("add", matches)) => {
+                let domain = matches
+                    .value_of("domain")
+                    .unwrap()
+                    .trim_end_matches('.')
+                    .to_ascii_lowercase();
+                if domain.is_empty() {
+                    clap::Error::raw(clap::ErrorKind::ValueValidation, "Invalid domain").exit();
+                }
+                let resolvers = matches
+                    .values_of("resolvers")
+                    .into_iter()
+                    .flatten()
+                    .map(|value| value.parse::<IpAddr>().map_err(|e| format!("{value}: {e}")))
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .unwrap_or_else(|error| {
+                        clap::Error::raw(clap::ErrorKind::ValueValidation, error).exit()
+                    });
+                self.update_split_dns_rules(|rules| {
+                    let rule = types::SplitDnsRule {
+                        domain,
+                        resolvers: resolvers.iter().map(|ip| ip.to_string()).collect(),
+                    };
+                    match rules
+                        .iter_mut()
+                        .find(|existing| existing.domain == rule.domain)
+                    {
+                        Some(existing) => *existing = rule,
+                        None => rules.push(rule),
+                    }
+                    Ok(())
+                })
+                .await
+            }
+            Some(("remove", matches)) => {
+                let domain = matches
+                    .value_of("domain")
+                    .unwrap()
+                    .trim_end_matches('.')
+                    .to_ascii_lowercase();
+                self.update_split_dns_rules(|rules| {
+                    let old_len = rules.len();
+                    rules.retain(|rule| rule.domain != domain);
+                    if rules.len() == old_len {
+                        return Err(Error::CommandFailed("There is no rule for the domain"));
+                    }
+                    Ok(())
+                })
+                .await
+            }
+            _ => unreachable!("No split DNS command given"),
+        }
+    }
+
+    async fn update_split_dns_rules(
+        &self,
+        update: impl FnOnce(&mut Vec<types::SplitDnsRule>) -> Result<()>,
+    ) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let mut options = rpc
+            .get_settings(())
+            .await?
+            .into_inner()
+            .tunnel_options
+            .unwrap()
+            .dns_options
+            .unwrap();
+        update(&mut options.split_dns_rules)?;
+        rpc.set_dns_options(options).await?;
+        println!("Updated DNS settings");
+        Ok(())
+    }
+
+    async fn list_split_dns_rules(&self, json: bool) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let options: DnsOptions = rpc
+            .get_settings(())
+            .await?
+            .into_inner()
+            .tunnel_options
+            .unwrap()
+            .dns_options
+            .unwrap()
+            .try_into()
+            .unwrap();
+
+        if json {
+            return format::print_json(&options.split_dns_rules);
+        }
+
+        if options.split_dns_rules.is_empty() {
+            println!("No split DNS rules");
+        }
+        for rule in &options.split_dns_rules {
+            let resolvers: Vec<_> = rule.resolvers.iter().map(|ip| ip.to_string()).collect();
+            println!("{}: {}", rule.domain, resolvers.join(", "));
+        }
+        Ok(())
+    }
 }
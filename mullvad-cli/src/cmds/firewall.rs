@@ -0,0 +1,242 @@
+use crate::{format, new_rpc_client, Command, Error, Result};
+use mullvad_management_interface::{types, ManagementServiceClient};
+use talpid_types::net::AllowListEndpoint;
+#[cfg(target_os = "linux")]
+use talpid_types::net::{InboundRule, TransportProtocol};
+
+pub struct Firewall;
+
+#[mullvad_management_interface::async_trait]
+impl Command for Firewall {
+    fn name(&self) -> &'static str {
+        "firewall"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        let subcommand = clap::App::new(self.name())
+            .about("Manage exceptions to the firewall")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(create_allow_subcommand());
+        #[cfg(target_os = "linux")]
+        let subcommand = subcommand.subcommand(create_inbound_subcommand());
+        subcommand
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("allow", allow_matches)) => Self::handle_allow_cmd(allow_matches).await,
+            #[cfg(target_os = "linux")]
+            Some(("inbound", inbound_matches)) => Self::handle_inbound_cmd(inbound_matches).await,
+            _ => unreachable!("unhandled command"),
+        }
+    }
+}
+
+fn create_allow_subcommand() -> clap::App<'static> {
+    let endpoint_arg = || {
+        clap::Arg::new("endpoint")
+            .required(true)
+            .value_name("NETWORK:PORT/PROTOCOL")
+            .help("Network, port and transport protocol, e.g. 10.0.0.0/24:443/tcp")
+            .validator(|endpoint| {
+                endpoint
+                    .parse::<AllowListEndpoint>()
+                    .map_err(|error| error.to_string())
+            })
+    };
+    clap::App::new("allow")
+        .about("Manage endpoints that may be reached outside the tunnel in every tunnel state")
+        .long_about(
+            "Manage endpoints that may be reached outside the tunnel in every tunnel state. \
+            Traffic to these endpoints is allowed by the firewall, even when all other traffic \
+            is blocked. On Linux, it is also routed outside the tunnel when connected, and DNS \
+            requests to an allowed endpoint on port 53 bypass the DNS leak protection. IPv6 \
+            networks must be enclosed in brackets, e.g. [2001:db8::/32]:53/udp.",
+        )
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            clap::App::new("add")
+                .about("Allow traffic to an endpoint")
+                .arg(endpoint_arg()),
+        )
+        .subcommand(
+            clap::App::new("remove")
+                .about("Remove an endpoint from the allow list")
+                .arg(endpoint_arg()),
+        )
+        .subcommand(clap::App::new("list").about("List the allowed endpoints"))
+}
+
+#[cfg(target_os = "linux")]
+fn create_inbound_subcommand() -> clap::App<'static> {
+    clap::App::new("inbound")
+        .about("Manage local ports that may be reached from outside the tunnel while connected")
+        .long_about(
+            "Manage local ports that may be reached from outside the tunnel while connected. \
+            Connections to an exposed port are only accepted from the given network and on the \
+            given interface. Unlike 'lan', this does not allow any other local network traffic.",
+        )
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            clap::App::new("add")
+                .about("Expose a local port")
+                .arg(
+                    clap::Arg::new("port")
+                        .help("Local port to expose")
+                        .required(true)
+                        .validator(str::parse::<u16>),
+                )
+                .arg(
+                    clap::Arg::new("protocol")
+                        .help("Transport protocol of the port")
+                        .required(true)
+                        .possible_values(["tcp", "udp"]),
+                )
+                .arg(
+                    clap::Arg::new("source")
+                        .help("Network that connections may come from, e.g. 192.168.10.0/24")
+                        .long("source")
+                        .takes_value(true)
+                        .required(true)
+                        .validator(str::parse::<ipnetwork::IpNetwork>),
+                )
+                .arg(
+                    clap::Arg::new("interface")
+                        .help("Interface that connections may arrive on, e.g. eth0")
+                        .long("interface")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            clap::App::new("remove")
+                .about("Remove an inbound rule")
+                .arg(
+                    clap::Arg::new("index")
+                        .help("Position of the rule, as shown by 'list'")
+                        .required(true),
+                ),
+        )
+        .subcommand(clap::App::new("list").about("List the inbound rules"))
+}
+
+impl Firewall {
+    async fn handle_allow_cmd(matches: &clap::ArgMatches) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let mut allow_list = Self::get_allow_list(&mut rpc).await?;
+        match matches.subcommand() {
+            Some(("add", matches)) => {
+                let endpoint: AllowListEndpoint = matches.value_of_t_or_exit("endpoint");
+                if allow_list.contains(&endpoint) {
+                    return Err(Error::CommandFailed(
+                        "The endpoint is already in the allow list",
+                    ));
+                }
+                allow_list.push(endpoint);
+            }
+            Some(("remove", matches)) => {
+                let endpoint: AllowListEndpoint = matches.value_of_t_or_exit("endpoint");
+                let len = allow_list.len();
+                allow_list.retain(|allowed| *allowed != endpoint);
+                if allow_list.len() == len {
+                    return Err(Error::CommandFailed(
+                        "The endpoint is not in the allow list",
+                    ));
+                }
+            }
+            Some(("list", matches)) => {
+                if matches.is_present("json") {
+                    return format::print_json(&allow_list);
+                }
+                println!("Endpoints allowed outside the tunnel:");
+                for endpoint in &allow_list {
+                    println!("    {endpoint}");
+                }
+                return Ok(());
+            }
+            _ => unreachable!("unhandled command"),
+        }
+        rpc.set_firewall_allow_list(types::FirewallAllowList {
+            endpoints: allow_list
+                .into_iter()
+                .map(types::AllowListEndpoint::from)
+                .collect(),
+        })
+        .await?;
+        println!("Updated the firewall allow list");
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn handle_inbound_cmd(matches: &clap::ArgMatches) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let mut inbound_rules = Self::get_inbound_rules(&mut rpc).await?;
+        match matches.subcommand() {
+            Some(("add", matches)) => {
+                let protocol = match matches.value_of("protocol").unwrap() {
+                    "tcp" => TransportProtocol::Tcp,
+                    "udp" => TransportProtocol::Udp,
+                    _ => unreachable!("invalid protocol"),
+                };
+                let inbound_rule = InboundRule {
+                    port: matches.value_of_t_or_exit("port"),
+                    protocol,
+                    source: matches.value_of_t_or_exit("source"),
+                    interface: matches.value_of("interface").unwrap().to_owned(),
+                };
+                if inbound_rules.contains(&inbound_rule) {
+                    return Err(Error::CommandFailed("The inbound rule already exists"));
+                }
+                inbound_rules.push(inbound_rule);
+            }
+            Some(("remove", matches)) => {
+                let index: usize = matches.value_of_t_or_exit("index");
+                if index == 0 || index > inbound_rules.len() {
+                    return Err(Error::InvalidCommand("There is no rule at that position"));
+                }
+                inbound_rules.remove(index - 1);
+            }
+            Some(("list", matches)) => {
+                if matches.is_present("json") {
+                    return format::print_json(&inbound_rules);
+                }
+                if inbound_rules.is_empty() {
+                    println!("No inbound rules");
+                }
+                for (index, inbound_rule) in inbound_rules.iter().enumerate() {
+                    println!("{}. {}", index + 1, inbound_rule);
+                }
+                return Ok(());
+            }
+            _ => unreachable!("unhandled command"),
+        }
+        rpc.set_firewall_inbound_rules(types::FirewallInboundRules {
+            rules: inbound_rules
+                .into_iter()
+                .map(types::InboundRule::from)
+                .collect(),
+        })
+        .await?;
+        println!("Updated the inbound rules");
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn get_inbound_rules(rpc: &mut ManagementServiceClient) -> Result<Vec<InboundRule>> {
+        let settings = rpc.get_settings(()).await?.into_inner();
+        settings
+            .firewall_inbound_rules
+            .into_iter()
+            .map(|rule| Ok(InboundRule::try_from(rule)?))
+            .collect()
+    }
+
+    async fn get_allow_list(rpc: &mut ManagementServiceClient) -> Result<Vec<AllowListEndpoint>> {
+        let settings = rpc.get_settings(()).await?.into_inner();
+        settings
+            .firewall_allow_list
+            .into_iter()
+            .map(|endpoint| Ok(AllowListEndpoint::try_from(endpoint)?))
+            .collect()
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{format, new_rpc_client, Command, Error, Result};
+use ipnetwork::IpNetwork;
+use mullvad_management_interface::{types, ManagementServiceClient};
+use talpid_types::net::{default_lan_networks, validate_lan_network};
 
 pub struct Lan;
 
@@ -22,6 +25,7 @@ impl Command for Lan {
             .subcommand(
                 clap::App::new("get").about("Display the current local network sharing setting"),
             )
+            .subcommand(create_network_subcommand())
     }
 
     async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
@@ -30,13 +34,102 @@ impl Command for Lan {
             self.set(allow_lan == "allow").await
         } else if let Some(_matches) = matches.subcommand_matches("get") {
             self.get().await
+        } else if let Some(network_matches) = matches.subcommand_matches("network") {
+            Self::handle_network_cmd(network_matches).await
         } else {
             unreachable!("No lan command given");
         }
     }
 }
 
+fn create_network_subcommand() -> clap::App<'static> {
+    let network_arg = || {
+        clap::Arg::new("network")
+            .required(true)
+            .help("Network in CIDR notation, e.g. 100.64.0.0/10")
+    };
+    clap::App::new("network")
+        .about("Manage the networks that are considered local")
+        .long_about(
+            "Manage the networks that are considered local. When local network sharing is \
+            allowed, traffic to and from these networks is allowed outside the tunnel. By \
+            default, these are the private, link-local and unique local address ranges.",
+        )
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::App::new("add").about("Consider a network local").arg(
+            network_arg().validator(|network| {
+                let network: IpNetwork = network.parse().map_err(|error| format!("{error}"))?;
+                validate_lan_network(network, &[]).map_err(|error| error.to_string())
+            }),
+        ))
+        .subcommand(
+            clap::App::new("remove")
+                .about("Stop considering a network local")
+                .arg(network_arg().validator(|network| network.parse::<IpNetwork>())),
+        )
+        .subcommand(clap::App::new("list").about("List the networks that are considered local"))
+        .subcommand(clap::App::new("reset").about("Restore the default local networks"))
+}
+
 impl Lan {
+    async fn handle_network_cmd(matches: &clap::ArgMatches) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let mut lan_networks = Self::get_lan_networks(&mut rpc).await?;
+        match matches.subcommand() {
+            Some(("add", matches)) => {
+                let network = parse_network_arg(matches);
+                if lan_networks.contains(&network) {
+                    return Err(Error::CommandFailed(
+                        "The network is already considered local",
+                    ));
+                }
+                lan_networks.push(network);
+            }
+            Some(("remove", matches)) => {
+                let network = parse_network_arg(matches);
+                let len = lan_networks.len();
+                lan_networks.retain(|lan_network| *lan_network != network);
+                if lan_networks.len() == len {
+                    return Err(Error::CommandFailed("The network is not considered local"));
+                }
+            }
+            Some(("list", matches)) => {
+                if matches.is_present("json") {
+                    return format::print_json(&lan_networks);
+                }
+                println!("Local networks:");
+                for network in &lan_networks {
+                    println!("    {network}");
+                }
+                return Ok(());
+            }
+            Some(("reset", _)) => lan_networks = default_lan_networks(),
+            _ => unreachable!("unhandled command"),
+        }
+        rpc.set_lan_networks(types::LanNetworks {
+            networks: lan_networks
+                .iter()
+                .map(|network| network.to_string())
+                .collect(),
+        })
+        .await?;
+        println!("Updated the local networks");
+        Ok(())
+    }
+
+    async fn get_lan_networks(rpc: &mut ManagementServiceClient) -> Result<Vec<IpNetwork>> {
+        let settings = rpc.get_settings(()).await?.into_inner();
+        settings
+            .lan_networks
+            .iter()
+            .map(|network| {
+                network
+                    .parse()
+                    .map_err(|_| Error::InvalidDaemonResponse("invalid LAN network"))
+            })
+            .collect()
+    }
+
     async fn set(&self, allow_lan: bool) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         rpc.set_allow_lan(allow_lan).await?;
@@ -54,3 +147,10 @@ impl Lan {
         Ok(())
     }
 }
+
+/// Parses the network argument, and clears the host bits so that the network compares equal to
+/// the stored one.
+fn parse_network_arg(matches: &clap::ArgMatches) -> IpNetwork {
+    let network: IpNetwork = matches.value_of_t_or_exit("network");
+    IpNetwork::new(network.network(), network.prefix()).expect("prefix of a valid network")
+}
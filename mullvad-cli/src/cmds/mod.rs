@@ -19,12 +19,23 @@ pub use self::bridge::Bridge;
 mod connect;
 pub use self::connect::Connect;
 
+mod custom_list;
+pub use self::custom_list::CustomList;
+
+#[cfg(target_os = "linux")]
+mod debug;
+#[cfg(target_os = "linux")]
+pub use self::debug::Debug;
+
 mod disconnect;
 pub use self::disconnect::Disconnect;
 
 mod dns;
 pub use self::dns::Dns;
 
+mod firewall;
+pub use self::firewall::Firewall;
+
 mod lan;
 pub use self::lan::Lan;
 
@@ -40,6 +51,9 @@ pub use self::relay::Relay;
 mod reset;
 pub use self::reset::Reset;
 
+mod settings;
+pub use self::settings::Settings;
+
 #[cfg(any(target_os = "linux", windows))]
 mod split_tunnel;
 #[cfg(any(target_os = "linux", windows))]
@@ -63,13 +77,18 @@ pub fn get_commands() -> HashMap<&'static str, Box<dyn Command>> {
         Box::new(BlockWhenDisconnected),
         Box::new(Bridge),
         Box::new(Connect),
+        Box::new(CustomList),
+        #[cfg(target_os = "linux")]
+        Box::new(Debug),
         Box::new(Disconnect),
         Box::new(Dns),
+        Box::new(Firewall),
         Box::new(Reconnect),
         Box::new(Lan),
         Box::new(Obfuscation),
         Box::new(Relay),
         Box::new(Reset),
+        Box::new(Settings),
         #[cfg(any(target_os = "linux", windows))]
         Box::new(SplitTunnel),
         Box::new(Status),
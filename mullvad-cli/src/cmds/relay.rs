@@ -1,18 +1,29 @@
-use crate::{location, new_rpc_client, Command, Error, Result};
+use crate::{format, location, new_rpc_client, Command, Error, Result};
 use itertools::Itertools;
 use std::{
     convert::TryFrom,
-    io::{self, BufRead},
+    io::{self, BufRead, Read},
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     str::FromStr,
 };
 
 use mullvad_management_interface::{types, ManagementServiceClient};
-use mullvad_types::relay_constraints::{Constraint, RelaySettings};
+use mullvad_types::{
+    relay_constraints::{Constraint, RelaySelectionStrategy, RelaySettings},
+    relay_list::{RelayListCountry, RelayListVerification},
+    wg_quick::WgQuickConfig,
+};
+use serde::Serialize;
 use talpid_types::net::all_of_the_internet;
 
 pub struct Relay;
 
+#[derive(Serialize)]
+struct RelayConstraintsOutput {
+    relay_settings: RelaySettings,
+    relay_selection_strategy: RelaySelectionStrategy,
+}
+
 #[mullvad_management_interface::async_trait]
 impl Command for Relay {
     fn name(&self) -> &'static str {
@@ -67,6 +78,30 @@ impl Command for Relay {
                                         .takes_value(true),
                                 )
                             )
+                            .subcommand(clap::App::new("wireguard-config")
+                                .about("Use the relay in a wg-quick config file")
+                                .arg(
+                                    clap::Arg::new("file")
+                                        .help("Path to the config file, or '-' to read it from \
+                                              standard input")
+                                        .required(true)
+                                        .allow_invalid_utf8(true),
+                                )
+                                .arg(
+                                    clap::Arg::new("v4-gateway")
+                                        .help("IPv4 gateway address. Defaults to the first IPv4 DNS \
+                                              server in the config")
+                                        .long("v4-gateway")
+                                        .takes_value(true),
+                                )
+                                .arg(
+                                    clap::Arg::new("v6-gateway")
+                                        .help("IPv6 gateway address. Defaults to the first IPv6 DNS \
+                                              server in the config")
+                                        .long("v6-gateway")
+                                        .takes_value(true),
+                                )
+                            )
                             .subcommand(clap::App::new("openvpn")
                                 .arg(
                                     clap::Arg::new("host")
@@ -181,6 +216,16 @@ impl Command for Relay {
                                             .min_values(1)
                                             .max_values(3),
                                     )
+                                    .arg(
+                                        clap::Arg::new("diversity")
+                                            .help("Properties that the entry and exit relays must \
+                                                   not have in common when using multihop, or \
+                                                   'none'")
+                                            .long("diversity")
+                                            .possible_values(["none", "country", "provider", "ownership"])
+                                            .min_values(1)
+                                            .max_values(3),
+                                    )
                             )
                     )
                     .subcommand(clap::App::new("tunnel-protocol")
@@ -191,27 +236,45 @@ impl Command for Relay {
                                     .index(1)
                                     .possible_values(["any", "wireguard", "openvpn", ]),
                                     )
+                                )
+                    .subcommand(clap::App::new("selection-strategy")
+                                .about("Set how to pick among the relays that match the constraints. \
+                                       'lowest-latency' prefers the relays with the lowest \
+                                       measured latency")
+                                .arg(
+                                    clap::Arg::new("strategy")
+                                    .required(true)
+                                    .index(1)
+                                    .possible_values(["weighted", "lowest-latency"]),
+                                    )
                                 ),
             )
             .subcommand(clap::App::new("get"))
             .subcommand(
-                clap::App::new("list").about("List available countries and cities"),
+                clap::App::new("list")
+                    .about("List available countries and cities"),
             )
             .subcommand(
                 clap::App::new("update")
                     .about("Update the list of available countries and cities"),
             )
+            .subcommand(
+                clap::App::new("verify")
+                    .about("Verify the signatures of the relay lists stored on disk"),
+            )
     }
 
     async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
         if let Some(set_matches) = matches.subcommand_matches("set") {
             self.set(set_matches).await
-        } else if matches.subcommand_matches("get").is_some() {
-            self.get().await
-        } else if matches.subcommand_matches("list").is_some() {
-            self.list().await
+        } else if let Some(get_matches) = matches.subcommand_matches("get") {
+            self.get(get_matches.is_present("json")).await
+        } else if let Some(list_matches) = matches.subcommand_matches("list") {
+            self.list(list_matches.is_present("json")).await
         } else if matches.subcommand_matches("update").is_some() {
             self.update().await
+        } else if let Some(verify_matches) = matches.subcommand_matches("verify") {
+            self.verify(verify_matches.is_present("json")).await
         } else {
             unreachable!("No relay command given");
         }
@@ -249,6 +312,8 @@ impl Relay {
             }
         } else if let Some(tunnel_matches) = matches.subcommand_matches("tunnel-protocol") {
             self.set_tunnel_protocol(tunnel_matches).await
+        } else if let Some(strategy_matches) = matches.subcommand_matches("selection-strategy") {
+            self.set_selection_strategy(strategy_matches).await
         } else {
             unreachable!("No set relay command given");
         }
@@ -258,6 +323,9 @@ impl Relay {
         let custom_endpoint = match matches.subcommand() {
             Some(("openvpn", openvpn_matches)) => Self::read_custom_openvpn_relay(openvpn_matches),
             Some(("wireguard", wg_matches)) => Self::read_custom_wireguard_relay(wg_matches),
+            Some(("wireguard-config", config_matches)) => {
+                Self::read_custom_wireguard_config(config_matches)?
+            }
             _ => unreachable!("No set relay command given"),
         };
 
@@ -300,13 +368,7 @@ impl Relay {
         let addresses: Vec<IpAddr> = matches.values_of_t_or_exit("addr");
         let peer_key_str: String = matches.value_of_t_or_exit("peer-pubkey");
         let ipv4_gateway: Ipv4Addr = matches.value_of_t_or_exit("v4-gateway");
-        let ipv6_gateway = match matches.value_of_t::<Ipv6Addr>("v6-gateway") {
-            Ok(gateway) => Some(gateway),
-            Err(e) => match e.kind {
-                clap::ErrorKind::ArgumentNotFound => None,
-                _ => e.exit(),
-            },
-        };
+        let ipv6_gateway = Self::optional_value_of_t::<Ipv6Addr>(matches, "v6-gateway");
         let mut private_key_str = String::new();
         println!("Reading private key from standard input");
         let _ = io::stdin().lock().read_line(&mut private_key_str);
@@ -336,6 +398,7 @@ impl Relay {
                                 .collect(),
                             endpoint: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port)
                                 .to_string(),
+                            persistent_keepalive: 0,
                         }),
                         ipv4_gateway: ipv4_gateway.to_string(),
                         ipv6_gateway: ipv6_gateway
@@ -348,6 +411,52 @@ impl Relay {
         }
     }
 
+    fn read_custom_wireguard_config(
+        matches: &clap::ArgMatches,
+    ) -> Result<types::CustomRelaySettings> {
+        let path = matches.value_of_os("file").unwrap();
+        let config = if path == "-" {
+            let mut config = String::new();
+            io::stdin().read_to_string(&mut config).map(|_| config)
+        } else {
+            std::fs::read_to_string(path)
+        }
+        .map_err(Error::ReadWireguardConfig)?;
+        let config: WgQuickConfig = config.parse()?;
+
+        for key in &config.ignored_keys {
+            eprintln!("Ignoring unsupported key: {key}");
+        }
+        if config.mtu.is_some() {
+            eprintln!(
+                "Ignoring MTU. Use 'mullvad tunnel wireguard mtu set' to set it for all tunnels"
+            );
+        }
+
+        let endpoint = config.into_custom_tunnel_endpoint(
+            Self::optional_value_of_t(matches, "v4-gateway"),
+            Self::optional_value_of_t(matches, "v6-gateway"),
+        )?;
+        Ok(types::CustomRelaySettings {
+            host: endpoint.host,
+            config: Some(types::ConnectionConfig::from(endpoint.config)),
+        })
+    }
+
+    fn optional_value_of_t<T>(matches: &clap::ArgMatches, name: &str) -> Option<T>
+    where
+        T: FromStr,
+        <T as FromStr>::Err: std::fmt::Display,
+    {
+        match matches.value_of_t::<T>(name) {
+            Ok(value) => Some(value),
+            Err(e) => match e.kind {
+                clap::ErrorKind::ArgumentNotFound => None,
+                _ => e.exit(),
+            },
+        }
+    }
+
     fn validate_wireguard_key(key_str: &str) -> [u8; 32] {
         let key_bytes = base64::decode(key_str.trim()).unwrap_or_else(|e| {
             eprintln!("Failed to decode wireguard key: {e}");
@@ -392,6 +501,7 @@ impl Relay {
                                 country: country.code,
                                 city: city.code,
                                 hostname: relay.hostname,
+                                ..Default::default()
                             });
                         }
                     }
@@ -424,7 +534,24 @@ impl Relay {
         let location_constraint = location::get_constraint_from_args(matches);
         let mut found = false;
 
-        if !location_constraint.country.is_empty() {
+        if !location_constraint.custom_list.is_empty() {
+            let mut rpc = new_rpc_client().await?;
+            let custom_lists = rpc
+                .get_settings(())
+                .await?
+                .into_inner()
+                .custom_lists
+                .unwrap_or_default()
+                .custom_lists;
+            if !custom_lists
+                .iter()
+                .any(|list| list.name == location_constraint.custom_list)
+            {
+                return Err(Error::InvalidCommand(
+                    "No custom list with that name exists",
+                ));
+            }
+        } else if !location_constraint.country.is_empty() {
             // TODO: `mullvad_types::relay_constraints::LocationConstraint::matches(&relay)`
             //       could be used to guarantee consistency with the daemon.
             let countries = Self::get_filtered_relays().await?;
@@ -594,6 +721,9 @@ impl Relay {
             let use_multihop = wireguard_constraints.entry_location.is_some();
             wireguard_constraints.use_multihop = use_multihop;
         }
+        if let Some(diversity) = matches.values_of("diversity") {
+            wireguard_constraints.diversity = Some(parse_multihop_diversity(diversity));
+        }
 
         self.update_constraints(types::RelaySettingsUpdate {
             r#type: Some(types::relay_settings_update::Type::Normal(
@@ -651,34 +781,72 @@ impl Relay {
         .await
     }
 
-    async fn get(&self) -> Result<()> {
+    async fn set_selection_strategy(&self, matches: &clap::ArgMatches) -> Result<()> {
+        let strategy = match matches.value_of("strategy").unwrap() {
+            "weighted" => types::relay_selection_strategy::Strategy::Weighted,
+            "lowest-latency" => types::relay_selection_strategy::Strategy::LowestLatency,
+            _ => unreachable!(),
+        };
         let mut rpc = new_rpc_client().await?;
-        let relay_settings = rpc
-            .get_settings(())
-            .await?
-            .into_inner()
-            .relay_settings
-            .unwrap();
+        rpc.set_relay_selection_strategy(types::RelaySelectionStrategy {
+            strategy: strategy as i32,
+        })
+        .await?;
+        println!("Relay selection strategy updated");
+        Ok(())
+    }
+
+    async fn get(&self, json: bool) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let settings = rpc.get_settings(()).await?.into_inner();
 
-        println!(
-            "Current constraints: {}",
-            RelaySettings::try_from(relay_settings).unwrap()
-        );
+        let relay_settings = RelaySettings::try_from(
+            settings
+                .relay_settings
+                .ok_or(Error::InvalidDaemonResponse("missing relay settings"))?,
+        )?;
+        let relay_selection_strategy =
+            RelaySelectionStrategy::try_from(settings.relay_selection_strategy.ok_or(
+                Error::InvalidDaemonResponse("missing relay selection strategy"),
+            )?)?;
+
+        if json {
+            return format::print_json(&RelayConstraintsOutput {
+                relay_settings,
+                relay_selection_strategy,
+            });
+        }
+
+        println!("Current constraints: {relay_settings}");
+        println!("Selection strategy: {relay_selection_strategy}");
 
         Ok(())
     }
 
-    async fn list(&self) -> Result<()> {
+    async fn list(&self, json: bool) -> Result<()> {
         let mut countries = Self::get_filtered_relays().await?;
         countries.sort_by(|c1, c2| natord::compare_ignore_case(&c1.name, &c2.name));
-        for mut country in countries {
+        for country in &mut countries {
             country
                 .cities
                 .sort_by(|c1, c2| natord::compare_ignore_case(&c1.name, &c2.name));
-            println!("{} ({})", country.name, country.code);
-            for mut city in country.cities {
+            for city in &mut country.cities {
                 city.relays
                     .sort_by(|r1, r2| natord::compare_ignore_case(&r1.hostname, &r2.hostname));
+            }
+        }
+
+        if json {
+            let countries = countries
+                .into_iter()
+                .map(RelayListCountry::try_from)
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            return format::print_json(&countries);
+        }
+
+        for country in countries {
+            println!("{} ({})", country.name, country.code);
+            for city in country.cities {
                 println!(
                     "\t{} ({}) @ {:.5}°N, {:.5}°W",
                     city.name, city.code, city.latitude, city.longitude
@@ -718,6 +886,51 @@ impl Relay {
         Ok(())
     }
 
+    async fn verify(&self, json: bool) -> Result<()> {
+        let verifications = new_rpc_client()
+            .await?
+            .verify_relay_lists(())
+            .await?
+            .into_inner()
+            .lists
+            .into_iter()
+            .map(RelayListVerification::try_from)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|_| Error::CommandFailed("Received invalid relay list verification"))?;
+        let all_verified = verifications
+            .iter()
+            .all(|verification| verification.error.is_none());
+
+        if json {
+            format::print_json(&verifications)?;
+        } else {
+            for verification in &verifications {
+                println!("{}", verification.path.display());
+                match (&verification.error, verification.last_updated) {
+                    (Some(error), _) => println!("\tRejected: {error}"),
+                    (None, Some(last_updated)) => println!(
+                        "\tValid signature, {} relays, last updated {}",
+                        verification.relay_count,
+                        last_updated.with_timezone(&chrono::Local)
+                    ),
+                    (None, None) => {
+                        println!("\tValid signature, {} relays", verification.relay_count)
+                    }
+                }
+            }
+        }
+
+        if verifications.is_empty() {
+            return Err(Error::CommandFailed("No relay lists were found"));
+        }
+        if !all_verified {
+            return Err(Error::CommandFailed(
+                "One or more relay lists failed verification",
+            ));
+        }
+        Ok(())
+    }
+
     async fn get_filtered_relays() -> Result<Vec<types::RelayListCountry>> {
         let mut rpc = new_rpc_client().await?;
         let relay_list = rpc
@@ -796,6 +1009,22 @@ fn parse_entry_location_constraint<'a, T: Iterator<Item = &'a str>>(
     ))
 }
 
+fn parse_multihop_diversity<'a, T: Iterator<Item = &'a str>>(
+    values: T,
+) -> types::MultihopDiversity {
+    let mut diversity = types::MultihopDiversity::default();
+    for value in values {
+        match value {
+            "none" => return types::MultihopDiversity::default(),
+            "country" => diversity.country = true,
+            "provider" => diversity.provider = true,
+            "ownership" => diversity.ownership = true,
+            _ => unreachable!("unhandled diversity property"),
+        }
+    }
+    diversity
+}
+
 fn parse_transport_port(
     matches: &clap::ArgMatches,
     current_constraint: &mut Option<types::TransportPort>,
@@ -0,0 +1,81 @@
+use crate::{new_rpc_client, Command, Error, Result};
+use std::io::{self, Read, Write};
+
+pub struct Settings;
+
+#[mullvad_management_interface::async_trait]
+impl Command for Settings {
+    fn name(&self) -> &'static str {
+        "settings"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about("Export or import all settings")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::App::new("export")
+                    .about("Write all settings to a file")
+                    .arg(
+                        clap::Arg::new("file")
+                            .help("Path to the file, or '-' to write to standard output")
+                            .required(true)
+                            .allow_invalid_utf8(true),
+                    ),
+            )
+            .subcommand(
+                clap::App::new("import")
+                    .about(
+                        "Replace all settings with settings that were previously exported. \
+                        Settings that are locked by the policy file are not changed",
+                    )
+                    .arg(
+                        clap::Arg::new("file")
+                            .help("Path to the file, or '-' to read from standard input")
+                            .required(true)
+                            .allow_invalid_utf8(true),
+                    ),
+            )
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("export", matches)) => Self::export(matches).await,
+            Some(("import", matches)) => Self::import(matches).await,
+            _ => unreachable!("unhandled command"),
+        }
+    }
+}
+
+impl Settings {
+    async fn export(matches: &clap::ArgMatches) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let settings = rpc.export_settings(()).await?.into_inner();
+
+        let path = matches.value_of_os("file").unwrap();
+        if path == "-" {
+            io::stdout().write_all(settings.as_bytes())
+        } else {
+            std::fs::write(path, settings)
+        }
+        .map_err(Error::WriteSettingsFile)
+    }
+
+    async fn import(matches: &clap::ArgMatches) -> Result<()> {
+        let path = matches.value_of_os("file").unwrap();
+        let settings = if path == "-" {
+            let mut settings = String::new();
+            io::stdin().read_to_string(&mut settings).map(|_| settings)
+        } else {
+            std::fs::read_to_string(path)
+        }
+        .map_err(Error::ReadSettingsFile)?;
+
+        let mut rpc = new_rpc_client().await?;
+        rpc.import_settings(settings)
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to import settings", error))?;
+        println!("Imported settings");
+        Ok(())
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{format, new_rpc_client, Command, Error, Result};
+use mullvad_management_interface::{types, ManagementServiceClient};
+use mullvad_types::settings::ExcludedUsers;
+use std::collections::BTreeSet;
 
 pub struct SplitTunnel;
 
@@ -15,17 +18,67 @@ impl Command for SplitTunnel {
                     the tunnel, use the program 'mullvad-exclude' instead of this command.",
             )
             .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(create_app_subcommand())
+            .subcommand(
+                clap::App::new("set")
+                    .about("Enable or disable exclusion of applications")
+                    .arg(
+                        clap::Arg::new("policy")
+                            .required(true)
+                            .possible_values(["on", "off"]),
+                    ),
+            )
+            .subcommand(
+                clap::App::new("get").about("Display whether applications are being excluded"),
+            )
             .subcommand(create_pid_subcommand())
+            .subcommand(create_id_subcommand(
+                "user",
+                "Manage users whose traffic is excluded from the tunnel",
+                "uid",
+            ))
+            .subcommand(create_id_subcommand(
+                "group",
+                "Manage groups whose traffic is excluded from the tunnel",
+                "gid",
+            ))
     }
 
     async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
         match matches.subcommand() {
+            Some(("app", matches)) => Self::handle_app_subcommand(matches).await,
+            Some(("get", matches)) => self.get(matches.is_present("json")).await,
+            Some(("set", matches)) => {
+                let enabled = matches.value_of("policy").expect("missing policy");
+                self.set(enabled == "on").await
+            }
             Some(("pid", pid_matches)) => Self::handle_pid_cmd(pid_matches).await,
+            Some(("user", matches)) => {
+                Self::handle_id_cmd(matches, "uid", "Excluded users:", |users| &mut users.uids)
+                    .await
+            }
+            Some(("group", matches)) => {
+                Self::handle_id_cmd(matches, "gid", "Excluded groups:", |users| &mut users.gids)
+                    .await
+            }
             _ => unreachable!("unhandled command"),
         }
     }
 }
 
+fn create_app_subcommand() -> clap::App<'static> {
+    clap::App::new("app")
+        .about(
+            "Manage applications to exclude from the tunnel. Processes started from these \
+                executables are excluded while 'split-tunnel set on' is in effect",
+        )
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::App::new("list"))
+        .subcommand(clap::App::new("add").arg(clap::Arg::new("path").required(true)))
+        .subcommand(clap::App::new("remove").arg(clap::Arg::new("path").required(true)))
+        .subcommand(clap::App::new("clear"))
+}
+
 fn create_pid_subcommand() -> clap::App<'static> {
     clap::App::new("pid")
         .about("Manage processes to exclude from the tunnel")
@@ -36,7 +89,107 @@ fn create_pid_subcommand() -> clap::App<'static> {
         .subcommand(clap::App::new("list"))
 }
 
+fn create_id_subcommand(
+    name: &'static str,
+    about: &'static str,
+    id_name: &'static str,
+) -> clap::App<'static> {
+    let id_arg = || {
+        clap::Arg::new(id_name)
+            .required(true)
+            .validator(str::parse::<u32>)
+    };
+    clap::App::new(name)
+        .about(about)
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::App::new("add").arg(id_arg()))
+        .subcommand(clap::App::new("delete").arg(id_arg()))
+        .subcommand(clap::App::new("clear"))
+        .subcommand(clap::App::new("list"))
+}
+
 impl SplitTunnel {
+    async fn handle_app_subcommand(matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("list", matches)) => {
+                let paths = new_rpc_client()
+                    .await?
+                    .get_settings(())
+                    .await?
+                    .into_inner()
+                    .split_tunnel
+                    .unwrap()
+                    .apps;
+
+                if matches.is_present("json") {
+                    return format::print_json(&paths);
+                }
+
+                println!("Excluded applications:");
+                for path in &paths {
+                    println!("    {path}");
+                }
+
+                Ok(())
+            }
+            Some(("add", matches)) => {
+                let path = Self::absolute_path(matches.value_of("path").unwrap())?;
+                new_rpc_client().await?.add_split_tunnel_app(path).await?;
+                Ok(())
+            }
+            Some(("remove", matches)) => {
+                let path = Self::absolute_path(matches.value_of("path").unwrap())?;
+                new_rpc_client()
+                    .await?
+                    .remove_split_tunnel_app(path)
+                    .await?;
+                Ok(())
+            }
+            Some(("clear", _)) => {
+                new_rpc_client().await?.clear_split_tunnel_apps(()).await?;
+                Ok(())
+            }
+            _ => unreachable!("unhandled subcommand"),
+        }
+    }
+
+    /// Makes a relative path absolute, since the daemon does not share the working directory of
+    /// the CLI.
+    fn absolute_path(path: &str) -> Result<String> {
+        let path = std::env::current_dir()
+            .map_err(|_| Error::CommandFailed("Failed to get the working directory"))?
+            .join(path);
+        path.into_os_string()
+            .into_string()
+            .map_err(|_| Error::InvalidCommand("The path must be valid UTF-8"))
+    }
+
+    async fn set(&self, enabled: bool) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_split_tunnel_state(enabled).await?;
+        println!("Changed split tunnel setting");
+        Ok(())
+    }
+
+    async fn get(&self, json: bool) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let enabled = rpc
+            .get_settings(())
+            .await?
+            .into_inner()
+            .split_tunnel
+            .unwrap()
+            .enable_exclusions;
+        if json {
+            return format::print_json(&serde_json::json!({ "enable_exclusions": enabled }));
+        }
+        println!(
+            "Split tunnel status: {}",
+            if enabled { "on" } else { "off" }
+        );
+        Ok(())
+    }
+
     async fn handle_pid_cmd(matches: &clap::ArgMatches) -> Result<()> {
         match matches.subcommand() {
             Some(("add", matches)) => {
@@ -62,14 +215,22 @@ impl SplitTunnel {
                     .await?;
                 Ok(())
             }
-            Some(("list", _)) => {
+            Some(("list", matches)) => {
                 let mut pids_stream = new_rpc_client()
                     .await?
                     .get_split_tunnel_processes(())
                     .await?
                     .into_inner();
-                println!("Excluded PIDs:");
 
+                if matches.is_present("json") {
+                    let mut pids = vec![];
+                    while let Some(pid) = pids_stream.message().await? {
+                        pids.push(pid);
+                    }
+                    return format::print_json(&pids);
+                }
+
+                println!("Excluded PIDs:");
                 while let Some(pid) = pids_stream.message().await? {
                     println!("    {pid}");
                 }
@@ -79,4 +240,46 @@ impl SplitTunnel {
             _ => unreachable!("unhandled command"),
         }
     }
+
+    async fn handle_id_cmd(
+        matches: &clap::ArgMatches,
+        id_name: &str,
+        list_header: &str,
+        ids: impl Fn(&mut ExcludedUsers) -> &mut BTreeSet<u32>,
+    ) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let mut users = Self::get_excluded_users(&mut rpc).await?;
+        match matches.subcommand() {
+            Some(("add", matches)) => {
+                ids(&mut users).insert(matches.value_of_t_or_exit(id_name));
+            }
+            Some(("delete", matches)) => {
+                ids(&mut users).remove(&matches.value_of_t_or_exit(id_name));
+            }
+            Some(("clear", _)) => ids(&mut users).clear(),
+            Some(("list", matches)) => {
+                if matches.is_present("json") {
+                    return format::print_json(ids(&mut users));
+                }
+                println!("{list_header}");
+                for id in ids(&mut users).iter() {
+                    println!("    {id}");
+                }
+                return Ok(());
+            }
+            _ => unreachable!("unhandled command"),
+        }
+        rpc.set_split_tunnel_excluded_users(types::ExcludedUsers::from(&users))
+            .await?;
+        Ok(())
+    }
+
+    async fn get_excluded_users(rpc: &mut ManagementServiceClient) -> Result<ExcludedUsers> {
+        let settings = rpc.get_settings(()).await?.into_inner();
+        Ok(settings
+            .split_tunnel
+            .and_then(|split_tunnel| split_tunnel.excluded_users)
+            .map(ExcludedUsers::from)
+            .unwrap_or_default())
+    }
 }
@@ -1,9 +1,17 @@
 use std::{ffi::OsStr, path::Path};
 
-use crate::{new_rpc_client, Command, Result};
+use crate::{format, new_rpc_client, Command, Result};
+use serde::Serialize;
 
 pub struct SplitTunnel;
 
+#[derive(Serialize)]
+struct ExcludedProcess {
+    pid: u32,
+    image: String,
+    inherited: bool,
+}
+
 #[mullvad_management_interface::async_trait]
 impl Command for SplitTunnel {
     fn name(&self) -> &'static str {
@@ -32,7 +40,7 @@ impl Command for SplitTunnel {
         match matches.subcommand() {
             Some(("app", matches)) => Self::handle_app_subcommand(matches).await,
             Some(("pid", matches)) => Self::handle_pid_subcommand(matches).await,
-            Some(("get", _)) => self.get().await,
+            Some(("get", matches)) => self.get(matches.is_present("json")).await,
             Some(("set", matches)) => {
                 let enabled = matches.value_of("policy").expect("missing policy");
                 self.set(enabled == "on").await
@@ -67,7 +75,7 @@ fn create_pid_subcommand() -> clap::App<'static> {
 impl SplitTunnel {
     async fn handle_app_subcommand(matches: &clap::ArgMatches) -> Result<()> {
         match matches.subcommand() {
-            Some(("list", _)) => {
+            Some(("list", matches)) => {
                 let paths = new_rpc_client()
                     .await?
                     .get_settings(())
@@ -77,6 +85,10 @@ impl SplitTunnel {
                     .unwrap()
                     .apps;
 
+                if matches.is_present("json") {
+                    return format::print_json(&paths);
+                }
+
                 println!("Excluded applications:");
                 for path in &paths {
                     println!("    {}", path);
@@ -107,13 +119,26 @@ impl SplitTunnel {
 
     async fn handle_pid_subcommand(matches: &clap::ArgMatches) -> Result<()> {
         match matches.subcommand() {
-            Some(("list", _)) => {
+            Some(("list", matches)) => {
                 let processes = new_rpc_client()
                     .await?
                     .get_excluded_processes(())
                     .await?
                     .into_inner();
 
+                if matches.is_present("json") {
+                    let processes: Vec<_> = processes
+                        .processes
+                        .into_iter()
+                        .map(|process| ExcludedProcess {
+                            pid: process.pid,
+                            image: process.image,
+                            inherited: process.inherited,
+                        })
+                        .collect();
+                    return format::print_json(&processes);
+                }
+
                 for process in &processes.processes {
                     let subproc = if process.inherited { "subprocess" } else { "" };
                     println!(
@@ -139,7 +164,7 @@ impl SplitTunnel {
         Ok(())
     }
 
-    async fn get(&self) -> Result<()> {
+    async fn get(&self, json: bool) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         let enabled = rpc
             .get_settings(())
@@ -148,6 +173,9 @@ impl SplitTunnel {
             .split_tunnel
             .unwrap()
             .enable_exclusions;
+        if json {
+            return format::print_json(&serde_json::json!({ "enable_exclusions": enabled }));
+        }
         println!(
             "Split tunnel status: {}",
             if enabled { "on" } else { "off" }
@@ -1,11 +1,41 @@
+use super::version::VersionInfo;
 use crate::{format, new_rpc_client, Command, Error, Result};
 use mullvad_management_interface::{
     types::daemon_event::Event as EventType, ManagementServiceClient,
 };
-use mullvad_types::{location::GeoIpLocation, states::TunnelState};
+use mullvad_types::{
+    connection_history::ConnectionHistoryEntry,
+    device::{DeviceEvent, RemoveDeviceEvent},
+    location::GeoIpLocation,
+    relay_list::RelayList,
+    settings::Settings,
+    states::TunnelState,
+    tunnel_stats::{PeerStats, TunnelStats},
+};
+use serde::Serialize;
 
 pub struct Status;
 
+#[derive(Serialize)]
+struct StatusOutput {
+    state: TunnelState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location: Option<GeoIpLocation>,
+}
+
+/// A daemon event as printed by `status listen --json`.
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum DaemonEvent {
+    TunnelState(TunnelState),
+    /// The settings in the same format as the settings file.
+    Settings(Settings),
+    RelayList(RelayList),
+    VersionInfo(VersionInfo),
+    Device(DeviceEvent),
+    RemoveDevice(RemoveDeviceEvent),
+}
+
 #[mullvad_management_interface::async_trait]
 impl Command for Status {
     fn name(&self) -> &'static str {
@@ -26,6 +56,11 @@ impl Command for Status {
                     .short('l')
                     .help("Prints the current location and IP. Based on GeoIP lookups"),
             )
+            .arg(
+                clap::Arg::new("stats")
+                    .long("stats")
+                    .help("Continuously prints the traffic statistics of the tunnel"),
+            )
             .arg(
                 clap::Arg::new("debug")
                     .long("debug")
@@ -33,6 +68,29 @@ impl Command for Status {
                     .help("Enables debug output"),
             )
             .subcommand(clap::App::new("listen").about("Listen for VPN tunnel state changes"))
+            .subcommand(
+                clap::App::new("history")
+                    .about("Show previous VPN tunnel states and the traffic of each session")
+                    .long_about(
+                        "Show previous VPN tunnel states and the traffic of each session. The \
+                        history is only recorded once it has been enabled with \
+                        'history set on', and is stored in the cache directory of the daemon.",
+                    )
+                    .subcommand(
+                        clap::App::new("set")
+                            .about("Enable or disable recording of the history")
+                            .long_about(
+                                "Enable or disable recording of the history. Disabling it \
+                                also removes the recorded history.",
+                            )
+                            .arg(
+                                clap::Arg::new("policy")
+                                    .required(true)
+                                    .possible_values(["on", "off"]),
+                            ),
+                    )
+                    .subcommand(clap::App::new("clear").about("Remove the recorded history")),
+            )
     }
 
     async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
@@ -41,6 +99,35 @@ impl Command for Status {
         let show_full_location = matches.is_present("location");
 
         let mut rpc = new_rpc_client().await?;
+
+        if let Some(history_matches) = matches.subcommand_matches("history") {
+            return match history_matches.subcommand() {
+                Some(("set", set_matches)) => {
+                    let enabled = set_matches.value_of("policy").unwrap() == "on";
+                    rpc.set_record_connection_history(enabled).await?;
+                    println!("Changed connection history setting");
+                    Ok(())
+                }
+                Some(("clear", _)) => {
+                    rpc.clear_connection_history(()).await?;
+                    println!("Cleared the connection history");
+                    Ok(())
+                }
+                _ => {
+                    let json = matches.is_present("json") || history_matches.is_present("json");
+                    print_history(&mut rpc, verbose, json).await
+                }
+            };
+        }
+
+        let listen_json = matches
+            .subcommand_matches("listen")
+            .map(|listen_matches| listen_matches.is_present("json"))
+            .unwrap_or(false);
+        if matches.is_present("json") || listen_json {
+            return print_json_status(&mut rpc, matches).await;
+        }
+
         let state = rpc.get_tunnel_state(()).await?.into_inner();
 
         if debug {
@@ -54,6 +141,10 @@ impl Command for Status {
             print_location(&mut rpc).await?;
         }
 
+        if matches.is_present("stats") {
+            return print_stats(&mut rpc, false).await;
+        }
+
         if matches.subcommand_matches("listen").is_some() {
             let mut events = rpc.events_listen(()).await?.into_inner();
 
@@ -111,16 +202,157 @@ impl Command for Status {
     }
 }
 
-async fn print_location(rpc: &mut ManagementServiceClient) -> Result<()> {
-    let location = match rpc.get_current_location(()).await {
-        Ok(response) => GeoIpLocation::try_from(response.into_inner()).expect("invalid geoip data"),
-        Err(status) => {
-            if status.code() == mullvad_management_interface::Code::NotFound {
-                println!("Location data unavailable");
-                return Ok(());
-            } else {
-                return Err(Error::RpcFailed(status));
+async fn print_json_status(
+    rpc: &mut ManagementServiceClient,
+    matches: &clap::ArgMatches,
+) -> Result<()> {
+    if matches.is_present("stats") {
+        return print_stats(rpc, true).await;
+    }
+
+    let state = TunnelState::try_from(rpc.get_tunnel_state(()).await?.into_inner())?;
+
+    if matches.subcommand_matches("listen").is_some() {
+        format::print_json(&DaemonEvent::TunnelState(state))?;
+        return listen_json(rpc).await;
+    }
+
+    let location = if matches.is_present("location") {
+        get_location(rpc).await?
+    } else {
+        None
+    };
+    format::print_json(&StatusOutput { state, location })
+}
+
+async fn listen_json(rpc: &mut ManagementServiceClient) -> Result<()> {
+    let mut events = rpc.events_listen(()).await?.into_inner();
+
+    while let Some(event) = events.message().await? {
+        let event = match event
+            .event
+            .ok_or(Error::InvalidDaemonResponse("missing daemon event"))?
+        {
+            EventType::TunnelState(new_state) => {
+                DaemonEvent::TunnelState(TunnelState::try_from(new_state)?)
+            }
+            EventType::Settings(settings) => DaemonEvent::Settings(Settings::try_from(settings)?),
+            EventType::RelayList(relay_list) => {
+                DaemonEvent::RelayList(RelayList::try_from(relay_list)?)
+            }
+            EventType::VersionInfo(app_version_info) => {
+                DaemonEvent::VersionInfo(VersionInfo::from(app_version_info))
+            }
+            EventType::Device(device) => DaemonEvent::Device(DeviceEvent::try_from(device)?),
+            EventType::RemoveDevice(device) => {
+                DaemonEvent::RemoveDevice(RemoveDeviceEvent::try_from(device)?)
             }
+        };
+        format::print_json(&event)?;
+    }
+
+    Ok(())
+}
+
+async fn print_history(rpc: &mut ManagementServiceClient, verbose: bool, json: bool) -> Result<()> {
+    let entries = rpc.get_connection_history(()).await?.into_inner().entries;
+    if json {
+        let entries = entries
+            .into_iter()
+            .map(ConnectionHistoryEntry::try_from)
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        return format::print_json(&entries);
+    }
+    if entries.is_empty() {
+        let settings = rpc.get_settings(()).await?.into_inner();
+        if settings.record_connection_history {
+            println!("No connection history");
+        } else {
+            println!("The connection history is disabled. Enable it with 'status history set on'");
+        }
+    }
+    for entry in entries {
+        let entry = ConnectionHistoryEntry::try_from(entry)?;
+        print!(
+            "{}: ",
+            entry
+                .timestamp
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M:%S")
+        );
+        format::print_state(&entry.state, verbose);
+        if let Some(session) = &entry.ended_session {
+            print!(
+                "\tEnded session of {}",
+                format::format_duration(session.duration)
+            );
+            match &session.traffic {
+                Some(traffic) => println!(
+                    ", received {}, sent {}",
+                    format::format_bytes(traffic.rx_bytes),
+                    format::format_bytes(traffic.tx_bytes)
+                ),
+                None => println!(),
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn print_stats(rpc: &mut ManagementServiceClient, json: bool) -> Result<()> {
+    let mut stats_stream = rpc.tunnel_stats_listen(()).await?.into_inner();
+    while let Some(stats) = stats_stream.message().await? {
+        let stats = Option::<TunnelStats>::try_from(stats)?;
+        if json {
+            format::print_json(&stats)?;
+            continue;
+        }
+        match stats {
+            Some(stats) => {
+                if let Some(entry) = &stats.entry {
+                    print_peer_stats("Entry relay", entry);
+                }
+                print_peer_stats("Exit relay", &stats.exit);
+            }
+            None => println!("No tunnel statistics available"),
+        }
+    }
+    Ok(())
+}
+
+fn print_peer_stats(name: &str, stats: &PeerStats) {
+    let last_handshake = match stats.last_handshake {
+        Some(time) => {
+            let elapsed = (chrono::Utc::now() - time).to_std().unwrap_or_default();
+            format!("{} ago", format::format_duration(elapsed))
+        }
+        None => "never".to_owned(),
+    };
+    println!(
+        "{name}: received {} ({}/s), sent {} ({}/s), last handshake {last_handshake}",
+        format::format_bytes(stats.rx_bytes),
+        format::format_bytes(stats.rx_rate),
+        format::format_bytes(stats.tx_bytes),
+        format::format_bytes(stats.tx_rate),
+    );
+}
+
+async fn get_location(rpc: &mut ManagementServiceClient) -> Result<Option<GeoIpLocation>> {
+    match rpc.get_current_location(()).await {
+        Ok(response) => Ok(Some(
+            GeoIpLocation::try_from(response.into_inner()).expect("invalid geoip data"),
+        )),
+        Err(status) if status.code() == mullvad_management_interface::Code::NotFound => Ok(None),
+        Err(status) => Err(Error::RpcFailed(status)),
+    }
+}
+
+async fn print_location(rpc: &mut ManagementServiceClient) -> Result<()> {
+    let location = match get_location(rpc).await? {
+        Some(location) => location,
+        None => {
+            println!("Location data unavailable");
+            return Ok(());
         }
     };
     if let Some(ipv4) = location.ipv4 {
@@ -136,3 +368,27 @@ async fn print_location(rpc: &mut ManagementServiceClient) -> Result<()> {
     );
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_json_output() {
+        let status = StatusOutput {
+            state: TunnelState::Disconnected,
+            location: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&status).unwrap(),
+            json!({ "state": { "state": "disconnected" } })
+        );
+
+        let event = DaemonEvent::TunnelState(TunnelState::Disconnected);
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({ "tunnel_state": { "state": "disconnected" } })
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{new_rpc_client, Command, Error, Result};
 use mullvad_management_interface::types::{self, Timestamp, TunnelOptions};
-use mullvad_types::wireguard::DEFAULT_ROTATION_INTERVAL;
+use mullvad_types::{relay_rotation::RelayRotation, wireguard::DEFAULT_ROTATION_INTERVAL};
 use std::{convert::TryFrom, time::Duration};
 
 pub struct Tunnel;
@@ -18,6 +18,8 @@ impl Command for Tunnel {
             .subcommand(create_openvpn_subcommand())
             .subcommand(create_wireguard_subcommand())
             .subcommand(create_ipv6_subcommand())
+            .subcommand(create_set_subcommand())
+            .subcommand(create_get_subcommand())
     }
 
     async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
@@ -25,6 +27,8 @@ impl Command for Tunnel {
             Some(("openvpn", openvpn_matches)) => Self::handle_openvpn_cmd(openvpn_matches).await,
             Some(("wireguard", wg_matches)) => Self::handle_wireguard_cmd(wg_matches).await,
             Some(("ipv6", ipv6_matches)) => Self::handle_ipv6_cmd(ipv6_matches).await,
+            Some(("set", set_matches)) => Self::handle_set_cmd(set_matches).await,
+            Some(("get", get_matches)) => Self::handle_get_cmd(get_matches).await,
             _ => {
                 unreachable!("unhandled command");
             }
@@ -38,7 +42,8 @@ fn create_wireguard_subcommand() -> clap::App<'static> {
         .setting(clap::AppSettings::SubcommandRequiredElseHelp)
         .subcommand(create_wireguard_mtu_subcommand())
         .subcommand(create_wireguard_quantum_resistant_tunnel_subcommand())
-        .subcommand(create_wireguard_keys_subcommand());
+        .subcommand(create_wireguard_keys_subcommand())
+        .subcommand(create_wireguard_export_config_subcommand());
     #[cfg(windows)]
     {
         subcmd.subcommand(create_wireguard_use_wg_nt_subcommand())
@@ -81,6 +86,13 @@ fn create_wireguard_keys_subcommand() -> clap::App<'static> {
         .subcommand(create_wireguard_keys_rotation_interval_subcommand())
 }
 
+fn create_wireguard_export_config_subcommand() -> clap::App<'static> {
+    clap::App::new("export-config").about(
+        "Print the config of the current tunnel in the wg-quick format. \
+        Note that it contains the private key of this device",
+    )
+}
+
 #[cfg(windows)]
 fn create_wireguard_use_wg_nt_subcommand() -> clap::App<'static> {
     clap::App::new("use-wireguard-nt")
@@ -136,6 +148,48 @@ fn create_ipv6_subcommand() -> clap::App<'static> {
         )
 }
 
+fn create_set_subcommand() -> clap::App<'static> {
+    clap::App::new("set")
+        .about("Change tunnel options")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            clap::App::new("relay-rotation")
+                .about(
+                    "Reconnect to a newly selected relay on a schedule. The relay is selected \
+                    using the current relay constraints, but the current exit relay is avoided",
+                )
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    clap::App::new("interval")
+                        .about("Rotate after being connected for the given number of minutes")
+                        .arg(clap::Arg::new("minutes").required(true)),
+                )
+                .subcommand(
+                    clap::App::new("daily")
+                        .about("Rotate every day at the given times")
+                        .arg(
+                            clap::Arg::new("times")
+                                .help("Local times of day, such as 08:00")
+                                .required(true)
+                                .multiple_values(true)
+                                .validator(parse_time_of_day),
+                        ),
+                )
+                .subcommand(clap::App::new("off").about("Disable relay rotation")),
+        )
+}
+
+fn create_get_subcommand() -> clap::App<'static> {
+    clap::App::new("get")
+        .about("Display tunnel options")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::App::new("relay-rotation").about("Display the relay rotation schedule"))
+}
+
+fn parse_time_of_day(time: &str) -> std::result::Result<chrono::NaiveTime, chrono::ParseError> {
+    chrono::NaiveTime::parse_from_str(time, "%H:%M")
+}
+
 impl Tunnel {
     async fn handle_openvpn_cmd(matches: &clap::ArgMatches) -> Result<()> {
         match matches.subcommand() {
@@ -178,6 +232,8 @@ impl Tunnel {
                 _ => unreachable!("unhandled command"),
             },
 
+            Some(("export-config", _)) => Self::process_wireguard_export_config().await,
+
             Some(("quantum-resistant-tunnel", matches)) => match matches.subcommand() {
                 Some(("get", _)) => Self::process_wireguard_quantum_resistant_tunnel_get().await,
                 Some(("set", matches)) => {
@@ -226,6 +282,13 @@ impl Tunnel {
         Ok(())
     }
 
+    async fn process_wireguard_export_config() -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let config = rpc.export_wireguard_config(()).await?.into_inner();
+        print!("{config}");
+        Ok(())
+    }
+
     async fn process_wireguard_quantum_resistant_tunnel_get() -> Result<()> {
         let tunnel_options = Self::get_tunnel_options().await?;
         match tunnel_options
@@ -359,6 +422,72 @@ impl Tunnel {
         }
     }
 
+    async fn handle_set_cmd(matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("relay-rotation", matches)) => {
+                let rotation = match matches.subcommand() {
+                    Some(("interval", matches)) => {
+                        let minutes = matches.value_of_t_or_exit::<u64>("minutes");
+                        Some(
+                            RelayRotation::interval(Duration::from_secs(60 * minutes)).map_err(
+                                |_| {
+                                    Error::InvalidCommand(
+                                        "The interval must be at least 10 minutes",
+                                    )
+                                },
+                            )?,
+                        )
+                    }
+                    Some(("daily", matches)) => {
+                        let times = matches
+                            .values_of("times")
+                            .unwrap()
+                            .map(|time| parse_time_of_day(time).unwrap())
+                            .collect();
+                        Some(RelayRotation::daily(times).unwrap())
+                    }
+                    Some(("off", _)) => None,
+                    _ => unreachable!("unhandled command"),
+                };
+                Self::process_relay_rotation_set(rotation).await
+            }
+            _ => unreachable!("unhandled command"),
+        }
+    }
+
+    async fn handle_get_cmd(matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("relay-rotation", _)) => Self::process_relay_rotation_get().await,
+            _ => unreachable!("unhandled command"),
+        }
+    }
+
+    async fn process_relay_rotation_set(rotation: Option<RelayRotation>) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_relay_rotation(types::RelayRotation::from(rotation.as_ref()))
+            .await?;
+        match rotation {
+            Some(rotation) => println!("Relay rotation: {rotation}"),
+            None => println!("Disabled relay rotation"),
+        }
+        Ok(())
+    }
+
+    async fn process_relay_rotation_get() -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let rotation = rpc
+            .get_settings(())
+            .await?
+            .into_inner()
+            .relay_rotation
+            .unwrap_or_default();
+        match Option::<RelayRotation>::try_from(rotation).expect("invalid relay rotation") {
+            Some(rotation) => println!("Relay rotation: {rotation}"),
+            None => println!("Relay rotation: off"),
+        }
+        Ok(())
+    }
+
     async fn process_openvpn_mssfix_get() -> Result<()> {
         let tunnel_options = Self::get_tunnel_options().await?;
         let mssfix = tunnel_options.openvpn.unwrap().mssfix;
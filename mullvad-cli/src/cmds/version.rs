@@ -1,7 +1,38 @@
-use crate::{new_rpc_client, Command, Error, Result};
+use crate::{format, new_rpc_client, Command, Error, Result};
+use mullvad_management_interface::types;
+use serde::Serialize;
 
 pub struct Version;
 
+/// Same fields as `mullvad_types::version::AppVersionInfo`, apart from those that are not exposed
+/// by the management interface.
+#[derive(Serialize)]
+pub struct VersionInfo {
+    pub supported: bool,
+    pub latest_stable: String,
+    pub latest_beta: String,
+    pub suggested_upgrade: Option<String>,
+}
+
+impl From<types::AppVersionInfo> for VersionInfo {
+    fn from(version_info: types::AppVersionInfo) -> Self {
+        VersionInfo {
+            supported: version_info.supported,
+            latest_stable: version_info.latest_stable,
+            latest_beta: version_info.latest_beta,
+            suggested_upgrade: Some(version_info.suggested_upgrade)
+                .filter(|version| !version.is_empty()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct VersionOutput {
+    current_version: String,
+    #[serde(flatten)]
+    version_info: VersionInfo,
+}
+
 #[mullvad_management_interface::async_trait]
 impl Command for Version {
     fn name(&self) -> &'static str {
@@ -13,19 +44,27 @@ impl Command for Version {
             .about("Shows current version, and the currently supported versions")
     }
 
-    async fn run(&self, _: &clap::ArgMatches) -> Result<()> {
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         let current_version = rpc
             .get_current_version(())
             .await
             .map_err(|error| Error::RpcFailedExt("Failed to obtain current version", error))?
             .into_inner();
-        println!("{:21}: {}", "Current version", current_version);
         let version_info = rpc
             .get_version_info(())
             .await
             .map_err(|error| Error::RpcFailedExt("Failed to obtain version info", error))?
             .into_inner();
+
+        if matches.is_present("json") {
+            return format::print_json(&VersionOutput {
+                current_version,
+                version_info: VersionInfo::from(version_info),
+            });
+        }
+
+        println!("{:21}: {}", "Current version", current_version);
         println!("{:21}: {}", "Is supported", version_info.supported);
 
         if !version_info.suggested_upgrade.is_empty() {
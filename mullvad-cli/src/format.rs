@@ -1,9 +1,71 @@
+use crate::{Error, Result};
 use mullvad_types::{auth_failed::AuthFailed, location::GeoIpLocation, states::TunnelState};
+use serde::Serialize;
+use std::time::Duration;
 use talpid_types::{
     net::{Endpoint, TunnelEndpoint},
     tunnel::ErrorState,
 };
 
+/// The global `--json` flag. Only the commands in [`JSON_COMMANDS`] accept it.
+pub fn json_arg() -> clap::Arg<'static> {
+    clap::Arg::new("json")
+        .long("json")
+        .global(true)
+        .help("Print information as JSON instead of text")
+}
+
+/// Subcommand paths of the commands that can print their output as JSON.
+const JSON_COMMANDS: &[&[&str]] = &[
+    &["account", "get"],
+    &["account", "list-devices"],
+    &["bridge", "get"],
+    &["debug", "leak-test"],
+    &["dns", "blocklist", "list"],
+    &["dns", "blocklist", "stats"],
+    &["dns", "get"],
+    &["dns", "split", "list"],
+    &["firewall", "allow", "list"],
+    &["firewall", "inbound", "list"],
+    &["lan", "network", "list"],
+    &["relay", "get"],
+    &["relay", "list"],
+    &["relay", "verify"],
+    &["split-tunnel", "app", "list"],
+    &["split-tunnel", "get"],
+    &["split-tunnel", "group", "list"],
+    &["split-tunnel", "pid", "list"],
+    &["split-tunnel", "user", "list"],
+    &["status"],
+    &["status", "history"],
+    &["status", "listen"],
+    &["version"],
+];
+
+/// Fails if `--json` was passed to a command that has no JSON output.
+pub fn check_json_arg(matches: &clap::ArgMatches) -> Result<()> {
+    let mut path = vec![];
+    let mut json = matches.is_present("json");
+    let mut matches = matches;
+    while let Some((name, sub_matches)) = matches.subcommand() {
+        path.push(name);
+        json |= sub_matches.is_present("json");
+        matches = sub_matches;
+    }
+    if json && !JSON_COMMANDS.contains(&path.as_slice()) {
+        return Err(Error::InvalidCommand("This command cannot print JSON"));
+    }
+    Ok(())
+}
+
+/// Prints a value as a single line of JSON. Commands print one value per invocation, or one value
+/// per line when streaming events, so that the output can be parsed line by line.
+pub fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+    let json = serde_json::to_string(value).map_err(Error::SerializeJson)?;
+    println!("{json}");
+    Ok(())
+}
+
 pub fn print_state(state: &TunnelState, verbose: bool) {
     use TunnelState::*;
 
@@ -145,6 +207,34 @@ fn format_relay_connection(
     )
 }
 
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, secs) = (secs / 3600, (secs / 60) % 60, secs % 60);
+    if hours > 0 {
+        format!("{hours}h {minutes}m {secs}s")
+    } else if minutes > 0 {
+        format!("{minutes}m {secs}s")
+    } else {
+        format!("{secs}s")
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.2} {}", UNITS[unit])
+    }
+}
+
 fn format_endpoint(hostname: Option<&str>, endpoint: &Endpoint, verbose: bool) -> String {
     match (hostname, verbose) {
         (Some(hostname), true) => format!("{hostname} ({endpoint})"),
@@ -189,3 +279,27 @@ const fn get_auth_failed_message(auth_failed: AuthFailed) -> &'static str {
         AuthFailed::Unknown => UNKNOWN_MSG,
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check_args(args: &[&str]) -> Result<()> {
+        let matches = crate::build_cli(&crate::cmds::get_commands())
+            .try_get_matches_from(args)
+            .unwrap();
+        check_json_arg(&matches)
+    }
+
+    #[test]
+    fn test_json_arg() {
+        assert!(check_args(&["mullvad", "--json", "status"]).is_ok());
+        assert!(check_args(&["mullvad", "status", "--json"]).is_ok());
+        assert!(check_args(&["mullvad", "--json", "relay", "list"]).is_ok());
+        assert!(check_args(&["mullvad", "relay", "get", "--json"]).is_ok());
+        assert!(check_args(&["mullvad", "connect"]).is_ok());
+
+        assert!(check_args(&["mullvad", "--json", "connect"]).is_err());
+        assert!(check_args(&["mullvad", "relay", "update", "--json"]).is_err());
+    }
+}
@@ -16,9 +16,26 @@ pub fn get_subcommand() -> clap::App<'static> {
                 .validator(city_code_validator),
         )
         .arg(clap::Arg::new("hostname").help("The hostname").index(3))
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            clap::App::new("list")
+                .about("Select relays from all locations in a custom list")
+                .arg(
+                    clap::Arg::new("name")
+                        .help("Name of the custom list")
+                        .required(true),
+                ),
+        )
 }
 
 pub fn get_constraint_from_args(matches: &clap::ArgMatches) -> RelayLocation {
+    if let Some(list_matches) = matches.subcommand_matches("list") {
+        return RelayLocation {
+            custom_list: list_matches.value_of("name").unwrap().to_owned(),
+            ..Default::default()
+        };
+    }
     let country = matches.value_of("country").unwrap();
     let city = matches.value_of("city");
     let hostname = matches.value_of("hostname");
@@ -55,6 +72,7 @@ pub fn get_constraint<T: AsRef<str>>(
             country,
             city,
             hostname,
+            ..Default::default()
         },
         (..) => clap::Error::raw(
             clap::ErrorKind::InvalidValue,
@@ -45,6 +45,27 @@ pub enum Error {
     #[error(display = "Failed to listen for status updates")]
     StatusListenerFailed,
 
+    #[error(display = "Failed to read WireGuard config")]
+    ReadWireguardConfig(#[error(source, no_from)] io::Error),
+
+    #[error(display = "Failed to read settings file")]
+    ReadSettingsFile(#[error(source, no_from)] io::Error),
+
+    #[error(display = "Failed to write settings file")]
+    WriteSettingsFile(#[error(source, no_from)] io::Error),
+
+    #[error(display = "Failed to read DNS blocklist")]
+    ReadBlocklist(#[error(source, no_from)] io::Error),
+
+    #[error(display = "Failed to serialize output as JSON")]
+    SerializeJson(#[error(source, no_from)] serde_json::Error),
+
+    #[error(display = "Received invalid data from the daemon: {}", _0)]
+    InvalidDaemonResponse(&'static str),
+
+    #[error(display = "Invalid WireGuard config")]
+    InvalidWireguardConfig(#[error(source)] mullvad_types::wg_quick::Error),
+
     //#[cfg(all(unix, not(target_os = "android"))
     #[error(display = "Failed to generate shell completions")]
     CompletionsError(#[error(source, no_from)] io::Error),
@@ -53,12 +74,27 @@ pub enum Error {
     Other(&'static str),
 }
 
+impl From<mullvad_management_interface::types::FromProtobufTypeError> for Error {
+    fn from(error: mullvad_management_interface::types::FromProtobufTypeError) -> Self {
+        match error {
+            mullvad_management_interface::types::FromProtobufTypeError::InvalidArgument(msg) => {
+                Error::InvalidDaemonResponse(msg)
+            }
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let exit_code = match run().await {
         Ok(_) => 0,
         Err(error) => {
             match &error {
+                Error::RpcFailed(status) | Error::RpcFailedExt(_, status)
+                    if status.code() == mullvad_management_interface::Code::PermissionDenied =>
+                {
+                    eprintln!("Permission denied: {}", status.message())
+                }
                 Error::RpcFailed(status) => {
                     eprintln!("{}: {:?}: {}", error, status.code(), status.message())
                 }
@@ -102,6 +138,7 @@ async fn run() -> Result<()> {
     );
 
     let app_matches = app.get_matches();
+    format::check_json_arg(&app_matches)?;
     match app_matches.subcommand() {
         #[cfg(all(unix, not(target_os = "android")))]
         Some(("shell-completions", sub_matches)) => {
@@ -137,6 +174,7 @@ fn build_cli(commands: &HashMap<&'static str, Box<dyn Command>>) -> clap::App<'s
         .setting(clap::AppSettings::SubcommandRequiredElseHelp)
         .global_setting(clap::AppSettings::DisableHelpSubcommand)
         .global_setting(clap::AppSettings::DisableVersionFlag)
+        .arg(format::json_arg())
         .subcommands(commands.values().map(|cmd| cmd.clap_subcommand()))
 }
 
@@ -0,0 +1,347 @@
+use chrono::DateTime;
+use mullvad_types::{
+    connection_history::{ConnectionHistoryEntry, SessionSummary, TrafficStats},
+    tunnel_stats::{PeerStats, TunnelStats},
+};
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+use talpid_core::tunnel::StatsHandle;
+use talpid_types::{net::wireguard, ErrorExt};
+use tokio::{
+    fs,
+    io::{self, AsyncWriteExt},
+};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    #[error(display = "Failed to serialize connection history entry")]
+    Serialize(#[error(source)] serde_json::Error),
+
+    #[error(display = "Unable to write connection history file")]
+    Write(#[error(source)] io::Error),
+
+    #[error(display = "Unable to remove connection history file")]
+    Remove(#[error(source)] io::Error),
+}
+
+const CONNECTION_HISTORY_FILE: &str = "connection-history.jsonl";
+/// Maximum number of entries to keep in the history.
+const MAX_ENTRIES: usize = 500;
+
+/// Journal of tunnel states that is stored as one JSON object per line. New entries are appended
+/// to the file, and once it holds twice as many entries as are kept, it is rewritten to only
+/// contain the most recent ones. Nothing is recorded unless the user has enabled it, since the
+/// entries reveal which relays were used and when.
+pub struct ConnectionHistory {
+    path: PathBuf,
+    entries: VecDeque<ConnectionHistoryEntry>,
+    lines_in_file: usize,
+    rewrite_on_next_push: bool,
+}
+
+impl ConnectionHistory {
+    pub async fn new(cache_dir: &Path, enabled: bool) -> ConnectionHistory {
+        let path = cache_dir.join(CONNECTION_HISTORY_FILE);
+        if !enabled {
+            let mut history = ConnectionHistory {
+                path,
+                entries: VecDeque::new(),
+                lines_in_file: 0,
+                rewrite_on_next_push: false,
+            };
+            // Remove any history that was recorded while it was enabled
+            if let Err(error) = history.clear().await {
+                log::error!("{}", error.display_chain());
+            }
+            return history;
+        }
+        log::info!("Opening connection history file in {}", path.display());
+
+        let mut entries = VecDeque::new();
+        let mut lines_in_file = 0;
+        let mut rewrite_on_next_push = false;
+
+        match fs::read_to_string(&path).await {
+            Ok(content) => {
+                for line in content.lines() {
+                    lines_in_file += 1;
+                    match serde_json::from_str(line) {
+                        Ok(entry) => {
+                            if entries.len() == MAX_ENTRIES {
+                                entries.pop_front();
+                            }
+                            entries.push_back(entry);
+                        }
+                        Err(_) => rewrite_on_next_push = true,
+                    }
+                }
+                if rewrite_on_next_push {
+                    log::warn!("Discarding unparsable entries in the connection history");
+                }
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to read connection history")
+                );
+                rewrite_on_next_push = true;
+            }
+        }
+
+        ConnectionHistory {
+            path,
+            entries,
+            lines_in_file,
+            rewrite_on_next_push,
+        }
+    }
+
+    /// Returns all entries in the history, oldest first.
+    pub fn entries(&self) -> Vec<ConnectionHistoryEntry> {
+        self.entries.iter().cloned().collect()
+    }
+
+    /// Adds an entry to the history and saves it to disk.
+    pub async fn push(&mut self, entry: ConnectionHistoryEntry) -> Result<()> {
+        if self.entries.len() == MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+
+        if self.rewrite_on_next_push || self.lines_in_file >= 2 * MAX_ENTRIES {
+            self.rewrite().await
+        } else {
+            self.append().await
+        }
+    }
+
+    /// Removes all entries from the history and deletes the file.
+    pub async fn clear(&mut self) -> Result<()> {
+        self.entries.clear();
+        self.lines_in_file = 0;
+        self.rewrite_on_next_push = false;
+        match fs::remove_file(&self.path).await {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(Error::Remove(error)),
+        }
+    }
+
+    async fn append(&mut self) -> Result<()> {
+        let entry = self.entries.back().expect("no entry to append");
+        let mut line = serde_json::to_string(entry).map_err(Error::Serialize)?;
+        line.push('\n');
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(Error::Write)?;
+        file.write_all(line.as_bytes())
+            .await
+            .map_err(Error::Write)?;
+        self.lines_in_file += 1;
+        Ok(())
+    }
+
+    async fn rewrite(&mut self) -> Result<()> {
+        let mut content = String::new();
+        for entry in &self.entries {
+            content.push_str(&serde_json::to_string(entry).map_err(Error::Serialize)?);
+            content.push('\n');
+        }
+        fs::write(&self.path, content).await.map_err(Error::Write)?;
+        self.lines_in_file = self.entries.len();
+        self.rewrite_on_next_push = false;
+        Ok(())
+    }
+}
+
+/// Keeps track of the connected state so that it can be summarized once it is left.
+#[derive(Clone)]
+pub struct ConnectedSession {
+    start: Instant,
+    stats: Option<StatsHandle>,
+    exit_peer: Option<wireguard::PublicKey>,
+    entry_peer: Option<wireguard::PublicKey>,
+}
+
+impl ConnectedSession {
+    pub fn new(
+        stats: Option<StatsHandle>,
+        exit_peer: Option<wireguard::PublicKey>,
+        entry_peer: Option<wireguard::PublicKey>,
+    ) -> Self {
+        ConnectedSession {
+            start: Instant::now(),
+            stats,
+            exit_peer,
+            entry_peer,
+        }
+    }
+
+    /// Reads the current traffic stats of the tunnel. These are only available for WireGuard
+    /// tunnels.
+    pub async fn tunnel_stats(&self) -> Option<TunnelStats> {
+        let stats = self.stats.clone()?;
+        let exit_peer = self.exit_peer.as_ref()?;
+        // Reading the stats may block on the tunnel device
+        let stats = tokio::task::spawn_blocking(move || stats.get())
+            .await
+            .ok()??;
+        let peer_stats = |peer: &wireguard::PublicKey| {
+            stats.get(peer.as_bytes()).map(|stats| PeerStats {
+                rx_bytes: stats.rx_bytes,
+                tx_bytes: stats.tx_bytes,
+                last_handshake: stats.last_handshake_time.map(DateTime::from),
+                ..Default::default()
+            })
+        };
+        Some(TunnelStats {
+            exit: peer_stats(exit_peer)?,
+            entry: self.entry_peer.as_ref().and_then(peer_stats),
+        })
+    }
+
+    pub async fn summarize(&self) -> SessionSummary {
+        let duration = self.start.elapsed();
+        let traffic = self.tunnel_stats().await.map(|stats| TrafficStats {
+            rx_bytes: stats.exit.rx_bytes,
+            tx_bytes: stats.exit.tx_bytes,
+        });
+        SessionSummary { duration, traffic }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Utc;
+    use mullvad_types::states::TunnelState;
+    use std::time::Duration;
+
+    /// Returns an entry that can be told apart from others by `id`.
+    fn entry(id: u64) -> ConnectionHistoryEntry {
+        ConnectionHistoryEntry {
+            timestamp: Utc::now(),
+            state: TunnelState::Disconnected,
+            ended_session: Some(SessionSummary {
+                duration: Duration::from_secs(id),
+                traffic: None,
+            }),
+        }
+    }
+
+    fn ids(entries: &[ConnectionHistoryEntry]) -> Vec<u64> {
+        entries
+            .iter()
+            .map(|entry| entry.ended_session.unwrap().duration.as_secs())
+            .collect()
+    }
+
+    async fn lines_in_file(dir: &Path) -> usize {
+        fs::read_to_string(dir.join(CONNECTION_HISTORY_FILE))
+            .await
+            .unwrap()
+            .lines()
+            .count()
+    }
+
+    #[tokio::test]
+    async fn test_append() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut history = ConnectionHistory::new(dir.path(), true).await;
+        for id in 0..3 {
+            history.push(entry(id)).await.unwrap();
+        }
+        assert_eq!(lines_in_file(dir.path()).await, 3);
+
+        let history = ConnectionHistory::new(dir.path(), true).await;
+        assert_eq!(ids(&history.entries()), [0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_rewrite_when_file_is_full() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut history = ConnectionHistory::new(dir.path(), true).await;
+        let count = 2 * MAX_ENTRIES as u64;
+        for id in 0..count {
+            history.push(entry(id)).await.unwrap();
+        }
+        // Entries are appended until the file holds twice as many as are kept
+        assert_eq!(lines_in_file(dir.path()).await, 2 * MAX_ENTRIES);
+        assert_eq!(history.entries().len(), MAX_ENTRIES);
+
+        history.push(entry(count)).await.unwrap();
+        assert_eq!(lines_in_file(dir.path()).await, MAX_ENTRIES);
+
+        let expected: Vec<u64> = (count + 1 - MAX_ENTRIES as u64..=count).collect();
+        assert_eq!(ids(&history.entries()), expected);
+        let history = ConnectionHistory::new(dir.path(), true).await;
+        assert_eq!(ids(&history.entries()), expected);
+    }
+
+    #[tokio::test]
+    async fn test_trim_when_loading() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let count = MAX_ENTRIES as u64 + 10;
+        let mut content = String::new();
+        for id in 0..count {
+            content.push_str(&serde_json::to_string(&entry(id)).unwrap());
+            content.push('\n');
+        }
+        fs::write(dir.path().join(CONNECTION_HISTORY_FILE), content)
+            .await
+            .unwrap();
+
+        let history = ConnectionHistory::new(dir.path(), true).await;
+        let expected: Vec<u64> = (10..count).collect();
+        assert_eq!(ids(&history.entries()), expected);
+    }
+
+    #[tokio::test]
+    async fn test_discard_corrupt_lines() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let content = format!(
+            "{}\nnot an entry\n{}\n",
+            serde_json::to_string(&entry(0)).unwrap(),
+            serde_json::to_string(&entry(1)).unwrap(),
+        );
+        fs::write(dir.path().join(CONNECTION_HISTORY_FILE), content)
+            .await
+            .unwrap();
+
+        let mut history = ConnectionHistory::new(dir.path(), true).await;
+        assert_eq!(ids(&history.entries()), [0, 1]);
+
+        // The next entry causes the file to be rewritten without the corrupt line
+        history.push(entry(2)).await.unwrap();
+        assert_eq!(lines_in_file(dir.path()).await, 3);
+        let history = ConnectionHistory::new(dir.path(), true).await;
+        assert_eq!(ids(&history.entries()), [0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_clear_when_disabled() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut history = ConnectionHistory::new(dir.path(), true).await;
+        history.push(entry(0)).await.unwrap();
+
+        let history = ConnectionHistory::new(dir.path(), false).await;
+        assert!(history.entries().is_empty());
+        assert!(!dir.path().join(CONNECTION_HISTORY_FILE).exists());
+    }
+}
@@ -1,5 +1,13 @@
+#[cfg(not(target_os = "android"))]
+use mullvad_types::settings::DnsBlocklistOptions;
 use mullvad_types::settings::{DnsOptions, DnsState};
 use std::net::{IpAddr, Ipv4Addr};
+#[cfg(not(target_os = "android"))]
+use std::sync::Arc;
+#[cfg(not(target_os = "android"))]
+use talpid_core::dns_forwarder::DnsBlocklist;
+#[cfg(not(target_os = "android"))]
+use talpid_types::{net::dns::EncryptedDnsServer, ErrorExt};
 
 /// When we want to block certain contents with the help of DNS server side,
 /// we compute the resolver IP to use based on these constants. The last
@@ -53,3 +61,45 @@ pub fn addresses_from_options(options: &DnsOptions) -> Option<Vec<IpAddr>> {
         }
     }
 }
+
+/// Return the DNS-over-HTTPS and DNS-over-TLS resolvers to use. These take precedence over the
+/// resolvers returned by [`addresses_from_options`] when non-empty.
+#[cfg(not(target_os = "android"))]
+pub fn encrypted_servers_from_options(options: &DnsOptions) -> Vec<EncryptedDnsServer> {
+    match options.state {
+        DnsState::Default => vec![],
+        DnsState::Custom => options.custom_options.encrypted_servers.clone(),
+    }
+}
+
+/// Reads the configured local DNS blocklists. Returns `None` if no lists are configured. Lists
+/// that cannot be read are skipped.
+#[cfg(not(target_os = "android"))]
+pub async fn load_blocklist(options: &DnsBlocklistOptions) -> Option<Arc<DnsBlocklist>> {
+    if options.lists.is_empty() {
+        return None;
+    }
+    let options = options.clone();
+    let load = move || {
+        let mut blocklist = DnsBlocklist::new(options.allowlist);
+        for path in options.lists {
+            if let Err(error) = blocklist.add_list(path.clone()) {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg(&format!(
+                        "Failed to read DNS blocklist {}",
+                        path.display()
+                    ))
+                );
+            }
+        }
+        blocklist
+    };
+    match tokio::task::spawn_blocking(load).await {
+        Ok(blocklist) => Some(Arc::new(blocklist)),
+        Err(error) => {
+            log::error!("Failed to load DNS blocklists: {}", error);
+            None
+        }
+    }
+}
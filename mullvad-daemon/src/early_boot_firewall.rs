@@ -1,5 +1,7 @@
+use ipnetwork::IpNetwork;
 use mullvad_daemon::settings::{self, SettingsPersister};
 use talpid_core::firewall::{self, Firewall, FirewallPolicy};
+use talpid_types::net::{default_lan_networks, AllowListEndpoint};
 
 #[derive(err_derive::Error, Debug)]
 pub enum Error {
@@ -15,24 +17,30 @@ pub enum Error {
 
 pub async fn initialize_firewall() -> Result<(), Error> {
     let mut firewall = Firewall::new(mullvad_types::TUNNEL_FWMARK)?;
-    let allow_lan = get_allow_lan().await.unwrap_or_else(|err| {
+    let (allow_lan, lan_networks, allow_list) = get_allowed_traffic().await.unwrap_or_else(|err| {
         log::info!(
             "Not allowing LAN traffic due to failing to read settings: {}",
             err
         );
-        false
+        (false, default_lan_networks(), vec![])
     });
     let policy = FirewallPolicy::Blocked {
         allow_lan,
+        lan_networks,
         allowed_endpoint: None,
+        allow_list,
     };
     log::info!("Applying firewall policy {policy}");
     firewall.apply_policy(policy)?;
     Ok(())
 }
 
-async fn get_allow_lan() -> Result<bool, Error> {
+async fn get_allowed_traffic() -> Result<(bool, Vec<IpNetwork>, Vec<AllowListEndpoint>), Error> {
     let path = mullvad_paths::settings_dir()?;
     let settings = SettingsPersister::load(&path).await;
-    Ok(settings.allow_lan)
+    Ok((
+        settings.allow_lan,
+        settings.lan_networks.clone(),
+        settings.firewall_allow_list.clone(),
+    ))
 }
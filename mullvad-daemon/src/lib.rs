@@ -8,6 +8,7 @@ pub mod account_history;
 mod api;
 #[cfg(not(target_os = "android"))]
 mod cleanup;
+mod connection_history;
 pub mod device;
 mod dns;
 pub mod exception_logging;
@@ -17,7 +18,11 @@ pub mod logging;
 mod macos;
 #[cfg(not(target_os = "android"))]
 pub mod management_interface;
+#[cfg(not(target_os = "android"))]
+mod metrics;
 mod migrations;
+#[cfg(target_os = "linux")]
+mod network_rules;
 #[cfg(not(target_os = "android"))]
 pub mod rpc_uniqueness_check;
 pub mod runtime;
@@ -35,35 +40,53 @@ use futures::{
     future::{abortable, AbortHandle, Future, LocalBoxFuture},
     StreamExt,
 };
+use ipnetwork::IpNetwork;
 use mullvad_relay_selector::{
     updater::{RelayListUpdater, RelayListUpdaterHandle},
     RelaySelector, SelectorConfig,
 };
+#[cfg(target_os = "linux")]
+use mullvad_types::network_rules::{NetworkInfo, NetworkRulesSettings, NetworkTrust};
+#[cfg(target_os = "linux")]
+use mullvad_types::settings::ExcludedUsers;
 use mullvad_types::{
     account::{AccountData, AccountToken, VoucherSubmission},
     auth_failed::AuthFailed,
+    connection_history::ConnectionHistoryEntry,
+    custom_list::{self, CustomList, CustomListName, CustomListsSettings},
     device::{Device, DeviceEvent, DeviceEventCause, DeviceId, DeviceState, RemoveDeviceEvent},
     location::GeoIpLocation,
-    relay_constraints::{BridgeSettings, BridgeState, ObfuscationSettings, RelaySettingsUpdate},
-    relay_list::RelayList,
+    relay_constraints::{
+        BridgeSettings, BridgeState, Constraint, LocationConstraint, ObfuscationSettings,
+        RelaySelectionStrategy, RelaySettings, RelaySettingsUpdate,
+    },
+    relay_list::{RelayList, RelayListVerification},
+    relay_rotation::RelayRotation,
     settings::{DnsOptions, Settings},
     states::{TargetState, TunnelState},
+    tunnel_stats::TunnelStats,
     version::{AppVersion, AppVersionInfo},
+    wg_quick::WgQuickConfig,
     wireguard::{PublicKey, QuantumResistantState, RotationInterval},
 };
 use settings::SettingsPersister;
+#[cfg(any(windows, target_os = "linux"))]
+use std::collections::HashSet;
+#[cfg(target_os = "windows")]
+use std::ffi::OsString;
 #[cfg(target_os = "android")]
 use std::os::unix::io::RawFd;
-#[cfg(target_os = "windows")]
-use std::{collections::HashSet, ffi::OsString};
 use std::{
     marker::PhantomData,
     mem,
+    net::IpAddr,
     path::PathBuf,
     pin::Pin,
     sync::{Arc, Weak},
     time::Duration,
 };
+#[cfg(not(target_os = "android"))]
+use talpid_core::dns_forwarder::DnsBlocklist;
 #[cfg(any(target_os = "linux", windows))]
 use talpid_core::split_tunnel;
 use talpid_core::{
@@ -72,8 +95,12 @@ use talpid_core::{
 };
 #[cfg(target_os = "android")]
 use talpid_types::android::AndroidContext;
+#[cfg(target_os = "linux")]
+use talpid_types::net::InboundRule;
+#[cfg(not(target_os = "android"))]
+use talpid_types::net::{dns::DnsBlocklistStats, AllowListEndpoint};
 use talpid_types::{
-    net::{TunnelEndpoint, TunnelType},
+    net::{validate_lan_network, TunnelEndpoint, TunnelType},
     tunnel::{ErrorStateCause, TunnelStateTransition},
     ErrorExt,
 };
@@ -154,6 +181,12 @@ pub enum Error {
     #[error(display = "Account history error")]
     AccountHistory(#[error(source)] account_history::Error),
 
+    #[error(display = "Custom list error")]
+    CustomListError(#[error(source)] custom_list::Error),
+
+    #[error(display = "Unable to export WireGuard config: {}", _0)]
+    ExportWireguardConfig(&'static str),
+
     #[cfg(not(target_os = "android"))]
     #[error(display = "Factory reset partially failed: {}", _0)]
     FactoryResetError(&'static str),
@@ -161,6 +194,9 @@ pub enum Error {
     #[error(display = "Tunnel state machine error")]
     TunnelError(#[error(source)] tunnel_state_machine::Error),
 
+    #[error(display = "Failed to clear the connection history")]
+    ClearConnectionHistory(#[error(source)] connection_history::Error),
+
     #[cfg(target_os = "macos")]
     #[error(display = "Failed to set exclusion group")]
     GroupIdError(#[error(source)] io::Error),
@@ -174,6 +210,15 @@ pub enum DaemonCommand {
     Reconnect(oneshot::Sender<bool>),
     /// Request the current state.
     GetState(oneshot::Sender<TunnelState>),
+    /// Request the recorded tunnel states, oldest first.
+    GetConnectionHistory(oneshot::Sender<Vec<ConnectionHistoryEntry>>),
+    /// Remove all recorded tunnel states.
+    ClearConnectionHistory(ResponseTx<(), Error>),
+    /// Request the traffic stats of the tunnel, if it is connected.
+    GetTunnelStats(oneshot::Sender<Option<TunnelStats>>),
+    /// Request the number of entries in and queries blocked by each local DNS blocklist.
+    #[cfg(not(target_os = "android"))]
+    GetDnsBlocklistStats(oneshot::Sender<Vec<DnsBlocklistStats>>),
     /// Get the current geographical location.
     GetCurrentLocation(oneshot::Sender<Option<GeoIpLocation>>),
     CreateNewAccount(ResponseTx<String, Error>),
@@ -195,6 +240,8 @@ pub enum DaemonCommand {
     /// Trigger an asynchronous relay list update. This returns before the relay list is actually
     /// updated.
     UpdateRelayLocations,
+    /// Verify the signatures of the relay lists stored on disk.
+    VerifyRelayLists(oneshot::Sender<Vec<RelayListVerification>>),
     /// Log in with a given account and create a new device.
     LoginAccount(ResponseTx<(), Error>, AccountToken),
     /// Log out of the current account and remove the device, if they exist.
@@ -209,20 +256,46 @@ pub enum DaemonCommand {
     RemoveDevice(ResponseTx<(), Error>, AccountToken, DeviceId),
     /// Place constraints on the type of tunnel and relay
     UpdateRelaySettings(ResponseTx<(), settings::Error>, RelaySettingsUpdate),
+    /// Create a new, empty custom list
+    CreateCustomList(ResponseTx<(), Error>, CustomListName),
+    /// Delete a custom list. Fails if the list is in use by the relay or bridge constraints
+    DeleteCustomList(ResponseTx<(), Error>, CustomListName),
+    /// Replace the locations of an existing custom list
+    UpdateCustomList(ResponseTx<(), Error>, CustomList),
     /// Set the allow LAN setting.
     SetAllowLan(ResponseTx<(), settings::Error>, bool),
+    /// Set the networks that are considered local.
+    SetLanNetworks(ResponseTx<(), settings::Error>, Vec<IpNetwork>),
     /// Set the beta program setting.
     SetShowBetaReleases(ResponseTx<(), settings::Error>, bool),
+    /// Set whether to record the connection history. Disabling it clears the history.
+    SetRecordConnectionHistory(ResponseTx<(), settings::Error>, bool),
     /// Set the block_when_disconnected setting.
     SetBlockWhenDisconnected(ResponseTx<(), settings::Error>, bool),
+    /// Set the endpoints that may be reached outside the tunnel in every tunnel state.
+    #[cfg(not(target_os = "android"))]
+    SetFirewallAllowList(ResponseTx<(), settings::Error>, Vec<AllowListEndpoint>),
+    /// Set the local ports that may be reached from outside the tunnel while connected.
+    #[cfg(target_os = "linux")]
+    SetFirewallInboundRules(ResponseTx<(), settings::Error>, Vec<InboundRule>),
     /// Set the auto-connect setting.
     SetAutoConnect(ResponseTx<(), settings::Error>, bool),
+    /// Set the rules that connect or disconnect the tunnel when joining specific networks.
+    #[cfg(target_os = "linux")]
+    SetNetworkRules(ResponseTx<(), settings::Error>, NetworkRulesSettings),
+    /// Request the network that the device is connected to.
+    #[cfg(target_os = "linux")]
+    GetCurrentNetwork(oneshot::Sender<Option<NetworkInfo>>),
     /// Set the mssfix argument for OpenVPN
     SetOpenVpnMssfix(ResponseTx<(), settings::Error>, Option<u16>),
     /// Set proxy details for OpenVPN
     SetBridgeSettings(ResponseTx<(), settings::Error>, BridgeSettings),
     /// Set proxy state
     SetBridgeState(ResponseTx<(), settings::Error>, BridgeState),
+    /// Set how relays are picked among the ones matching the relay constraints
+    SetRelaySelectionStrategy(ResponseTx<(), settings::Error>, RelaySelectionStrategy),
+    /// Set when to reconnect to a newly selected relay
+    SetRelayRotation(ResponseTx<(), settings::Error>, Option<RelayRotation>),
     /// Set if IPv6 should be enabled in the tunnel
     SetEnableIpv6(ResponseTx<(), settings::Error>, bool),
     /// Set whether to enable PQ PSK exchange in the tunnel
@@ -236,10 +309,16 @@ pub enum DaemonCommand {
     SetWireguardRotationInterval(ResponseTx<(), settings::Error>, Option<RotationInterval>),
     /// Get the daemon settings
     GetSettings(oneshot::Sender<Settings>),
+    /// Serialize the settings so that they can be imported on another device
+    ExportSettings(ResponseTx<String, settings::Error>),
+    /// Replace all settings with previously exported settings
+    ImportSettings(ResponseTx<(), settings::Error>, String),
     /// Generate new wireguard key
     RotateWireguardKey(ResponseTx<(), Error>),
     /// Return a public key of the currently set wireguard private key, if there is one
     GetWireguardKey(ResponseTx<Option<PublicKey>, Error>),
+    /// Return the config of the current WireGuard tunnel in the wg-quick format
+    ExportWireguardConfig(ResponseTx<String, Error>),
     /// Get information about the currently running and latest app versions
     GetVersionInfo(oneshot::Sender<Option<AppVersionInfo>>),
     /// Return whether the daemon is performing post-upgrade tasks
@@ -261,17 +340,20 @@ pub enum DaemonCommand {
     /// Clear list of processes excluded from the tunnel
     #[cfg(target_os = "linux")]
     ClearSplitTunnelProcesses(ResponseTx<(), split_tunnel::Error>),
+    /// Set users and groups whose traffic is excluded from the tunnel
+    #[cfg(target_os = "linux")]
+    SetSplitTunnelExcludedUsers(ResponseTx<(), settings::Error>, ExcludedUsers),
     /// Exclude traffic of an application from the tunnel
-    #[cfg(windows)]
+    #[cfg(any(windows, target_os = "linux"))]
     AddSplitTunnelApp(ResponseTx<(), Error>, PathBuf),
     /// Remove application from list of apps to exclude from the tunnel
-    #[cfg(windows)]
+    #[cfg(any(windows, target_os = "linux"))]
     RemoveSplitTunnelApp(ResponseTx<(), Error>, PathBuf),
     /// Clear list of apps to exclude from the tunnel
-    #[cfg(windows)]
+    #[cfg(any(windows, target_os = "linux"))]
     ClearSplitTunnelApps(ResponseTx<(), Error>),
     /// Enable or disable split tunneling
-    #[cfg(windows)]
+    #[cfg(any(windows, target_os = "linux"))]
     SetSplitTunnelState(ResponseTx<(), Error>, bool),
     /// Returns all processes currently being excluded from the tunnel
     #[cfg(windows)]
@@ -311,9 +393,14 @@ pub(crate) enum InternalDaemonEvent {
     /// The split tunnel paths or state were updated.
     #[cfg(target_os = "windows")]
     ExcludedPathsEvent(ExcludedPathsUpdate, oneshot::Sender<Result<(), Error>>),
+    /// The device joined or left a network.
+    #[cfg(target_os = "linux")]
+    NetworkChanged(Option<NetworkInfo>),
+    /// It is time to reconnect to a newly selected relay.
+    RotateRelay,
 }
 
-#[cfg(target_os = "windows")]
+#[cfg(any(windows, target_os = "linux"))]
 pub(crate) enum ExcludedPathsUpdate {
     SetState(bool),
     SetPaths(HashSet<PathBuf>),
@@ -533,13 +620,29 @@ pub struct Daemon<L: EventListener> {
     state: DaemonExecutionState,
     #[cfg(target_os = "linux")]
     exclude_pids: split_tunnel::PidManager,
+    #[cfg(target_os = "linux")]
+    exclude_apps: split_tunnel::ExcludedAppsMonitor,
+    #[cfg(target_os = "linux")]
+    current_network: Option<NetworkInfo>,
+    /// Whether the current network is trusted according to the network rules. Traffic is not
+    /// blocked while disconnected on trusted networks.
+    #[cfg(target_os = "linux")]
+    on_trusted_network: bool,
     rx: mpsc::UnboundedReceiver<InternalDaemonEvent>,
     tx: DaemonEventSender,
     reconnection_job: Option<AbortHandle>,
+    relay_rotation_job: Option<AbortHandle>,
     event_listener: L,
     migration_complete: migrations::MigrationComplete,
     settings: SettingsPersister,
     account_history: account_history::AccountHistory,
+    connection_history: connection_history::ConnectionHistory,
+    connected_session: Option<connection_history::ConnectedSession>,
+    #[cfg(not(target_os = "android"))]
+    tunnel_metrics: metrics::TunnelMetrics,
+    /// Local DNS blocklists that are currently loaded.
+    #[cfg(not(target_os = "android"))]
+    dns_blocklist: Option<Arc<DnsBlocklist>>,
     device_checker: device::TunnelStateChangeHandler,
     account_manager: device::AccountManagerHandle,
     api_runtime: mullvad_api::Runtime,
@@ -547,6 +650,8 @@ pub struct Daemon<L: EventListener> {
     version_updater_handle: version_check::VersionUpdaterHandle,
     relay_selector: RelaySelector,
     relay_list_updater: RelayListUpdaterHandle,
+    resource_dir: PathBuf,
+    cache_dir: PathBuf,
     parameters_generator: tunnel::ParametersGenerator,
     app_version_info: Option<AppVersionInfo>,
     shutdown_tasks: Vec<Pin<Box<dyn Future<Output = ()>>>>,
@@ -640,6 +745,12 @@ where
         .await
         .map_err(Error::LoadAccountHistory)?;
 
+        let connection_history = connection_history::ConnectionHistory::new(
+            &cache_dir,
+            settings.record_connection_history,
+        )
+        .await;
+
         let target_state = if settings.auto_connect {
             log::info!("Automatically connecting since auto-connect is turned on");
             PersistentTargetState::force(&cache_dir, TargetState::Secured).await
@@ -659,6 +770,15 @@ where
             vec![]
         };
 
+        // The exclusion cgroup must exist before the firewall rules that match it are applied
+        #[cfg(target_os = "linux")]
+        let exclude_pids = split_tunnel::PidManager::new().map_err(Error::InitSplitTunneling)?;
+        #[cfg(target_os = "linux")]
+        let exclude_apps = split_tunnel::ExcludedAppsMonitor::spawn(
+            exclude_pids.clone(),
+            Self::active_split_tunnel_apps(&settings),
+        );
+
         let initial_api_endpoint =
             api::get_allowed_endpoint(api_runtime.address_cache.get_address().await);
         let parameters_generator = tunnel::ParametersGenerator::new(
@@ -666,18 +786,50 @@ where
             relay_selector.clone(),
             settings.tunnel_options.clone(),
         );
+        #[cfg(not(target_os = "android"))]
+        let dns_blocklist =
+            dns::load_blocklist(&settings.tunnel_options.dns_options.blocklist_options).await;
         let (offline_state_tx, offline_state_rx) = mpsc::unbounded();
         #[cfg(target_os = "windows")]
         let (volume_update_tx, volume_update_rx) = mpsc::unbounded();
         let tunnel_state_machine_handle = tunnel_state_machine::spawn(
             tunnel_state_machine::InitialTunnelState {
                 allow_lan: settings.allow_lan,
+                lan_networks: settings.lan_networks.clone(),
                 block_when_disconnected: settings.block_when_disconnected,
                 dns_servers: dns::addresses_from_options(&settings.tunnel_options.dns_options),
+                #[cfg(not(target_os = "android"))]
+                encrypted_dns_servers: dns::encrypted_servers_from_options(
+                    &settings.tunnel_options.dns_options,
+                ),
+                #[cfg(not(target_os = "android"))]
+                dns_blocklist: dns_blocklist.clone(),
+                #[cfg(not(target_os = "android"))]
+                split_dns_rules: settings.tunnel_options.dns_options.split_dns_rules.clone(),
                 allowed_endpoint: initial_api_endpoint,
+                #[cfg(not(target_os = "android"))]
+                allow_list: settings.firewall_allow_list.clone(),
+                #[cfg(target_os = "linux")]
+                inbound_rules: settings.firewall_inbound_rules.clone(),
                 reset_firewall: *target_state != TargetState::Secured,
                 #[cfg(windows)]
                 exclude_paths,
+                #[cfg(target_os = "linux")]
+                excluded_uids: settings
+                    .split_tunnel
+                    .excluded_users
+                    .uids
+                    .iter()
+                    .cloned()
+                    .collect(),
+                #[cfg(target_os = "linux")]
+                excluded_gids: settings
+                    .split_tunnel
+                    .excluded_users
+                    .gids
+                    .iter()
+                    .cloned()
+                    .collect(),
             },
             parameters_generator.clone(),
             log_dir,
@@ -727,19 +879,50 @@ where
         // Attempt to download a fresh relay list
         relay_list_updater.update().await;
 
+        #[cfg(target_os = "linux")]
+        network_rules::spawn_monitor(internal_event_tx.clone());
+
+        #[cfg(not(target_os = "android"))]
+        let tunnel_metrics = metrics::TunnelMetrics::default();
+        #[cfg(not(target_os = "android"))]
+        if let Err(error) = metrics::spawn_server(metrics::MetricsCollector {
+            tunnel_metrics: tunnel_metrics.clone(),
+            daemon_tx: internal_event_tx.to_specialized_sender(),
+            api_service: api_handle.service(),
+            relay_selector: relay_selector.clone(),
+        }) {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to start metrics server")
+            );
+        }
+
         let daemon = Daemon {
             tunnel_state: TunnelState::Disconnected,
             target_state,
             state: DaemonExecutionState::Running,
             #[cfg(target_os = "linux")]
-            exclude_pids: split_tunnel::PidManager::new().map_err(Error::InitSplitTunneling)?,
+            exclude_pids,
+            #[cfg(target_os = "linux")]
+            exclude_apps,
+            #[cfg(target_os = "linux")]
+            current_network: None,
+            #[cfg(target_os = "linux")]
+            on_trusted_network: false,
             rx: internal_event_rx,
             tx: internal_event_tx,
             reconnection_job: None,
+            relay_rotation_job: None,
             event_listener,
             migration_complete,
             settings,
             account_history,
+            connection_history,
+            connected_session: None,
+            #[cfg(not(target_os = "android"))]
+            tunnel_metrics,
+            #[cfg(not(target_os = "android"))]
+            dns_blocklist,
             device_checker: device::TunnelStateChangeHandler::new(account_manager.clone()),
             account_manager,
             api_runtime,
@@ -747,6 +930,8 @@ where
             version_updater_handle,
             relay_selector,
             relay_list_updater,
+            resource_dir,
+            cache_dir,
             parameters_generator,
             app_version_info,
             shutdown_tasks: vec![],
@@ -844,6 +1029,9 @@ where
             DeviceMigrationEvent(event) => self.handle_device_migration_event(event).await,
             #[cfg(windows)]
             ExcludedPathsEvent(update, tx) => self.handle_new_excluded_paths(update, tx).await,
+            #[cfg(target_os = "linux")]
+            NetworkChanged(network) => self.handle_network_change(network).await,
+            RotateRelay => self.handle_relay_rotation().await,
         }
     }
 
@@ -878,8 +1066,19 @@ where
             self.unschedule_reconnect();
         }
 
+        if !tunnel_state.is_connected() {
+            self.unschedule_relay_rotation();
+        } else if !self.tunnel_state.is_connected() {
+            self.schedule_relay_rotation();
+        }
+
         log::debug!("New tunnel state: {:?}", tunnel_state);
 
+        #[cfg(not(target_os = "android"))]
+        self.tunnel_metrics.record_state(&tunnel_state);
+
+        self.record_connection_history(&tunnel_state).await;
+
         match tunnel_state {
             TunnelState::Disconnected => {
                 self.api_handle.availability.reset_inactivity_timer();
@@ -890,7 +1089,10 @@ where
         }
 
         match &tunnel_state {
-            TunnelState::Disconnected => self.state.disconnected(),
+            TunnelState::Disconnected => {
+                self.state.disconnected();
+                self.update_relay_latencies();
+            }
             TunnelState::Connecting { .. } => {
                 log::debug!("Settings: {}", self.settings.summary());
             }
@@ -920,6 +1122,50 @@ where
         self.event_listener.notify_new_state(tunnel_state);
     }
 
+    async fn record_connection_history(&mut self, tunnel_state: &TunnelState) {
+        let mut ended_session = None;
+        if !tunnel_state.is_connected() {
+            if let Some(session) = self.connected_session.take() {
+                ended_session = Some(session.summarize().await);
+            }
+        } else if !self.tunnel_state.is_connected() {
+            let (exit_peer, entry_peer) = match self
+                .parameters_generator
+                .get_last_wireguard_parameters()
+                .await
+            {
+                Some(parameters) => match parameters.connection.exit_peer {
+                    Some(exit_peer) => (
+                        Some(exit_peer.public_key),
+                        Some(parameters.connection.peer.public_key),
+                    ),
+                    None => (Some(parameters.connection.peer.public_key), None),
+                },
+                None => (None, None),
+            };
+            self.connected_session = Some(connection_history::ConnectedSession::new(
+                self.tunnel_state_machine_handle.tunnel_stats(),
+                exit_peer,
+                entry_peer,
+            ));
+        }
+
+        if !self.settings.record_connection_history {
+            return;
+        }
+        let entry = ConnectionHistoryEntry {
+            timestamp: chrono::Utc::now(),
+            state: tunnel_state.clone(),
+            ended_session,
+        };
+        if let Err(error) = self.connection_history.push(entry).await {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to save connection history")
+            );
+        }
+    }
+
     fn reset_rpc_sockets_on_tunnel_state_transition(
         &mut self,
         tunnel_state_transition: &TunnelStateTransition,
@@ -956,6 +1202,47 @@ where
         }
     }
 
+    /// Schedules a reconnect to a newly selected relay, if relay rotation is enabled.
+    fn schedule_relay_rotation(&mut self) {
+        self.unschedule_relay_rotation();
+
+        let delay = match self
+            .settings
+            .relay_rotation
+            .as_ref()
+            .and_then(|rotation| rotation.time_until_rotation(&chrono::Local::now()))
+        {
+            Some(delay) => delay,
+            None => return,
+        };
+        log::debug!("Rotating relay in {} minute(s)", delay.as_secs() / 60);
+
+        let daemon_tx = self.tx.clone();
+        let (future, abort_handle) = abortable(Box::pin(async move {
+            tokio::time::sleep(delay).await;
+            let _ = daemon_tx.send(InternalDaemonEvent::RotateRelay);
+        }));
+
+        tokio::spawn(future);
+        self.relay_rotation_job = Some(abort_handle);
+    }
+
+    fn unschedule_relay_rotation(&mut self) {
+        if let Some(job) = self.relay_rotation_job.take() {
+            job.abort();
+        }
+    }
+
+    async fn handle_relay_rotation(&mut self) {
+        self.relay_rotation_job = None;
+        if !self.tunnel_state.is_connected() {
+            return;
+        }
+        log::info!("Reconnecting to rotate the relay");
+        self.parameters_generator.avoid_last_exit_relay().await;
+        self.reconnect_tunnel();
+    }
+
     async fn handle_command(&mut self, command: DaemonCommand) {
         use self::DaemonCommand::*;
         if !self.state.is_running() {
@@ -971,6 +1258,11 @@ where
             SetTargetState(tx, state) => self.on_set_target_state(tx, state).await,
             Reconnect(tx) => self.on_reconnect(tx),
             GetState(tx) => self.on_get_state(tx),
+            GetConnectionHistory(tx) => self.on_get_connection_history(tx),
+            ClearConnectionHistory(tx) => self.on_clear_connection_history(tx).await,
+            GetTunnelStats(tx) => self.on_get_tunnel_stats(tx),
+            #[cfg(not(target_os = "android"))]
+            GetDnsBlocklistStats(tx) => self.on_get_dns_blocklist_stats(tx),
             GetCurrentLocation(tx) => self.on_get_current_location(tx).await,
             CreateNewAccount(tx) => self.on_create_new_account(tx).await,
             GetAccountData(tx, account_token) => self.on_get_account_data(tx, account_token).await,
@@ -978,6 +1270,7 @@ where
             SubmitVoucher(tx, voucher) => self.on_submit_voucher(tx, voucher).await,
             GetRelayLocations(tx) => self.on_get_relay_locations(tx),
             UpdateRelayLocations => self.on_update_relay_locations().await,
+            VerifyRelayLists(tx) => self.on_verify_relay_lists(tx),
             LoginAccount(tx, account_token) => self.on_login_account(tx, account_token),
             LogoutAccount(tx) => self.on_logout_account(tx),
             GetDevice(tx) => self.on_get_device(tx).await,
@@ -989,18 +1282,43 @@ where
             GetAccountHistory(tx) => self.on_get_account_history(tx),
             ClearAccountHistory(tx) => self.on_clear_account_history(tx).await,
             UpdateRelaySettings(tx, update) => self.on_update_relay_settings(tx, update).await,
+            CreateCustomList(tx, name) => self.on_create_custom_list(tx, name).await,
+            DeleteCustomList(tx, name) => self.on_delete_custom_list(tx, name).await,
+            UpdateCustomList(tx, list) => self.on_update_custom_list(tx, list).await,
             SetAllowLan(tx, allow_lan) => self.on_set_allow_lan(tx, allow_lan).await,
+            SetLanNetworks(tx, lan_networks) => self.on_set_lan_networks(tx, lan_networks).await,
             SetShowBetaReleases(tx, enabled) => self.on_set_show_beta_releases(tx, enabled).await,
+            SetRecordConnectionHistory(tx, enabled) => {
+                self.on_set_record_connection_history(tx, enabled).await
+            }
             SetBlockWhenDisconnected(tx, block_when_disconnected) => {
                 self.on_set_block_when_disconnected(tx, block_when_disconnected)
                     .await
             }
+            #[cfg(not(target_os = "android"))]
+            SetFirewallAllowList(tx, allow_list) => {
+                self.on_set_firewall_allow_list(tx, allow_list).await
+            }
+            #[cfg(target_os = "linux")]
+            SetFirewallInboundRules(tx, inbound_rules) => {
+                self.on_set_firewall_inbound_rules(tx, inbound_rules).await
+            }
             SetAutoConnect(tx, auto_connect) => self.on_set_auto_connect(tx, auto_connect).await,
+            #[cfg(target_os = "linux")]
+            SetNetworkRules(tx, network_rules) => {
+                self.on_set_network_rules(tx, network_rules).await
+            }
+            #[cfg(target_os = "linux")]
+            GetCurrentNetwork(tx) => self.on_get_current_network(tx),
             SetOpenVpnMssfix(tx, mssfix_arg) => self.on_set_openvpn_mssfix(tx, mssfix_arg).await,
             SetBridgeSettings(tx, bridge_settings) => {
                 self.on_set_bridge_settings(tx, bridge_settings).await
             }
             SetBridgeState(tx, bridge_state) => self.on_set_bridge_state(tx, bridge_state).await,
+            SetRelaySelectionStrategy(tx, strategy) => {
+                self.on_set_relay_selection_strategy(tx, strategy).await
+            }
+            SetRelayRotation(tx, rotation) => self.on_set_relay_rotation(tx, rotation).await,
             SetEnableIpv6(tx, enable_ipv6) => self.on_set_enable_ipv6(tx, enable_ipv6).await,
             SetQuantumResistantTunnel(tx, quantum_resistant_state) => {
                 self.on_set_quantum_resistant_tunnel(tx, quantum_resistant_state)
@@ -1012,8 +1330,11 @@ where
                 self.on_set_wireguard_rotation_interval(tx, interval).await
             }
             GetSettings(tx) => self.on_get_settings(tx),
+            ExportSettings(tx) => self.on_export_settings(tx),
+            ImportSettings(tx, settings) => self.on_import_settings(tx, settings).await,
             RotateWireguardKey(tx) => self.on_rotate_wireguard_key(tx).await,
             GetWireguardKey(tx) => self.on_get_wireguard_key(tx).await,
+            ExportWireguardConfig(tx) => self.on_export_wireguard_config(tx).await,
             GetVersionInfo(tx) => self.on_get_version_info(tx).await,
             IsPerformingPostUpgrade(tx) => self.on_is_performing_post_upgrade(tx),
             GetCurrentVersion(tx) => self.on_get_current_version(tx),
@@ -1027,13 +1348,17 @@ where
             RemoveSplitTunnelProcess(tx, pid) => self.on_remove_split_tunnel_process(tx, pid),
             #[cfg(target_os = "linux")]
             ClearSplitTunnelProcesses(tx) => self.on_clear_split_tunnel_processes(tx),
-            #[cfg(windows)]
+            #[cfg(target_os = "linux")]
+            SetSplitTunnelExcludedUsers(tx, users) => {
+                self.on_set_split_tunnel_excluded_users(tx, users).await
+            }
+            #[cfg(any(windows, target_os = "linux"))]
             AddSplitTunnelApp(tx, path) => self.on_add_split_tunnel_app(tx, path).await,
-            #[cfg(windows)]
+            #[cfg(any(windows, target_os = "linux"))]
             RemoveSplitTunnelApp(tx, path) => self.on_remove_split_tunnel_app(tx, path).await,
-            #[cfg(windows)]
+            #[cfg(any(windows, target_os = "linux"))]
             ClearSplitTunnelApps(tx) => self.on_clear_split_tunnel_apps(tx).await,
-            #[cfg(windows)]
+            #[cfg(any(windows, target_os = "linux"))]
             SetSplitTunnelState(tx, enabled) => self.on_set_split_tunnel_state(tx, enabled).await,
             #[cfg(windows)]
             GetSplitTunnelProcesses(tx) => self.on_get_split_tunnel_processes(tx),
@@ -1354,6 +1679,15 @@ where
         self.relay_list_updater.update().await;
     }
 
+    fn on_verify_relay_lists(&mut self, tx: oneshot::Sender<Vec<RelayListVerification>>) {
+        let resource_dir = self.resource_dir.clone();
+        let cache_dir = self.cache_dir.clone();
+        tokio::task::spawn_blocking(move || {
+            let verifications = RelaySelector::verify_relay_lists(&resource_dir, &cache_dir);
+            Self::oneshot_send(tx, verifications, "relay list verifications");
+        });
+    }
+
     fn on_login_account(&mut self, tx: ResponseTx<(), Error>, account_token: String) {
         let account_manager = self.account_manager.clone();
         tokio::spawn(async move {
@@ -1453,6 +1787,44 @@ where
         });
     }
 
+    fn on_get_connection_history(&mut self, tx: oneshot::Sender<Vec<ConnectionHistoryEntry>>) {
+        Self::oneshot_send(
+            tx,
+            self.connection_history.entries(),
+            "get_connection_history response",
+        );
+    }
+
+    async fn on_clear_connection_history(&mut self, tx: ResponseTx<(), Error>) {
+        let result = self
+            .connection_history
+            .clear()
+            .await
+            .map_err(Error::ClearConnectionHistory);
+        Self::oneshot_send(tx, result, "clear_connection_history response");
+    }
+
+    #[cfg(not(target_os = "android"))]
+    fn on_get_dns_blocklist_stats(&mut self, tx: oneshot::Sender<Vec<DnsBlocklistStats>>) {
+        let stats = self
+            .dns_blocklist
+            .as_ref()
+            .map(|blocklist| blocklist.stats())
+            .unwrap_or_default();
+        Self::oneshot_send(tx, stats, "get_dns_blocklist_stats response");
+    }
+
+    fn on_get_tunnel_stats(&mut self, tx: oneshot::Sender<Option<TunnelStats>>) {
+        let session = self.connected_session.clone();
+        tokio::spawn(async move {
+            let stats = match session {
+                Some(session) => session.tunnel_stats().await,
+                None => None,
+            };
+            Self::oneshot_send(tx, stats, "get_tunnel_stats response");
+        });
+    }
+
     fn on_get_account_history(&mut self, tx: oneshot::Sender<Option<AccountToken>>) {
         Self::oneshot_send(
             tx,
@@ -1588,6 +1960,36 @@ where
         Self::oneshot_send(tx, result, "clear_split_tunnel_processes response");
     }
 
+    #[cfg(target_os = "linux")]
+    async fn on_set_split_tunnel_excluded_users(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        users: ExcludedUsers,
+    ) {
+        let new_users = users.clone();
+        match self
+            .settings
+            .update(move |settings| settings.split_tunnel.excluded_users = new_users)
+            .await
+        {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_split_tunnel_excluded_users response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.send_tunnel_command(TunnelCommand::SetExcludedUsers(
+                        users.uids.into_iter().collect(),
+                        users.gids.into_iter().collect(),
+                    ));
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_split_tunnel_excluded_users response");
+            }
+        }
+    }
+
     /// Update the split app paths in both the settings and tunnel
     #[cfg(windows)]
     async fn set_split_tunnel_paths(
@@ -1655,7 +2057,52 @@ where
         }
     }
 
-    #[cfg(windows)]
+    /// Update the split app paths or state in the settings, and exclude matching processes
+    #[cfg(target_os = "linux")]
+    async fn set_split_tunnel_paths(
+        &mut self,
+        tx: ResponseTx<(), Error>,
+        response_msg: &'static str,
+        _settings: Settings,
+        update: ExcludedPathsUpdate,
+    ) {
+        let save_result = self
+            .settings
+            .update(move |settings| match update {
+                ExcludedPathsUpdate::SetState(state) => {
+                    settings.split_tunnel.enable_exclusions = state
+                }
+                ExcludedPathsUpdate::SetPaths(paths) => settings.split_tunnel.apps = paths,
+            })
+            .await;
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), response_msg);
+                if settings_changed {
+                    let settings = self.settings.to_settings();
+                    self.exclude_apps
+                        .set_paths(Self::active_split_tunnel_apps(&settings));
+                    self.event_listener.notify_settings(settings);
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(Error::SettingsError(e)), response_msg);
+            }
+        }
+    }
+
+    /// Returns the executables whose processes should be excluded from the tunnel
+    #[cfg(target_os = "linux")]
+    fn active_split_tunnel_apps(settings: &Settings) -> HashSet<PathBuf> {
+        if settings.split_tunnel.enable_exclusions {
+            settings.split_tunnel.apps.clone()
+        } else {
+            HashSet::new()
+        }
+    }
+
+    #[cfg(any(windows, target_os = "linux"))]
     async fn on_add_split_tunnel_app(&mut self, tx: ResponseTx<(), Error>, path: PathBuf) {
         let settings = self.settings.to_settings();
 
@@ -1671,7 +2118,7 @@ where
         .await;
     }
 
-    #[cfg(windows)]
+    #[cfg(any(windows, target_os = "linux"))]
     async fn on_remove_split_tunnel_app(&mut self, tx: ResponseTx<(), Error>, path: PathBuf) {
         let settings = self.settings.to_settings();
 
@@ -1687,7 +2134,7 @@ where
         .await;
     }
 
-    #[cfg(windows)]
+    #[cfg(any(windows, target_os = "linux"))]
     async fn on_clear_split_tunnel_apps(&mut self, tx: ResponseTx<(), Error>) {
         let settings = self.settings.to_settings();
         let new_list = HashSet::new();
@@ -1700,7 +2147,7 @@ where
         .await;
     }
 
-    #[cfg(windows)]
+    #[cfg(any(windows, target_os = "linux"))]
     async fn on_set_split_tunnel_state(&mut self, tx: ResponseTx<(), Error>, state: bool) {
         let settings = self.settings.to_settings();
         self.set_split_tunnel_paths(
@@ -1796,6 +2243,84 @@ where
         }
     }
 
+    async fn on_create_custom_list(&mut self, tx: ResponseTx<(), Error>, name: CustomListName) {
+        let result = self
+            .update_custom_lists(move |lists| lists.create(name))
+            .await;
+        Self::oneshot_send(tx, result, "create_custom_list response");
+    }
+
+    async fn on_delete_custom_list(&mut self, tx: ResponseTx<(), Error>, name: CustomListName) {
+        let result = if self.is_custom_list_in_use(&name) {
+            Err(Error::CustomListError(custom_list::Error::ListInUse(name)))
+        } else {
+            self.update_custom_lists(move |lists| lists.delete(&name))
+                .await
+        };
+        Self::oneshot_send(tx, result, "delete_custom_list response");
+    }
+
+    async fn on_update_custom_list(&mut self, tx: ResponseTx<(), Error>, new_list: CustomList) {
+        let name = new_list.name.clone();
+        let result = self
+            .update_custom_lists(move |lists| lists.update(new_list))
+            .await;
+        if result.is_ok() && self.is_custom_list_in_use(&name) {
+            log::info!("Initiating tunnel restart because a selected custom list changed");
+            self.reconnect_tunnel();
+        }
+        Self::oneshot_send(tx, result, "update_custom_list response");
+    }
+
+    /// Applies `update_fn` to a copy of the custom lists and saves the result if it succeeds.
+    async fn update_custom_lists(
+        &mut self,
+        update_fn: impl FnOnce(&mut CustomListsSettings) -> Result<(), custom_list::Error>,
+    ) -> Result<(), Error> {
+        let mut custom_lists = self.settings.custom_lists.clone();
+        update_fn(&mut custom_lists).map_err(Error::CustomListError)?;
+
+        match self
+            .settings
+            .update(move |settings| settings.custom_lists = custom_lists)
+            .await
+        {
+            Ok(settings_changed) => {
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.relay_selector
+                        .set_config(new_selector_config(&self.settings, &self.app_version_info));
+                }
+                Ok(())
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Err(Error::SettingsError(e))
+            }
+        }
+    }
+
+    /// Returns whether the relay or bridge constraints refer to the custom list `name`.
+    fn is_custom_list_in_use(&self, name: &str) -> bool {
+        let is_list = |location: &Constraint<LocationConstraint>| match location {
+            Constraint::Only(LocationConstraint::CustomList(list)) => list == name,
+            _ => false,
+        };
+        let relay_uses_list = match self.settings.get_relay_settings() {
+            RelaySettings::Normal(constraints) => {
+                is_list(&constraints.location)
+                    || is_list(&constraints.wireguard_constraints.entry_location)
+            }
+            RelaySettings::CustomTunnelEndpoint(_) => false,
+        };
+        let bridge_uses_list = match &self.settings.bridge_settings {
+            BridgeSettings::Normal(constraints) => is_list(&constraints.location),
+            BridgeSettings::Custom(_) => false,
+        };
+        relay_uses_list || bridge_uses_list
+    }
+
     async fn on_set_allow_lan(&mut self, tx: ResponseTx<(), settings::Error>, allow_lan: bool) {
         match self
             .settings
@@ -1807,7 +2332,10 @@ where
                 if settings_changed {
                     self.event_listener
                         .notify_settings(self.settings.to_settings());
-                    self.send_tunnel_command(TunnelCommand::AllowLan(allow_lan));
+                    self.send_tunnel_command(TunnelCommand::AllowLan(
+                        allow_lan,
+                        self.settings.lan_networks.clone(),
+                    ));
                 }
             }
             Err(e) => {
@@ -1817,6 +2345,74 @@ where
         }
     }
 
+    async fn on_set_lan_networks(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        lan_networks: Vec<IpNetwork>,
+    ) {
+        let tunnel_addresses = self.tunnel_addresses().await;
+        let lan_networks = match lan_networks
+            .into_iter()
+            .map(|network| validate_lan_network(network, &tunnel_addresses))
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(lan_networks) => lan_networks,
+            Err(error) => {
+                log::error!("{}", error.display_chain_with_msg("Invalid LAN network"));
+                Self::oneshot_send(
+                    tx,
+                    Err(settings::Error::InvalidLanNetwork(error)),
+                    "set_lan_networks response",
+                );
+                return;
+            }
+        };
+        match self
+            .settings
+            .update(move |settings| settings.lan_networks = lan_networks)
+            .await
+        {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_lan_networks response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.send_tunnel_command(TunnelCommand::AllowLan(
+                        self.settings.allow_lan,
+                        self.settings.lan_networks.clone(),
+                    ));
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_lan_networks response");
+            }
+        }
+    }
+
+    /// Returns the addresses that must only be reached through the tunnel: the endpoints of the
+    /// current relay and the addresses of the tunnel interface.
+    async fn tunnel_addresses(&self) -> Vec<IpAddr> {
+        let mut addresses = vec![];
+        if let TunnelState::Connecting { endpoint, .. } | TunnelState::Connected { endpoint, .. } =
+            &self.tunnel_state
+        {
+            addresses.push(endpoint.endpoint.address.ip());
+            addresses.extend(
+                endpoint
+                    .entry_endpoint
+                    .as_ref()
+                    .map(|entry| entry.address.ip()),
+            );
+        }
+        if let Ok(Some(device)) = self.account_manager.data().await.map(|s| s.into_device()) {
+            let wg_addresses = &device.device.wg_data.addresses;
+            addresses.push(wg_addresses.ipv4_address.ip().into());
+            addresses.push(wg_addresses.ipv6_address.ip().into());
+        }
+        addresses
+    }
+
     async fn on_set_show_beta_releases(
         &mut self,
         tx: ResponseTx<(), settings::Error>,
@@ -1843,6 +2439,39 @@ where
         }
     }
 
+    async fn on_set_record_connection_history(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        enabled: bool,
+    ) {
+        match self
+            .settings
+            .update(move |settings| settings.record_connection_history = enabled)
+            .await
+        {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_record_connection_history response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    if !enabled {
+                        self.clear_connection_history().await;
+                    }
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_record_connection_history response");
+            }
+        }
+    }
+
+    async fn clear_connection_history(&mut self) {
+        if let Err(error) = self.connection_history.clear().await {
+            log::error!("{}", error.display_chain());
+        }
+    }
+
     async fn on_set_block_when_disconnected(
         &mut self,
         tx: ResponseTx<(), settings::Error>,
@@ -1859,7 +2488,7 @@ where
                     self.event_listener
                         .notify_settings(self.settings.to_settings());
                     self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(
-                        block_when_disconnected,
+                        self.block_when_disconnected(),
                     ));
                 }
             }
@@ -1870,6 +2499,62 @@ where
         }
     }
 
+    #[cfg(not(target_os = "android"))]
+    async fn on_set_firewall_allow_list(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        allow_list: Vec<AllowListEndpoint>,
+    ) {
+        match self
+            .settings
+            .update(move |settings| settings.firewall_allow_list = allow_list)
+            .await
+        {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_firewall_allow_list response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.send_tunnel_command(TunnelCommand::AllowList(
+                        self.settings.firewall_allow_list.clone(),
+                    ));
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_firewall_allow_list response");
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn on_set_firewall_inbound_rules(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        inbound_rules: Vec<InboundRule>,
+    ) {
+        match self
+            .settings
+            .update(move |settings| settings.firewall_inbound_rules = inbound_rules)
+            .await
+        {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_firewall_inbound_rules response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.send_tunnel_command(TunnelCommand::InboundRules(
+                        self.settings.firewall_inbound_rules.clone(),
+                    ));
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_firewall_inbound_rules response");
+            }
+        }
+    }
+
     async fn on_set_auto_connect(
         &mut self,
         tx: ResponseTx<(), settings::Error>,
@@ -1894,6 +2579,96 @@ where
         }
     }
 
+    #[cfg(target_os = "linux")]
+    async fn on_set_network_rules(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        network_rules: NetworkRulesSettings,
+    ) {
+        match self
+            .settings
+            .update(move |settings| settings.network_rules = network_rules)
+            .await
+        {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_network_rules response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.apply_network_rules().await;
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_network_rules response");
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn on_get_current_network(&self, tx: oneshot::Sender<Option<NetworkInfo>>) {
+        Self::oneshot_send(tx, self.current_network.clone(), "current network");
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn handle_network_change(&mut self, network: Option<NetworkInfo>) {
+        if network == self.current_network {
+            return;
+        }
+        match &network {
+            Some(network) => log::info!("Joined network \"{}\"", network.connection_name),
+            None => log::info!("Not connected to any network"),
+        }
+        self.current_network = network;
+        self.apply_network_rules().await;
+    }
+
+    /// Connects or disconnects the tunnel depending on whether the network rules consider the
+    /// current network trusted. Does nothing if no rule applies to the network.
+    #[cfg(target_os = "linux")]
+    async fn apply_network_rules(&mut self) {
+        if !self.state.is_running() {
+            return;
+        }
+
+        let trust = self
+            .current_network
+            .as_ref()
+            .and_then(|network| self.settings.network_rules.evaluate(network));
+
+        let on_trusted_network = trust == Some(NetworkTrust::Trusted);
+        if on_trusted_network != self.on_trusted_network {
+            self.on_trusted_network = on_trusted_network;
+            if self.settings.block_when_disconnected {
+                self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(
+                    self.block_when_disconnected(),
+                ));
+            }
+        }
+
+        match trust {
+            Some(NetworkTrust::Trusted) => {
+                log::info!("Disconnecting since the current network is trusted");
+                self.set_target_state(TargetState::Unsecured).await;
+            }
+            Some(NetworkTrust::Untrusted) => {
+                log::info!("Connecting since the current network is untrusted");
+                self.set_target_state(TargetState::Secured).await;
+            }
+            None => (),
+        }
+    }
+
+    /// Returns whether traffic should be blocked while disconnected. This is the
+    /// `block_when_disconnected` setting, except on trusted networks.
+    fn block_when_disconnected(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        if self.on_trusted_network {
+            return false;
+        }
+        self.settings.block_when_disconnected
+    }
+
     async fn on_set_openvpn_mssfix(
         &mut self,
         tx: ResponseTx<(), settings::Error>,
@@ -2023,6 +2798,79 @@ where
         Self::oneshot_send(tx, result, "on_set_bridge_state response");
     }
 
+    async fn on_set_relay_selection_strategy(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        strategy: RelaySelectionStrategy,
+    ) {
+        let result = match self
+            .settings
+            .update(move |settings| settings.relay_selection_strategy = strategy)
+            .await
+        {
+            Ok(settings_changed) => {
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.relay_selector
+                        .set_config(new_selector_config(&self.settings, &self.app_version_info));
+                    if self.tunnel_state.is_disconnected() {
+                        self.update_relay_latencies();
+                    }
+                }
+                Ok(())
+            }
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to set relay selection strategy")
+                );
+                Err(error)
+            }
+        };
+        Self::oneshot_send(tx, result, "on_set_relay_selection_strategy response");
+    }
+
+    async fn on_set_relay_rotation(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        rotation: Option<RelayRotation>,
+    ) {
+        let result = match self
+            .settings
+            .update(move |settings| settings.relay_rotation = rotation)
+            .await
+        {
+            Ok(settings_changed) => {
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    if self.tunnel_state.is_connected() {
+                        self.schedule_relay_rotation();
+                    }
+                }
+                Ok(())
+            }
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to set relay rotation")
+                );
+                Err(error)
+            }
+        };
+        Self::oneshot_send(tx, result, "on_set_relay_rotation response");
+    }
+
+    /// Measure the latency to relays in the background, if the relay selector needs it.
+    fn update_relay_latencies(&self) {
+        // The firewall would block all probes.
+        if self.block_when_disconnected() {
+            return;
+        }
+        tokio::spawn(self.relay_selector.update_latencies());
+    }
+
     async fn on_set_enable_ipv6(&mut self, tx: ResponseTx<(), settings::Error>, enable_ipv6: bool) {
         match self
             .settings
@@ -2086,6 +2934,9 @@ where
         tx: ResponseTx<(), settings::Error>,
         dns_options: DnsOptions,
     ) {
+        #[cfg(not(target_os = "android"))]
+        let blocklist_changed = self.settings.tunnel_options.dns_options.blocklist_options
+            != dns_options.blocklist_options;
         match self
             .settings
             .update(move |settings| settings.tunnel_options.dns_options = dns_options)
@@ -2097,11 +2948,25 @@ where
                     let settings = self.settings.to_settings();
                     let resolvers =
                         dns::addresses_from_options(&settings.tunnel_options.dns_options);
+                    #[cfg(not(target_os = "android"))]
+                    let encrypted_resolvers =
+                        dns::encrypted_servers_from_options(&settings.tunnel_options.dns_options);
+                    #[cfg(not(target_os = "android"))]
+                    let split_dns_rules =
+                        settings.tunnel_options.dns_options.split_dns_rules.clone();
                     self.parameters_generator
                         .set_tunnel_options(&settings.tunnel_options)
                         .await;
                     self.event_listener.notify_settings(settings);
                     self.send_tunnel_command(TunnelCommand::Dns(resolvers));
+                    #[cfg(not(target_os = "android"))]
+                    self.send_tunnel_command(TunnelCommand::EncryptedDns(encrypted_resolvers));
+                    #[cfg(not(target_os = "android"))]
+                    self.send_tunnel_command(TunnelCommand::SplitDns(split_dns_rules));
+                    #[cfg(not(target_os = "android"))]
+                    if blocklist_changed {
+                        self.reload_dns_blocklist().await;
+                    }
                 }
             }
             Err(e) => {
@@ -2111,6 +2976,14 @@ where
         }
     }
 
+    /// Reloads the local DNS blocklists from disk and passes them on to the tunnel state machine.
+    #[cfg(not(target_os = "android"))]
+    async fn reload_dns_blocklist(&mut self) {
+        self.dns_blocklist =
+            dns::load_blocklist(&self.settings.tunnel_options.dns_options.blocklist_options).await;
+        self.send_tunnel_command(TunnelCommand::DnsBlocklist(self.dns_blocklist.clone()));
+    }
+
     async fn on_set_wireguard_mtu(
         &mut self,
         tx: ResponseTx<(), settings::Error>,
@@ -2203,10 +3076,176 @@ where
         Self::oneshot_send(tx, result, "get_wireguard_key response");
     }
 
+    async fn on_export_wireguard_config(&self, tx: ResponseTx<String, Error>) {
+        let result = self.export_wireguard_config().await;
+        Self::oneshot_send(tx, result, "export_wireguard_config response");
+    }
+
+    async fn export_wireguard_config(&self) -> Result<String, Error> {
+        let parameters = match self.get_connected_tunnel_type() {
+            Some(TunnelType::Wireguard) => {
+                self.parameters_generator
+                    .get_last_wireguard_parameters()
+                    .await
+            }
+            _ => None,
+        }
+        .ok_or(Error::ExportWireguardConfig(
+            "Not connected to a WireGuard relay",
+        ))?;
+
+        if parameters.connection.exit_peer.is_some() {
+            return Err(Error::ExportWireguardConfig(
+                "Multihop tunnels cannot be exported",
+            ));
+        }
+        if parameters.obfuscation.is_some() {
+            return Err(Error::ExportWireguardConfig(
+                "Obfuscated tunnels cannot be exported",
+            ));
+        }
+        if parameters.options.quantum_resistant {
+            return Err(Error::ExportWireguardConfig(
+                "Quantum-resistant tunnels cannot be exported",
+            ));
+        }
+
+        let mut config = WgQuickConfig::from_connection_config(&parameters.connection);
+        config.dns_servers = dns::addresses_from_options(&self.settings.tunnel_options.dns_options)
+            .unwrap_or_else(|| vec![parameters.connection.ipv4_gateway.into()]);
+        config.mtu = parameters.options.mtu;
+        Ok(config.to_string())
+    }
+
     fn on_get_settings(&self, tx: oneshot::Sender<Settings>) {
         Self::oneshot_send(tx, self.settings.to_settings(), "get_settings response");
     }
 
+    fn on_export_settings(&self, tx: ResponseTx<String, settings::Error>) {
+        Self::oneshot_send(tx, self.settings.export(), "export_settings response");
+    }
+
+    async fn on_import_settings(&mut self, tx: ResponseTx<(), settings::Error>, settings: String) {
+        match self.settings.import(&settings).await {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "import_settings response");
+                if settings_changed {
+                    log::info!("Applying imported settings");
+                    self.apply_all_settings().await;
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to import settings"));
+                Self::oneshot_send(tx, Err(e), "import_settings response");
+            }
+        }
+    }
+
+    /// Passes all settings on to the components that use them, and reconnects the tunnel. This
+    /// is used when the settings are replaced as a whole rather than one at a time.
+    async fn apply_all_settings(&mut self) {
+        let settings = self.settings.to_settings();
+
+        self.parameters_generator
+            .set_tunnel_options(&settings.tunnel_options)
+            .await;
+        self.relay_selector
+            .set_config(new_selector_config(&self.settings, &self.app_version_info));
+        let mut handle = self.version_updater_handle.clone();
+        handle
+            .set_show_beta_releases(settings.show_beta_releases)
+            .await;
+        if !settings.record_connection_history {
+            self.clear_connection_history().await;
+        }
+        if let Err(error) = self
+            .account_manager
+            .set_rotation_interval(
+                settings
+                    .tunnel_options
+                    .wireguard
+                    .rotation_interval
+                    .unwrap_or_default(),
+            )
+            .await
+        {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to update rotation interval")
+            );
+        }
+
+        self.send_tunnel_command(TunnelCommand::AllowLan(
+            settings.allow_lan,
+            settings.lan_networks.clone(),
+        ));
+        #[cfg(not(target_os = "android"))]
+        self.send_tunnel_command(TunnelCommand::AllowList(
+            settings.firewall_allow_list.clone(),
+        ));
+        #[cfg(target_os = "linux")]
+        self.send_tunnel_command(TunnelCommand::InboundRules(
+            settings.firewall_inbound_rules.clone(),
+        ));
+        self.send_tunnel_command(TunnelCommand::Dns(dns::addresses_from_options(
+            &settings.tunnel_options.dns_options,
+        )));
+        #[cfg(not(target_os = "android"))]
+        self.send_tunnel_command(TunnelCommand::EncryptedDns(
+            dns::encrypted_servers_from_options(&settings.tunnel_options.dns_options),
+        ));
+        #[cfg(not(target_os = "android"))]
+        self.send_tunnel_command(TunnelCommand::SplitDns(
+            settings.tunnel_options.dns_options.split_dns_rules.clone(),
+        ));
+        #[cfg(not(target_os = "android"))]
+        self.reload_dns_blocklist().await;
+        #[cfg(target_os = "linux")]
+        {
+            let users = &settings.split_tunnel.excluded_users;
+            self.send_tunnel_command(TunnelCommand::SetExcludedUsers(
+                users.uids.iter().copied().collect(),
+                users.gids.iter().copied().collect(),
+            ));
+            self.exclude_apps
+                .set_paths(Self::active_split_tunnel_apps(&settings));
+        }
+        #[cfg(windows)]
+        {
+            let excluded_apps = if settings.split_tunnel.enable_exclusions {
+                settings
+                    .split_tunnel
+                    .apps
+                    .iter()
+                    .map(OsString::from)
+                    .collect()
+            } else {
+                vec![]
+            };
+            let (result_tx, result_rx) = oneshot::channel();
+            self.send_tunnel_command(TunnelCommand::SetExcludedApps(result_tx, excluded_apps));
+            tokio::spawn(async move {
+                if let Ok(Err(error)) = result_rx.await {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to set excluded apps list")
+                    );
+                }
+            });
+        }
+
+        self.event_listener.notify_settings(settings);
+
+        #[cfg(target_os = "linux")]
+        self.apply_network_rules().await;
+        self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(
+            self.block_when_disconnected(),
+        ));
+
+        log::info!("Initiating tunnel restart because the settings were replaced");
+        self.reconnect_tunnel();
+    }
+
     fn oneshot_send<T>(tx: oneshot::Sender<T>, t: T, msg: &'static str) {
         if tx.send(t).is_err() {
             log::warn!("Unable to send {} to the daemon command sender", msg);
@@ -2366,6 +3405,8 @@ fn new_selector_config(
         bridge_state: settings.bridge_state,
         bridge_settings: settings.bridge_settings.clone(),
         obfuscation_settings: settings.obfuscation_settings.clone(),
+        custom_lists: settings.custom_lists.clone(),
+        selection_strategy: settings.relay_selection_strategy,
         default_tunnel_type,
     }
 }
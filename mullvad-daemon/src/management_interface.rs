@@ -3,32 +3,45 @@ use futures::{
     channel::{mpsc, oneshot},
     StreamExt,
 };
+use ipnetwork::IpNetwork;
 use mullvad_api::{rest::Error as RestError, StatusCode};
 use mullvad_management_interface::{
     types::{self, daemon_event, management_service_server::ManagementService},
     Code, Request, Response, Status,
 };
 use mullvad_paths;
+#[cfg(target_os = "linux")]
+use mullvad_types::network_rules::NetworkRulesSettings;
 #[cfg(not(target_os = "android"))]
 use mullvad_types::settings::DnsOptions;
 use mullvad_types::{
     account::AccountToken,
-    relay_constraints::{BridgeSettings, BridgeState, ObfuscationSettings, RelaySettingsUpdate},
+    custom_list::{self, CustomList},
+    relay_constraints::{
+        BridgeSettings, BridgeState, ObfuscationSettings, RelaySelectionStrategy,
+        RelaySettingsUpdate,
+    },
     relay_list::RelayList,
+    relay_rotation::RelayRotation,
     settings::Settings,
     states::{TargetState, TunnelState},
+    tunnel_stats::TunnelStats,
     version,
     wireguard::{RotationInterval, RotationIntervalError},
 };
-use parking_lot::RwLock;
-#[cfg(windows)]
+use parking_lot::{Mutex, RwLock};
+#[cfg(any(windows, target_os = "linux"))]
 use std::path::PathBuf;
 use std::{
     convert::{TryFrom, TryInto},
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
-use talpid_types::ErrorExt;
+#[cfg(not(target_os = "android"))]
+use talpid_types::net::AllowListEndpoint;
+#[cfg(target_os = "linux")]
+use talpid_types::net::InboundRule;
+use talpid_types::{net::validate_lan_network, ErrorExt};
 use tokio_stream::wrappers::UnboundedReceiverStream;
 
 #[derive(err_derive::Error, Debug)]
@@ -41,12 +54,24 @@ pub enum Error {
 
 struct ManagementServiceImpl {
     daemon_tx: DaemonCommandSender,
-    subscriptions: Arc<RwLock<Vec<EventsListenerSender>>>,
+    subscriptions: Arc<RwLock<Vec<EventsSubscriber>>>,
+    tunnel_stats_subscriptions: Arc<Mutex<Vec<TunnelStatsListenerSender>>>,
+}
+
+struct EventsSubscriber {
+    tx: EventsListenerSender,
+    /// Unprivileged subscribers are not sent secrets.
+    privileged: bool,
 }
 
 pub type ServiceResult<T> = std::result::Result<Response<T>, Status>;
 type EventsListenerReceiver = UnboundedReceiverStream<Result<types::DaemonEvent, Status>>;
 type EventsListenerSender = tokio::sync::mpsc::UnboundedSender<Result<types::DaemonEvent, Status>>;
+type TunnelStatsListenerSender =
+    tokio::sync::mpsc::UnboundedSender<Result<types::TunnelStats, Status>>;
+
+/// How often to emit tunnel stats to clients that listen for them.
+const TUNNEL_STATS_INTERVAL: Duration = Duration::from_secs(1);
 
 const INVALID_VOUCHER_MESSAGE: &str = "This voucher code is invalid";
 const USED_VOUCHER_MESSAGE: &str = "This voucher code has already been used";
@@ -55,6 +80,7 @@ const USED_VOUCHER_MESSAGE: &str = "This voucher code has already been used";
 impl ManagementService for ManagementServiceImpl {
     type GetSplitTunnelProcessesStream = UnboundedReceiverStream<Result<i32, Status>>;
     type EventsListenStream = EventsListenerReceiver;
+    type TunnelStatsListenStream = UnboundedReceiverStream<Result<types::TunnelStats, Status>>;
 
     // Control and get the tunnel state
     //
@@ -93,14 +119,64 @@ impl ManagementService for ManagementServiceImpl {
         Ok(Response::new(types::TunnelState::from(state)))
     }
 
+    async fn get_connection_history(
+        &self,
+        _: Request<()>,
+    ) -> ServiceResult<types::ConnectionHistory> {
+        log::debug!("get_connection_history");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetConnectionHistory(tx))?;
+        let entries = self.wait_for_result(rx).await?;
+        Ok(Response::new(types::ConnectionHistory {
+            entries: entries
+                .into_iter()
+                .map(types::ConnectionHistoryEntry::from)
+                .collect(),
+        }))
+    }
+
+    async fn clear_connection_history(&self, _: Request<()>) -> ServiceResult<()> {
+        log::debug!("clear_connection_history");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::ClearConnectionHistory(tx))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn tunnel_stats_listen(
+        &self,
+        _: Request<()>,
+    ) -> ServiceResult<Self::TunnelStatsListenStream> {
+        log::debug!("tunnel_stats_listen");
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+
+        // All listeners share a single poller, which is started by the first listener and stops
+        // once the last one has gone away.
+        let mut subscriptions = self.tunnel_stats_subscriptions.lock();
+        if subscriptions.is_empty() {
+            tokio::spawn(poll_tunnel_stats(
+                self.daemon_tx.clone(),
+                self.tunnel_stats_subscriptions.clone(),
+            ));
+        }
+        subscriptions.push(tx);
+
+        Ok(Response::new(UnboundedReceiverStream::new(rx)))
+    }
+
     // Control the daemon and receive events
     //
 
-    async fn events_listen(&self, _: Request<()>) -> ServiceResult<Self::EventsListenStream> {
+    async fn events_listen(&self, request: Request<()>) -> ServiceResult<Self::EventsListenStream> {
         let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
 
         let mut subscriptions = self.subscriptions.write();
-        subscriptions.push(tx);
+        subscriptions.push(EventsSubscriber {
+            tx,
+            privileged: mullvad_management_interface::is_privileged(&request),
+        });
 
         Ok(Response::new(UnboundedReceiverStream::new(rx)))
     }
@@ -191,6 +267,22 @@ impl ManagementService for ManagementServiceImpl {
             .map(|relays| Response::new(types::RelayList::from(relays)))
     }
 
+    async fn verify_relay_lists(
+        &self,
+        _: Request<()>,
+    ) -> ServiceResult<types::RelayListVerifications> {
+        log::debug!("verify_relay_lists");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::VerifyRelayLists(tx))?;
+        let lists = self.wait_for_result(rx).await?;
+        Ok(Response::new(types::RelayListVerifications {
+            lists: lists
+                .into_iter()
+                .map(types::RelayListVerification::from)
+                .collect(),
+        }))
+    }
+
     async fn get_current_location(&self, _: Request<()>) -> ServiceResult<types::GeoIpLocation> {
         log::debug!("get_current_location");
         let (tx, rx) = oneshot::channel();
@@ -234,6 +326,70 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_settings_error)
     }
 
+    async fn set_relay_selection_strategy(
+        &self,
+        request: Request<types::RelaySelectionStrategy>,
+    ) -> ServiceResult<()> {
+        let strategy = RelaySelectionStrategy::try_from(request.into_inner())
+            .map_err(map_protobuf_type_err)?;
+        log::debug!("set_relay_selection_strategy({:?})", strategy);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetRelaySelectionStrategy(tx, strategy))?;
+        let settings_result = self.wait_for_result(rx).await?;
+        settings_result
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
+    async fn set_relay_rotation(
+        &self,
+        request: Request<types::RelayRotation>,
+    ) -> ServiceResult<()> {
+        let rotation = Option::<RelayRotation>::try_from(request.into_inner())
+            .map_err(map_protobuf_type_err)?;
+        log::debug!("set_relay_rotation({:?})", rotation);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetRelayRotation(tx, rotation))?;
+        let settings_result = self.wait_for_result(rx).await?;
+        settings_result
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
+    async fn create_custom_list(&self, request: Request<String>) -> ServiceResult<()> {
+        let name = request.into_inner();
+        log::debug!("create_custom_list({})", name);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::CreateCustomList(tx, name))?;
+        self.wait_for_result(rx)
+            .await?
+            .map_err(map_daemon_error)
+            .map(Response::new)
+    }
+
+    async fn delete_custom_list(&self, request: Request<String>) -> ServiceResult<()> {
+        let name = request.into_inner();
+        log::debug!("delete_custom_list({})", name);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::DeleteCustomList(tx, name))?;
+        self.wait_for_result(rx)
+            .await?
+            .map_err(map_daemon_error)
+            .map(Response::new)
+    }
+
+    async fn update_custom_list(&self, request: Request<types::CustomList>) -> ServiceResult<()> {
+        let custom_list =
+            CustomList::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
+        log::debug!("update_custom_list({:?})", custom_list);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::UpdateCustomList(tx, custom_list))?;
+        self.wait_for_result(rx)
+            .await?
+            .map_err(map_daemon_error)
+            .map(Response::new)
+    }
+
     async fn set_bridge_state(&self, request: Request<types::BridgeState>) -> ServiceResult<()> {
         let bridge_state =
             BridgeState::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
@@ -250,13 +406,38 @@ impl ManagementService for ManagementServiceImpl {
     // Settings
     //
 
-    async fn get_settings(&self, _: Request<()>) -> ServiceResult<types::Settings> {
+    async fn get_settings(&self, request: Request<()>) -> ServiceResult<types::Settings> {
         log::debug!("get_settings");
+        let privileged = mullvad_management_interface::is_privileged(&request);
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::GetSettings(tx))?;
+        self.wait_for_result(rx).await.map(|settings| {
+            let mut settings = types::Settings::from(&settings);
+            if !privileged {
+                settings.redact_secrets();
+            }
+            Response::new(settings)
+        })
+    }
+
+    async fn export_settings(&self, _: Request<()>) -> ServiceResult<String> {
+        log::debug!("export_settings");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::ExportSettings(tx))?;
         self.wait_for_result(rx)
-            .await
-            .map(|settings| Response::new(types::Settings::from(&settings)))
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
+    async fn import_settings(&self, request: Request<String>) -> ServiceResult<()> {
+        log::debug!("import_settings");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::ImportSettings(tx, request.into_inner()))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
     }
 
     async fn set_allow_lan(&self, request: Request<bool>) -> ServiceResult<()> {
@@ -270,6 +451,28 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_settings_error)
     }
 
+    async fn set_lan_networks(&self, request: Request<types::LanNetworks>) -> ServiceResult<()> {
+        let lan_networks = request
+            .into_inner()
+            .networks
+            .iter()
+            .map(|network| {
+                let network = network
+                    .parse::<IpNetwork>()
+                    .map_err(|error| Status::invalid_argument(error.to_string()))?;
+                validate_lan_network(network, &[])
+                    .map_err(|error| Status::invalid_argument(error.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        log::debug!("set_lan_networks({:?})", lan_networks);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetLanNetworks(tx, lan_networks))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
     async fn set_show_beta_releases(&self, request: Request<bool>) -> ServiceResult<()> {
         let enabled = request.into_inner();
         log::debug!("set_show_beta_releases({})", enabled);
@@ -281,6 +484,17 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_settings_error)
     }
 
+    async fn set_record_connection_history(&self, request: Request<bool>) -> ServiceResult<()> {
+        let enabled = request.into_inner();
+        log::debug!("set_record_connection_history({})", enabled);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetRecordConnectionHistory(tx, enabled))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
     async fn set_block_when_disconnected(&self, request: Request<bool>) -> ServiceResult<()> {
         let block_when_disconnected = request.into_inner();
         log::debug!("set_block_when_disconnected({})", block_when_disconnected);
@@ -295,6 +509,62 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_settings_error)
     }
 
+    #[cfg(not(target_os = "android"))]
+    async fn set_firewall_allow_list(
+        &self,
+        request: Request<types::FirewallAllowList>,
+    ) -> ServiceResult<()> {
+        let allow_list = request
+            .into_inner()
+            .endpoints
+            .into_iter()
+            .map(AllowListEndpoint::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(map_protobuf_type_err)?;
+        log::debug!("set_firewall_allow_list({:?})", allow_list);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetFirewallAllowList(tx, allow_list))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+    #[cfg(target_os = "android")]
+    async fn set_firewall_allow_list(
+        &self,
+        _: Request<types::FirewallAllowList>,
+    ) -> ServiceResult<()> {
+        Ok(Response::new(()))
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn set_firewall_inbound_rules(
+        &self,
+        request: Request<types::FirewallInboundRules>,
+    ) -> ServiceResult<()> {
+        let inbound_rules = request
+            .into_inner()
+            .rules
+            .into_iter()
+            .map(InboundRule::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(map_protobuf_type_err)?;
+        log::debug!("set_firewall_inbound_rules({:?})", inbound_rules);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetFirewallInboundRules(tx, inbound_rules))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+    #[cfg(not(target_os = "linux"))]
+    async fn set_firewall_inbound_rules(
+        &self,
+        _: Request<types::FirewallInboundRules>,
+    ) -> ServiceResult<()> {
+        Ok(Response::new(()))
+    }
+
     async fn set_auto_connect(&self, request: Request<bool>) -> ServiceResult<()> {
         let auto_connect = request.into_inner();
         log::debug!("set_auto_connect({})", auto_connect);
@@ -379,6 +649,68 @@ impl ManagementService for ManagementServiceImpl {
         Ok(Response::new(()))
     }
 
+    #[cfg(not(target_os = "android"))]
+    async fn get_dns_blocklist_stats(
+        &self,
+        _: Request<()>,
+    ) -> ServiceResult<types::DnsBlocklistStatsList> {
+        log::debug!("get_dns_blocklist_stats");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetDnsBlocklistStats(tx))?;
+        let lists = self.wait_for_result(rx).await?;
+        Ok(Response::new(types::DnsBlocklistStatsList {
+            lists: lists
+                .into_iter()
+                .map(types::DnsBlocklistStats::from)
+                .collect(),
+        }))
+    }
+
+    #[cfg(target_os = "android")]
+    async fn get_dns_blocklist_stats(
+        &self,
+        _: Request<()>,
+    ) -> ServiceResult<types::DnsBlocklistStatsList> {
+        Ok(Response::new(types::DnsBlocklistStatsList {
+            lists: vec![],
+        }))
+    }
+
+    // Network rules
+    //
+
+    #[cfg(target_os = "linux")]
+    async fn set_network_rules(&self, request: Request<types::NetworkRules>) -> ServiceResult<()> {
+        let network_rules =
+            NetworkRulesSettings::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
+        log::debug!("set_network_rules({:?})", network_rules);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetNetworkRules(tx, network_rules))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+    #[cfg(not(target_os = "linux"))]
+    async fn set_network_rules(&self, _: Request<types::NetworkRules>) -> ServiceResult<()> {
+        Ok(Response::new(()))
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn get_current_network(&self, _: Request<()>) -> ServiceResult<types::NetworkInfo> {
+        log::debug!("get_current_network");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetCurrentNetwork(tx))?;
+        match self.wait_for_result(rx).await? {
+            Some(network) => Ok(Response::new(types::NetworkInfo::from(network))),
+            None => Err(Status::not_found("not connected to any network")),
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    async fn get_current_network(&self, _: Request<()>) -> ServiceResult<types::NetworkInfo> {
+        Err(Status::not_found("not connected to any network"))
+    }
+
     // Account management
     //
 
@@ -596,6 +928,16 @@ impl ManagementService for ManagementServiceImpl {
         }
     }
 
+    async fn export_wireguard_config(&self, _: Request<()>) -> ServiceResult<String> {
+        log::debug!("export_wireguard_config");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::ExportWireguardConfig(tx))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
     // Split tunneling
     //
 
@@ -678,7 +1020,29 @@ impl ManagementService for ManagementServiceImpl {
         }
     }
 
-    #[cfg(windows)]
+    #[cfg(target_os = "linux")]
+    async fn set_split_tunnel_excluded_users(
+        &self,
+        request: Request<types::ExcludedUsers>,
+    ) -> ServiceResult<()> {
+        let users = ExcludedUsers::from(request.into_inner());
+        log::debug!("set_split_tunnel_excluded_users({:?})", users);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetSplitTunnelExcludedUsers(tx, users))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+    #[cfg(not(target_os = "linux"))]
+    async fn set_split_tunnel_excluded_users(
+        &self,
+        _: Request<types::ExcludedUsers>,
+    ) -> ServiceResult<()> {
+        Ok(Response::new(()))
+    }
+
+    #[cfg(any(windows, target_os = "linux"))]
     async fn add_split_tunnel_app(&self, request: Request<String>) -> ServiceResult<()> {
         log::debug!("add_split_tunnel_app");
         let path = PathBuf::from(request.into_inner());
@@ -689,12 +1053,12 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_daemon_error)
             .map(Response::new)
     }
-    #[cfg(not(windows))]
+    #[cfg(not(any(windows, target_os = "linux")))]
     async fn add_split_tunnel_app(&self, _: Request<String>) -> ServiceResult<()> {
         Ok(Response::new(()))
     }
 
-    #[cfg(windows)]
+    #[cfg(any(windows, target_os = "linux"))]
     async fn remove_split_tunnel_app(&self, request: Request<String>) -> ServiceResult<()> {
         log::debug!("remove_split_tunnel_app");
         let path = PathBuf::from(request.into_inner());
@@ -705,12 +1069,12 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_daemon_error)
             .map(Response::new)
     }
-    #[cfg(not(windows))]
+    #[cfg(not(any(windows, target_os = "linux")))]
     async fn remove_split_tunnel_app(&self, _: Request<String>) -> ServiceResult<()> {
         Ok(Response::new(()))
     }
 
-    #[cfg(windows)]
+    #[cfg(any(windows, target_os = "linux"))]
     async fn clear_split_tunnel_apps(&self, _: Request<()>) -> ServiceResult<()> {
         log::debug!("clear_split_tunnel_apps");
         let (tx, rx) = oneshot::channel();
@@ -720,12 +1084,12 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_daemon_error)
             .map(Response::new)
     }
-    #[cfg(not(windows))]
+    #[cfg(not(any(windows, target_os = "linux")))]
     async fn clear_split_tunnel_apps(&self, _: Request<()>) -> ServiceResult<()> {
         Ok(Response::new(()))
     }
 
-    #[cfg(windows)]
+    #[cfg(any(windows, target_os = "linux"))]
     async fn set_split_tunnel_state(&self, request: Request<bool>) -> ServiceResult<()> {
         log::debug!("set_split_tunnel_state");
         let enabled = request.into_inner();
@@ -736,7 +1100,7 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_daemon_error)
             .map(Response::new)
     }
-    #[cfg(not(windows))]
+    #[cfg(not(any(windows, target_os = "linux")))]
     async fn set_split_tunnel_state(&self, _: Request<bool>) -> ServiceResult<()> {
         Ok(Response::new(()))
     }
@@ -822,13 +1186,60 @@ impl ManagementServiceImpl {
     }
 }
 
+/// Samples the tunnel stats every [`TUNNEL_STATS_INTERVAL`] and sends them to all listeners.
+/// Returns when there are no listeners left.
+async fn poll_tunnel_stats(
+    daemon_tx: DaemonCommandSender,
+    subscriptions: Arc<Mutex<Vec<TunnelStatsListenerSender>>>,
+) {
+    let mut interval = tokio::time::interval(TUNNEL_STATS_INTERVAL);
+    let mut previous: Option<(Instant, TunnelStats)> = None;
+    loop {
+        interval.tick().await;
+
+        {
+            let mut subscriptions = subscriptions.lock();
+            subscriptions.retain(|tx| !tx.is_closed());
+            if subscriptions.is_empty() {
+                return;
+            }
+        }
+
+        let (result_tx, result_rx) = oneshot::channel();
+        let result = match daemon_tx.send(DaemonCommand::GetTunnelStats(result_tx)) {
+            Ok(()) => result_rx.await.ok(),
+            Err(_) => None,
+        };
+        let mut stats = match result {
+            Some(stats) => stats,
+            None => {
+                // The daemon has shut down, so end all streams
+                subscriptions.lock().clear();
+                return;
+            }
+        };
+
+        let now = Instant::now();
+        if let (Some(stats), Some((sampled_at, previous_stats))) = (&mut stats, &previous) {
+            stats.set_rates(previous_stats, now.duration_since(*sampled_at));
+        }
+        previous = stats.clone().map(|stats| (now, stats));
+
+        let mut subscriptions = subscriptions.lock();
+        subscriptions.retain(|tx| tx.send(Ok(types::TunnelStats::from(stats.clone()))).is_ok());
+        if subscriptions.is_empty() {
+            return;
+        }
+    }
+}
+
 pub struct ManagementInterfaceServer(());
 
 impl ManagementInterfaceServer {
     pub async fn start(
         tunnel_tx: DaemonCommandSender,
     ) -> Result<(String, ManagementInterfaceEventBroadcaster), Error> {
-        let subscriptions = Arc::<RwLock<Vec<EventsListenerSender>>>::default();
+        let subscriptions = Arc::<RwLock<Vec<EventsSubscriber>>>::default();
 
         let socket_path = mullvad_paths::get_rpc_socket_path()
             .to_string_lossy()
@@ -838,6 +1249,7 @@ impl ManagementInterfaceServer {
         let server = ManagementServiceImpl {
             daemon_tx: tunnel_tx,
             subscriptions: subscriptions.clone(),
+            tunnel_stats_subscriptions: Arc::default(),
         };
         let join_handle = mullvad_management_interface::spawn_rpc_server(server, async move {
             server_abort_rx.into_future().await;
@@ -865,7 +1277,7 @@ impl ManagementInterfaceServer {
 /// A handle that allows broadcasting messages to all subscribers of the management interface.
 #[derive(Clone)]
 pub struct ManagementInterfaceEventBroadcaster {
-    subscriptions: Arc<RwLock<Vec<EventsListenerSender>>>,
+    subscriptions: Arc<RwLock<Vec<EventsSubscriber>>>,
     _close_handle: mpsc::Sender<()>,
 }
 
@@ -929,9 +1341,25 @@ impl EventListener for ManagementInterfaceEventBroadcaster {
 
 impl ManagementInterfaceEventBroadcaster {
     fn notify(&self, value: types::DaemonEvent) {
+        let mut redacted_value = None;
         let mut subscriptions = self.subscriptions.write();
         // TODO: using write-lock everywhere. use a mutex instead?
-        subscriptions.retain(|tx| tx.send(Ok(value.clone())).is_ok());
+        subscriptions.retain(|subscriber| {
+            let value = if subscriber.privileged {
+                value.clone()
+            } else {
+                redacted_value
+                    .get_or_insert_with(|| {
+                        let mut value = value.clone();
+                        if let Some(daemon_event::Event::Settings(settings)) = &mut value.event {
+                            settings.redact_secrets();
+                        }
+                        value
+                    })
+                    .clone()
+            };
+            subscriber.tx.send(Ok(value)).is_ok()
+        });
     }
 }
 
@@ -953,6 +1381,8 @@ fn map_daemon_error(error: crate::Error) -> Status {
         #[cfg(windows)]
         DaemonError::SplitTunnelError(error) => map_split_tunnel_error(error),
         DaemonError::AccountHistory(error) => map_account_history_error(error),
+        DaemonError::CustomListError(error) => map_custom_list_error(error),
+        DaemonError::ExportWireguardConfig(_) => Status::failed_precondition(error.to_string()),
         DaemonError::NoAccountToken | DaemonError::NoAccountTokenHistory => {
             Status::unauthenticated(error.to_string())
         }
@@ -999,9 +1429,16 @@ fn map_settings_error(error: settings::Error) -> Status {
         | settings::Error::ReadError(..) => {
             Status::new(Code::FailedPrecondition, error.to_string())
         }
-        settings::Error::SerializeError(..) | settings::Error::ParseError(..) => {
-            Status::new(Code::Internal, error.to_string())
+        settings::Error::SerializeError(..) => Status::new(Code::Internal, error.to_string()),
+        settings::Error::ParseError(..)
+        | settings::Error::MigrationError(..)
+        | settings::Error::InvalidRelayRotation(..)
+        | settings::Error::InvalidLanNetwork(..)
+        | settings::Error::ReadPolicyError(..)
+        | settings::Error::InvalidPolicy(..) => {
+            Status::new(Code::InvalidArgument, error.to_string())
         }
+        settings::Error::LockedByPolicy => Status::new(Code::PermissionDenied, error.to_string()),
     }
 }
 
@@ -1036,6 +1473,18 @@ fn map_account_history_error(error: account_history::Error) -> Status {
     }
 }
 
+/// Converts an instance of [`mullvad_types::custom_list::Error`] into a tonic status.
+fn map_custom_list_error(error: custom_list::Error) -> Status {
+    match error {
+        custom_list::Error::ListExists(..) => Status::already_exists(error.to_string()),
+        custom_list::Error::ListNotFound(..) => Status::not_found(error.to_string()),
+        custom_list::Error::ListInUse(..) => Status::failed_precondition(error.to_string()),
+        custom_list::Error::InvalidName | custom_list::Error::NestedList => {
+            Status::invalid_argument(error.to_string())
+        }
+    }
+}
+
 fn map_protobuf_type_err(err: types::FromProtobufTypeError) -> Status {
     match err {
         types::FromProtobufTypeError::InvalidArgument(err) => Status::invalid_argument(err),
@@ -0,0 +1,438 @@
+//! Opt-in HTTP endpoint that exposes the health of the daemon and the tunnel as Prometheus
+//! metrics. The server only listens on the loopback interface.
+
+use crate::{DaemonCommand, DaemonEventSender};
+use chrono::{DateTime, Utc};
+use futures::channel::oneshot;
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, StatusCode,
+};
+use mullvad_api::rest::{RequestLatencies, RequestServiceHandle, LATENCY_BUCKETS};
+use mullvad_relay_selector::RelaySelector;
+use mullvad_types::{
+    states::TunnelState,
+    tunnel_stats::{PeerStats, TunnelStats},
+};
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    fmt,
+    net::{Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+use talpid_core::mpsc::Sender;
+use talpid_types::{tunnel::ErrorStateCause, ErrorExt};
+
+/// Environment variable that enables the metrics endpoint on the given port.
+const METRICS_PORT_VAR: &str = "MULLVAD_METRICS_PORT";
+
+const TUNNEL_STATES: [&str; 5] = [
+    "disconnected",
+    "connecting",
+    "connected",
+    "disconnecting",
+    "error",
+];
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    #[error(display = "Invalid port in {}: {}", METRICS_PORT_VAR, _0)]
+    InvalidPort(String),
+
+    #[error(display = "Failed to bind the metrics server to {}", _0)]
+    Bind(SocketAddr, #[error(source)] hyper::Error),
+}
+
+/// Metrics about tunnel state transitions. They are updated by the daemon and kept regardless of
+/// whether the metrics endpoint is enabled.
+#[derive(Clone)]
+pub struct TunnelMetrics(Arc<Mutex<TunnelMetricsInner>>);
+
+#[derive(Clone)]
+struct TunnelMetricsInner {
+    state: &'static str,
+    state_since: Instant,
+    /// Number of times the tunnel entered the connecting state.
+    connection_attempts: u64,
+    /// Number of times the tunnel entered the error state, by cause.
+    errors: BTreeMap<&'static str, u64>,
+}
+
+impl Default for TunnelMetrics {
+    fn default() -> Self {
+        TunnelMetrics(Arc::new(Mutex::new(TunnelMetricsInner {
+            state: "disconnected",
+            state_since: Instant::now(),
+            connection_attempts: 0,
+            errors: BTreeMap::new(),
+        })))
+    }
+}
+
+impl TunnelMetrics {
+    /// Records that the tunnel entered a new state.
+    pub fn record_state(&self, tunnel_state: &TunnelState) {
+        let mut inner = self.0.lock().unwrap();
+        let state = match tunnel_state {
+            TunnelState::Disconnected => "disconnected",
+            TunnelState::Connecting { .. } => {
+                inner.connection_attempts += 1;
+                "connecting"
+            }
+            TunnelState::Connected { .. } => "connected",
+            TunnelState::Disconnecting(_) => "disconnecting",
+            TunnelState::Error(error_state) => {
+                *inner
+                    .errors
+                    .entry(error_cause_label(error_state.cause()))
+                    .or_default() += 1;
+                "error"
+            }
+        };
+        if state != inner.state {
+            inner.state = state;
+            inner.state_since = Instant::now();
+        }
+    }
+}
+
+fn error_cause_label(cause: &ErrorStateCause) -> &'static str {
+    match cause {
+        ErrorStateCause::AuthFailed(_) => "auth_failed",
+        ErrorStateCause::Ipv6Unavailable => "ipv6_unavailable",
+        ErrorStateCause::SetFirewallPolicyError(_) => "set_firewall_policy_error",
+        ErrorStateCause::SetDnsError => "set_dns_error",
+        #[cfg(target_os = "android")]
+        ErrorStateCause::InvalidDnsServers(_) => "invalid_dns_servers",
+        ErrorStateCause::StartTunnelError => "start_tunnel_error",
+        ErrorStateCause::TunnelParameterError(_) => "tunnel_parameter_error",
+        ErrorStateCause::IsOffline => "is_offline",
+        #[cfg(target_os = "android")]
+        ErrorStateCause::VpnPermissionDenied => "vpn_permission_denied",
+        #[cfg(target_os = "windows")]
+        ErrorStateCause::SplitTunnelError => "split_tunnel_error",
+    }
+}
+
+/// Gathers metrics from the different parts of the daemon when they are requested.
+#[derive(Clone)]
+pub struct MetricsCollector {
+    pub tunnel_metrics: TunnelMetrics,
+    pub daemon_tx: DaemonEventSender<DaemonCommand>,
+    pub api_service: RequestServiceHandle,
+    pub relay_selector: RelaySelector,
+}
+
+impl MetricsCollector {
+    async fn collect(&self) -> Metrics {
+        let tunnel = self.tunnel_metrics.0.lock().unwrap().clone();
+
+        let (tx, rx) = oneshot::channel();
+        let tunnel_stats = match self.daemon_tx.send(DaemonCommand::GetTunnelStats(tx)) {
+            Ok(()) => rx.await.ok().flatten(),
+            Err(_) => None,
+        };
+
+        Metrics {
+            tunnel_state: tunnel.state,
+            tunnel_state_duration: tunnel.state_since.elapsed(),
+            connection_attempts: tunnel.connection_attempts,
+            errors: tunnel.errors,
+            tunnel_stats,
+            api_latencies: self.api_service.latencies(),
+            relay_list_age: SystemTime::now()
+                .duration_since(self.relay_selector.last_updated())
+                .unwrap_or_default(),
+            now: Utc::now(),
+        }
+    }
+}
+
+/// Starts serving metrics on `http://127.0.0.1:<port>/metrics`, if a port is given in
+/// `MULLVAD_METRICS_PORT`.
+pub fn spawn_server(collector: MetricsCollector) -> Result<(), Error> {
+    let port: u16 = match std::env::var(METRICS_PORT_VAR) {
+        Ok(port) => port.parse().map_err(|_| Error::InvalidPort(port))?,
+        Err(_) => return Ok(()),
+    };
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let server = hyper::Server::try_bind(&address).map_err(|error| Error::Bind(address, error))?;
+
+    let make_service = make_service_fn(move |_| {
+        let collector = collector.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle_request(collector.clone(), request)
+            }))
+        }
+    });
+
+    log::info!("Serving metrics on http://{}/metrics", address);
+    tokio::spawn(async move {
+        if let Err(error) = server.serve(make_service).await {
+            log::error!("{}", error.display_chain_with_msg("Metrics server failed"));
+        }
+    });
+    Ok(())
+}
+
+async fn handle_request(
+    collector: MetricsCollector,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let response = if request.method() == Method::GET && request.uri().path() == "/metrics" {
+        let metrics = collector.collect().await;
+        Response::builder()
+            .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(Body::from(metrics.to_string()))
+    } else {
+        Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+    };
+    Ok(response.expect("Invalid metrics response"))
+}
+
+/// A snapshot of all metrics, which is formatted in the Prometheus text format.
+struct Metrics {
+    tunnel_state: &'static str,
+    tunnel_state_duration: Duration,
+    connection_attempts: u64,
+    errors: BTreeMap<&'static str, u64>,
+    tunnel_stats: Option<TunnelStats>,
+    api_latencies: RequestLatencies,
+    relay_list_age: Duration,
+    now: DateTime<Utc>,
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_header(
+            f,
+            "mullvad_tunnel_state",
+            "gauge",
+            "Whether the tunnel is in the given state",
+        )?;
+        for state in TUNNEL_STATES {
+            let value = u8::from(state == self.tunnel_state);
+            writeln!(f, "mullvad_tunnel_state{{state=\"{state}\"}} {value}")?;
+        }
+
+        write_header(
+            f,
+            "mullvad_tunnel_state_duration_seconds",
+            "gauge",
+            "Time spent in the current tunnel state",
+        )?;
+        writeln!(
+            f,
+            "mullvad_tunnel_state_duration_seconds {}",
+            self.tunnel_state_duration.as_secs_f64()
+        )?;
+
+        write_header(
+            f,
+            "mullvad_tunnel_connection_attempts_total",
+            "counter",
+            "Number of times the tunnel started connecting",
+        )?;
+        writeln!(
+            f,
+            "mullvad_tunnel_connection_attempts_total {}",
+            self.connection_attempts
+        )?;
+
+        write_header(
+            f,
+            "mullvad_tunnel_errors_total",
+            "counter",
+            "Number of times the tunnel entered the error state, by cause",
+        )?;
+        for (cause, count) in &self.errors {
+            writeln!(
+                f,
+                "mullvad_tunnel_errors_total{{cause=\"{cause}\"}} {count}"
+            )?;
+        }
+
+        if let Some(stats) = &self.tunnel_stats {
+            let peers = std::iter::once(("exit", &stats.exit))
+                .chain(stats.entry.as_ref().map(|entry| ("entry", entry)));
+            self.fmt_peer_stats(f, peers)?;
+        }
+
+        write_header(
+            f,
+            "mullvad_api_request_duration_seconds",
+            "histogram",
+            "Latency of requests to the Mullvad API",
+        )?;
+        for (upper_bound, count) in LATENCY_BUCKETS.iter().zip(self.api_latencies.buckets) {
+            writeln!(
+                f,
+                "mullvad_api_request_duration_seconds_bucket{{le=\"{upper_bound}\"}} {count}"
+            )?;
+        }
+        writeln!(
+            f,
+            "mullvad_api_request_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            self.api_latencies.count
+        )?;
+        writeln!(
+            f,
+            "mullvad_api_request_duration_seconds_sum {}",
+            self.api_latencies.sum.as_secs_f64()
+        )?;
+        writeln!(
+            f,
+            "mullvad_api_request_duration_seconds_count {}",
+            self.api_latencies.count
+        )?;
+
+        write_header(
+            f,
+            "mullvad_relay_list_age_seconds",
+            "gauge",
+            "Time since the relay list was last updated",
+        )?;
+        writeln!(
+            f,
+            "mullvad_relay_list_age_seconds {}",
+            self.relay_list_age.as_secs()
+        )
+    }
+}
+
+impl Metrics {
+    fn fmt_peer_stats<'a>(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        peers: impl Iterator<Item = (&'static str, &'a PeerStats)> + Clone,
+    ) -> fmt::Result {
+        write_header(
+            f,
+            "mullvad_wireguard_rx_bytes_total",
+            "counter",
+            "Bytes received from the WireGuard peer in the current tunnel",
+        )?;
+        for (peer, stats) in peers.clone() {
+            writeln!(
+                f,
+                "mullvad_wireguard_rx_bytes_total{{peer=\"{peer}\"}} {}",
+                stats.rx_bytes
+            )?;
+        }
+
+        write_header(
+            f,
+            "mullvad_wireguard_tx_bytes_total",
+            "counter",
+            "Bytes sent to the WireGuard peer in the current tunnel",
+        )?;
+        for (peer, stats) in peers.clone() {
+            writeln!(
+                f,
+                "mullvad_wireguard_tx_bytes_total{{peer=\"{peer}\"}} {}",
+                stats.tx_bytes
+            )?;
+        }
+
+        write_header(
+            f,
+            "mullvad_wireguard_handshake_age_seconds",
+            "gauge",
+            "Time since the last handshake with the WireGuard peer",
+        )?;
+        for (peer, stats) in peers {
+            if let Some(last_handshake) = stats.last_handshake {
+                let age = (self.now - last_handshake).num_seconds().max(0);
+                writeln!(
+                    f,
+                    "mullvad_wireguard_handshake_age_seconds{{peer=\"{peer}\"}} {age}"
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_header(
+    f: &mut fmt::Formatter<'_>,
+    name: &str,
+    metric_type: &str,
+    help: &str,
+) -> fmt::Result {
+    writeln!(f, "# HELP {name} {help}")?;
+    writeln!(f, "# TYPE {name} {metric_type}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_metrics() {
+        let now = Utc::now();
+        let mut api_latencies = RequestLatencies::default();
+        api_latencies.buckets = [0, 1, 1, 1, 1, 1, 1, 1];
+        api_latencies.count = 2;
+        api_latencies.sum = Duration::from_millis(60_200);
+
+        let metrics = Metrics {
+            tunnel_state: "connected",
+            tunnel_state_duration: Duration::from_secs(90),
+            connection_attempts: 3,
+            errors: BTreeMap::from([("is_offline", 2)]),
+            tunnel_stats: Some(TunnelStats {
+                exit: PeerStats {
+                    rx_bytes: 1000,
+                    tx_bytes: 500,
+                    last_handshake: Some(now - chrono::Duration::seconds(30)),
+                    ..Default::default()
+                },
+                entry: None,
+            }),
+            api_latencies,
+            relay_list_age: Duration::from_secs(3600),
+            now,
+        }
+        .to_string();
+
+        let lines: Vec<&str> = metrics
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "mullvad_tunnel_state{state=\"disconnected\"} 0",
+                "mullvad_tunnel_state{state=\"connecting\"} 0",
+                "mullvad_tunnel_state{state=\"connected\"} 1",
+                "mullvad_tunnel_state{state=\"disconnecting\"} 0",
+                "mullvad_tunnel_state{state=\"error\"} 0",
+                "mullvad_tunnel_state_duration_seconds 90",
+                "mullvad_tunnel_connection_attempts_total 3",
+                "mullvad_tunnel_errors_total{cause=\"is_offline\"} 2",
+                "mullvad_wireguard_rx_bytes_total{peer=\"exit\"} 1000",
+                "mullvad_wireguard_tx_bytes_total{peer=\"exit\"} 500",
+                "mullvad_wireguard_handshake_age_seconds{peer=\"exit\"} 30",
+                "mullvad_api_request_duration_seconds_bucket{le=\"0.1\"} 0",
+                "mullvad_api_request_duration_seconds_bucket{le=\"0.25\"} 1",
+                "mullvad_api_request_duration_seconds_bucket{le=\"0.5\"} 1",
+                "mullvad_api_request_duration_seconds_bucket{le=\"1\"} 1",
+                "mullvad_api_request_duration_seconds_bucket{le=\"2.5\"} 1",
+                "mullvad_api_request_duration_seconds_bucket{le=\"5\"} 1",
+                "mullvad_api_request_duration_seconds_bucket{le=\"10\"} 1",
+                "mullvad_api_request_duration_seconds_bucket{le=\"30\"} 1",
+                "mullvad_api_request_duration_seconds_bucket{le=\"+Inf\"} 2",
+                "mullvad_api_request_duration_seconds_sum 60.2",
+                "mullvad_api_request_duration_seconds_count 2",
+                "mullvad_relay_list_age_seconds 3600",
+            ]
+        );
+    }
+}
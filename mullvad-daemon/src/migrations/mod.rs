@@ -131,23 +131,10 @@ pub async fn migrate_all(cache_dir: &Path, settings_dir: &Path) -> Result<Option
 
     let mut settings: serde_json::Value =
         serde_json::from_reader(&settings_bytes[..]).map_err(Error::Deserialize)?;
-
-    if !settings.is_object() {
-        return Err(Error::InvalidSettingsContent);
-    }
-
     let old_settings = settings.clone();
 
-    v1::migrate(&mut settings)?;
-    v2::migrate(&mut settings)?;
-    v3::migrate(&mut settings)?;
-    v4::migrate(&mut settings)?;
-
     account_history::migrate_location(cache_dir, settings_dir).await;
-    account_history::migrate_formats(settings_dir, &mut settings).await?;
-
-    let migration_data = v5::migrate(&mut settings)?;
-    v6::migrate(&mut settings)?;
+    let migration_data = migrate_settings_inner(Some(settings_dir), &mut settings).await?;
 
     if settings == old_settings {
         // Nothing changed
@@ -173,6 +160,40 @@ pub async fn migrate_all(cache_dir: &Path, settings_dir: &Path) -> Result<Option
     Ok(migration_data)
 }
 
+/// Migrates settings that were not read from the settings directory, such as imported settings,
+/// to the current format. Nothing on disk is touched. Data that is no longer part of the
+/// settings, such as the account token in old settings, is discarded.
+pub async fn migrate_settings(settings: &mut serde_json::Value) -> Result<()> {
+    migrate_settings_inner(None, settings)
+        .await
+        .map(|_migration_data| ())
+}
+
+/// Runs all migrations on `settings`. If `settings_dir` is given, the account history in it is
+/// migrated as well, since older formats stored some settings in it.
+async fn migrate_settings_inner(
+    settings_dir: Option<&Path>,
+    settings: &mut serde_json::Value,
+) -> Result<Option<MigrationData>> {
+    if !settings.is_object() {
+        return Err(Error::InvalidSettingsContent);
+    }
+
+    v1::migrate(settings)?;
+    v2::migrate(settings)?;
+    v3::migrate(settings)?;
+    v4::migrate(settings)?;
+
+    if let Some(settings_dir) = settings_dir {
+        account_history::migrate_formats(settings_dir, settings).await?;
+    }
+
+    let migration_data = v5::migrate(settings)?;
+    v6::migrate(settings)?;
+
+    Ok(migration_data)
+}
+
 pub(crate) fn migrate_device(
     migration_data: MigrationData,
     rest_handle: mullvad_api::rest::MullvadRestHandle,
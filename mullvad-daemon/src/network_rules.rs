@@ -0,0 +1,129 @@
+//! Keeps track of the network that the device is connected to, so that the network rules in the
+//! settings can be applied when the device joins a network.
+
+use crate::{DaemonEventSender, InternalDaemonEvent};
+use mullvad_types::network_rules::{MacAddress, NetworkInfo};
+use std::{fs, io, net::Ipv4Addr, thread, time::Duration};
+use talpid_core::mpsc::Sender;
+use talpid_dbus::network_manager::{NetworkManager, PrimaryConnection};
+use talpid_types::ErrorExt;
+
+const ARP_TABLE_PATH: &str = "/proc/net/arp";
+
+/// The gateway may not be in the ARP table yet right after a connection is activated.
+const GATEWAY_LOOKUP_ATTEMPTS: u32 = 5;
+const GATEWAY_LOOKUP_DELAY: Duration = Duration::from_millis(500);
+
+/// Spawns a thread that sends [`InternalDaemonEvent::NetworkChanged`] with the current network,
+/// and again every time NetworkManager switches to a different network.
+pub(crate) fn spawn_monitor(tx: DaemonEventSender) {
+    thread::spawn(move || {
+        let network_manager = match NetworkManager::new_connection().and_then(|network_manager| {
+            network_manager.ensure_network_manager_exists()?;
+            Ok(network_manager)
+        }) {
+            Ok(network_manager) => network_manager,
+            Err(error) => {
+                log::warn!(
+                    "{}",
+                    error.display_chain_with_msg(
+                        "Network rules are unavailable since NetworkManager cannot be reached"
+                    )
+                );
+                return;
+            }
+        };
+
+        let is_closed_tx = tx.clone();
+        let result = network_manager.watch_primary_connection(
+            |connection| {
+                let network = connection.map(network_info);
+                let _ = tx.send(InternalDaemonEvent::NetworkChanged(network));
+            },
+            move || !is_closed_tx.is_closed(),
+        );
+        if let Err(error) = result {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to watch the current network")
+            );
+        }
+    });
+}
+
+fn network_info(connection: PrimaryConnection) -> NetworkInfo {
+    let gateway_mac = connection.ipv4_gateway.and_then(find_gateway_mac);
+    NetworkInfo {
+        connection_uuid: connection.uuid,
+        connection_name: connection.id,
+        ssid: connection.ssid,
+        gateway_mac,
+    }
+}
+
+fn find_gateway_mac(gateway: Ipv4Addr) -> Option<MacAddress> {
+    for _ in 0..GATEWAY_LOOKUP_ATTEMPTS {
+        match lookup_hardware_address(gateway) {
+            Ok(Some(mac)) => return Some(mac),
+            Ok(None) => thread::sleep(GATEWAY_LOOKUP_DELAY),
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to read the ARP table")
+                );
+                return None;
+            }
+        }
+    }
+    log::debug!("Hardware address of gateway {} is unknown", gateway);
+    None
+}
+
+/// Looks up the hardware address of `address` in the kernel's ARP table.
+fn lookup_hardware_address(address: Ipv4Addr) -> io::Result<Option<MacAddress>> {
+    let arp_table = fs::read_to_string(ARP_TABLE_PATH)?;
+    Ok(parse_arp_table(&arp_table, address))
+}
+
+fn parse_arp_table(arp_table: &str, address: Ipv4Addr) -> Option<MacAddress> {
+    // The first line contains the column names:
+    // IP address  HW type  Flags  HW address  Mask  Device
+    arp_table.lines().skip(1).find_map(|line| {
+        let mut columns = line.split_whitespace();
+        if columns.next()?.parse::<Ipv4Addr>().ok()? != address {
+            return None;
+        }
+        let mac: MacAddress = columns.nth(2)?.parse().ok()?;
+        // Incomplete entries have an all-zero address
+        if mac.0 == [0; 6] {
+            return None;
+        }
+        Some(mac)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_arp_table() {
+        let arp_table = "\
+IP address       HW type     Flags       HW address            Mask     Device
+192.168.1.23     0x1         0x0         00:00:00:00:00:00     *        wlan0
+192.168.1.1      0x1         0x2         00:11:22:aa:bb:cc     *        wlan0
+";
+        assert_eq!(
+            parse_arp_table(arp_table, "192.168.1.1".parse().unwrap()),
+            Some(MacAddress([0x00, 0x11, 0x22, 0xaa, 0xbb, 0xcc]))
+        );
+        assert_eq!(
+            parse_arp_table(arp_table, "192.168.1.23".parse().unwrap()),
+            None
+        );
+        assert_eq!(
+            parse_arp_table(arp_table, "192.168.1.2".parse().unwrap()),
+            None
+        );
+    }
+}
@@ -2,6 +2,7 @@
 use futures::TryFutureExt;
 use mullvad_types::{
     relay_constraints::{RelayConstraints, RelaySettings, WireguardConstraints},
+    relay_rotation::RelayRotationError,
     settings::{DnsState, Settings},
 };
 use rand::Rng;
@@ -11,13 +12,18 @@ use std::{
     path::{Path, PathBuf},
 };
 use talpid_core::firewall::is_local_address;
-use talpid_types::ErrorExt;
+use talpid_types::{
+    net::{validate_lan_network, LanNetworkError},
+    ErrorExt,
+};
 use tokio::{
     fs,
     io::{self, AsyncWriteExt},
 };
 
 const SETTINGS_FILE: &str = "settings.json";
+/// File containing settings enforced by an administrator. It is never written by the daemon.
+const POLICY_FILE: &str = "policy.json";
 
 #[derive(err_derive::Error, Debug)]
 #[error(no_from)]
@@ -37,12 +43,41 @@ pub enum Error {
 
     #[error(display = "Unable to write settings to {}", _0)]
     WriteError(String, #[error(source)] io::Error),
+
+    #[error(display = "Unable to migrate imported settings")]
+    MigrationError(#[error(source)] crate::migrations::Error),
+
+    #[error(display = "Invalid relay rotation")]
+    InvalidRelayRotation(#[error(source)] RelayRotationError),
+
+    #[error(display = "Invalid LAN network")]
+    InvalidLanNetwork(#[error(source)] LanNetworkError),
+
+    #[error(display = "Unable to read policy file {}", _0)]
+    ReadPolicyError(String, #[error(source)] io::Error),
+
+    #[error(display = "Unable to apply the policy")]
+    InvalidPolicy(#[error(source)] serde_json::Error),
+
+    #[error(display = "The setting is locked by the policy file")]
+    LockedByPolicy,
 }
 
 #[derive(Debug)]
 pub struct SettingsPersister {
     settings: Settings,
     path: PathBuf,
+    policy: Option<Policy>,
+}
+
+#[derive(Debug)]
+enum Policy {
+    /// Settings that override the user's settings and cannot be changed. This is a JSON object
+    /// with the same structure as the settings file, but containing only the locked settings.
+    Enforced(serde_json::Value),
+    /// The policy file exists but could not be applied. Since it is unknown which settings it was
+    /// meant to lock, all of them are locked.
+    Invalid,
 }
 
 pub type MadeChanges = bool;
@@ -51,6 +86,16 @@ impl SettingsPersister {
     /// Loads user settings from file. If it fails, it returns the defaults.
     pub async fn load(settings_dir: &Path) -> Self {
         let path = settings_dir.join(SETTINGS_FILE);
+        let mut policy = match Self::load_policy(&settings_dir.join(POLICY_FILE)).await {
+            Ok(policy) => policy.map(Policy::Enforced),
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to load policy. Locking all settings.")
+                );
+                Some(Policy::Invalid)
+            }
+        };
         let (mut settings, mut should_save) = match Self::load_from_file(&path).await {
             Ok(value) => value,
             Err(error) => {
@@ -87,7 +132,29 @@ impl SettingsPersister {
             settings.show_beta_releases = true;
         }
 
-        let mut persister = SettingsPersister { settings, path };
+        if let Some(Policy::Enforced(enforced)) = &policy {
+            match apply_policy(&settings, enforced) {
+                Ok(enforced_settings) => {
+                    should_save |= enforced_settings != settings;
+                    settings = enforced_settings;
+                }
+                Err(error) => {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg(
+                            "Failed to apply policy. Locking all settings."
+                        )
+                    );
+                    policy = Some(Policy::Invalid);
+                }
+            }
+        }
+
+        let mut persister = SettingsPersister {
+            settings,
+            path,
+            policy,
+        };
 
         if should_save {
             if let Err(error) = persister.save().await {
@@ -122,6 +189,25 @@ impl SettingsPersister {
         serde_json::from_slice(bytes).map_err(Error::ParseError)
     }
 
+    /// Reads the policy file, if there is one. The policy must be a JSON object.
+    async fn load_policy(path: &Path) -> Result<Option<serde_json::Value>, Error> {
+        let policy_bytes = match fs::read(path).await {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(Error::ReadPolicyError(path.display().to_string(), error)),
+        };
+        log::info!("Loading policy from {}", path.display());
+
+        let policy: serde_json::Value =
+            serde_json::from_slice(&policy_bytes).map_err(Error::InvalidPolicy)?;
+        if !policy.is_object() {
+            return Err(Error::InvalidPolicy(serde::de::Error::custom(
+                "the policy must be a JSON object",
+            )));
+        }
+        Ok(Some(policy))
+    }
+
     async fn save(&mut self) -> Result<(), Error> {
         Self::save_inner(&self.path, &self.settings).await
     }
@@ -147,7 +233,13 @@ impl SettingsPersister {
     /// Resets default settings
     #[cfg(not(target_os = "android"))]
     pub async fn reset(&mut self) -> Result<(), Error> {
-        self.settings = Self::default_settings();
+        let mut settings = Self::default_settings();
+        match &self.policy {
+            Some(Policy::Enforced(policy)) => settings = apply_policy(&settings, policy)?,
+            Some(Policy::Invalid) => return Err(Error::LockedByPolicy),
+            None => (),
+        }
+        self.settings = settings;
         let path = self.path.clone();
         self.save()
             .or_else(|e| async move {
@@ -167,6 +259,41 @@ impl SettingsPersister {
         self.settings.clone()
     }
 
+    /// Serializes the settings for importing them on another device.
+    pub fn export(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(&self.settings).map_err(Error::SerializeError)
+    }
+
+    /// Replaces all settings with settings that were previously exported. Settings in older
+    /// formats are migrated to the current format. Settings locked by the policy are not
+    /// imported.
+    pub async fn import(&mut self, settings: &str) -> Result<MadeChanges, Error> {
+        let mut settings: serde_json::Value =
+            serde_json::from_str(settings).map_err(Error::ParseError)?;
+        crate::migrations::migrate_settings(&mut settings)
+            .await
+            .map_err(Error::MigrationError)?;
+        let mut settings: Settings = serde_json::from_value(settings).map_err(Error::ParseError)?;
+
+        if let Some(rotation) = &settings.relay_rotation {
+            rotation.validate().map_err(Error::InvalidRelayRotation)?;
+        }
+        settings.lan_networks = settings
+            .lan_networks
+            .into_iter()
+            .map(|network| validate_lan_network(network, &[]))
+            .collect::<Result<_, _>>()
+            .map_err(Error::InvalidLanNetwork)?;
+        match &self.policy {
+            Some(Policy::Enforced(policy)) => settings = apply_policy(&settings, policy)?,
+            Some(Policy::Invalid) => return Err(Error::LockedByPolicy),
+            None => (),
+        }
+
+        self.update(move |current_settings| *current_settings = settings)
+            .await
+    }
+
     /// Modifies `Settings::default()` somewhat, e.g. depending on whether a beta version
     /// is being run or not.
     fn default_settings() -> Settings {
@@ -195,6 +322,18 @@ impl SettingsPersister {
             return Ok(false);
         }
 
+        match &self.policy {
+            Some(Policy::Enforced(policy)) => {
+                let enforced_settings =
+                    apply_policy(&new_settings, policy).map_err(|_| Error::LockedByPolicy)?;
+                if enforced_settings != new_settings {
+                    return Err(Error::LockedByPolicy);
+                }
+            }
+            Some(Policy::Invalid) => return Err(Error::LockedByPolicy),
+            None => (),
+        }
+
         Self::save_inner(&self.path, &new_settings).await?;
         self.settings = new_settings;
         Ok(true)
@@ -208,6 +347,31 @@ impl SettingsPersister {
     }
 }
 
+/// Returns the settings with all settings in the policy overriding the given ones.
+fn apply_policy(settings: &Settings, policy: &serde_json::Value) -> Result<Settings, Error> {
+    let mut settings = serde_json::to_value(settings).map_err(Error::SerializeError)?;
+    merge_json(&mut settings, policy);
+    serde_json::from_value(settings).map_err(Error::InvalidPolicy)
+}
+
+/// Recursively merges `overrides` into `value`. Objects are only merged if `value` contains all
+/// keys in `overrides`. Otherwise, such as when the variant of an enum differs, `value` is
+/// replaced, as are all values other than objects.
+fn merge_json(value: &mut serde_json::Value, overrides: &serde_json::Value) {
+    match (value, overrides) {
+        (serde_json::Value::Object(object), serde_json::Value::Object(overrides))
+            if overrides.keys().all(|key| object.contains_key(key)) =>
+        {
+            for (key, override_value) in overrides {
+                if let Some(value) = object.get_mut(key) {
+                    merge_json(value, override_value);
+                }
+            }
+        }
+        (value, overrides) => *value = overrides.clone(),
+    }
+}
+
 impl Deref for SettingsPersister {
     type Target = Settings;
 
@@ -296,6 +460,17 @@ impl<'a> Display for SettingsSummary<'a> {
                 }
                 write!(f, "{}", content.join(" "))?;
             }
+            DnsState::Custom
+                if !self
+                    .settings
+                    .tunnel_options
+                    .dns_options
+                    .custom_options
+                    .encrypted_servers
+                    .is_empty() =>
+            {
+                f.write_str("custom, encrypted")?
+            }
             DnsState::Custom => {
                 // NOTE: Technically inaccurate, as the gateway IP is a local IP but isn't treated
                 // as one.
@@ -306,7 +481,7 @@ impl<'a> Display for SettingsSummary<'a> {
                     .custom_options
                     .addresses
                     .iter()
-                    .any(is_local_address);
+                    .any(|addr| is_local_address(addr, &self.settings.lan_networks));
                 let contains_public = self
                     .settings
                     .tunnel_options
@@ -314,7 +489,7 @@ impl<'a> Display for SettingsSummary<'a> {
                     .custom_options
                     .addresses
                     .iter()
-                    .any(|addr| !is_local_address(addr));
+                    .any(|addr| !is_local_address(addr, &self.settings.lan_networks));
 
                 match (contains_public, contains_local) {
                     (true, true) => f.write_str("custom, public, local")?,
@@ -340,8 +515,11 @@ impl<'a> SettingsSummary<'a> {
 
 #[cfg(test)]
 mod test {
-    use super::SettingsPersister;
-    use mullvad_types::settings::SettingsVersion;
+    use super::{apply_policy, Error, Policy, SettingsPersister};
+    use mullvad_types::{
+        relay_constraints::{Constraint, LocationConstraint, RelaySettings},
+        settings::{Settings, SettingsVersion, CURRENT_SETTINGS_VERSION},
+    };
     use serde_json;
 
     #[test]
@@ -419,4 +597,116 @@ mod test {
 
         let _ = SettingsPersister::load_from_bytes(settings).unwrap();
     }
+
+    #[tokio::test]
+    async fn test_import_old_settings() {
+        let settings_dir = tempfile::tempdir().unwrap();
+        let mut persister = SettingsPersister {
+            settings: Settings::default(),
+            path: settings_dir.path().join(super::SETTINGS_FILE),
+            policy: None,
+        };
+
+        let v5_settings = r#"{
+              "account_token": "0000000000000000",
+              "relay_settings": {
+                "normal": {
+                  "location": { "only": { "country": "gb" } },
+                  "tunnel_protocol": { "only": "wireguard" },
+                  "wireguard_constraints": { "port": "any" },
+                  "openvpn_constraints": { "port": "any", "protocol": "any" }
+                }
+              },
+              "bridge_settings": { "normal": { "location": "any" } },
+              "bridge_state": "auto",
+              "allow_lan": true,
+              "block_when_disconnected": false,
+              "auto_connect": true,
+              "tunnel_options": {
+                "openvpn": { "mssfix": null },
+                "wireguard": { "mtu": null, "rotation_interval": null },
+                "generic": { "enable_ipv6": true }
+              },
+              "settings_version": 5,
+              "show_beta_releases": false
+        }"#;
+        assert!(persister.import(v5_settings).await.unwrap());
+        let settings = persister.to_settings();
+        assert_eq!(settings.get_settings_version(), CURRENT_SETTINGS_VERSION);
+        assert!(settings.allow_lan);
+        let constraints = match settings.get_relay_settings() {
+            RelaySettings::Normal(constraints) => constraints,
+            _ => panic!("Unexpected relay settings"),
+        };
+        assert_eq!(
+            constraints.location,
+            Constraint::Only(LocationConstraint::Country("gb".to_owned()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_locked_by_policy() {
+        let settings_dir = tempfile::tempdir().unwrap();
+        let mut settings = Settings::default();
+        settings.allow_lan = true;
+        let mut persister = SettingsPersister {
+            settings,
+            path: settings_dir.path().join(super::SETTINGS_FILE),
+            policy: Some(Policy::Enforced(serde_json::json!({ "allow_lan": true }))),
+        };
+
+        let result = persister
+            .update(|settings| settings.allow_lan = false)
+            .await;
+        assert!(matches!(result, Err(Error::LockedByPolicy)));
+        assert!(persister.to_settings().allow_lan);
+
+        // Settings that are not locked can still be changed
+        let result = persister
+            .update(|settings| settings.auto_connect = !settings.auto_connect)
+            .await;
+        assert!(result.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_policy_locks_all_settings() {
+        let settings_dir = tempfile::tempdir().unwrap();
+        std::fs::write(settings_dir.path().join(super::POLICY_FILE), "not json").unwrap();
+
+        let mut persister = SettingsPersister::load(settings_dir.path()).await;
+        assert!(matches!(persister.policy, Some(Policy::Invalid)));
+
+        let result = persister
+            .update(|settings| settings.auto_connect = !settings.auto_connect)
+            .await;
+        assert!(matches!(result, Err(Error::LockedByPolicy)));
+    }
+
+    #[test]
+    fn test_apply_policy() {
+        let policy = serde_json::json!({
+            "allow_lan": true,
+            "relay_settings": {
+                "normal": {
+                    "location": { "only": { "city": ["de", "ber"] } }
+                }
+            }
+        });
+        let settings = apply_policy(&Settings::default(), &policy).unwrap();
+
+        assert!(settings.allow_lan);
+        let constraints = match settings.get_relay_settings() {
+            RelaySettings::Normal(constraints) => constraints,
+            _ => panic!("Unexpected relay settings"),
+        };
+        assert_eq!(
+            constraints.location,
+            Constraint::Only(LocationConstraint::City("de".to_owned(), "ber".to_owned()))
+        );
+        // Settings that are not in the policy are kept
+        assert_eq!(
+            constraints.wireguard_constraints.entry_location,
+            Constraint::Only(LocationConstraint::Country("se".to_owned()))
+        );
+    }
 }
@@ -29,6 +29,9 @@ pub enum Error {
     #[error(display = "No bridge available")]
     NoBridgeAvailable,
 
+    #[error(display = "No entry and exit relays satisfy the multihop diversity constraints")]
+    NoDiverseMultihopRelays,
+
     #[error(display = "Failed to resolve hostname for custom relay")]
     ResolveCustomHostname,
 }
@@ -42,6 +45,7 @@ struct InnerParametersGenerator {
     account_manager: AccountManagerHandle,
 
     last_generated_relays: Option<LastSelectedRelays>,
+    last_wireguard_parameters: Option<wireguard::TunnelParameters>,
 }
 
 impl ParametersGenerator {
@@ -58,6 +62,7 @@ impl ParametersGenerator {
             account_manager,
 
             last_generated_relays: None,
+            last_wireguard_parameters: None,
         })))
     }
 
@@ -66,6 +71,22 @@ impl ParametersGenerator {
         self.0.lock().await.tunnel_options = tunnel_options.clone();
     }
 
+    /// Makes the next tunnel parameters use a different exit relay than the last generated ones,
+    /// unless it is the only relay that matches the constraints.
+    pub async fn avoid_last_exit_relay(&self) {
+        let inner = self.0.lock().await;
+        let hostname = inner
+            .last_generated_relays
+            .as_ref()
+            .map(|relays| relays.exit_relay().hostname.clone());
+        inner.relay_selector.set_avoided_exit_relay(hostname);
+    }
+
+    /// Gets the last generated tunnel parameters, if they were for a WireGuard tunnel.
+    pub async fn get_last_wireguard_parameters(&self) -> Option<wireguard::TunnelParameters> {
+        self.0.lock().await.last_wireguard_parameters.clone()
+    }
+
     /// Gets the location associated with the last generated tunnel parameters.
     pub async fn get_last_location(&self) -> Option<GeoIpLocation> {
         let inner = self.0.lock().await;
@@ -120,6 +141,18 @@ impl ParametersGenerator {
 
 impl InnerParametersGenerator {
     async fn generate(&mut self, retry_attempt: u32) -> Result<TunnelParameters, Error> {
+        let parameters = self.generate_parameters(retry_attempt).await;
+        if parameters.is_ok() {
+            self.relay_selector.set_avoided_exit_relay(None);
+        }
+        self.last_wireguard_parameters = match &parameters {
+            Ok(TunnelParameters::Wireguard(parameters)) => Some(parameters.clone()),
+            _ => None,
+        };
+        parameters
+    }
+
+    async fn generate_parameters(&mut self, retry_attempt: u32) -> Result<TunnelParameters, Error> {
         let _data = self.device().await?;
         match self.relay_selector.get_relay(retry_attempt) {
             Ok((SelectedRelay::Custom(custom_relay), _bridge, _obfsucator)) => {
@@ -143,6 +176,9 @@ impl InnerParametersGenerator {
                 .await
             }
             Err(mullvad_relay_selector::Error::NoBridge) => Err(Error::NoBridgeAvailable),
+            Err(mullvad_relay_selector::Error::NoDiverseRelayPair) => {
+                Err(Error::NoDiverseMultihopRelays)
+            }
             Err(_error) => Err(Error::NoRelayAvailable),
         }
     }
@@ -258,6 +294,9 @@ impl TunnelParametersGenerator for ParametersGenerator {
                 .await
                 .map_err(|error| match error {
                     Error::NoBridgeAvailable => ParameterGenerationError::NoMatchingBridgeRelay,
+                    Error::NoDiverseMultihopRelays => {
+                        ParameterGenerationError::NoDiverseMultihopRelays
+                    }
                     Error::ResolveCustomHostname => {
                         ParameterGenerationError::CustomTunnelHostResultionError
                     }
@@ -291,3 +330,13 @@ enum LastSelectedRelays {
     #[cfg(not(target_os = "android"))]
     OpenVpn { relay: Relay, bridge: Option<Relay> },
 }
+
+impl LastSelectedRelays {
+    fn exit_relay(&self) -> &Relay {
+        match self {
+            LastSelectedRelays::WireGuard { wg_exit, .. } => wg_exit,
+            #[cfg(not(target_os = "android"))]
+            LastSelectedRelays::OpenVpn { relay, .. } => relay,
+        }
+    }
+}
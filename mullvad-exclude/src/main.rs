@@ -14,7 +14,7 @@ use std::{
 };
 
 #[cfg(target_os = "linux")]
-use talpid_types::cgroup::{find_net_cls_mount, SPLIT_TUNNEL_CGROUP_NAME};
+use talpid_types::cgroup::{find_cgroup2_mount, find_net_cls_mount, SPLIT_TUNNEL_CGROUP_NAME};
 
 #[cfg(target_os = "linux")]
 const PROGRAM_NAME: &str = "mullvad-exclude";
@@ -41,11 +41,11 @@ enum Error {
     #[error(display = "An argument contains interior nul bytes")]
     ArgumentNulError(#[error(source)] NulError),
 
-    #[error(display = "Failed to find net_cls controller")]
-    FindNetClsController(#[error(source)] io::Error),
+    #[error(display = "Failed to find net_cls controller or cgroup v2 hierarchy")]
+    FindCGroupController(#[error(source)] io::Error),
 
-    #[error(display = "No net_cls controller")]
-    NoNetClsController,
+    #[error(display = "No net_cls controller or cgroup v2 hierarchy")]
+    NoCGroupController,
 }
 
 fn main() {
@@ -84,9 +84,13 @@ fn run() -> Result<Infallible, Error> {
         .collect::<Result<Vec<CString>, NulError>>()
         .map_err(Error::ArgumentNulError)?;
 
-    let cgroup_dir = find_net_cls_mount()
-        .map_err(Error::FindNetClsController)?
-        .ok_or(Error::NoNetClsController)?;
+    // The daemon prefers net_cls over cgroup v2, so the same order must be used here
+    let cgroup_dir = match find_net_cls_mount().map_err(Error::FindCGroupController)? {
+        Some(cgroup_dir) => cgroup_dir,
+        None => find_cgroup2_mount()
+            .map_err(Error::FindCGroupController)?
+            .ok_or(Error::NoCGroupController)?,
+    };
 
     let procs_path = cgroup_dir
         .join(SPLIT_TUNNEL_CGROUP_NAME)
@@ -0,0 +1,243 @@
+//! Authorization of management interface clients based on the credentials of the peer process.
+//! Unprivileged users may only call RPCs that do not change anything, while privileged users may
+//! call any RPC. Responses to unprivileged users must not contain secrets, so requests from them
+//! are marked with [`Unprivileged`].
+use crate::{Error, Unprivileged};
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    env,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tonic::{
+    body::BoxBody,
+    codegen::{http, BoxFuture, Service},
+    transport::{server::UdsConnectInfo, Body, NamedService},
+    Status,
+};
+
+/// RPCs that unprivileged users are allowed to call.
+const READ_ONLY_METHODS: &[&str] = &[
+    "GetTunnelState",
+    "EventsListen",
+    "GetSettings",
+    "GetCurrentVersion",
+    "GetVersionInfo",
+    "IsPerformingPostUpgrade",
+    "GetRelayLocations",
+    "VerifyRelayLists",
+    "GetCurrentLocation",
+    "GetCurrentNetwork",
+    "GetConnectionHistory",
+    "GetDnsBlocklistStats",
+    "TunnelStatsListen",
+];
+
+/// Groups whose members are privileged unless `MULLVAD_MANAGEMENT_ADMIN_GROUP` is set. These are
+/// the groups of users that may administer the system.
+#[cfg(target_os = "macos")]
+const DEFAULT_ADMIN_GROUPS: &[&str] = &["admin"];
+#[cfg(not(target_os = "macos"))]
+const DEFAULT_ADMIN_GROUPS: &[&str] = &["sudo", "wheel"];
+
+lazy_static::lazy_static! {
+    static ref MULLVAD_MANAGEMENT_ADMIN_GROUP: Option<String> =
+        env::var("MULLVAD_MANAGEMENT_ADMIN_GROUP").ok();
+    static ref MULLVAD_MANAGEMENT_ADMIN_UIDS: Option<String> =
+        env::var("MULLVAD_MANAGEMENT_ADMIN_UIDS").ok();
+}
+
+/// Determines which users are privileged. The root user is always privileged.
+#[derive(Debug, Default)]
+pub struct AccessPolicy {
+    /// Members of these groups are privileged.
+    admin_groups: Vec<String>,
+    /// Users with these UIDs are privileged.
+    admin_uids: HashSet<u32>,
+}
+
+impl AccessPolicy {
+    /// Returns the policy configured using environment variables. Unless
+    /// `MULLVAD_MANAGEMENT_ADMIN_GROUP` is set, the members of [`DEFAULT_ADMIN_GROUPS`] are
+    /// privileged.
+    pub fn from_env() -> Result<Self, Error> {
+        let admin_groups = match &*MULLVAD_MANAGEMENT_ADMIN_GROUP {
+            Some(group_name) => {
+                // Fail early if the group does not exist
+                nix::unistd::Group::from_name(group_name)
+                    .map_err(Error::ObtainGidError)?
+                    .ok_or(Error::NoGidError)?;
+                vec![group_name.clone()]
+            }
+            None => DEFAULT_ADMIN_GROUPS
+                .iter()
+                .map(|group_name| group_name.to_string())
+                .collect(),
+        };
+        let admin_uids = match &*MULLVAD_MANAGEMENT_ADMIN_UIDS {
+            Some(uids) => parse_uids(uids).ok_or(Error::InvalidAdminUids)?,
+            None => HashSet::new(),
+        };
+
+        Ok(AccessPolicy {
+            admin_groups,
+            admin_uids,
+        })
+    }
+
+    /// Returns an error if the peer is not allowed to call the given RPC. `path` is the path of
+    /// the gRPC request, which ends with the method name.
+    fn authorize(&self, path: &str, peer: Option<(u32, u32)>) -> Result<(), Status> {
+        let method = path.rsplit('/').next().unwrap_or_default();
+        if READ_ONLY_METHODS.contains(&method) {
+            return Ok(());
+        }
+        match peer {
+            Some((uid, gid)) if self.is_privileged(uid, gid) => Ok(()),
+            Some(_) => Err(Status::permission_denied(format!(
+                "{method} requires a privileged user. Ask an administrator to add you to the \
+                management interface admin group"
+            ))),
+            None => Err(Status::permission_denied(
+                "Unable to obtain the credentials of the client",
+            )),
+        }
+    }
+
+    fn is_privileged(&self, uid: u32, gid: u32) -> bool {
+        if uid == 0 || self.admin_uids.contains(&uid) {
+            return true;
+        }
+        self.admin_groups
+            .iter()
+            .any(|group_name| is_group_member(group_name, uid, gid))
+    }
+}
+
+/// Parses a comma-separated list of UIDs.
+fn parse_uids(uids: &str) -> Option<HashSet<u32>> {
+    uids.split(',')
+        .filter(|uid| !uid.trim().is_empty())
+        .map(|uid| uid.trim().parse().ok())
+        .collect()
+}
+
+/// Returns whether the user is a member of the given group. Group membership is looked up for
+/// each request, so that changes take effect without restarting the daemon.
+fn is_group_member(group_name: &str, uid: u32, gid: u32) -> bool {
+    use nix::unistd::{Gid, Group, Uid, User};
+
+    let group = match Group::from_name(group_name) {
+        Ok(Some(group)) => group,
+        _ => return false,
+    };
+    if group.gid == Gid::from_raw(gid) {
+        return true;
+    }
+    match User::from_uid(Uid::from_raw(uid)) {
+        Ok(Some(user)) => group.mem.contains(&user.name),
+        _ => false,
+    }
+}
+
+/// Service that only passes on requests that the client is authorized to make, according to an
+/// [`AccessPolicy`].
+#[derive(Clone)]
+pub struct AccessControl<S> {
+    inner: S,
+    policy: Arc<AccessPolicy>,
+}
+
+impl<S> AccessControl<S> {
+    pub fn new(inner: S, policy: AccessPolicy) -> Self {
+        AccessControl {
+            inner,
+            policy: Arc::new(policy),
+        }
+    }
+}
+
+impl<S: NamedService> NamedService for AccessControl<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S> Service<http::Request<Body>> for AccessControl<S>
+where
+    S: Service<http::Request<Body>, Response = http::Response<BoxBody>, Error = Infallible>,
+    S::Future: Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<Body>) -> Self::Future {
+        let peer = request
+            .extensions()
+            .get::<UdsConnectInfo>()
+            .and_then(|info| info.peer_cred)
+            .map(|cred| (cred.uid(), cred.gid()));
+        let privileged = matches!(peer, Some((uid, gid)) if self.policy.is_privileged(uid, gid));
+        if !privileged {
+            request.extensions_mut().insert(Unprivileged);
+        }
+        match self.policy.authorize(request.uri().path(), peer) {
+            Ok(()) => Box::pin(self.inner.call(request)),
+            Err(status) => {
+                log::warn!(
+                    "Denied {} for client {:?}: {}",
+                    request.uri().path(),
+                    peer,
+                    status.message()
+                );
+                Box::pin(async move { Ok(status.to_http()) })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SERVICE_PATH: &str = "/mullvad_daemon.management_interface.ManagementService";
+
+    #[test]
+    fn test_authorize() {
+        let policy = AccessPolicy {
+            admin_groups: vec![],
+            admin_uids: parse_uids("1000, 1001").unwrap(),
+        };
+        let unprivileged = Some((1002, 1002));
+
+        let get_settings = format!("{SERVICE_PATH}/GetSettings");
+        let export_settings = format!("{SERVICE_PATH}/ExportSettings");
+        let factory_reset = format!("{SERVICE_PATH}/FactoryReset");
+
+        assert!(policy.authorize(&get_settings, unprivileged).is_ok());
+        assert!(policy.authorize(&get_settings, None).is_ok());
+        assert!(policy.authorize(&factory_reset, Some((0, 0))).is_ok());
+        assert!(policy.authorize(&factory_reset, Some((1001, 1001))).is_ok());
+
+        let status = policy.authorize(&factory_reset, unprivileged).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        assert!(policy.authorize(&factory_reset, None).is_err());
+
+        // Exported settings contain secrets
+        assert!(policy.authorize(&export_settings, unprivileged).is_err());
+        assert!(policy
+            .authorize(&export_settings, Some((1000, 1000)))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_parse_uids() {
+        assert_eq!(parse_uids(""), Some(HashSet::new()));
+        assert_eq!(parse_uids("0,1000"), Some(HashSet::from([0, 1000])));
+        assert_eq!(parse_uids("0,user"), None);
+    }
+}
@@ -1,16 +1,21 @@
+#[cfg(unix)]
+mod access_control;
 pub mod types;
 
 use parity_tokio_ipc::Endpoint as IpcEndpoint;
 #[cfg(unix)]
 use std::{env, fs, os::unix::fs::PermissionsExt};
+use std::{future::Future, io};
+#[cfg(windows)]
 use std::{
-    future::Future,
-    io,
     pin::Pin,
     task::{Context, Poll},
 };
+#[cfg(windows)]
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
-use tonic::transport::{server::Connected, Endpoint, Server, Uri};
+#[cfg(windows)]
+use tonic::transport::server::Connected;
+use tonic::transport::{Endpoint, Server, Uri};
 use tower::service_fn;
 
 pub use tonic::{async_trait, transport::Channel, Code, Request, Response, Status};
@@ -19,6 +24,16 @@ pub type ManagementServiceClient =
     types::management_service_client::ManagementServiceClient<Channel>;
 pub use types::management_service_server::{ManagementService, ManagementServiceServer};
 
+/// Marks requests from clients that may only call read-only RPCs. Responses to such requests must
+/// not contain secrets.
+#[derive(Debug, Clone, Copy)]
+pub struct Unprivileged;
+
+/// Returns whether a request comes from a client that may call every RPC.
+pub fn is_privileged<T>(request: &Request<T>) -> bool {
+    request.extensions().get::<Unprivileged>().is_none()
+}
+
 #[cfg(unix)]
 lazy_static::lazy_static! {
     static ref MULLVAD_MANAGEMENT_SOCKET_GROUP: Option<String> = env::var("MULLVAD_MANAGEMENT_SOCKET_GROUP")
@@ -51,6 +66,10 @@ pub enum Error {
     #[cfg(unix)]
     #[error(display = "Failed to set group ID")]
     SetGidError(#[error(source)] nix::Error),
+
+    #[cfg(unix)]
+    #[error(display = "MULLVAD_MANAGEMENT_ADMIN_UIDS must be a comma-separated list of UIDs")]
+    InvalidAdminUids,
 }
 
 pub async fn new_rpc_client() -> Result<ManagementServiceClient, Error> {
@@ -69,6 +88,7 @@ pub async fn new_rpc_client() -> Result<ManagementServiceClient, Error> {
 
 pub type ServerJoinHandle = tokio::task::JoinHandle<Result<(), Error>>;
 
+#[cfg(windows)]
 pub async fn spawn_rpc_server<T: ManagementService, F: Future<Output = ()> + Send + 'static>(
     service: T,
     abort_rx: F,
@@ -87,7 +107,36 @@ pub async fn spawn_rpc_server<T: ManagementService, F: Future<Output = ()> + Sen
     );
     let incoming = endpoint.incoming().map_err(Error::StartServerError)?;
 
-    #[cfg(unix)]
+    Ok(tokio::spawn(async move {
+        Server::builder()
+            .add_service(ManagementServiceServer::new(service))
+            .serve_with_incoming_shutdown(incoming.map_ok(StreamBox), abort_rx)
+            .await
+            .map_err(Error::GrpcTransportError)
+    }))
+}
+
+/// Starts the management interface server on a Unix domain socket. Unlike on Windows, the socket
+/// is accepted directly, so that the credentials of clients are available to the access control.
+#[cfg(unix)]
+pub async fn spawn_rpc_server<T: ManagementService, F: Future<Output = ()> + Send + 'static>(
+    service: T,
+    abort_rx: F,
+) -> std::result::Result<ServerJoinHandle, Error> {
+    use access_control::{AccessControl, AccessPolicy};
+    use tokio::net::UnixListener;
+
+    let socket_path = mullvad_paths::get_rpc_socket_path();
+
+    let listener = UnixListener::bind(&socket_path).map_err(Error::StartServerError)?;
+    fs::set_permissions(&socket_path, PermissionsExt::from_mode(0o766))
+        .map_err(Error::PermissionsError)?;
+    let incoming = futures::stream::poll_fn(move |cx| {
+        listener
+            .poll_accept(cx)
+            .map(|result| Some(result.map(|(stream, _addr)| stream)))
+    });
+
     if let Some(group_name) = &*MULLVAD_MANAGEMENT_SOCKET_GROUP {
         let group = nix::unistd::Group::from_name(group_name)
             .map_err(Error::ObtainGidError)?
@@ -97,17 +146,25 @@ pub async fn spawn_rpc_server<T: ManagementService, F: Future<Output = ()> + Sen
             .map_err(Error::PermissionsError)?;
     }
 
+    let policy = AccessPolicy::from_env()?;
+    log::info!("Restricting management interface access: {:?}", policy);
+    let router = Server::builder().add_service(AccessControl::new(
+        ManagementServiceServer::new(service),
+        policy,
+    ));
+
     Ok(tokio::spawn(async move {
-        Server::builder()
-            .add_service(ManagementServiceServer::new(service))
-            .serve_with_incoming_shutdown(incoming.map_ok(StreamBox), abort_rx)
+        router
+            .serve_with_incoming_shutdown(incoming, abort_rx)
             .await
             .map_err(Error::GrpcTransportError)
     }))
 }
 
+#[cfg(windows)]
 #[derive(Debug)]
 struct StreamBox<T: AsyncRead + AsyncWrite>(pub T);
+#[cfg(windows)]
 impl<T: AsyncRead + AsyncWrite> Connected for StreamBox<T> {
     type ConnectInfo = Option<()>;
 
@@ -115,6 +172,7 @@ impl<T: AsyncRead + AsyncWrite> Connected for StreamBox<T> {
         None
     }
 }
+#[cfg(windows)]
 impl<T: AsyncRead + AsyncWrite + Unpin> AsyncRead for StreamBox<T> {
     fn poll_read(
         mut self: Pin<&mut Self>,
@@ -124,6 +182,7 @@ impl<T: AsyncRead + AsyncWrite + Unpin> AsyncRead for StreamBox<T> {
         Pin::new(&mut self.0).poll_read(cx, buf)
     }
 }
+#[cfg(windows)]
 impl<T: AsyncRead + AsyncWrite + Unpin> AsyncWrite for StreamBox<T> {
     fn poll_write(
         mut self: Pin<&mut Self>,
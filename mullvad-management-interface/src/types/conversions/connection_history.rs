@@ -0,0 +1,80 @@
+use crate::types::{proto, FromProtobufTypeError};
+use chrono::TimeZone;
+use mullvad_types::connection_history::{ConnectionHistoryEntry, SessionSummary, TrafficStats};
+use prost_types::Timestamp;
+
+impl From<ConnectionHistoryEntry> for proto::ConnectionHistoryEntry {
+    fn from(entry: ConnectionHistoryEntry) -> Self {
+        proto::ConnectionHistoryEntry {
+            timestamp: Some(Timestamp {
+                seconds: entry.timestamp.timestamp(),
+                nanos: entry.timestamp.timestamp_subsec_nanos() as i32,
+            }),
+            state: Some(proto::TunnelState::from(entry.state)),
+            ended_session: entry.ended_session.map(proto::SessionSummary::from),
+        }
+    }
+}
+
+impl From<SessionSummary> for proto::SessionSummary {
+    fn from(session: SessionSummary) -> Self {
+        proto::SessionSummary {
+            duration: Some(
+                prost_types::Duration::try_from(session.duration)
+                    .expect("Failed to convert session duration"),
+            ),
+            traffic: session.traffic.map(|traffic| proto::TrafficStats {
+                rx_bytes: traffic.rx_bytes,
+                tx_bytes: traffic.tx_bytes,
+            }),
+        }
+    }
+}
+
+impl TryFrom<proto::ConnectionHistoryEntry> for ConnectionHistoryEntry {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(entry: proto::ConnectionHistoryEntry) -> Result<Self, Self::Error> {
+        let timestamp = entry
+            .timestamp
+            .ok_or(FromProtobufTypeError::InvalidArgument(
+                "missing 'timestamp' field",
+            ))?;
+        let timestamp = chrono::Utc
+            .timestamp_opt(timestamp.seconds, timestamp.nanos as u32)
+            .single()
+            .ok_or(FromProtobufTypeError::InvalidArgument("invalid timestamp"))?;
+        let state = entry.state.ok_or(FromProtobufTypeError::InvalidArgument(
+            "missing tunnel state",
+        ))?;
+
+        Ok(ConnectionHistoryEntry {
+            timestamp,
+            state: mullvad_types::states::TunnelState::try_from(state)?,
+            ended_session: entry
+                .ended_session
+                .map(SessionSummary::try_from)
+                .transpose()?,
+        })
+    }
+}
+
+impl TryFrom<proto::SessionSummary> for SessionSummary {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(session: proto::SessionSummary) -> Result<Self, Self::Error> {
+        let duration = session
+            .duration
+            .ok_or(FromProtobufTypeError::InvalidArgument(
+                "missing session duration",
+            ))?;
+        Ok(SessionSummary {
+            duration: std::time::Duration::try_from(duration)
+                .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid duration"))?,
+            traffic: session.traffic.map(|traffic| TrafficStats {
+                rx_bytes: traffic.rx_bytes,
+                tx_bytes: traffic.tx_bytes,
+            }),
+        })
+    }
+}
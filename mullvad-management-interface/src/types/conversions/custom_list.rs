@@ -0,0 +1,68 @@
+use crate::types::{proto, FromProtobufTypeError};
+use mullvad_types::{
+    custom_list::{CustomList, CustomListsSettings},
+    relay_constraints::{Constraint, LocationConstraint},
+};
+
+impl From<&CustomListsSettings> for proto::CustomListSettings {
+    fn from(settings: &CustomListsSettings) -> Self {
+        Self {
+            custom_lists: settings
+                .custom_lists
+                .iter()
+                .cloned()
+                .map(proto::CustomList::from)
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<proto::CustomListSettings> for CustomListsSettings {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(settings: proto::CustomListSettings) -> Result<Self, Self::Error> {
+        Ok(Self {
+            custom_lists: settings
+                .custom_lists
+                .into_iter()
+                .map(CustomList::try_from)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl From<CustomList> for proto::CustomList {
+    fn from(custom_list: CustomList) -> Self {
+        Self {
+            name: custom_list.name,
+            locations: custom_list
+                .locations
+                .into_iter()
+                .map(proto::RelayLocation::from)
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<proto::CustomList> for CustomList {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(custom_list: proto::CustomList) -> Result<Self, Self::Error> {
+        let locations = custom_list
+            .locations
+            .into_iter()
+            .map(|location| {
+                Constraint::<LocationConstraint>::from(location)
+                    .option()
+                    .ok_or(FromProtobufTypeError::InvalidArgument(
+                        "custom list locations must not be empty",
+                    ))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            name: custom_list.name,
+            locations,
+        })
+    }
+}
@@ -71,6 +71,13 @@ impl TryFrom<proto::ConnectionConfig> for mullvad_types::ConnectionConfig {
                     tunnel_addresses.push(address);
                 }
 
+                let persistent_keepalive = match peer.persistent_keepalive {
+                    0 => None,
+                    interval => Some(u16::try_from(interval).map_err(|_err| {
+                        FromProtobufTypeError::InvalidArgument("invalid keepalive interval")
+                    })?),
+                };
+
                 let mut allowed_ips = Vec::new();
                 for address in peer.allowed_ips {
                     let address = address
@@ -90,6 +97,7 @@ impl TryFrom<proto::ConnectionConfig> for mullvad_types::ConnectionConfig {
                             allowed_ips,
                             endpoint,
                             psk: None,
+                            persistent_keepalive,
                         },
                         exit_peer: None,
                         ipv4_gateway,
@@ -139,6 +147,9 @@ impl From<mullvad_types::ConnectionConfig> for proto::ConnectionConfig {
                                 .map(|address| address.to_string())
                                 .collect(),
                             endpoint: config.peer.endpoint.to_string(),
+                            persistent_keepalive: u32::from(
+                                config.peer.persistent_keepalive.unwrap_or(0),
+                            ),
                         }),
                         ipv4_gateway: config.ipv4_gateway.to_string(),
                         ipv6_gateway: config
@@ -137,3 +137,89 @@ impl From<proto::DevicePort> for mullvad_types::device::DevicePort {
         mullvad_types::device::DevicePort { id: port.id }
     }
 }
+
+impl TryFrom<proto::DeviceState> for mullvad_types::device::DeviceState {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(state: proto::DeviceState) -> Result<Self, Self::Error> {
+        use mullvad_types::device::DeviceState as MullvadState;
+
+        let state_type = proto::device_state::State::from_i32(state.state).ok_or(
+            FromProtobufTypeError::InvalidArgument("invalid device state"),
+        )?;
+        match state_type {
+            proto::device_state::State::LoggedIn => {
+                let device = state
+                    .device
+                    .ok_or(FromProtobufTypeError::InvalidArgument("missing device"))?;
+                Ok(MullvadState::LoggedIn(
+                    mullvad_types::device::AccountAndDevice::try_from(device)?,
+                ))
+            }
+            proto::device_state::State::LoggedOut => Ok(MullvadState::LoggedOut),
+            proto::device_state::State::Revoked => Ok(MullvadState::Revoked),
+        }
+    }
+}
+
+impl TryFrom<proto::DeviceEvent> for mullvad_types::device::DeviceEvent {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(event: proto::DeviceEvent) -> Result<Self, Self::Error> {
+        let cause = proto::device_event::Cause::from_i32(event.cause).ok_or(
+            FromProtobufTypeError::InvalidArgument("invalid device event cause"),
+        )?;
+        let new_state = event
+            .new_state
+            .ok_or(FromProtobufTypeError::InvalidArgument(
+                "missing device state",
+            ))?;
+        Ok(mullvad_types::device::DeviceEvent {
+            cause: mullvad_types::device::DeviceEventCause::from(cause),
+            new_state: mullvad_types::device::DeviceState::try_from(new_state)?,
+        })
+    }
+}
+
+impl From<proto::device_event::Cause> for mullvad_types::device::DeviceEventCause {
+    fn from(cause: proto::device_event::Cause) -> Self {
+        use mullvad_types::device::DeviceEventCause as MullvadEvent;
+        match cause {
+            proto::device_event::Cause::LoggedIn => MullvadEvent::LoggedIn,
+            proto::device_event::Cause::LoggedOut => MullvadEvent::LoggedOut,
+            proto::device_event::Cause::Revoked => MullvadEvent::Revoked,
+            proto::device_event::Cause::Updated => MullvadEvent::Updated,
+            proto::device_event::Cause::RotatedKey => MullvadEvent::RotatedKey,
+        }
+    }
+}
+
+impl TryFrom<proto::RemoveDeviceEvent> for mullvad_types::device::RemoveDeviceEvent {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(event: proto::RemoveDeviceEvent) -> Result<Self, Self::Error> {
+        Ok(mullvad_types::device::RemoveDeviceEvent {
+            account_token: event.account_token,
+            new_devices: event
+                .new_device_list
+                .into_iter()
+                .map(mullvad_types::device::Device::try_from)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl TryFrom<proto::AccountAndDevice> for mullvad_types::device::AccountAndDevice {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(device: proto::AccountAndDevice) -> Result<Self, Self::Error> {
+        Ok(mullvad_types::device::AccountAndDevice {
+            account_token: device.account_token,
+            device: mullvad_types::device::Device::try_from(
+                device
+                    .device
+                    .ok_or(FromProtobufTypeError::InvalidArgument("missing device"))?,
+            )?,
+        })
+    }
+}
@@ -1,13 +1,18 @@
 use std::str::FromStr;
 
+mod connection_history;
+mod custom_list;
 mod custom_tunnel;
 mod device;
 mod location;
 mod net;
+mod network_rules;
 pub mod relay_constraints;
 mod relay_list;
+mod relay_rotation;
 mod settings;
 mod states;
+mod tunnel_stats;
 mod version;
 mod wireguard;
 
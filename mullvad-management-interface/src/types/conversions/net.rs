@@ -31,6 +31,9 @@ impl From<talpid_types::net::TunnelEndpoint> for proto::TunnelEndpoint {
                     )),
                     obfuscation_type: match obfuscation_endpoint.obfuscation_type {
                         net::ObfuscationType::Udp2Tcp => i32::from(proto::ObfuscationType::Udp2tcp),
+                        net::ObfuscationType::Shadowsocks => {
+                            i32::from(proto::ObfuscationType::ShadowsocksUdp)
+                        }
                     },
                 }
             }),
@@ -100,6 +103,9 @@ impl TryFrom<proto::TunnelEndpoint> for talpid_types::net::TunnelEndpoint {
                             Some(proto::ObfuscationType::Udp2tcp) => {
                                 talpid_net::ObfuscationType::Udp2Tcp
                             }
+                            Some(proto::ObfuscationType::ShadowsocksUdp) => {
+                                talpid_net::ObfuscationType::Shadowsocks
+                            }
                             None => {
                                 return Err(FromProtobufTypeError::InvalidArgument(
                                     "unknown obfuscation type",
@@ -149,6 +155,59 @@ impl From<proto::TransportProtocol> for talpid_types::net::TransportProtocol {
     }
 }
 
+impl From<talpid_types::net::AllowListEndpoint> for proto::AllowListEndpoint {
+    fn from(endpoint: talpid_types::net::AllowListEndpoint) -> Self {
+        proto::AllowListEndpoint {
+            network: endpoint.network.to_string(),
+            port: u32::from(endpoint.port),
+            protocol: i32::from(proto::TransportProtocol::from(endpoint.protocol)),
+        }
+    }
+}
+
+impl TryFrom<proto::AllowListEndpoint> for talpid_types::net::AllowListEndpoint {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(endpoint: proto::AllowListEndpoint) -> Result<Self, Self::Error> {
+        Ok(talpid_types::net::AllowListEndpoint {
+            network: arg_from_str(&endpoint.network, "invalid allow list network")?,
+            port: u16::try_from(endpoint.port)
+                .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid allow list port"))?,
+            protocol: try_transport_protocol_from_i32(endpoint.protocol)?,
+        })
+    }
+}
+
+impl From<talpid_types::net::InboundRule> for proto::InboundRule {
+    fn from(rule: talpid_types::net::InboundRule) -> Self {
+        proto::InboundRule {
+            port: u32::from(rule.port),
+            protocol: i32::from(proto::TransportProtocol::from(rule.protocol)),
+            source: rule.source.to_string(),
+            interface: rule.interface,
+        }
+    }
+}
+
+impl TryFrom<proto::InboundRule> for talpid_types::net::InboundRule {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(rule: proto::InboundRule) -> Result<Self, Self::Error> {
+        if rule.interface.is_empty() {
+            return Err(FromProtobufTypeError::InvalidArgument(
+                "missing inbound rule interface",
+            ));
+        }
+        Ok(talpid_types::net::InboundRule {
+            port: u16::try_from(rule.port)
+                .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid inbound rule port"))?,
+            protocol: try_transport_protocol_from_i32(rule.protocol)?,
+            source: arg_from_str(&rule.source, "invalid inbound rule source")?,
+            interface: rule.interface,
+        })
+    }
+}
+
 impl TryFrom<proto::TunnelTypeConstraint> for Constraint<talpid_types::net::TunnelType> {
     type Error = FromProtobufTypeError;
 
@@ -189,3 +248,23 @@ pub fn try_transport_protocol_from_i32(
         ))?
         .into())
 }
+
+impl From<talpid_types::net::dns::DnsBlocklistStats> for proto::DnsBlocklistStats {
+    fn from(stats: talpid_types::net::dns::DnsBlocklistStats) -> Self {
+        proto::DnsBlocklistStats {
+            path: stats.path.to_string_lossy().into_owned(),
+            entries: stats.entries,
+            hits: stats.hits,
+        }
+    }
+}
+
+impl From<proto::DnsBlocklistStats> for talpid_types::net::dns::DnsBlocklistStats {
+    fn from(stats: proto::DnsBlocklistStats) -> Self {
+        talpid_types::net::dns::DnsBlocklistStats {
+            path: stats.path.into(),
+            entries: stats.entries,
+            hits: stats.hits,
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use crate::types::{proto, FromProtobufTypeError};
+use mullvad_types::network_rules::{
+    MacAddress, NetworkInfo, NetworkMatch, NetworkRule, NetworkRuleError, NetworkRulesSettings,
+    NetworkTrust,
+};
+
+impl From<&NetworkRulesSettings> for proto::NetworkRules {
+    fn from(settings: &NetworkRulesSettings) -> Self {
+        use proto::network_rules::UnknownNetworkTrust;
+
+        let unknown_network_trust = match settings.unknown_network_trust {
+            None => UnknownNetworkTrust::Unchanged,
+            Some(NetworkTrust::Trusted) => UnknownNetworkTrust::Trusted,
+            Some(NetworkTrust::Untrusted) => UnknownNetworkTrust::Untrusted,
+        };
+        Self {
+            rules: settings
+                .rules
+                .iter()
+                .cloned()
+                .map(proto::NetworkRule::from)
+                .collect(),
+            unknown_network_trust: i32::from(unknown_network_trust),
+        }
+    }
+}
+
+impl TryFrom<proto::NetworkRules> for NetworkRulesSettings {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(settings: proto::NetworkRules) -> Result<Self, Self::Error> {
+        use proto::network_rules::UnknownNetworkTrust;
+
+        let unknown_network_trust =
+            match UnknownNetworkTrust::from_i32(settings.unknown_network_trust) {
+                Some(UnknownNetworkTrust::Unchanged) => None,
+                Some(UnknownNetworkTrust::Trusted) => Some(NetworkTrust::Trusted),
+                Some(UnknownNetworkTrust::Untrusted) => Some(NetworkTrust::Untrusted),
+                None => {
+                    return Err(FromProtobufTypeError::InvalidArgument(
+                        "invalid unknown network trust",
+                    ))
+                }
+            };
+        let settings = Self {
+            rules: settings
+                .rules
+                .into_iter()
+                .map(NetworkRule::try_from)
+                .collect::<Result<_, _>>()?,
+            unknown_network_trust,
+        };
+        settings.validate().map_err(|error| match error {
+            NetworkRuleError::NoNetwork => {
+                FromProtobufTypeError::InvalidArgument("missing network of network rule")
+            }
+            NetworkRuleError::TrustedBySsidOnly => FromProtobufTypeError::InvalidArgument(
+                "trusted networks must not be matched by SSID alone",
+            ),
+        })?;
+        Ok(settings)
+    }
+}
+
+impl From<NetworkRule> for proto::NetworkRule {
+    fn from(rule: NetworkRule) -> Self {
+        use proto::network_rule::{network::Network, Trust};
+
+        let networks = rule
+            .networks
+            .into_iter()
+            .map(|network| {
+                let network = match network {
+                    NetworkMatch::Ssid(ssid) => Network::Ssid(ssid),
+                    NetworkMatch::ConnectionUuid(uuid) => Network::ConnectionUuid(uuid),
+                    NetworkMatch::GatewayMac(mac) => Network::GatewayMac(mac.to_string()),
+                };
+                proto::network_rule::Network {
+                    network: Some(network),
+                }
+            })
+            .collect();
+        let trust = match rule.trust {
+            NetworkTrust::Trusted => Trust::Trusted,
+            NetworkTrust::Untrusted => Trust::Untrusted,
+        };
+        Self {
+            networks,
+            trust: i32::from(trust),
+        }
+    }
+}
+
+impl TryFrom<proto::NetworkRule> for NetworkRule {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(rule: proto::NetworkRule) -> Result<Self, Self::Error> {
+        use proto::network_rule::{network::Network, Trust};
+
+        let networks = rule
+            .networks
+            .into_iter()
+            .map(|network| match network.network {
+                Some(Network::Ssid(ssid)) => Ok(NetworkMatch::Ssid(ssid)),
+                Some(Network::ConnectionUuid(uuid)) => Ok(NetworkMatch::ConnectionUuid(uuid)),
+                Some(Network::GatewayMac(mac)) => mac
+                    .parse()
+                    .map(NetworkMatch::GatewayMac)
+                    .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid MAC address")),
+                None => Err(FromProtobufTypeError::InvalidArgument(
+                    "missing network of network rule",
+                )),
+            })
+            .collect::<Result<_, _>>()?;
+        let trust = match Trust::from_i32(rule.trust) {
+            Some(Trust::Trusted) => NetworkTrust::Trusted,
+            Some(Trust::Untrusted) => NetworkTrust::Untrusted,
+            None => {
+                return Err(FromProtobufTypeError::InvalidArgument(
+                    "invalid network trust",
+                ))
+            }
+        };
+        Ok(Self { networks, trust })
+    }
+}
+
+impl From<NetworkInfo> for proto::NetworkInfo {
+    fn from(network: NetworkInfo) -> Self {
+        Self {
+            connection_uuid: network.connection_uuid,
+            connection_name: network.connection_name,
+            ssid: network.ssid.unwrap_or_default(),
+            gateway_mac: network
+                .gateway_mac
+                .map(|mac| mac.to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl TryFrom<proto::NetworkInfo> for NetworkInfo {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(network: proto::NetworkInfo) -> Result<Self, Self::Error> {
+        let gateway_mac = if network.gateway_mac.is_empty() {
+            None
+        } else {
+            Some(
+                network
+                    .gateway_mac
+                    .parse::<MacAddress>()
+                    .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid MAC address"))?,
+            )
+        };
+        Ok(Self {
+            connection_uuid: network.connection_uuid,
+            connection_name: network.connection_name,
+            ssid: Some(network.ssid).filter(|ssid| !ssid.is_empty()),
+            gateway_mac,
+        })
+    }
+}
@@ -26,6 +26,12 @@ impl TryFrom<&proto::WireguardConstraints>
             None => None,
         };
 
+        if !constraints.middle_locations.is_empty() {
+            return Err(FromProtobufTypeError::InvalidArgument(
+                "multihop with more than two hops is not supported",
+            ));
+        }
+
         Ok(mullvad_constraints::WireguardConstraints {
             port: if constraints.port == 0 {
                 Constraint::Any
@@ -39,10 +45,41 @@ impl TryFrom<&proto::WireguardConstraints>
                 .clone()
                 .map(Constraint::<mullvad_types::relay_constraints::LocationConstraint>::from)
                 .unwrap_or(Constraint::Any),
+            middle_locations: constraints
+                .middle_locations
+                .iter()
+                .cloned()
+                .map(Constraint::<mullvad_types::relay_constraints::LocationConstraint>::from)
+                .collect(),
+            diversity: constraints
+                .diversity
+                .clone()
+                .map(mullvad_constraints::MultihopDiversity::from)
+                .unwrap_or_default(),
         })
     }
 }
 
+impl From<proto::MultihopDiversity> for mullvad_types::relay_constraints::MultihopDiversity {
+    fn from(diversity: proto::MultihopDiversity) -> Self {
+        Self {
+            country: diversity.country,
+            provider: diversity.provider,
+            ownership: diversity.ownership,
+        }
+    }
+}
+
+impl From<mullvad_types::relay_constraints::MultihopDiversity> for proto::MultihopDiversity {
+    fn from(diversity: mullvad_types::relay_constraints::MultihopDiversity) -> Self {
+        Self {
+            country: diversity.country,
+            provider: diversity.provider,
+            ownership: diversity.ownership,
+        }
+    }
+}
+
 impl TryFrom<&proto::OpenvpnConstraints> for mullvad_types::relay_constraints::OpenVpnConstraints {
     type Error = FromProtobufTypeError;
 
@@ -177,6 +214,14 @@ impl From<RelaySettingsUpdate> for proto::RelaySettingsUpdate {
                                     .entry_location
                                     .option()
                                     .map(proto::RelayLocation::from),
+                                middle_locations: wireguard_constraints
+                                    .middle_locations
+                                    .into_iter()
+                                    .map(proto::RelayLocation::from)
+                                    .collect(),
+                                diversity: Some(proto::MultihopDiversity::from(
+                                    wireguard_constraints.diversity,
+                                )),
                             },
                         ),
                         openvpn_constraints: constraints.openvpn_constraints.map(
@@ -311,6 +356,24 @@ impl From<mullvad_types::relay_constraints::BridgeState> for proto::BridgeState
     }
 }
 
+impl From<mullvad_types::relay_constraints::RelaySelectionStrategy>
+    for proto::RelaySelectionStrategy
+{
+    fn from(strategy: mullvad_types::relay_constraints::RelaySelectionStrategy) -> Self {
+        use mullvad_types::relay_constraints::RelaySelectionStrategy;
+        Self {
+            strategy: i32::from(match strategy {
+                RelaySelectionStrategy::Weighted => {
+                    proto::relay_selection_strategy::Strategy::Weighted
+                }
+                RelaySelectionStrategy::LowestLatency => {
+                    proto::relay_selection_strategy::Strategy::LowestLatency
+                }
+            }),
+        }
+    }
+}
+
 impl From<&mullvad_types::relay_constraints::ObfuscationSettings> for proto::ObfuscationSettings {
     fn from(settings: &mullvad_types::relay_constraints::ObfuscationSettings) -> Self {
         use mullvad_types::relay_constraints::SelectedObfuscation;
@@ -320,10 +383,16 @@ impl From<&mullvad_types::relay_constraints::ObfuscationSettings> for proto::Obf
             SelectedObfuscation::Udp2Tcp => {
                 proto::obfuscation_settings::SelectedObfuscation::Udp2tcp
             }
+            SelectedObfuscation::Shadowsocks => {
+                proto::obfuscation_settings::SelectedObfuscation::Shadowsocks
+            }
         });
         Self {
             selected_obfuscation,
             udp2tcp: Some(proto::Udp2TcpObfuscationSettings::from(&settings.udp2tcp)),
+            shadowsocks: Some(proto::ShadowsocksObfuscationSettings::from(
+                &settings.shadowsocks,
+            )),
         }
     }
 }
@@ -338,6 +407,16 @@ impl From<&mullvad_types::relay_constraints::Udp2TcpObfuscationSettings>
     }
 }
 
+impl From<&mullvad_types::relay_constraints::ShadowsocksObfuscationSettings>
+    for proto::ShadowsocksObfuscationSettings
+{
+    fn from(settings: &mullvad_types::relay_constraints::ShadowsocksObfuscationSettings) -> Self {
+        Self {
+            port: u32::from(settings.port.unwrap_or(0)),
+        }
+    }
+}
+
 impl From<mullvad_types::relay_constraints::BridgeSettings> for proto::BridgeSettings {
     fn from(settings: mullvad_types::relay_constraints::BridgeSettings) -> Self {
         use mullvad_types::relay_constraints::BridgeSettings as MullvadBridgeSettings;
@@ -438,6 +517,15 @@ impl From<mullvad_types::relay_constraints::RelaySettings> for proto::RelaySetti
                             .entry_location
                             .option()
                             .map(proto::RelayLocation::from),
+                        middle_locations: constraints
+                            .wireguard_constraints
+                            .middle_locations
+                            .into_iter()
+                            .map(proto::RelayLocation::from)
+                            .collect(),
+                        diversity: Some(proto::MultihopDiversity::from(
+                            constraints.wireguard_constraints.diversity,
+                        )),
                     }),
 
                     openvpn_constraints: Some(proto::OpenvpnConstraints {
@@ -503,6 +591,11 @@ impl From<mullvad_types::relay_constraints::LocationConstraint> for proto::Relay
                 country,
                 city,
                 hostname,
+                ..Default::default()
+            },
+            LocationConstraint::CustomList(custom_list) => Self {
+                custom_list,
+                ..Default::default()
             },
         }
     }
@@ -514,7 +607,9 @@ impl From<proto::RelayLocation>
     fn from(location: proto::RelayLocation) -> Self {
         use mullvad_types::relay_constraints::LocationConstraint;
 
-        if let Some(hostname) = option_from_proto_string(location.hostname) {
+        if let Some(custom_list) = option_from_proto_string(location.custom_list) {
+            Constraint::Only(LocationConstraint::CustomList(custom_list))
+        } else if let Some(hostname) = option_from_proto_string(location.hostname) {
             Constraint::Only(LocationConstraint::Hostname(
                 location.country,
                 location.city,
@@ -617,6 +712,7 @@ impl TryFrom<proto::ObfuscationSettings> for mullvad_types::relay_constraints::O
                 Some(IpcSelectedObfuscation::Auto) => SelectedObfuscation::Auto,
                 Some(IpcSelectedObfuscation::Off) => SelectedObfuscation::Off,
                 Some(IpcSelectedObfuscation::Udp2tcp) => SelectedObfuscation::Udp2Tcp,
+                Some(IpcSelectedObfuscation::Shadowsocks) => SelectedObfuscation::Shadowsocks,
                 None => {
                     return Err(FromProtobufTypeError::InvalidArgument(
                         "invalid selected obfuscator",
@@ -635,9 +731,20 @@ impl TryFrom<proto::ObfuscationSettings> for mullvad_types::relay_constraints::O
             }
         };
 
+        // Older clients do not know about Shadowsocks, so fall back to the defaults.
+        let shadowsocks = match settings.shadowsocks {
+            Some(settings) => {
+                mullvad_types::relay_constraints::ShadowsocksObfuscationSettings::try_from(
+                    &settings,
+                )?
+            }
+            None => Default::default(),
+        };
+
         Ok(Self {
             selected_obfuscation,
             udp2tcp,
+            shadowsocks,
         })
     }
 }
@@ -658,6 +765,22 @@ impl TryFrom<&proto::Udp2TcpObfuscationSettings>
     }
 }
 
+impl TryFrom<&proto::ShadowsocksObfuscationSettings>
+    for mullvad_types::relay_constraints::ShadowsocksObfuscationSettings
+{
+    type Error = FromProtobufTypeError;
+
+    fn try_from(settings: &proto::ShadowsocksObfuscationSettings) -> Result<Self, Self::Error> {
+        Ok(Self {
+            port: if settings.port == 0 {
+                Constraint::Any
+            } else {
+                Constraint::Only(settings.port as u16)
+            },
+        })
+    }
+}
+
 impl TryFrom<proto::BridgeState> for mullvad_types::relay_constraints::BridgeState {
     type Error = FromProtobufTypeError;
 
@@ -679,6 +802,27 @@ impl TryFrom<proto::BridgeState> for mullvad_types::relay_constraints::BridgeSta
     }
 }
 
+impl TryFrom<proto::RelaySelectionStrategy>
+    for mullvad_types::relay_constraints::RelaySelectionStrategy
+{
+    type Error = FromProtobufTypeError;
+
+    fn try_from(strategy: proto::RelaySelectionStrategy) -> Result<Self, Self::Error> {
+        use mullvad_types::relay_constraints::RelaySelectionStrategy;
+        match proto::relay_selection_strategy::Strategy::from_i32(strategy.strategy) {
+            Some(proto::relay_selection_strategy::Strategy::Weighted) => {
+                Ok(RelaySelectionStrategy::Weighted)
+            }
+            Some(proto::relay_selection_strategy::Strategy::LowestLatency) => {
+                Ok(RelaySelectionStrategy::LowestLatency)
+            }
+            None => Err(FromProtobufTypeError::InvalidArgument(
+                "invalid relay selection strategy",
+            )),
+        }
+    }
+}
+
 impl TryFrom<proto::TransportPort> for mullvad_types::relay_constraints::TransportPort {
     type Error = FromProtobufTypeError;
 
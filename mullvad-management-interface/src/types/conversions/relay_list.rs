@@ -1,7 +1,11 @@
 use crate::types::{
-    conversions::{bytes_to_pubkey, option_from_proto_string, to_proto_any, try_from_proto_any},
+    conversions::{
+        arg_from_str, bytes_to_pubkey, net::try_transport_protocol_from_i32,
+        option_from_proto_string, to_proto_any, try_from_proto_any,
+    },
     proto, FromProtobufTypeError,
 };
+use chrono::TimeZone;
 
 impl From<mullvad_types::relay_list::RelayList> for proto::RelayList {
     fn from(relay_list: mullvad_types::relay_list::RelayList) -> Self {
@@ -203,3 +207,170 @@ impl TryFrom<proto::Relay> for mullvad_types::relay_list::Relay {
         })
     }
 }
+
+impl TryFrom<proto::RelayList> for mullvad_types::relay_list::RelayList {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(relay_list: proto::RelayList) -> Result<Self, Self::Error> {
+        Ok(mullvad_types::relay_list::RelayList {
+            etag: None,
+            countries: relay_list
+                .countries
+                .into_iter()
+                .map(mullvad_types::relay_list::RelayListCountry::try_from)
+                .collect::<Result<_, _>>()?,
+            openvpn: mullvad_types::relay_list::OpenVpnEndpointData::try_from(
+                relay_list.openvpn.unwrap_or_default(),
+            )?,
+            bridge: mullvad_types::relay_list::BridgeEndpointData::try_from(
+                relay_list.bridge.unwrap_or_default(),
+            )?,
+            wireguard: mullvad_types::relay_list::WireguardEndpointData::try_from(
+                relay_list
+                    .wireguard
+                    .ok_or(FromProtobufTypeError::InvalidArgument(
+                        "missing wireguard endpoint data",
+                    ))?,
+            )?,
+        })
+    }
+}
+
+impl TryFrom<proto::OpenVpnEndpointData> for mullvad_types::relay_list::OpenVpnEndpointData {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(openvpn: proto::OpenVpnEndpointData) -> Result<Self, Self::Error> {
+        Ok(mullvad_types::relay_list::OpenVpnEndpointData {
+            ports: openvpn
+                .endpoints
+                .into_iter()
+                .map(|endpoint| {
+                    Ok(mullvad_types::relay_list::OpenVpnEndpoint {
+                        port: try_port_from_u32(endpoint.port)?,
+                        protocol: try_transport_protocol_from_i32(endpoint.protocol)?,
+                    })
+                })
+                .collect::<Result<_, FromProtobufTypeError>>()?,
+        })
+    }
+}
+
+impl TryFrom<proto::BridgeEndpointData> for mullvad_types::relay_list::BridgeEndpointData {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(bridge: proto::BridgeEndpointData) -> Result<Self, Self::Error> {
+        Ok(mullvad_types::relay_list::BridgeEndpointData {
+            shadowsocks: bridge
+                .shadowsocks
+                .into_iter()
+                .map(|endpoint| {
+                    Ok(mullvad_types::relay_list::ShadowsocksEndpointData {
+                        port: try_port_from_u32(endpoint.port)?,
+                        cipher: endpoint.cipher,
+                        password: endpoint.password,
+                        protocol: try_transport_protocol_from_i32(endpoint.protocol)?,
+                    })
+                })
+                .collect::<Result<_, FromProtobufTypeError>>()?,
+        })
+    }
+}
+
+impl TryFrom<proto::WireguardEndpointData> for mullvad_types::relay_list::WireguardEndpointData {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(wireguard: proto::WireguardEndpointData) -> Result<Self, Self::Error> {
+        Ok(mullvad_types::relay_list::WireguardEndpointData {
+            port_ranges: wireguard
+                .port_ranges
+                .into_iter()
+                .map(|range| {
+                    Ok((
+                        try_port_from_u32(range.first)?,
+                        try_port_from_u32(range.last)?,
+                    ))
+                })
+                .collect::<Result<_, FromProtobufTypeError>>()?,
+            ipv4_gateway: arg_from_str(&wireguard.ipv4_gateway, "invalid IPv4 gateway")?,
+            ipv6_gateway: arg_from_str(&wireguard.ipv6_gateway, "invalid IPv6 gateway")?,
+            udp2tcp_ports: wireguard
+                .udp2tcp_ports
+                .into_iter()
+                .map(try_port_from_u32)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl TryFrom<proto::RelayListCountry> for mullvad_types::relay_list::RelayListCountry {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(country: proto::RelayListCountry) -> Result<Self, Self::Error> {
+        let cities = country
+            .cities
+            .into_iter()
+            .map(|city| {
+                Ok(mullvad_types::relay_list::RelayListCity {
+                    name: city.name,
+                    code: city.code,
+                    latitude: city.latitude,
+                    longitude: city.longitude,
+                    relays: city
+                        .relays
+                        .into_iter()
+                        .map(mullvad_types::relay_list::Relay::try_from)
+                        .collect::<Result<_, _>>()?,
+                })
+            })
+            .collect::<Result<_, FromProtobufTypeError>>()?;
+
+        Ok(mullvad_types::relay_list::RelayListCountry {
+            name: country.name,
+            code: country.code,
+            cities,
+        })
+    }
+}
+
+impl From<mullvad_types::relay_list::RelayListVerification> for proto::RelayListVerification {
+    fn from(verification: mullvad_types::relay_list::RelayListVerification) -> Self {
+        proto::RelayListVerification {
+            path: verification.path.to_string_lossy().into_owned(),
+            error: verification.error.unwrap_or_default(),
+            relay_count: u32::try_from(verification.relay_count).unwrap_or(u32::MAX),
+            last_updated: verification
+                .last_updated
+                .map(|last_updated| prost_types::Timestamp {
+                    seconds: last_updated.timestamp(),
+                    nanos: last_updated.timestamp_subsec_nanos() as i32,
+                }),
+        }
+    }
+}
+
+impl TryFrom<proto::RelayListVerification> for mullvad_types::relay_list::RelayListVerification {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(verification: proto::RelayListVerification) -> Result<Self, Self::Error> {
+        let last_updated = verification
+            .last_updated
+            .map(|last_updated| {
+                chrono::Utc
+                    .timestamp_opt(last_updated.seconds, last_updated.nanos as u32)
+                    .single()
+                    .ok_or(FromProtobufTypeError::InvalidArgument("invalid timestamp"))
+            })
+            .transpose()?;
+
+        Ok(mullvad_types::relay_list::RelayListVerification {
+            path: verification.path.into(),
+            error: option_from_proto_string(verification.error),
+            relay_count: verification.relay_count as usize,
+            last_updated,
+        })
+    }
+}
+
+fn try_port_from_u32(port: u32) -> Result<u16, FromProtobufTypeError> {
+    u16::try_from(port).map_err(|_| FromProtobufTypeError::InvalidArgument("invalid port"))
+}
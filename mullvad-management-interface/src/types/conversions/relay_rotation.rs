@@ -0,0 +1,61 @@
+use crate::types::{proto, FromProtobufTypeError};
+use chrono::{NaiveTime, Timelike};
+use mullvad_types::relay_rotation::{RelayRotation, RelayRotationError};
+
+impl From<Option<&RelayRotation>> for proto::RelayRotation {
+    fn from(rotation: Option<&RelayRotation>) -> Self {
+        use proto::relay_rotation::{Daily, Schedule, TimeOfDay};
+
+        let schedule = rotation.map(|rotation| match rotation {
+            RelayRotation::Interval(interval) => Schedule::Interval(
+                prost_types::Duration::try_from(*interval)
+                    .expect("Failed to convert relay rotation interval"),
+            ),
+            RelayRotation::Daily(times) => Schedule::Daily(Daily {
+                times: times
+                    .iter()
+                    .map(|time| TimeOfDay {
+                        hour: time.hour(),
+                        minute: time.minute(),
+                    })
+                    .collect(),
+            }),
+        });
+        Self { schedule }
+    }
+}
+
+impl TryFrom<proto::RelayRotation> for Option<RelayRotation> {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(rotation: proto::RelayRotation) -> Result<Self, Self::Error> {
+        use proto::relay_rotation::Schedule;
+
+        let rotation = match rotation.schedule {
+            Some(Schedule::Interval(interval)) => RelayRotation::interval(
+                std::time::Duration::try_from(interval)
+                    .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid duration"))?,
+            ),
+            Some(Schedule::Daily(daily)) => RelayRotation::daily(
+                daily
+                    .times
+                    .into_iter()
+                    .map(|time| {
+                        NaiveTime::from_hms_opt(time.hour, time.minute, 0).ok_or(
+                            FromProtobufTypeError::InvalidArgument("invalid time of day"),
+                        )
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            None => return Ok(None),
+        };
+        rotation.map(Some).map_err(|error| {
+            FromProtobufTypeError::InvalidArgument(match error {
+                RelayRotationError::IntervalTooSmall => {
+                    "relay rotation interval must be at least 10 minutes"
+                }
+                RelayRotationError::NoTimesOfDay => "no relay rotation times given",
+            })
+        })
+    }
+}
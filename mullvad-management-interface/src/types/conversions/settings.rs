@@ -3,7 +3,7 @@ use talpid_types::ErrorExt;
 
 impl From<&mullvad_types::settings::Settings> for proto::Settings {
     fn from(settings: &mullvad_types::settings::Settings) -> Self {
-        #[cfg(windows)]
+        #[cfg(any(windows, target_os = "linux"))]
         let split_tunnel = {
             let mut converted_list = vec![];
             for path in settings.split_tunnel.apps.clone().iter() {
@@ -18,11 +18,32 @@ impl From<&mullvad_types::settings::Settings> for proto::Settings {
             Some(proto::SplitTunnelSettings {
                 enable_exclusions: settings.split_tunnel.enable_exclusions,
                 apps: converted_list,
+                #[cfg(windows)]
+                excluded_users: None,
+                #[cfg(target_os = "linux")]
+                excluded_users: Some(proto::ExcludedUsers::from(
+                    &settings.split_tunnel.excluded_users,
+                )),
             })
         };
-        #[cfg(not(windows))]
+        #[cfg(not(any(windows, target_os = "linux")))]
         let split_tunnel = None;
 
+        #[cfg(target_os = "linux")]
+        let network_rules = Some(proto::NetworkRules::from(&settings.network_rules));
+        #[cfg(not(target_os = "linux"))]
+        let network_rules = None;
+
+        #[cfg(target_os = "linux")]
+        let firewall_inbound_rules = settings
+            .firewall_inbound_rules
+            .iter()
+            .cloned()
+            .map(proto::InboundRule::from)
+            .collect();
+        #[cfg(not(target_os = "linux"))]
+        let firewall_inbound_rules = vec![];
+
         Self {
             relay_settings: Some(proto::RelaySettings::from(settings.get_relay_settings())),
             bridge_settings: Some(proto::BridgeSettings::from(
@@ -30,14 +51,183 @@ impl From<&mullvad_types::settings::Settings> for proto::Settings {
             )),
             bridge_state: Some(proto::BridgeState::from(settings.bridge_state)),
             allow_lan: settings.allow_lan,
+            lan_networks: settings
+                .lan_networks
+                .iter()
+                .map(|network| network.to_string())
+                .collect(),
             block_when_disconnected: settings.block_when_disconnected,
             auto_connect: settings.auto_connect,
             tunnel_options: Some(proto::TunnelOptions::from(&settings.tunnel_options)),
             show_beta_releases: settings.show_beta_releases,
+            record_connection_history: settings.record_connection_history,
             obfuscation_settings: Some(proto::ObfuscationSettings::from(
                 &settings.obfuscation_settings,
             )),
             split_tunnel,
+            custom_lists: Some(proto::CustomListSettings::from(&settings.custom_lists)),
+            relay_selection_strategy: Some(proto::RelaySelectionStrategy::from(
+                settings.relay_selection_strategy,
+            )),
+            network_rules,
+            relay_rotation: Some(proto::RelayRotation::from(settings.relay_rotation.as_ref())),
+            firewall_allow_list: settings
+                .firewall_allow_list
+                .iter()
+                .cloned()
+                .map(proto::AllowListEndpoint::from)
+                .collect(),
+            firewall_inbound_rules,
+        }
+    }
+}
+
+impl proto::Settings {
+    /// Removes the credentials of a custom tunnel, so that the settings can be shown to clients
+    /// that are not allowed to see them.
+    pub fn redact_secrets(&mut self) {
+        use proto::{
+            connection_config::Config, relay_settings::Endpoint, CustomRelaySettings, RelaySettings,
+        };
+
+        if let Some(RelaySettings {
+            endpoint:
+                Some(Endpoint::Custom(CustomRelaySettings {
+                    config: Some(config),
+                    ..
+                })),
+        }) = &mut self.relay_settings
+        {
+            match &mut config.config {
+                Some(Config::Openvpn(config)) => config.password.clear(),
+                Some(Config::Wireguard(config)) => {
+                    if let Some(tunnel) = &mut config.tunnel {
+                        tunnel.private_key.clear();
+                    }
+                }
+                None => (),
+            }
+        }
+    }
+}
+
+impl TryFrom<proto::Settings> for mullvad_types::settings::Settings {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(settings: proto::Settings) -> Result<Self, Self::Error> {
+        use mullvad_types::relay_constraints;
+
+        let mut converted = mullvad_types::settings::Settings::default();
+
+        converted.set_relay_settings(relay_constraints::RelaySettings::try_from(
+            settings
+                .relay_settings
+                .ok_or(FromProtobufTypeError::InvalidArgument(
+                    "missing relay settings",
+                ))?,
+        )?);
+        converted.bridge_settings =
+            relay_constraints::BridgeSettings::try_from(settings.bridge_settings.ok_or(
+                FromProtobufTypeError::InvalidArgument("missing bridge settings"),
+            )?)?;
+        converted.bridge_state =
+            relay_constraints::BridgeState::try_from(settings.bridge_state.ok_or(
+                FromProtobufTypeError::InvalidArgument("missing bridge state"),
+            )?)?;
+        converted.obfuscation_settings = relay_constraints::ObfuscationSettings::try_from(
+            settings
+                .obfuscation_settings
+                .ok_or(FromProtobufTypeError::InvalidArgument(
+                    "missing obfuscation settings",
+                ))?,
+        )?;
+        converted.custom_lists = mullvad_types::custom_list::CustomListsSettings::try_from(
+            settings
+                .custom_lists
+                .ok_or(FromProtobufTypeError::InvalidArgument(
+                    "missing custom lists",
+                ))?,
+        )?;
+        if let Some(strategy) = settings.relay_selection_strategy {
+            converted.relay_selection_strategy =
+                relay_constraints::RelaySelectionStrategy::try_from(strategy)?;
+        }
+        if let Some(rotation) = settings.relay_rotation {
+            converted.relay_rotation = Option::try_from(rotation)?;
+        }
+        converted.tunnel_options =
+            mullvad_types::settings::TunnelOptions::try_from(settings.tunnel_options.ok_or(
+                FromProtobufTypeError::InvalidArgument("missing tunnel options"),
+            )?)?;
+
+        converted.allow_lan = settings.allow_lan;
+        converted.lan_networks = settings
+            .lan_networks
+            .iter()
+            .map(|network| {
+                network
+                    .parse()
+                    .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid LAN network"))
+            })
+            .collect::<Result<_, _>>()?;
+        converted.block_when_disconnected = settings.block_when_disconnected;
+        converted.auto_connect = settings.auto_connect;
+        converted.show_beta_releases = settings.show_beta_releases;
+        converted.record_connection_history = settings.record_connection_history;
+        converted.firewall_allow_list = settings
+            .firewall_allow_list
+            .into_iter()
+            .map(talpid_types::net::AllowListEndpoint::try_from)
+            .collect::<Result<_, _>>()?;
+
+        #[cfg(target_os = "linux")]
+        {
+            converted.firewall_inbound_rules = settings
+                .firewall_inbound_rules
+                .into_iter()
+                .map(talpid_types::net::InboundRule::try_from)
+                .collect::<Result<_, _>>()?;
+            if let Some(network_rules) = settings.network_rules {
+                converted.network_rules =
+                    mullvad_types::network_rules::NetworkRulesSettings::try_from(network_rules)?;
+            }
+        }
+
+        #[cfg(any(windows, target_os = "linux"))]
+        if let Some(split_tunnel) = settings.split_tunnel {
+            converted.split_tunnel.enable_exclusions = split_tunnel.enable_exclusions;
+            converted.split_tunnel.apps = split_tunnel
+                .apps
+                .into_iter()
+                .map(std::path::PathBuf::from)
+                .collect();
+            #[cfg(target_os = "linux")]
+            if let Some(excluded_users) = split_tunnel.excluded_users {
+                converted.split_tunnel.excluded_users =
+                    mullvad_types::settings::ExcludedUsers::from(excluded_users);
+            }
+        }
+
+        Ok(converted)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl From<&mullvad_types::settings::ExcludedUsers> for proto::ExcludedUsers {
+    fn from(users: &mullvad_types::settings::ExcludedUsers) -> Self {
+        proto::ExcludedUsers {
+            uids: users.uids.iter().cloned().collect(),
+            gids: users.gids.iter().cloned().collect(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl From<proto::ExcludedUsers> for mullvad_types::settings::ExcludedUsers {
+    fn from(users: proto::ExcludedUsers) -> Self {
+        mullvad_types::settings::ExcludedUsers {
+            uids: users.uids.into_iter().collect(),
+            gids: users.gids.into_iter().collect(),
         }
     }
 }
@@ -65,7 +255,36 @@ impl From<&mullvad_types::settings::DnsOptions> for proto::DnsOptions {
                     .iter()
                     .map(|addr| addr.to_string())
                     .collect(),
+                encrypted_servers: options
+                    .custom_options
+                    .encrypted_servers
+                    .iter()
+                    .map(|server| server.to_string())
+                    .collect(),
             }),
+            blocklist_options: Some(proto::DnsBlocklistOptions {
+                lists: options
+                    .blocklist_options
+                    .lists
+                    .iter()
+                    .filter_map(|path| match path.to_str() {
+                        Some(path) => Some(path.to_string()),
+                        None => {
+                            log::error!("failed to convert OS string: {:?}", path);
+                            None
+                        }
+                    })
+                    .collect(),
+                allowlist: options.blocklist_options.allowlist.clone(),
+            }),
+            split_dns_rules: options
+                .split_dns_rules
+                .iter()
+                .map(|rule| proto::SplitDnsRule {
+                    domain: rule.domain.clone(),
+                    resolvers: rule.resolvers.iter().map(|addr| addr.to_string()).collect(),
+                })
+                .collect(),
         }
     }
 }
@@ -172,13 +391,37 @@ impl TryFrom<proto::TunnelOptions> for mullvad_types::settings::TunnelOptions {
     }
 }
 
+impl TryFrom<proto::SplitDnsRule> for talpid_types::net::dns::SplitDnsRule {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(rule: proto::SplitDnsRule) -> Result<Self, Self::Error> {
+        if rule.domain.trim_end_matches('.').is_empty() {
+            return Err(FromProtobufTypeError::InvalidArgument(
+                "missing split DNS domain",
+            ));
+        }
+        Ok(talpid_types::net::dns::SplitDnsRule {
+            domain: rule.domain,
+            resolvers: rule
+                .resolvers
+                .into_iter()
+                .map(|addr| {
+                    addr.parse()
+                        .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid IP address"))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        })
+    }
+}
+
 impl TryFrom<proto::DnsOptions> for mullvad_types::settings::DnsOptions {
     type Error = FromProtobufTypeError;
 
     fn try_from(options: proto::DnsOptions) -> Result<Self, Self::Error> {
         use mullvad_types::settings::{
             CustomDnsOptions as MullvadCustomDnsOptions,
-            DefaultDnsOptions as MullvadDefaultDnsOptions, DnsOptions as MullvadDnsOptions,
+            DefaultDnsOptions as MullvadDefaultDnsOptions,
+            DnsBlocklistOptions as MullvadDnsBlocklistOptions, DnsOptions as MullvadDnsOptions,
             DnsState as MullvadDnsState,
         };
 
@@ -204,6 +447,8 @@ impl TryFrom<proto::DnsOptions> for mullvad_types::settings::DnsOptions {
                 .ok_or(FromProtobufTypeError::InvalidArgument(
                     "missing default DNS options",
                 ))?;
+        // Clients that are unaware of blocklists do not send any blocklist options
+        let blocklist_options = options.blocklist_options.unwrap_or_default();
 
         Ok(MullvadDnsOptions {
             state,
@@ -224,7 +469,29 @@ impl TryFrom<proto::DnsOptions> for mullvad_types::settings::DnsOptions {
                         })
                     })
                     .collect::<Result<Vec<_>, _>>()?,
+                encrypted_servers: custom_options
+                    .encrypted_servers
+                    .into_iter()
+                    .map(|server| {
+                        server.parse().map_err(|_| {
+                            FromProtobufTypeError::InvalidArgument("invalid encrypted DNS server")
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+            },
+            blocklist_options: MullvadDnsBlocklistOptions {
+                lists: blocklist_options
+                    .lists
+                    .into_iter()
+                    .map(std::path::PathBuf::from)
+                    .collect(),
+                allowlist: blocklist_options.allowlist,
             },
+            split_dns_rules: options
+                .split_dns_rules
+                .into_iter()
+                .map(talpid_types::net::dns::SplitDnsRule::try_from)
+                .collect::<Result<Vec<_>, _>>()?,
         })
     }
 }
@@ -0,0 +1,74 @@
+use crate::types::{proto, FromProtobufTypeError};
+use chrono::TimeZone;
+use mullvad_types::tunnel_stats::{PeerStats, TunnelStats};
+use prost_types::Timestamp;
+
+impl From<Option<TunnelStats>> for proto::TunnelStats {
+    fn from(stats: Option<TunnelStats>) -> Self {
+        match stats {
+            Some(stats) => proto::TunnelStats {
+                exit: Some(proto::PeerStats::from(stats.exit)),
+                entry: stats.entry.map(proto::PeerStats::from),
+            },
+            None => proto::TunnelStats {
+                exit: None,
+                entry: None,
+            },
+        }
+    }
+}
+
+impl From<PeerStats> for proto::PeerStats {
+    fn from(stats: PeerStats) -> Self {
+        proto::PeerStats {
+            rx_bytes: stats.rx_bytes,
+            tx_bytes: stats.tx_bytes,
+            rx_rate: stats.rx_rate,
+            tx_rate: stats.tx_rate,
+            last_handshake: stats.last_handshake.map(|time| Timestamp {
+                seconds: time.timestamp(),
+                nanos: time.timestamp_subsec_nanos() as i32,
+            }),
+        }
+    }
+}
+
+impl TryFrom<proto::TunnelStats> for Option<TunnelStats> {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(stats: proto::TunnelStats) -> Result<Self, Self::Error> {
+        let exit = match stats.exit {
+            Some(exit) => PeerStats::try_from(exit)?,
+            None => return Ok(None),
+        };
+        Ok(Some(TunnelStats {
+            exit,
+            entry: stats.entry.map(PeerStats::try_from).transpose()?,
+        }))
+    }
+}
+
+impl TryFrom<proto::PeerStats> for PeerStats {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(stats: proto::PeerStats) -> Result<Self, Self::Error> {
+        let last_handshake = stats
+            .last_handshake
+            .map(|time| {
+                chrono::Utc
+                    .timestamp_opt(time.seconds, time.nanos as u32)
+                    .single()
+                    .ok_or(FromProtobufTypeError::InvalidArgument(
+                        "invalid handshake time",
+                    ))
+            })
+            .transpose()?;
+        Ok(PeerStats {
+            rx_bytes: stats.rx_bytes,
+            tx_bytes: stats.tx_bytes,
+            rx_rate: stats.rx_rate,
+            tx_rate: stats.tx_rate,
+            last_handshake,
+        })
+    }
+}
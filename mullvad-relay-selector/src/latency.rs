@@ -0,0 +1,244 @@
+//! Measures the latency to relays so that relay selection can be biased towards nearby relays.
+//!
+//! The latency to a relay is measured as the time it takes to establish a TCP connection to it.
+//! This does not require any special privileges, and all tunnel relays accept TCP connections:
+//! OpenVPN relays on their TCP ports, and WireGuard relays on their UDP-over-TCP ports.
+
+use super::{Error, ParsedRelays};
+use futures::{stream, StreamExt};
+use mullvad_types::relay_list::{Relay, RelayEndpointData};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use talpid_types::{net::TransportProtocol, ErrorExt};
+
+const LATENCIES_FILENAME: &str = "relay-latencies.json";
+
+/// How old the measurements need to be to trigger a new measurement.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Measurements older than this are not used for relay selection, since the user may have moved.
+const MAX_MEASUREMENT_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+/// How long to wait for a relay to accept a connection before giving up on it.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Maximum number of relays to probe at the same time.
+const MAX_CONCURRENT_PROBES: usize = 32;
+/// Relays whose latency is within this margin of the lowest latency are treated as equally good,
+/// so that the load is still spread out according to the relay weights.
+const LATENCY_MARGIN: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct Measurements {
+    last_updated: SystemTime,
+    /// Latency of each relay that responded, keyed by hostname.
+    latencies: HashMap<String, Duration>,
+}
+
+impl Measurements {
+    fn empty() -> Self {
+        Measurements {
+            last_updated: UNIX_EPOCH,
+            latencies: HashMap::new(),
+        }
+    }
+
+    fn age(&self) -> Duration {
+        // If the clock is skewed, treat the measurements as outdated.
+        SystemTime::now()
+            .duration_since(self.last_updated)
+            .unwrap_or(Duration::MAX)
+    }
+}
+
+/// Latency measurements and whether they should be used when selecting relays.
+pub(crate) struct RelayLatencies {
+    cache_path: PathBuf,
+    measurements: Measurements,
+    enabled: bool,
+    is_measuring: bool,
+}
+
+impl RelayLatencies {
+    /// Loads previous measurements from `cache_dir`, if there are any.
+    pub fn new(cache_dir: &Path, enabled: bool) -> Self {
+        let cache_path = cache_dir.join(LATENCIES_FILENAME);
+        let measurements = match Self::read_from_disk(&cache_path) {
+            Ok(measurements) => measurements,
+            Err(Error::OpenLatencyCache(error)) if error.kind() == io::ErrorKind::NotFound => {
+                Measurements::empty()
+            }
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Unable to load cached relay latencies")
+                );
+                Measurements::empty()
+            }
+        };
+
+        RelayLatencies {
+            cache_path,
+            measurements,
+            enabled,
+            is_measuring: false,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    #[cfg(test)]
+    pub fn from_latencies(latencies: HashMap<String, Duration>) -> Self {
+        RelayLatencies {
+            cache_path: PathBuf::new(),
+            measurements: Measurements {
+                last_updated: SystemTime::now(),
+                latencies,
+            },
+            enabled: false,
+            is_measuring: false,
+        }
+    }
+
+    /// Returns the relays in `relays` whose latency is within [`LATENCY_MARGIN`] of the lowest
+    /// measured latency. All relays are returned if latency-based selection is disabled, or if
+    /// no recent latency is known for any of the relays.
+    pub fn lowest_latency_relays<'a>(&self, relays: &'a [Relay]) -> Vec<&'a Relay> {
+        let latency = |relay: &Relay| self.measurements.latencies.get(&relay.hostname).copied();
+
+        let lowest_latency = if self.enabled && self.measurements.age() < MAX_MEASUREMENT_AGE {
+            relays.iter().filter_map(latency).min()
+        } else {
+            None
+        };
+
+        match lowest_latency {
+            Some(lowest_latency) => relays
+                .iter()
+                .filter(|relay| {
+                    latency(relay)
+                        .map(|latency| latency <= lowest_latency + LATENCY_MARGIN)
+                        .unwrap_or(false)
+                })
+                .collect(),
+            None => relays.iter().collect(),
+        }
+    }
+
+    fn should_update(&self) -> bool {
+        self.enabled && !self.is_measuring && self.measurements.age() >= UPDATE_INTERVAL
+    }
+
+    fn read_from_disk(path: &Path) -> Result<Measurements, Error> {
+        let file = std::fs::File::open(path).map_err(Error::OpenLatencyCache)?;
+        serde_json::from_reader(io::BufReader::new(file)).map_err(Error::Serialize)
+    }
+
+    async fn write_to_disk(path: &Path, measurements: &Measurements) -> Result<(), Error> {
+        log::debug!("Writing relay latencies to {}", path.display());
+        let bytes = serde_json::to_vec_pretty(measurements).map_err(Error::Serialize)?;
+        tokio::fs::write(path, bytes)
+            .await
+            .map_err(Error::WriteLatencyCache)
+    }
+}
+
+/// Measures the latency to all active tunnel relays and stores the result, unless latency-based
+/// selection is disabled or the latencies were measured recently.
+pub(crate) async fn update_latencies(
+    latencies: Arc<Mutex<RelayLatencies>>,
+    parsed_relays: Arc<Mutex<ParsedRelays>>,
+) {
+    {
+        let mut latencies = latencies.lock();
+        if !latencies.should_update() {
+            return;
+        }
+        latencies.is_measuring = true;
+    }
+
+    let targets = probe_targets(&parsed_relays.lock());
+    log::debug!("Measuring latency to {} relays", targets.len());
+
+    let results: HashMap<String, Duration> = stream::iter(targets)
+        .map(|(hostname, addr)| async move { probe(addr).await.map(|latency| (hostname, latency)) })
+        .buffer_unordered(MAX_CONCURRENT_PROBES)
+        .filter_map(|result| async move { result })
+        .collect()
+        .await;
+
+    let measurements = Measurements {
+        last_updated: SystemTime::now(),
+        latencies: results,
+    };
+    let cache_path = {
+        let mut latencies = latencies.lock();
+        latencies.is_measuring = false;
+
+        // Keep the old measurements if no relay could be reached, e.g. because the firewall
+        // blocked all probes.
+        if measurements.latencies.is_empty() {
+            log::warn!("Failed to measure the latency to any relay");
+            return;
+        }
+        log::info!(
+            "Measured the latency to {} relays",
+            measurements.latencies.len()
+        );
+
+        latencies.measurements = measurements.clone();
+        latencies.cache_path.clone()
+    };
+
+    if let Err(error) = RelayLatencies::write_to_disk(&cache_path, &measurements).await {
+        log::error!(
+            "{}",
+            error.display_chain_with_msg("Failed to write relay latencies to disk")
+        );
+    }
+}
+
+/// Returns the hostname and a TCP address to probe for every active tunnel relay.
+fn probe_targets(parsed_relays: &ParsedRelays) -> Vec<(String, SocketAddr)> {
+    let locations = parsed_relays.locations();
+    let openvpn_port = locations
+        .openvpn
+        .ports
+        .iter()
+        .find(|endpoint| endpoint.protocol == TransportProtocol::Tcp)
+        .map(|endpoint| endpoint.port);
+    let wireguard_port = locations.wireguard.udp2tcp_ports.first().copied();
+
+    parsed_relays
+        .relays()
+        .iter()
+        .filter(|relay| relay.active)
+        .filter_map(|relay| {
+            let port = match relay.endpoint_data {
+                RelayEndpointData::Openvpn => openvpn_port,
+                RelayEndpointData::Wireguard(_) => wireguard_port,
+                RelayEndpointData::Bridge => None,
+            }?;
+            Some((
+                relay.hostname.clone(),
+                SocketAddr::new(relay.ipv4_addr_in.into(), port),
+            ))
+        })
+        .collect()
+}
+
+/// Returns the time it takes to establish a TCP connection to `addr`.
+async fn probe(addr: SocketAddr) -> Option<Duration> {
+    let start = Instant::now();
+    match tokio::time::timeout(PROBE_TIMEOUT, tokio::net::TcpStream::connect(addr)).await {
+        Ok(Ok(_stream)) => Some(start.elapsed()),
+        _ => None,
+    }
+}
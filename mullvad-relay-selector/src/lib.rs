@@ -1,23 +1,27 @@
 //! When changing relay selection, please verify if `docs/relay-selector.md` needs to be
 //! updated as well.
 
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, Utc};
 use ipnetwork::IpNetwork;
+use mullvad_api::SignedRelayList;
 use mullvad_types::{
+    custom_list::CustomListsSettings,
     endpoint::{MullvadEndpoint, MullvadWireguardEndpoint},
     location::{Coordinates, Location},
     relay_constraints::{
         BridgeSettings, BridgeState, Constraint, InternalBridgeConstraints, LocationConstraint,
         Match, ObfuscationSettings, OpenVpnConstraints, Ownership, Providers, RelayConstraints,
-        RelaySettings, SelectedObfuscation, Set, TransportPort, Udp2TcpObfuscationSettings,
+        RelaySelectionStrategy, RelaySettings, ResolvedLocationConstraint, SelectedObfuscation,
+        Set, ShadowsocksObfuscationSettings, TransportPort, Udp2TcpObfuscationSettings,
         WireguardConstraints,
     },
-    relay_list::{BridgeEndpointData, Relay, RelayEndpointData, RelayList},
+    relay_list::{BridgeEndpointData, Relay, RelayEndpointData, RelayList, RelayListVerification},
     CustomTunnelEndpoint,
 };
 use parking_lot::{Mutex, MutexGuard};
 use rand::{seq::SliceRandom, Rng};
 use std::{
+    future::Future,
     io,
     net::{IpAddr, SocketAddr},
     path::Path,
@@ -32,10 +36,13 @@ use talpid_types::{
     ErrorExt,
 };
 
+use latency::RelayLatencies;
 use matcher::{BridgeMatcher, EndpointMatcher, OpenVpnMatcher, RelayMatcher, WireguardMatcher};
 
+mod latency;
 mod matcher;
 pub mod updater;
+mod verification;
 
 const DATE_TIME_FORMAT_STR: &str = "%Y-%m-%d %H:%M:%S%.3f";
 const RELAYS_FILENAME: &str = "relays.json";
@@ -60,18 +67,38 @@ pub enum Error {
     #[error(display = "Failed to write relay cache file to disk")]
     WriteRelayCache(#[error(source)] io::Error),
 
+    #[error(display = "Failed to open relay latency cache file")]
+    OpenLatencyCache(#[error(source)] io::Error),
+
+    #[error(display = "Failed to write relay latency cache file to disk")]
+    WriteLatencyCache(#[error(source)] io::Error),
+
     #[error(display = "No relays matching current constraints")]
     NoRelay,
 
     #[error(display = "No bridges matching current constraints")]
     NoBridge,
 
+    #[error(
+        display = "No pair of entry and exit relays satisfies the multihop diversity constraints"
+    )]
+    NoDiverseRelayPair,
+
+    #[error(display = "Multihop with more than two hops is not supported")]
+    UnsupportedMiddleHops,
+
     #[error(display = "No obfuscators matching current constraints")]
     NoObfuscator,
 
     #[error(display = "Failure in serialization of the relay list")]
     Serialize(#[error(source)] serde_json::Error),
 
+    #[error(display = "The relay list is not signed")]
+    MissingSignature,
+
+    #[error(display = "The relay list signature is invalid")]
+    InvalidSignature,
+
     #[error(display = "Downloader already shut down")]
     DownloaderShutDown,
 }
@@ -131,12 +158,15 @@ impl ParsedRelays {
         }
     }
 
+    /// Reads a signed relay list from disk. Fails unless the signature is valid.
     pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
         log::debug!("Reading relays from {}", path.as_ref().display());
         let (last_modified, file) =
             Self::open_file(path.as_ref()).map_err(Error::OpenRelayCache)?;
-        let relay_list =
+        let signed_relay_list: SignedRelayList =
             serde_json::from_reader(io::BufReader::new(file)).map_err(Error::Serialize)?;
+        verification::verify(&signed_relay_list)?;
+        let relay_list = signed_relay_list.parse().map_err(Error::Serialize)?;
 
         Ok(Self::from_relay_list(relay_list, last_modified))
     }
@@ -170,6 +200,8 @@ pub struct SelectorConfig {
     pub bridge_state: BridgeState,
     pub bridge_settings: BridgeSettings,
     pub obfuscation_settings: ObfuscationSettings,
+    pub custom_lists: CustomListsSettings,
+    pub selection_strategy: RelaySelectionStrategy,
     pub default_tunnel_type: TunnelType,
 }
 
@@ -177,6 +209,9 @@ pub struct SelectorConfig {
 pub struct RelaySelector {
     config: Arc<Mutex<SelectorConfig>>,
     parsed_relays: Arc<Mutex<ParsedRelays>>,
+    // Kept separately from `config` since relays are picked while `config` is locked.
+    latencies: Arc<Mutex<RelayLatencies>>,
+    avoided_exit_relay: Arc<Mutex<Option<String>>>,
 }
 
 impl RelaySelector {
@@ -199,16 +234,44 @@ impl RelaySelector {
                 .format(DATE_TIME_FORMAT_STR)
         );
 
+        let latencies = RelayLatencies::new(
+            cache_dir,
+            config.selection_strategy == RelaySelectionStrategy::LowestLatency,
+        );
+
         RelaySelector {
             config: Arc::new(Mutex::new(config)),
             parsed_relays: Arc::new(Mutex::new(unsynchronized_parsed_relays)),
+            latencies: Arc::new(Mutex::new(latencies)),
+            avoided_exit_relay: Arc::new(Mutex::new(None)),
         }
     }
 
     pub fn set_config(&mut self, config: SelectorConfig) {
+        self.latencies
+            .lock()
+            .set_enabled(config.selection_strategy == RelaySelectionStrategy::LowestLatency);
         *self.config.lock() = config;
     }
 
+    /// Measures the latency to all relays and caches the result on disk. This does nothing
+    /// unless the selection strategy is [`RelaySelectionStrategy::LowestLatency`] and the
+    /// latencies have not been measured recently.
+    pub fn update_latencies(&self) -> impl Future<Output = ()> + Send + 'static {
+        latency::update_latencies(self.latencies.clone(), self.parsed_relays.clone())
+    }
+
+    /// Makes the selector avoid picking the exit relay with the given hostname, unless it is the
+    /// only relay that matches the constraints.
+    pub fn set_avoided_exit_relay(&self, hostname: Option<String>) {
+        *self.avoided_exit_relay.lock() = hostname;
+    }
+
+    /// Returns the time when the relay list was last updated.
+    pub fn last_updated(&self) -> SystemTime {
+        self.parsed_relays.lock().last_updated()
+    }
+
     /// Returns all countries and cities. The cities in the object returned does not have any
     /// relays in them.
     pub fn get_locations(&mut self) -> RelayList {
@@ -238,6 +301,7 @@ impl RelaySelector {
                     config.bridge_state,
                     retry_attempt,
                     config.default_tunnel_type,
+                    &config.custom_lists,
                 )?;
                 let bridge = match relay.endpoint {
                     MullvadEndpoint::OpenVpn(endpoint)
@@ -278,7 +342,17 @@ impl RelaySelector {
         bridge_state: BridgeState,
         retry_attempt: u32,
         default_tunnel_type: TunnelType,
+        custom_lists: &CustomListsSettings,
     ) -> Result<NormalSelectedRelay, Error> {
+        // The tunnel only supports an entry and an exit peer
+        if !relay_constraints
+            .wireguard_constraints
+            .middle_locations
+            .is_empty()
+        {
+            return Err(Error::UnsupportedMiddleHops);
+        }
+
         match relay_constraints.tunnel_protocol {
             Constraint::Only(TunnelType::OpenVpn) => self.get_openvpn_endpoint(
                 &relay_constraints.location,
@@ -287,6 +361,7 @@ impl RelaySelector {
                 relay_constraints.openvpn_constraints,
                 bridge_state,
                 retry_attempt,
+                custom_lists,
             ),
 
             Constraint::Only(TunnelType::Wireguard) => self.get_wireguard_endpoint(
@@ -295,19 +370,25 @@ impl RelaySelector {
                 &relay_constraints.ownership,
                 &relay_constraints.wireguard_constraints,
                 retry_attempt,
+                custom_lists,
             ),
             Constraint::Any => self.get_any_tunnel_endpoint(
                 relay_constraints,
                 bridge_state,
                 retry_attempt,
                 default_tunnel_type,
+                custom_lists,
             ),
         }
     }
 
     /// Returns the average location of relays that match the given constraints.
     /// This returns none if the location is `any` or if no relays match the constraints.
-    pub fn get_relay_midpoint(&self, relay_constraints: &RelayConstraints) -> Option<Coordinates> {
+    pub fn get_relay_midpoint(
+        &self,
+        relay_constraints: &RelayConstraints,
+        custom_lists: &CustomListsSettings,
+    ) -> Option<Coordinates> {
         if relay_constraints.location.is_any() {
             return None;
         }
@@ -320,7 +401,12 @@ impl RelaySelector {
             )
         };
 
-        let matcher = RelayMatcher::new(relay_constraints.clone(), openvpn_data, wireguard_data);
+        let matcher = RelayMatcher::new(
+            relay_constraints.clone(),
+            custom_lists,
+            openvpn_data,
+            wireguard_data,
+        );
 
         let parsed_relays = self.parsed_relays.lock();
         let mut matching_locations: Vec<Location> = matcher
@@ -346,9 +432,10 @@ impl RelaySelector {
         openvpn_constraints: OpenVpnConstraints,
         bridge_state: BridgeState,
         retry_attempt: u32,
+        custom_lists: &CustomListsSettings,
     ) -> Result<NormalSelectedRelay, Error> {
         let mut relay_matcher = RelayMatcher {
-            location: location.clone(),
+            location: ResolvedLocationConstraint::from_constraint(location.clone(), custom_lists),
             providers: providers.clone(),
             ownership: *ownership,
             endpoint_matcher: OpenVpnMatcher::new(
@@ -402,18 +489,21 @@ impl RelaySelector {
     fn get_wireguard_multi_hop_endpoint(
         &self,
         mut entry_matcher: RelayMatcher<WireguardMatcher>,
-        exit_location: Constraint<LocationConstraint>,
+        exit_location: Constraint<ResolvedLocationConstraint>,
     ) -> Result<NormalSelectedRelay, Error> {
         let mut exit_matcher = RelayMatcher {
             location: exit_location,
             endpoint_matcher: self.wireguard_exit_matcher(),
             ..entry_matcher.clone()
         };
+        exit_matcher.endpoint_matcher.diversity = entry_matcher.endpoint_matcher.diversity;
 
         let (exit_relay, entry_relay, exit_endpoint, mut entry_endpoint) =
             if entry_matcher.location.is_subset(&exit_matcher.location) {
+                self.restrict_to_diverse_relays(&mut entry_matcher, &exit_matcher)?;
                 let (entry_relay, entry_endpoint) = self.get_entry_endpoint(&entry_matcher)?;
-                exit_matcher.set_peer(entry_relay.clone());
+                exit_matcher.set_peers(vec![entry_relay.clone()]);
+                exit_matcher.set_partners(vec![entry_relay.clone()]);
                 let exit_result = self.get_tunnel_endpoint_internal(&exit_matcher)?;
                 (
                     exit_result.exit_relay,
@@ -422,9 +512,11 @@ impl RelaySelector {
                     entry_endpoint,
                 )
             } else {
+                self.restrict_to_diverse_relays(&mut exit_matcher, &entry_matcher)?;
                 let exit_result = self.get_tunnel_endpoint_internal(&exit_matcher)?;
 
-                entry_matcher.set_peer(exit_result.exit_relay.clone());
+                entry_matcher.set_peers(vec![exit_result.exit_relay.clone()]);
+                entry_matcher.set_partners(vec![exit_result.exit_relay.clone()]);
                 let (entry_relay, entry_endpoint) = self.get_entry_endpoint(&entry_matcher)?;
                 (
                     exit_result.exit_relay,
@@ -460,7 +552,9 @@ impl RelaySelector {
         ownership: &Constraint<Ownership>,
         wireguard_constraints: &WireguardConstraints,
         retry_attempt: u32,
+        custom_lists: &CustomListsSettings,
     ) -> Result<NormalSelectedRelay, Error> {
+        let location = ResolvedLocationConstraint::from_constraint(location.clone(), custom_lists);
         let mut entry_relay_matcher = RelayMatcher {
             location: location.clone(),
             providers: providers.clone(),
@@ -483,18 +577,22 @@ impl RelaySelector {
                 .or_else(|_| self.get_tunnel_endpoint_internal(&entry_relay_matcher));
         }
 
-        entry_relay_matcher.location = wireguard_constraints.entry_location.clone();
+        entry_relay_matcher.location = ResolvedLocationConstraint::from_constraint(
+            wireguard_constraints.entry_location.clone(),
+            custom_lists,
+        );
         entry_relay_matcher.endpoint_matcher.port = entry_relay_matcher
             .endpoint_matcher
             .port
             .or(Self::preferred_wireguard_port(retry_attempt));
-        self.get_wireguard_multi_hop_endpoint(entry_relay_matcher, location.clone())
+        self.get_wireguard_multi_hop_endpoint(entry_relay_matcher, location)
     }
 
     /// Like [Self::get_tunnel_endpoint_internal] but also selects an entry endpoint if applicable.
     fn get_multihop_tunnel_endpoint_internal(
         &self,
         relay_constraints: &RelayConstraints,
+        custom_lists: &CustomListsSettings,
     ) -> Result<NormalSelectedRelay, Error> {
         let (openvpn_data, wireguard_data) = {
             let relays = self.parsed_relays.lock();
@@ -503,33 +601,45 @@ impl RelaySelector {
                 relays.locations.wireguard.clone(),
             )
         };
-        let mut matcher =
-            RelayMatcher::new(relay_constraints.clone(), openvpn_data, wireguard_data);
+        let mut matcher = RelayMatcher::new(
+            relay_constraints.clone(),
+            custom_lists,
+            openvpn_data,
+            wireguard_data,
+        );
 
         let mut selected_entry_relay = None;
         let mut selected_entry_endpoint = None;
         let mut entry_matcher = RelayMatcher {
-            location: relay_constraints
-                .wireguard_constraints
-                .entry_location
-                .clone(),
+            location: ResolvedLocationConstraint::from_constraint(
+                relay_constraints
+                    .wireguard_constraints
+                    .entry_location
+                    .clone(),
+                custom_lists,
+            ),
             ..matcher.clone()
         }
         .into_wireguard_matcher();
 
         // Pick the entry relay first if its location constraint is a subset of the exit location.
         if relay_constraints.wireguard_constraints.use_multihop {
-            matcher.endpoint_matcher.wireguard = self.wireguard_exit_matcher();
-            if relay_constraints
-                .wireguard_constraints
-                .entry_location
-                .is_subset(&matcher.location)
-            {
+            matcher.endpoint_matcher.wireguard = WireguardMatcher {
+                diversity: entry_matcher.endpoint_matcher.diversity,
+                ..self.wireguard_exit_matcher()
+            };
+            let mut exit_matcher = matcher.clone().into_wireguard_matcher();
+            if entry_matcher.location.is_subset(&matcher.location) {
+                self.restrict_to_diverse_relays(&mut entry_matcher, &exit_matcher)?;
                 if let Ok((entry_relay, entry_endpoint)) = self.get_entry_endpoint(&entry_matcher) {
-                    matcher.endpoint_matcher.wireguard.peer = Some(entry_relay.clone());
+                    matcher.endpoint_matcher.wireguard.peers = vec![entry_relay.clone()];
+                    matcher.endpoint_matcher.wireguard.partners = vec![entry_relay.clone()];
                     selected_entry_relay = Some(entry_relay);
                     selected_entry_endpoint = Some(entry_endpoint);
                 }
+            } else {
+                self.restrict_to_diverse_relays(&mut exit_matcher, &entry_matcher)?;
+                matcher.endpoint_matcher.wireguard = exit_matcher.endpoint_matcher;
             }
         }
 
@@ -540,12 +650,9 @@ impl RelaySelector {
         if matches!(selected_relay.endpoint, MullvadEndpoint::Wireguard(..))
             && relay_constraints.wireguard_constraints.use_multihop
         {
-            if !relay_constraints
-                .wireguard_constraints
-                .entry_location
-                .is_subset(&matcher.location)
-            {
-                entry_matcher.endpoint_matcher.peer = Some(selected_relay.exit_relay.clone());
+            if !entry_matcher.location.is_subset(&matcher.location) {
+                entry_matcher.endpoint_matcher.peers = vec![selected_relay.exit_relay.clone()];
+                entry_matcher.endpoint_matcher.partners = vec![selected_relay.exit_relay.clone()];
                 if let Ok((entry_relay, entry_endpoint)) = self.get_entry_endpoint(&entry_matcher) {
                     selected_entry_relay = Some(entry_relay);
                     selected_entry_endpoint = Some(entry_endpoint);
@@ -585,29 +692,37 @@ impl RelaySelector {
         bridge_state: BridgeState,
         retry_attempt: u32,
         default_tunnel_type: TunnelType,
+        custom_lists: &CustomListsSettings,
     ) -> Result<NormalSelectedRelay, Error> {
         let preferred_constraints = self.preferred_constraints(
             relay_constraints,
             bridge_state,
             retry_attempt,
             default_tunnel_type,
+            custom_lists,
         );
 
-        if let Ok(result) = self.get_multihop_tunnel_endpoint_internal(&preferred_constraints) {
+        if let Ok(result) =
+            self.get_multihop_tunnel_endpoint_internal(&preferred_constraints, custom_lists)
+        {
             log::debug!(
                 "Relay matched on highest preference for retry attempt {}",
                 retry_attempt
             );
             Ok(result)
-        } else if let Ok(result) = self.get_multihop_tunnel_endpoint_internal(relay_constraints) {
-            log::debug!(
-                "Relay matched on second preference for retry attempt {}",
-                retry_attempt
-            );
-            Ok(result)
         } else {
-            log::warn!("No relays matching {}", &relay_constraints);
-            Err(Error::NoRelay)
+            self.get_multihop_tunnel_endpoint_internal(relay_constraints, custom_lists)
+                .map(|result| {
+                    log::debug!(
+                        "Relay matched on second preference for retry attempt {}",
+                        retry_attempt
+                    );
+                    result
+                })
+                .map_err(|error| {
+                    log::warn!("No relays matching {}", &relay_constraints);
+                    error
+                })
         }
     }
 
@@ -618,12 +733,16 @@ impl RelaySelector {
         bridge_state: BridgeState,
         retry_attempt: u32,
         default_tunnel_type: TunnelType,
+        custom_lists: &CustomListsSettings,
     ) -> RelayConstraints {
         let (preferred_port, preferred_protocol, preferred_tunnel) = self
             .preferred_tunnel_constraints(
                 retry_attempt,
                 default_tunnel_type,
-                &original_constraints.location,
+                &ResolvedLocationConstraint::from_constraint(
+                    original_constraints.location.clone(),
+                    custom_lists,
+                ),
                 &original_constraints.providers,
                 &original_constraints.ownership,
             );
@@ -713,6 +832,36 @@ impl RelaySelector {
         Ok((relay, endpoint))
     }
 
+    /// Restricts `matcher` to relays that satisfy the multihop diversity constraints together with
+    /// at least one relay matching `other_matcher`, so that a relay can always be selected for the
+    /// other end of the tunnel afterwards.
+    fn restrict_to_diverse_relays(
+        &self,
+        matcher: &mut RelayMatcher<WireguardMatcher>,
+        other_matcher: &RelayMatcher<WireguardMatcher>,
+    ) -> Result<(), Error> {
+        if !matcher.endpoint_matcher.diversity.is_enabled() {
+            return Ok(());
+        }
+        let parsed_relays = self.parsed_relays.lock();
+        let relays = parsed_relays.relays();
+
+        let partners = other_matcher.filter_matching_relay_list(relays);
+        if partners.is_empty() || matcher.filter_matching_relay_list(relays).is_empty() {
+            // Fail with the usual error when selecting the relays instead
+            return Ok(());
+        }
+        matcher.set_partners(partners);
+        if matcher.filter_matching_relay_list(relays).is_empty() {
+            log::warn!(
+                "No entry and exit relays with different {} match the constraints",
+                matcher.endpoint_matcher.diversity
+            );
+            return Err(Error::NoDiverseRelayPair);
+        }
+        Ok(())
+    }
+
     fn set_entry_peers(
         exit_peer: &wireguard::PeerConfig,
         entry_endpoint: &mut MullvadWireguardEndpoint,
@@ -739,7 +888,11 @@ impl RelaySelector {
                 match config.bridge_state {
                     BridgeState::On => {
                         let (settings, relay) = self
-                            .get_proxy_settings(&bridge_constraints, Some(location))
+                            .get_proxy_settings(
+                                &bridge_constraints,
+                                Some(location),
+                                &config.custom_lists,
+                            )
                             .ok_or(Error::NoBridge)?;
                         Ok(Some(SelectedBridge::Normal(NormalSelectedBridge {
                             settings,
@@ -747,7 +900,11 @@ impl RelaySelector {
                         })))
                     }
                     BridgeState::Auto if Self::should_use_bridge(retry_attempt) => Ok(self
-                        .get_proxy_settings(&bridge_constraints, Some(location))
+                        .get_proxy_settings(
+                            &bridge_constraints,
+                            Some(location),
+                            &config.custom_lists,
+                        )
                         .map(|(settings, relay)| {
                             SelectedBridge::Normal(NormalSelectedBridge { settings, relay })
                         })),
@@ -769,7 +926,9 @@ impl RelaySelector {
         let config = self.config.lock();
 
         let near_location = match &config.relay_settings {
-            RelaySettings::Normal(settings) => self.get_relay_midpoint(settings),
+            RelaySettings::Normal(settings) => {
+                self.get_relay_midpoint(settings, &config.custom_lists)
+            }
             _ => None,
         };
 
@@ -788,7 +947,7 @@ impl RelaySelector {
             },
         };
 
-        self.get_proxy_settings(&constraints, near_location)
+        self.get_proxy_settings(&constraints, near_location, &config.custom_lists)
             .map(|(settings, _relay)| settings)
     }
 
@@ -806,9 +965,13 @@ impl RelaySelector {
         &self,
         constraints: &InternalBridgeConstraints,
         location: Option<T>,
+        custom_lists: &CustomListsSettings,
     ) -> Option<(ProxySettings, Relay)> {
         let matcher = RelayMatcher {
-            location: constraints.location.clone(),
+            location: ResolvedLocationConstraint::from_constraint(
+                constraints.location.clone(),
+                custom_lists,
+            ),
             providers: constraints.providers.clone(),
             ownership: constraints.ownership,
             endpoint_matcher: BridgeMatcher(()),
@@ -902,6 +1065,15 @@ impl RelaySelector {
                 )
                 .ok_or(Error::NoObfuscator)?,
             )),
+            SelectedObfuscation::Shadowsocks => Ok(Some(
+                self.get_shadowsocks_obfuscator(
+                    &config.obfuscation_settings.shadowsocks,
+                    relay,
+                    endpoint,
+                    retry_attempt,
+                )
+                .ok_or(Error::NoObfuscator)?,
+            )),
         }
     }
 
@@ -912,9 +1084,9 @@ impl RelaySelector {
         endpoint: &MullvadWireguardEndpoint,
         retry_attempt: u32,
     ) -> Option<SelectedObfuscator> {
-        if !self.should_use_auto_obfuscator(retry_attempt) {
-            return None;
-        }
+        let obfuscator_retry_attempt = self.get_auto_obfuscator_retry_attempt(retry_attempt)?;
+        // Shadowsocks is not tried automatically, since WireGuard relays do not advertise
+        // Shadowsocks endpoints.
         // TODO FIX: The third obfuscator entry will never be chosen
         // Because get_auto_obfuscator_retry_attempt() returns [0, 1]
         // And the udp2tcp endpoints are defined in a vector with entries [0, 1, 2]
@@ -922,16 +1094,10 @@ impl RelaySelector {
             &obfuscation_settings.udp2tcp,
             relay,
             endpoint,
-            self.get_auto_obfuscator_retry_attempt(retry_attempt)
-                .unwrap(),
+            obfuscator_retry_attempt,
         )
     }
 
-    fn should_use_auto_obfuscator(&self, retry_attempt: u32) -> bool {
-        self.get_auto_obfuscator_retry_attempt(retry_attempt)
-            .is_some()
-    }
-
     fn get_auto_obfuscator_retry_attempt(&self, retry_attempt: u32) -> Option<u32> {
         match retry_attempt % 4 {
             0 | 1 => None,
@@ -964,13 +1130,46 @@ impl RelaySelector {
             })
     }
 
+    fn get_shadowsocks_obfuscator(
+        &self,
+        obfuscation_settings: &ShadowsocksObfuscationSettings,
+        relay: &Relay,
+        endpoint: &MullvadWireguardEndpoint,
+        retry_attempt: u32,
+    ) -> Option<SelectedObfuscator> {
+        let parsed_relays = self.parsed_relays.lock();
+        let shadowsocks_endpoints: Vec<_> = parsed_relays
+            .locations
+            .bridge
+            .shadowsocks
+            .iter()
+            .filter(|candidate| {
+                candidate.protocol == TransportProtocol::Udp
+                    && obfuscation_settings.port.matches_eq(&candidate.port)
+            })
+            .collect();
+        if shadowsocks_endpoints.is_empty() {
+            return None;
+        }
+        let shadowsocks_endpoint =
+            shadowsocks_endpoints[retry_attempt as usize % shadowsocks_endpoints.len()];
+        Some(SelectedObfuscator {
+            config: ObfuscatorConfig::Shadowsocks {
+                endpoint: SocketAddr::new(endpoint.peer.endpoint.ip(), shadowsocks_endpoint.port),
+                password: shadowsocks_endpoint.password.clone(),
+                cipher: shadowsocks_endpoint.cipher.clone(),
+            },
+            relay: relay.clone(),
+        })
+    }
+
     /// Returns preferred constraints
     #[allow(unused_variables)]
     fn preferred_tunnel_constraints(
         &self,
         retry_attempt: u32,
         default_tunnel_type: TunnelType,
-        location_constraint: &Constraint<LocationConstraint>,
+        location_constraint: &Constraint<ResolvedLocationConstraint>,
         providers_constraint: &Constraint<Providers>,
         ownership_constraint: &Constraint<Ownership>,
     ) -> (Constraint<u16>, TransportProtocol, TunnelType) {
@@ -1066,11 +1265,20 @@ impl RelaySelector {
         &self,
         matcher: &RelayMatcher<T>,
     ) -> Result<NormalSelectedRelay, Error> {
-        let matching_relays: Vec<Relay> = matcher
+        let mut matching_relays: Vec<Relay> = matcher
             .filter_matching_relay_list(self.parsed_relays.lock().relays())
             .into_iter()
             .collect();
 
+        if let Some(avoided_hostname) = self.avoided_exit_relay.lock().as_ref() {
+            if matching_relays
+                .iter()
+                .any(|relay| &relay.hostname != avoided_hostname)
+            {
+                matching_relays.retain(|relay| &relay.hostname != avoided_hostname);
+            }
+        }
+
         self.pick_random_relay(&matching_relays)
             .and_then(|selected_relay| {
                 let endpoint = matcher.mullvad_endpoint(selected_relay);
@@ -1085,9 +1293,13 @@ impl RelaySelector {
     }
 
     /// Picks a relay using [Self::pick_random_relay_fn], using the `weight` member of each relay
-    /// as the weight function.
+    /// as the weight function. If the selection strategy is
+    /// [`RelaySelectionStrategy::LowestLatency`], only the relays with the lowest measured latency
+    /// are considered.
     fn pick_random_relay<'a>(&self, relays: &'a [Relay]) -> Option<&'a Relay> {
-        self.pick_random_relay_fn(relays, |relay| relay.weight)
+        let candidates = self.latencies.lock().lowest_latency_relays(relays);
+        self.pick_random_relay_fn(&candidates, |relay| relay.weight)
+            .copied()
     }
 
     /// Pick a random relay from the given slice. Will return `None` if the given slice is empty.
@@ -1151,9 +1363,17 @@ impl RelaySelector {
         cache_path: &Path,
         resource_path: &Path,
     ) -> Result<ParsedRelays, Error> {
-        // prefer the resource path's relay list if the cached one doesn't exist or was modified
-        // before the resource one was created.
+        // prefer the resource path's relay list if the cached one doesn't exist, cannot be
+        // verified or was modified before the resource one was created.
         let cached_relays = ParsedRelays::from_file(cache_path);
+        match &cached_relays {
+            Err(Error::OpenRelayCache(error)) if error.kind() == io::ErrorKind::NotFound => (),
+            Err(error) => log::error!(
+                "{}",
+                error.display_chain_with_msg("Rejecting cached relay list")
+            ),
+            Ok(_) => (),
+        }
         let bundled_relays = match ParsedRelays::from_file(resource_path) {
             Ok(bundled_relays) => bundled_relays,
             Err(e) => {
@@ -1173,6 +1393,32 @@ impl RelaySelector {
         }
     }
 
+    /// Verifies the signatures of the cached and bundled relay lists. Lists that do not exist are
+    /// skipped.
+    pub fn verify_relay_lists(resource_dir: &Path, cache_dir: &Path) -> Vec<RelayListVerification> {
+        [
+            cache_dir.join(RELAYS_FILENAME),
+            resource_dir.join(RELAYS_FILENAME),
+        ]
+        .into_iter()
+        .filter(|path| path.exists())
+        .map(|path| match ParsedRelays::from_file(&path) {
+            Ok(parsed_relays) => RelayListVerification {
+                path,
+                error: None,
+                relay_count: parsed_relays.relays().len(),
+                last_updated: Some(DateTime::<Utc>::from(parsed_relays.last_updated())),
+            },
+            Err(error) => RelayListVerification {
+                path,
+                error: Some(error.display_chain()),
+                relay_count: 0,
+                last_updated: None,
+            },
+        })
+        .collect()
+    }
+
     fn wireguard_exit_matcher(&self) -> WireguardMatcher {
         let mut tunnel =
             WireguardMatcher::from_endpoint(self.parsed_relays.lock().locations.wireguard.clone());
@@ -1239,15 +1485,20 @@ impl NormalSelectedRelay {
 mod test {
     use super::*;
     use mullvad_types::{
+        custom_list::CustomList,
         relay_constraints::{
-            BridgeConstraints, RelayConstraints, RelayConstraintsUpdate, RelaySettingsUpdate,
+            BridgeConstraints, MultihopDiversity, RelayConstraints, RelayConstraintsUpdate,
+            RelaySettingsUpdate,
         },
         relay_list::{
             OpenVpnEndpoint, OpenVpnEndpointData, Relay, RelayListCity, RelayListCountry,
             ShadowsocksEndpointData, WireguardEndpointData, WireguardRelayEndpointData,
         },
     };
-    use std::collections::HashSet;
+    use std::{
+        collections::{HashMap, HashSet},
+        time::Duration,
+    };
     use talpid_types::net::{wireguard::PublicKey, Endpoint};
 
     lazy_static::lazy_static! {
@@ -1405,8 +1656,12 @@ mod test {
                     ..Default::default()
                 },
                 bridge_state: BridgeState::Auto,
+                custom_lists: CustomListsSettings::default(),
+                selection_strategy: RelaySelectionStrategy::Weighted,
                 default_tunnel_type: default_tunnel_type(),
             })),
+            latencies: Arc::new(Mutex::new(RelayLatencies::from_latencies(HashMap::new()))),
+            avoided_exit_relay: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -1414,6 +1669,44 @@ mod test {
         new_relay_selector_with_relays(RELAYS.clone())
     }
 
+    #[test]
+    fn test_reject_unverified_relay_lists_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_path = dir.path().join("cache.json");
+        let resource_path = dir.path().join("resource.json");
+        let write_signed = |path: &Path, signature: &str| {
+            let relay_list = SignedRelayList {
+                content: r#"{"locations":{},"openvpn":{},"wireguard":{},"bridge":{}}"#.to_string(),
+                signature: signature.to_string(),
+                etag: None,
+            };
+            std::fs::write(path, serde_json::to_vec(&relay_list).unwrap()).unwrap();
+        };
+
+        // Relay lists stored without a signature are rejected
+        std::fs::write(&cache_path, serde_json::to_vec(&*RELAYS).unwrap()).unwrap();
+        assert!(matches!(
+            ParsedRelays::from_file(&cache_path),
+            Err(Error::Serialize(_))
+        ));
+        write_signed(&cache_path, "");
+        assert!(matches!(
+            ParsedRelays::from_file(&cache_path),
+            Err(Error::MissingSignature)
+        ));
+
+        // So are relay lists with a signature that is not made with the pinned key
+        write_signed(&cache_path, &"00".repeat(64));
+        assert!(matches!(
+            ParsedRelays::from_file(&cache_path),
+            Err(Error::InvalidSignature)
+        ));
+
+        // A rejected cached relay list is never used, even if the bundled one is unusable
+        write_signed(&resource_path, "");
+        assert!(RelaySelector::read_relays_from_disk(&cache_path, &resource_path).is_err());
+    }
+
     #[test]
     fn test_preferred_tunnel_protocol() {
         let relay_selector = new_relay_selector();
@@ -1435,6 +1728,7 @@ mod test {
             BridgeState::Off,
             0,
             TunnelType::Wireguard,
+            &CustomListsSettings::default(),
         );
         assert_eq!(
             preferred.tunnel_protocol,
@@ -1448,6 +1742,7 @@ mod test {
                     BridgeState::Off,
                     attempt,
                     TunnelType::Wireguard,
+                    &CustomListsSettings::default(),
                 )
                 .is_ok());
         }
@@ -1469,6 +1764,7 @@ mod test {
             BridgeState::Off,
             0,
             TunnelType::Wireguard,
+            &CustomListsSettings::default(),
         );
         assert_eq!(
             preferred.tunnel_protocol,
@@ -1482,6 +1778,7 @@ mod test {
                     BridgeState::Off,
                     attempt,
                     TunnelType::Wireguard,
+                    &CustomListsSettings::default(),
                 )
                 .is_ok());
         }
@@ -1496,6 +1793,7 @@ mod test {
                     BridgeState::Off,
                     attempt,
                     TunnelType::OpenVpn,
+                    &CustomListsSettings::default(),
                 );
                 assert_eq!(
                     preferred.tunnel_protocol,
@@ -1506,6 +1804,7 @@ mod test {
                     BridgeState::Off,
                     attempt,
                     TunnelType::OpenVpn,
+                    &CustomListsSettings::default(),
                 ) {
                     Ok(result) if matches!(result.endpoint, MullvadEndpoint::OpenVpn(_)) => (),
                     _ => panic!("OpenVPN endpoint was not selected"),
@@ -1545,6 +1844,7 @@ mod test {
                 BridgeState::Off,
                 0,
                 TunnelType::Wireguard,
+                &CustomListsSettings::default(),
             )
             .is_err());
 
@@ -1557,10 +1857,149 @@ mod test {
                 BridgeState::Off,
                 0,
                 TunnelType::Wireguard,
+                &CustomListsSettings::default(),
             )
             .is_ok());
     }
 
+    #[test]
+    fn test_avoided_exit_relay() {
+        let relay_selector = new_relay_selector();
+        relay_selector.set_avoided_exit_relay(Some("se9-wireguard".to_string()));
+
+        let mut relay_constraints = RelayConstraints {
+            location: Constraint::Only(LocationConstraint::City(
+                "se".to_string(),
+                "got".to_string(),
+            )),
+            tunnel_protocol: Constraint::Only(TunnelType::Wireguard),
+            ..RelayConstraints::default()
+        };
+
+        // The avoided relay is not picked if there are other matching relays
+        for _ in 0..10 {
+            let result = relay_selector
+                .get_tunnel_endpoint(
+                    &relay_constraints,
+                    BridgeState::Off,
+                    0,
+                    TunnelType::Wireguard,
+                    &CustomListsSettings::default(),
+                )
+                .expect("Failed to get relay");
+            assert_ne!(result.exit_relay.hostname, "se9-wireguard");
+        }
+
+        // The avoided relay is still picked if no other relay matches
+        relay_constraints.location = Constraint::Only(LocationConstraint::Hostname(
+            "se".to_string(),
+            "got".to_string(),
+            "se9-wireguard".to_string(),
+        ));
+        let result = relay_selector
+            .get_tunnel_endpoint(
+                &relay_constraints,
+                BridgeState::Off,
+                0,
+                TunnelType::Wireguard,
+                &CustomListsSettings::default(),
+            )
+            .expect("Failed to get relay");
+        assert_eq!(result.exit_relay.hostname, "se9-wireguard");
+    }
+
+    #[test]
+    fn test_custom_list_location() {
+        let relay_selector = new_relay_selector();
+
+        let hostname = "se10-wireguard";
+        let custom_lists = CustomListsSettings {
+            custom_lists: vec![CustomList {
+                name: "list".to_string(),
+                locations: vec![LocationConstraint::Hostname(
+                    "se".to_string(),
+                    "got".to_string(),
+                    hostname.to_string(),
+                )],
+            }],
+        };
+        let relay_constraints = RelayConstraints {
+            location: Constraint::Only(LocationConstraint::CustomList("list".to_string())),
+            tunnel_protocol: Constraint::Only(TunnelType::Wireguard),
+            ..RelayConstraints::default()
+        };
+
+        // Only relays in the custom list may be selected
+        for attempt in 0..10 {
+            let result = relay_selector
+                .get_tunnel_endpoint(
+                    &relay_constraints,
+                    BridgeState::Off,
+                    attempt,
+                    TunnelType::Wireguard,
+                    &custom_lists,
+                )
+                .expect("Failed to select a relay from the custom list");
+            assert_eq!(result.exit_relay.hostname, hostname);
+        }
+
+        // A list that does not exist matches no relays
+        assert!(relay_selector
+            .get_tunnel_endpoint(
+                &relay_constraints,
+                BridgeState::Off,
+                0,
+                TunnelType::Wireguard,
+                &CustomListsSettings::default(),
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_lowest_latency_selection() {
+        let relay_selector = new_relay_selector();
+        *relay_selector.latencies.lock() = RelayLatencies::from_latencies(HashMap::from([
+            ("se9-wireguard".to_string(), Duration::from_millis(20)),
+            ("se10-wireguard".to_string(), Duration::from_millis(100)),
+        ]));
+
+        let relay_constraints = RelayConstraints {
+            location: Constraint::Only(LocationConstraint::City(
+                "se".to_string(),
+                "got".to_string(),
+            )),
+            tunnel_protocol: Constraint::Only(TunnelType::Wireguard),
+            ..RelayConstraints::default()
+        };
+        let select_hostnames = || {
+            (0..20)
+                .map(|_| {
+                    relay_selector
+                        .get_tunnel_endpoint(
+                            &relay_constraints,
+                            BridgeState::Off,
+                            0,
+                            TunnelType::Wireguard,
+                            &CustomListsSettings::default(),
+                        )
+                        .expect("Failed to select a relay")
+                        .exit_relay
+                        .hostname
+                })
+                .collect::<HashSet<_>>()
+        };
+
+        // Latencies are ignored unless enabled
+        assert_eq!(select_hostnames().len(), 2);
+
+        // The relay with the lowest latency should always be picked
+        relay_selector.latencies.lock().set_enabled(true);
+        assert_eq!(
+            select_hostnames(),
+            HashSet::from(["se9-wireguard".to_string()])
+        );
+    }
+
     #[test]
     fn test_wg_entry_filter() -> Result<(), String> {
         let relay_selector = new_relay_selector();
@@ -1586,7 +2025,13 @@ mod test {
 
         // The exit must not equal the entry
         let exit_relay = relay_selector
-            .get_tunnel_endpoint(&relay_constraints, BridgeState::Off, 0, TunnelType::OpenVpn)
+            .get_tunnel_endpoint(
+                &relay_constraints,
+                BridgeState::Off,
+                0,
+                TunnelType::OpenVpn,
+                &CustomListsSettings::default(),
+            )
             .map_err(|error| error.to_string())?
             .exit_relay;
 
@@ -1606,6 +2051,7 @@ mod test {
                 BridgeState::Off,
                 0,
                 TunnelType::Wireguard,
+                &CustomListsSettings::default(),
             )
             .map_err(|error| error.to_string())?;
 
@@ -1621,6 +2067,77 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_wg_multihop_rejects_middle_hops() {
+        let relay_selector = new_relay_selector();
+
+        let mut relay_constraints = RelayConstraints {
+            tunnel_protocol: Constraint::Only(TunnelType::Wireguard),
+            ..RelayConstraints::default()
+        };
+        relay_constraints.wireguard_constraints.use_multihop = true;
+        relay_constraints.wireguard_constraints.middle_locations = vec![Constraint::Any];
+
+        for tunnel_protocol in [Constraint::Only(TunnelType::Wireguard), Constraint::Any] {
+            relay_constraints.tunnel_protocol = tunnel_protocol;
+            let result = relay_selector.get_tunnel_endpoint(
+                &relay_constraints,
+                BridgeState::Off,
+                0,
+                TunnelType::Wireguard,
+                &CustomListsSettings::default(),
+            );
+            assert!(matches!(result, Err(Error::UnsupportedMiddleHops)));
+        }
+    }
+
+    #[test]
+    fn test_wg_multihop_diversity() {
+        let mut relay_list = RELAYS.clone();
+        let mut third_relay = relay_list.countries[0].cities[0].relays[0].clone();
+        third_relay.hostname = "se11-wireguard".to_string();
+        third_relay.ipv4_addr_in = "185.213.154.70".parse().unwrap();
+        relay_list.countries[0].cities[0].relays.push(third_relay);
+        let relay_selector = new_relay_selector_with_relays(relay_list);
+
+        let mut relay_constraints = WIREGUARD_MULTIHOP_CONSTRAINTS;
+
+        // All relays are in the same country
+        relay_constraints.wireguard_constraints.diversity.country = true;
+        let result = relay_selector.get_tunnel_endpoint(
+            &relay_constraints,
+            BridgeState::Off,
+            0,
+            TunnelType::Wireguard,
+            &CustomListsSettings::default(),
+        );
+        assert!(matches!(result, Err(Error::NoDiverseRelayPair)));
+
+        // Only se10-wireguard has a different provider than the other relays, so it must always be
+        // selected
+        relay_constraints.wireguard_constraints.diversity = MultihopDiversity {
+            provider: true,
+            ..MultihopDiversity::default()
+        };
+        for _ in 0..20 {
+            let result = relay_selector
+                .get_tunnel_endpoint(
+                    &relay_constraints,
+                    BridgeState::Off,
+                    0,
+                    TunnelType::Wireguard,
+                    &CustomListsSettings::default(),
+                )
+                .expect("Failed to select diverse multihop relays");
+            let entry_relay = result.entry_relay.unwrap();
+            assert_ne!(entry_relay.provider, result.exit_relay.provider);
+            assert!(
+                entry_relay.hostname == "se10-wireguard"
+                    || result.exit_relay.hostname == "se10-wireguard"
+            );
+        }
+    }
+
     #[test]
     fn test_openvpn_constraints() -> Result<(), String> {
         let relay_selector = new_relay_selector();
@@ -1722,6 +2239,7 @@ mod test {
                     BridgeState::Auto,
                     retry_attempt,
                     default_tunnel_type(),
+                    &CustomListsSettings::default(),
                 );
 
                 println!("relay: {relay:?}, constraints: {relay_constraints:?}");
@@ -1770,6 +2288,7 @@ mod test {
             BridgeState::On,
             0,
             TunnelType::Wireguard,
+            &CustomListsSettings::default(),
         );
         assert_eq!(
             preferred.tunnel_protocol,
@@ -1800,6 +2319,7 @@ mod test {
             BridgeState::On,
             0,
             TunnelType::Wireguard,
+            &CustomListsSettings::default(),
         );
         assert_eq!(
             preferred.tunnel_protocol,
@@ -1823,6 +2343,7 @@ mod test {
                 BridgeState::On,
                 0,
                 TunnelType::Wireguard,
+                &CustomListsSettings::default(),
             );
             assert_eq!(
                 preferred.tunnel_protocol,
@@ -1834,6 +2355,7 @@ mod test {
             BridgeState::On,
             2,
             TunnelType::Wireguard,
+            &CustomListsSettings::default(),
         );
         assert_eq!(
             preferred.tunnel_protocol,
@@ -1865,7 +2387,7 @@ mod test {
 
         let relay_selector = new_relay_selector();
 
-        let result = relay_selector.get_tunnel_endpoint(&relay_constraints, BridgeState::Off, 0, default_tunnel_type())
+        let result = relay_selector.get_tunnel_endpoint(&relay_constraints, BridgeState::Off, 0, default_tunnel_type(), &CustomListsSettings::default())
             .expect("Failed to get relay when tunnel constraints are set to Any and retrying the selection");
         // Windows will ignore WireGuard until WireGuard is supported well enough
         // TODO: Remove this caveat once Windows defaults to using WireGuard
@@ -1890,6 +2412,12 @@ mod test {
             port: Constraint::Any,
             ip_version: Constraint::Any,
             entry_location: Constraint::Any,
+            middle_locations: Vec::new(),
+            diversity: MultihopDiversity {
+                country: false,
+                provider: false,
+                ownership: false,
+            },
         },
         tunnel_protocol: Constraint::Only(TunnelType::Wireguard),
         openvpn_constraints: OpenVpnConstraints {
@@ -1906,6 +2434,12 @@ mod test {
             port: Constraint::Any,
             ip_version: Constraint::Any,
             entry_location: Constraint::Any,
+            middle_locations: Vec::new(),
+            diversity: MultihopDiversity {
+                country: false,
+                provider: false,
+                ownership: false,
+            },
         },
         tunnel_protocol: Constraint::Only(TunnelType::Wireguard),
         openvpn_constraints: OpenVpnConstraints {
@@ -1917,7 +2451,7 @@ mod test {
     fn test_selecting_wireguard_location_will_consider_multihop() {
         let relay_selector = new_relay_selector();
 
-        let result = relay_selector.get_tunnel_endpoint(&WIREGUARD_MULTIHOP_CONSTRAINTS, BridgeState::Off, 0, default_tunnel_type())
+        let result = relay_selector.get_tunnel_endpoint(&WIREGUARD_MULTIHOP_CONSTRAINTS, BridgeState::Off, 0, default_tunnel_type(), &CustomListsSettings::default())
             .expect("Failed to get relay when tunnel constraints are set to default WireGuard multihop constraints");
 
         assert!(result.entry_relay.is_some());
@@ -1928,7 +2462,7 @@ mod test {
     fn test_selecting_wg_endpoint_with_udp2tcp_obfuscation() {
         let relay_selector = new_relay_selector();
 
-        let result = relay_selector.get_tunnel_endpoint(&WIREGUARD_SINGLEHOP_CONSTRAINTS, BridgeState::Off, 0, default_tunnel_type())
+        let result = relay_selector.get_tunnel_endpoint(&WIREGUARD_SINGLEHOP_CONSTRAINTS, BridgeState::Off, 0, default_tunnel_type(), &CustomListsSettings::default())
             .expect("Failed to get relay when tunnel constraints are set to default WireGuard constraints");
 
         assert!(result.entry_relay.is_none());
@@ -1957,7 +2491,7 @@ mod test {
     fn test_selecting_wg_endpoint_with_auto_obfuscation() {
         let relay_selector = new_relay_selector();
 
-        let result = relay_selector.get_tunnel_endpoint(&WIREGUARD_SINGLEHOP_CONSTRAINTS, BridgeState::Off, 0, default_tunnel_type())
+        let result = relay_selector.get_tunnel_endpoint(&WIREGUARD_SINGLEHOP_CONSTRAINTS, BridgeState::Off, 0, default_tunnel_type(), &CustomListsSettings::default())
             .expect("Failed to get relay when tunnel constraints are set to default WireGuard constraints");
 
         assert!(result.entry_relay.is_none());
@@ -1984,6 +2518,78 @@ mod test {
             .is_some());
     }
 
+    #[test]
+    fn test_selecting_wg_endpoint_with_shadowsocks_obfuscation() {
+        let relay_selector = new_relay_selector();
+
+        let result = relay_selector.get_tunnel_endpoint(&WIREGUARD_SINGLEHOP_CONSTRAINTS, BridgeState::Off, 0, default_tunnel_type(), &CustomListsSettings::default())
+            .expect("Failed to get relay when tunnel constraints are set to default WireGuard constraints");
+        let wg_endpoint = result.endpoint.unwrap_wireguard();
+
+        relay_selector.config.lock().obfuscation_settings = ObfuscationSettings {
+            selected_obfuscation: SelectedObfuscation::Shadowsocks,
+            shadowsocks: ShadowsocksObfuscationSettings {
+                port: Constraint::Only(1236),
+            },
+            ..ObfuscationSettings::default()
+        };
+
+        let obfs_config = relay_selector
+            .get_obfuscator(&result.exit_relay, wg_endpoint, 0)
+            .unwrap()
+            .unwrap();
+
+        match obfs_config.config {
+            ObfuscatorConfig::Shadowsocks {
+                endpoint, cipher, ..
+            } => {
+                assert_eq!(
+                    endpoint,
+                    SocketAddr::new(wg_endpoint.peer.endpoint.ip(), 1236)
+                );
+                assert_eq!(cipher, "aes-256-gcm");
+            }
+            config => panic!("Expected Shadowsocks obfuscator, got {config:?}"),
+        }
+
+        // Only UDP endpoints can carry WireGuard traffic
+        relay_selector
+            .config
+            .lock()
+            .obfuscation_settings
+            .shadowsocks
+            .port = Constraint::Only(443);
+        assert!(matches!(
+            relay_selector.get_obfuscator(&result.exit_relay, wg_endpoint, 0),
+            Err(Error::NoObfuscator)
+        ));
+    }
+
+    #[test]
+    fn test_auto_obfuscation_only_uses_udp2tcp() {
+        let relay_selector = new_relay_selector();
+
+        let result = relay_selector.get_tunnel_endpoint(&WIREGUARD_SINGLEHOP_CONSTRAINTS, BridgeState::Off, 0, default_tunnel_type(), &CustomListsSettings::default())
+            .expect("Failed to get relay when tunnel constraints are set to default WireGuard constraints");
+        let wg_endpoint = result.endpoint.unwrap_wireguard();
+
+        relay_selector.config.lock().obfuscation_settings = ObfuscationSettings {
+            selected_obfuscation: SelectedObfuscation::Auto,
+            ..ObfuscationSettings::default()
+        };
+
+        for attempt in 0..16 {
+            let obfuscator = relay_selector
+                .get_obfuscator(&result.exit_relay, wg_endpoint, attempt)
+                .unwrap()
+                .map(|obfuscator| obfuscator.config);
+            match attempt % 4 {
+                0 | 1 => assert!(obfuscator.is_none()),
+                _ => assert!(matches!(obfuscator, Some(ObfuscatorConfig::Udp2Tcp { .. }))),
+            }
+        }
+    }
+
     #[test]
     fn test_selected_endpoints_use_correct_port_ranges() {
         let relay_selector = new_relay_selector();
@@ -2002,6 +2608,7 @@ mod test {
                     BridgeState::Off,
                     attempt,
                     TunnelType::Wireguard,
+                    &CustomListsSettings::default(),
                 )
                 .expect("Failed to select a WireGuard relay");
             assert!(result.entry_relay.is_none());
@@ -2015,19 +2622,12 @@ mod test {
                 .unwrap()
                 .expect("Failed to get Tcp2Udp endpoint");
 
-            assert!(matches!(
-                obfs_config,
-                SelectedObfuscator {
-                    config: ObfuscatorConfig::Udp2Tcp { .. },
-                    ..
+            match obfs_config.config {
+                ObfuscatorConfig::Udp2Tcp { endpoint } => {
+                    assert!(TCP2UDP_PORTS.contains(&endpoint.port()))
                 }
-            ));
-
-            let SelectedObfuscator {
-                config: ObfuscatorConfig::Udp2Tcp { endpoint },
-                ..
-            } = obfs_config;
-            assert!(TCP2UDP_PORTS.contains(&endpoint.port()));
+                config => panic!("Expected Udp2Tcp obfuscator, got {config:?}"),
+            }
         }
     }
 
@@ -2038,7 +2638,13 @@ mod test {
         for i in 0..10 {
             constraints.ownership = Constraint::Only(Ownership::MullvadOwned);
             let relay = relay_selector
-                .get_tunnel_endpoint(&constraints, BridgeState::Auto, i, TunnelType::Wireguard)
+                .get_tunnel_endpoint(
+                    &constraints,
+                    BridgeState::Auto,
+                    i,
+                    TunnelType::Wireguard,
+                    &CustomListsSettings::default(),
+                )
                 .unwrap();
             assert!(matches!(
                 relay,
@@ -2050,7 +2656,13 @@ mod test {
 
             constraints.ownership = Constraint::Only(Ownership::Rented);
             let relay = relay_selector
-                .get_tunnel_endpoint(&constraints, BridgeState::Auto, i, TunnelType::Wireguard)
+                .get_tunnel_endpoint(
+                    &constraints,
+                    BridgeState::Auto,
+                    i,
+                    TunnelType::Wireguard,
+                    &CustomListsSettings::default(),
+                )
                 .unwrap();
             assert!(matches!(
                 relay,
@@ -2123,7 +2735,13 @@ mod test {
                 Providers::new(EXPECTED_PROVIDERS.into_iter().map(|p| p.to_owned())).unwrap(),
             );
             let relay = relay_selector
-                .get_tunnel_endpoint(&constraints, BridgeState::Auto, i, TunnelType::Wireguard)
+                .get_tunnel_endpoint(
+                    &constraints,
+                    BridgeState::Auto,
+                    i,
+                    TunnelType::Wireguard,
+                    &CustomListsSettings::default(),
+                )
                 .unwrap();
             assert!(
                 EXPECTED_PROVIDERS.contains(&relay.exit_relay.provider.as_str()),
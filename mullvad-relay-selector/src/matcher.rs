@@ -1,8 +1,9 @@
 use mullvad_types::{
+    custom_list::CustomListsSettings,
     endpoint::{MullvadEndpoint, MullvadWireguardEndpoint},
     relay_constraints::{
-        Constraint, LocationConstraint, Match, OpenVpnConstraints, Ownership, Providers,
-        RelayConstraints, WireguardConstraints,
+        Constraint, Match, MultihopDiversity, OpenVpnConstraints, Ownership, Providers,
+        RelayConstraints, ResolvedLocationConstraint, WireguardConstraints,
     },
     relay_list::{
         OpenVpnEndpoint, OpenVpnEndpointData, Relay, RelayEndpointData, WireguardEndpointData,
@@ -17,7 +18,7 @@ use talpid_types::net::{all_of_the_internet, wireguard, Endpoint, IpVersion, Tun
 
 #[derive(Clone)]
 pub struct RelayMatcher<T: EndpointMatcher> {
-    pub location: Constraint<LocationConstraint>,
+    pub location: Constraint<ResolvedLocationConstraint>,
     pub providers: Constraint<Providers>,
     pub ownership: Constraint<Ownership>,
     pub endpoint_matcher: T,
@@ -26,11 +27,15 @@ pub struct RelayMatcher<T: EndpointMatcher> {
 impl RelayMatcher<AnyTunnelMatcher> {
     pub fn new(
         constraints: RelayConstraints,
+        custom_lists: &CustomListsSettings,
         openvpn_data: OpenVpnEndpointData,
         wireguard_data: WireguardEndpointData,
     ) -> Self {
         Self {
-            location: constraints.location,
+            location: ResolvedLocationConstraint::from_constraint(
+                constraints.location,
+                custom_lists,
+            ),
             providers: constraints.providers,
             ownership: constraints.ownership,
             endpoint_matcher: AnyTunnelMatcher {
@@ -52,8 +57,12 @@ impl RelayMatcher<AnyTunnelMatcher> {
 }
 
 impl RelayMatcher<WireguardMatcher> {
-    pub fn set_peer(&mut self, peer: Relay) {
-        self.endpoint_matcher.peer = Some(peer);
+    pub fn set_peers(&mut self, peers: Vec<Relay>) {
+        self.endpoint_matcher.peers = peers;
+    }
+
+    pub fn set_partners(&mut self, partners: Vec<Relay>) {
+        self.endpoint_matcher.partners = partners;
     }
 }
 
@@ -207,9 +216,13 @@ impl EndpointMatcher for AnyTunnelMatcher {
 
 #[derive(Default, Clone)]
 pub struct WireguardMatcher {
-    /// The peer is an already selected peer relay to be used with multihop.
-    /// It's stored here so we can exclude it from further selections being made.
-    pub peer: Option<Relay>,
+    /// The peers are already selected peer relays to be used with multihop.
+    /// They're stored here so we can exclude them from further selections being made.
+    pub peers: Vec<Relay>,
+    /// Relays that the selected relay may be paired with when using multihop. If any are given,
+    /// only relays that satisfy `diversity` together with at least one of them match.
+    pub partners: Vec<Relay>,
+    pub diversity: MultihopDiversity,
     pub port: Constraint<u16>,
     pub ip_version: Constraint<IpVersion>,
 
@@ -219,7 +232,9 @@ pub struct WireguardMatcher {
 impl WireguardMatcher {
     pub fn new(constraints: WireguardConstraints, data: WireguardEndpointData) -> Self {
         Self {
-            peer: None,
+            peers: vec![],
+            partners: vec![],
+            diversity: constraints.diversity,
             port: constraints.port,
             ip_version: constraints.ip_version,
             data,
@@ -249,6 +264,7 @@ impl WireguardMatcher {
             endpoint: SocketAddr::new(host, port),
             allowed_ips: all_of_the_internet(),
             psk: None,
+            persistent_keepalive: None,
         };
         Some(MullvadEndpoint::Wireguard(MullvadWireguardEndpoint {
             peer: peer_config,
@@ -306,10 +322,14 @@ impl WireguardMatcher {
 impl EndpointMatcher for WireguardMatcher {
     fn is_matching_relay(&self, relay: &Relay) -> bool {
         !self
-            .peer
-            .as_ref()
-            .map(|peer_relay| peer_relay.hostname == relay.hostname)
-            .unwrap_or(false)
+            .peers
+            .iter()
+            .any(|peer_relay| peer_relay.hostname == relay.hostname)
+            && (self.partners.is_empty()
+                || self
+                    .partners
+                    .iter()
+                    .any(|partner| self.diversity.is_satisfied_by(partner, relay)))
             && matches!(relay.endpoint_data, RelayEndpointData::Wireguard(..))
     }
 
@@ -1,10 +1,12 @@
-use super::{Error, ParsedRelays};
+use super::{verification, Error, ParsedRelays};
 use futures::{
     channel::mpsc,
     future::{Fuse, FusedFuture},
     Future, FutureExt, SinkExt, StreamExt,
 };
-use mullvad_api::{availability::ApiAvailabilityHandle, rest::MullvadRestHandle, RelayListProxy};
+use mullvad_api::{
+    availability::ApiAvailabilityHandle, rest::MullvadRestHandle, RelayListProxy, SignedRelayList,
+};
 use mullvad_types::relay_list::RelayList;
 use parking_lot::Mutex;
 use std::{
@@ -119,12 +121,15 @@ impl RelayListUpdater {
 
     async fn consume_new_relay_list(
         &mut self,
-        result: Result<Option<RelayList>, mullvad_api::Error>,
+        result: Result<Option<SignedRelayList>, mullvad_api::Error>,
     ) {
         match result {
             Ok(Some(relay_list)) => {
-                if let Err(err) = self.update_cache(relay_list).await {
-                    log::error!("Failed to update relay list cache: {}", err);
+                if let Err(error) = self.update_cache(relay_list).await {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Rejecting downloaded relay list")
+                    );
                 }
             }
             Ok(None) => log::debug!("Relay list is up-to-date"),
@@ -151,7 +156,7 @@ impl RelayListUpdater {
         api_handle: ApiAvailabilityHandle,
         proxy: RelayListProxy,
         tag: Option<String>,
-    ) -> impl Future<Output = Result<Option<RelayList>, mullvad_api::Error>> + 'static {
+    ) -> impl Future<Output = Result<Option<SignedRelayList>, mullvad_api::Error>> + 'static {
         let download_futures = move || {
             let available = api_handle.wait_background();
             let req = proxy.relay_list(tag.clone());
@@ -172,7 +177,11 @@ impl RelayListUpdater {
         )
     }
 
-    async fn update_cache(&mut self, new_relay_list: RelayList) -> Result<(), Error> {
+    /// Verifies and starts using a downloaded relay list, and writes it to the cache file.
+    async fn update_cache(&mut self, new_relay_list: SignedRelayList) -> Result<(), Error> {
+        verification::verify(&new_relay_list)?;
+        let parsed_relay_list = new_relay_list.parse().map_err(Error::Serialize)?;
+
         if let Err(error) = Self::cache_relays(&self.cache_path, &new_relay_list).await {
             log::error!(
                 "{}",
@@ -180,7 +189,7 @@ impl RelayListUpdater {
             );
         }
 
-        let new_parsed_relays = ParsedRelays::from_relay_list(new_relay_list, SystemTime::now());
+        let new_parsed_relays = ParsedRelays::from_relay_list(parsed_relay_list, SystemTime::now());
         log::info!(
             "Downloaded relay inventory has {} relays",
             new_parsed_relays.relays().len()
@@ -192,8 +201,8 @@ impl RelayListUpdater {
         Ok(())
     }
 
-    /// Write a `RelayList` to the cache file.
-    async fn cache_relays(cache_path: &Path, relays: &RelayList) -> Result<(), Error> {
+    /// Write a `SignedRelayList` to the cache file.
+    async fn cache_relays(cache_path: &Path, relays: &SignedRelayList) -> Result<(), Error> {
         log::debug!("Writing relays cache to {}", cache_path.display());
         let mut file = File::create(cache_path)
             .await
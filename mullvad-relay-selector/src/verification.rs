@@ -0,0 +1,93 @@
+//! Verification of the detached signatures that relay lists are distributed with.
+
+use super::Error;
+use ed25519_dalek::{Signature, VerifyingKey};
+use mullvad_api::SignedRelayList;
+
+/// The ed25519 public key that relay lists must be signed with. Relay lists that are downloaded
+/// or read from disk are rejected unless they carry a valid signature made with its private half.
+const RELAY_LIST_PUBLIC_KEY: [u8; 32] = [
+    0xdd, 0xca, 0x04, 0xc3, 0xf7, 0xdb, 0xae, 0x06, 0xa4, 0x7a, 0xfb, 0xf0, 0x8a, 0x49, 0x69, 0xb6,
+    0x7e, 0xc1, 0x5b, 0x92, 0x45, 0x04, 0x25, 0x8c, 0xa4, 0x6d, 0x1a, 0x84, 0x0f, 0x52, 0xf3, 0x2a,
+];
+
+/// Verifies that `relay_list` is signed with the pinned relay list key.
+pub fn verify(relay_list: &SignedRelayList) -> Result<(), Error> {
+    verify_with_key(relay_list, &RELAY_LIST_PUBLIC_KEY)
+}
+
+fn verify_with_key(relay_list: &SignedRelayList, public_key: &[u8; 32]) -> Result<(), Error> {
+    if relay_list.signature.is_empty() {
+        return Err(Error::MissingSignature);
+    }
+    let public_key = VerifyingKey::from_bytes(public_key).expect("Invalid relay list public key");
+    let signature = hex::decode(relay_list.signature.trim())
+        .ok()
+        .and_then(|signature| Signature::from_slice(&signature).ok())
+        .ok_or(Error::InvalidSignature)?;
+    public_key
+        .verify_strict(relay_list.content.as_bytes(), &signature)
+        .map_err(|_| Error::InvalidSignature)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    const CONTENT: &str = r#"{"locations":{},"openvpn":{},"wireguard":{},"bridge":{}}"#;
+
+    fn sign(content: &str) -> (SignedRelayList, [u8; 32]) {
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let signature = signing_key.sign(content.as_bytes());
+        let relay_list = SignedRelayList {
+            content: content.to_string(),
+            signature: hex::encode(signature.to_bytes()),
+            etag: None,
+        };
+        (relay_list, signing_key.verifying_key().to_bytes())
+    }
+
+    #[test]
+    fn test_relay_list_public_key_is_valid() {
+        assert!(VerifyingKey::from_bytes(&RELAY_LIST_PUBLIC_KEY).is_ok());
+    }
+
+    #[test]
+    fn test_verify_signed_relay_list() {
+        let (relay_list, public_key) = sign(CONTENT);
+        assert!(verify_with_key(&relay_list, &public_key).is_ok());
+    }
+
+    #[test]
+    fn test_reject_tampered_relay_list() {
+        let (mut relay_list, public_key) = sign(CONTENT);
+        relay_list.content = relay_list.content.replace("wireguard", "WireGuard");
+        assert!(matches!(
+            verify_with_key(&relay_list, &public_key),
+            Err(Error::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_reject_wrong_key() {
+        let (relay_list, _public_key) = sign(CONTENT);
+        assert!(matches!(verify(&relay_list), Err(Error::InvalidSignature)));
+    }
+
+    #[test]
+    fn test_reject_missing_signature() {
+        let (mut relay_list, public_key) = sign(CONTENT);
+        relay_list.signature.clear();
+        assert!(matches!(
+            verify_with_key(&relay_list, &public_key),
+            Err(Error::MissingSignature)
+        ));
+
+        relay_list.signature = "not a signature".to_string();
+        assert!(matches!(
+            verify_with_key(&relay_list, &public_key),
+            Err(Error::InvalidSignature)
+        ));
+    }
+}
@@ -2,11 +2,17 @@ use clap::{crate_authors, crate_description, crate_name, App};
 use mullvad_api::{self, proxy::ApiConnectionMode};
 use mullvad_management_interface::new_rpc_client;
 use mullvad_types::version::ParsedAppVersion;
+#[cfg(target_os = "linux")]
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::{path::PathBuf, process, str::FromStr, time::Duration};
 use talpid_core::{
     firewall::{self, Firewall},
     future_retry::{constant_interval, retry_future_n},
 };
+#[cfg(target_os = "linux")]
+use talpid_types::net::{
+    default_lan_networks, AllowedEndpoint, AllowedTunnelTraffic, Endpoint, TransportProtocol,
+};
 use talpid_types::ErrorExt;
 
 lazy_static::lazy_static! {
@@ -69,6 +75,14 @@ pub enum Error {
 
     #[error(display = "Cannot parse the version string")]
     ParseVersionStringError,
+
+    #[cfg(target_os = "linux")]
+    #[error(display = "Invalid endpoint: {}", _0)]
+    InvalidEndpoint(String),
+
+    #[cfg(target_os = "linux")]
+    #[error(display = "Invalid allowed in-tunnel traffic")]
+    InvalidAllowedTunnelTraffic,
 }
 
 #[tokio::main]
@@ -97,12 +111,19 @@ async fn main() {
         .global_setting(clap::AppSettings::DisableHelpSubcommand)
         .global_setting(clap::AppSettings::DisableVersionFlag)
         .subcommands(subcommands);
+    #[cfg(target_os = "linux")]
+    let app = app.subcommand(create_firewall_subcommand());
 
     let matches = app.get_matches();
     let result = match matches.subcommand() {
         Some(("prepare-restart", _)) => prepare_restart().await,
         Some(("reset-firewall", _)) => reset_firewall().await,
         Some(("remove-device", _)) => remove_device().await,
+        #[cfg(target_os = "linux")]
+        Some(("firewall", sub_matches)) => match sub_matches.subcommand() {
+            Some(("dump", dump_matches)) => dump_firewall(dump_matches),
+            _ => unreachable!("No firewall command matched"),
+        },
         Some(("is-older-version", sub_matches)) => {
             let old_version = sub_matches.value_of("OLDVERSION").unwrap();
             match is_older_version(old_version) {
@@ -154,6 +175,268 @@ async fn reset_firewall() -> Result<(), Error> {
     .map_err(Error::FirewallError)
 }
 
+#[cfg(target_os = "linux")]
+fn create_firewall_subcommand() -> App<'static> {
+    let allow_lan = clap::Arg::new("allow-lan")
+        .long("allow-lan")
+        .help("Allow traffic to and from the local network");
+    let peer = clap::Arg::new("peer")
+        .long("peer")
+        .required(true)
+        .takes_value(true)
+        .value_name("ENDPOINT")
+        .help("The relay to connect to, as <address>:<port>[/udp|/tcp]");
+    let allowed_endpoint = clap::Arg::new("allowed-endpoint")
+        .long("allowed-endpoint")
+        .takes_value(true)
+        .value_name("ENDPOINT")
+        .help("An endpoint, such as the API, that is reachable outside the tunnel");
+    let tunnel = clap::Arg::new("tunnel")
+        .long("tunnel")
+        .takes_value(true)
+        .value_name("INTERFACE")
+        .requires_all(&["tunnel-ip", "gateway"])
+        .help("Name of the tunnel interface");
+    let tunnel_args = [
+        clap::Arg::new("tunnel-ip")
+            .long("tunnel-ip")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .value_name("IP")
+            .requires("tunnel")
+            .help("Address of the tunnel interface"),
+        clap::Arg::new("gateway")
+            .long("gateway")
+            .takes_value(true)
+            .value_name("IPV4")
+            .requires("tunnel")
+            .help("IPv4 gateway of the tunnel interface"),
+        clap::Arg::new("ipv6-gateway")
+            .long("ipv6-gateway")
+            .takes_value(true)
+            .value_name("IPV6")
+            .requires("tunnel")
+            .help("IPv6 gateway of the tunnel interface"),
+    ];
+
+    App::new("firewall")
+        .about("Inspect the firewall rules applied by the daemon")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            App::new("dump")
+                .about(
+                    "Print the nftables ruleset that would be applied for a firewall policy, \
+                     without applying it",
+                )
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .arg(
+                    clap::Arg::new("exclude-uid")
+                        .long("exclude-uid")
+                        .global(true)
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .value_name("UID")
+                        .help("Exclude the traffic of a user from the tunnel"),
+                )
+                .arg(
+                    clap::Arg::new("exclude-gid")
+                        .long("exclude-gid")
+                        .global(true)
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .value_name("GID")
+                        .help("Exclude the traffic of a group from the tunnel"),
+                )
+                .arg(
+                    clap::Arg::new("allow")
+                        .long("allow")
+                        .global(true)
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .value_name("NETWORK:PORT/PROTOCOL")
+                        .help("Endpoint that may be reached outside the tunnel"),
+                )
+                .arg(
+                    clap::Arg::new("lan-network")
+                        .long("lan-network")
+                        .global(true)
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .value_name("NETWORK")
+                        .help(
+                            "Network that is considered local. Defaults to the private address \
+                             ranges",
+                        ),
+                )
+                .subcommand(
+                    App::new("blocked")
+                        .about("Policy of the blocked state")
+                        .arg(allow_lan.clone())
+                        .arg(allowed_endpoint.clone()),
+                )
+                .subcommand(
+                    App::new("connecting")
+                        .about("Policy of the connecting state")
+                        .arg(peer.clone())
+                        .arg(allowed_endpoint.clone().required(true))
+                        .arg(tunnel.clone())
+                        .args(tunnel_args.clone())
+                        .arg(
+                            clap::Arg::new("allowed-tunnel-traffic")
+                                .long("allowed-tunnel-traffic")
+                                .takes_value(true)
+                                .multiple_occurrences(true)
+                                .max_occurrences(2)
+                                .value_name("ENDPOINT")
+                                .requires("tunnel")
+                                .help(
+                                    "Endpoint that may be reached inside the tunnel, or 'all'. \
+                                     All in-tunnel traffic is allowed by default",
+                                ),
+                        )
+                        .arg(allow_lan.clone()),
+                )
+                .subcommand(
+                    App::new("connected")
+                        .about("Policy of the connected state")
+                        .arg(peer)
+                        .arg(tunnel.required(true))
+                        .args(tunnel_args)
+                        .arg(
+                            clap::Arg::new("dns")
+                                .long("dns")
+                                .takes_value(true)
+                                .multiple_occurrences(true)
+                                .value_name("IP")
+                                .help("DNS server to allow"),
+                        )
+                        .arg(allow_lan),
+                ),
+        )
+}
+
+#[cfg(target_os = "linux")]
+fn dump_firewall(matches: &clap::ArgMatches) -> Result<(), Error> {
+    let mut lan_networks = values_or_empty(matches, "lan-network");
+    if lan_networks.is_empty() {
+        lan_networks = default_lan_networks();
+    }
+    let policy = match matches.subcommand() {
+        Some(("blocked", matches)) => firewall::FirewallPolicy::Blocked {
+            allow_lan: matches.is_present("allow-lan"),
+            lan_networks,
+            allowed_endpoint: matches
+                .value_of("allowed-endpoint")
+                .map(parse_allowed_endpoint)
+                .transpose()?,
+            allow_list: values_or_empty(matches, "allow"),
+        },
+        Some(("connecting", matches)) => {
+            let tunnel = parse_tunnel_metadata(matches);
+            let allowed_tunnel_traffic = match matches.values_of("allowed-tunnel-traffic") {
+                None if tunnel.is_some() => AllowedTunnelTraffic::All,
+                None => AllowedTunnelTraffic::None,
+                Some(values) => {
+                    let values: Vec<_> = values.collect();
+                    match values[..] {
+                        ["all"] => AllowedTunnelTraffic::All,
+                        [endpoint] => AllowedTunnelTraffic::One(parse_endpoint(endpoint)?),
+                        [endpoint1, endpoint2] => AllowedTunnelTraffic::Two(
+                            parse_endpoint(endpoint1)?,
+                            parse_endpoint(endpoint2)?,
+                        ),
+                        _ => return Err(Error::InvalidAllowedTunnelTraffic),
+                    }
+                }
+            };
+            firewall::FirewallPolicy::Connecting {
+                peer_endpoint: parse_endpoint(matches.value_of("peer").unwrap())?,
+                tunnel,
+                allow_lan: matches.is_present("allow-lan"),
+                lan_networks,
+                allowed_endpoint: parse_allowed_endpoint(
+                    matches.value_of("allowed-endpoint").unwrap(),
+                )?,
+                allowed_tunnel_traffic,
+                allow_list: values_or_empty(matches, "allow"),
+            }
+        }
+        Some(("connected", matches)) => firewall::FirewallPolicy::Connected {
+            peer_endpoint: parse_endpoint(matches.value_of("peer").unwrap())?,
+            tunnel: parse_tunnel_metadata(matches).unwrap(),
+            allow_lan: matches.is_present("allow-lan"),
+            lan_networks,
+            dns_servers: values_or_empty(matches, "dns"),
+            allow_list: values_or_empty(matches, "allow"),
+            inbound_rules: vec![],
+        },
+        _ => unreachable!("No policy matched"),
+    };
+
+    let mut firewall = Firewall::new(mullvad_types::TUNNEL_FWMARK).map_err(Error::FirewallError)?;
+    firewall
+        .set_excluded_users(
+            values_or_empty(matches, "exclude-uid"),
+            values_or_empty(matches, "exclude-gid"),
+        )
+        .map_err(Error::FirewallError)?;
+    print!(
+        "{}",
+        firewall
+            .dump_policy(&policy)
+            .map_err(Error::FirewallError)?
+    );
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn parse_tunnel_metadata(
+    matches: &clap::ArgMatches,
+) -> Option<talpid_core::tunnel::TunnelMetadata> {
+    Some(talpid_core::tunnel::TunnelMetadata {
+        interface: matches.value_of("tunnel")?.to_owned(),
+        ips: values_or_empty::<IpAddr>(matches, "tunnel-ip"),
+        ipv4_gateway: matches.value_of_t_or_exit::<Ipv4Addr>("gateway"),
+        ipv6_gateway: matches
+            .is_present("ipv6-gateway")
+            .then(|| matches.value_of_t_or_exit::<Ipv6Addr>("ipv6-gateway")),
+    })
+}
+
+/// Parses an endpoint on the form `<address>:<port>[/udp|/tcp]`. UDP is assumed if no protocol
+/// is given.
+#[cfg(target_os = "linux")]
+fn parse_endpoint(endpoint: &str) -> Result<Endpoint, Error> {
+    let (address, protocol) = match endpoint.rsplit_once('/') {
+        Some((address, protocol)) => (address, protocol.to_lowercase().parse().ok()),
+        None => (endpoint, Some(TransportProtocol::Udp)),
+    };
+    match (address.parse(), protocol) {
+        (Ok(address), Some(protocol)) => Ok(Endpoint::from_socket_address(address, protocol)),
+        _ => Err(Error::InvalidEndpoint(endpoint.to_owned())),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn parse_allowed_endpoint(endpoint: &str) -> Result<AllowedEndpoint, Error> {
+    Ok(AllowedEndpoint {
+        endpoint: parse_endpoint(endpoint)?,
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn values_or_empty<T>(matches: &clap::ArgMatches, name: &str) -> Vec<T>
+where
+    T: FromStr,
+    <T as FromStr>::Err: std::fmt::Display,
+{
+    if matches.is_present(name) {
+        matches.values_of_t_or_exit(name)
+    } else {
+        vec![]
+    }
+}
+
 async fn remove_device() -> Result<(), Error> {
     let (cache_path, settings_path) = get_paths()?;
     let (cacher, state) = mullvad_daemon::device::DeviceCacher::new(&settings_path)
@@ -0,0 +1,30 @@
+use crate::states::TunnelState;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// A tunnel state that the daemon has entered, as recorded in the connection history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionHistoryEntry {
+    /// When the state was entered.
+    pub timestamp: DateTime<Utc>,
+    pub state: TunnelState,
+    /// Summary of the connected session that ended when this state was entered, if any.
+    pub ended_session: Option<SessionSummary>,
+}
+
+/// Summary of a period spent in the connected state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionSummary {
+    /// How long the tunnel was connected.
+    pub duration: Duration,
+    /// Traffic sent through the exit relay. This is only available for WireGuard tunnels.
+    pub traffic: Option<TrafficStats>,
+}
+
+/// Byte counters of a tunnel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrafficStats {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
@@ -0,0 +1,140 @@
+use crate::relay_constraints::LocationConstraint;
+use serde::{Deserialize, Serialize};
+
+pub type CustomListName = String;
+
+#[derive(err_derive::Error, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// A custom list with the same name already exists
+    #[error(display = "A custom list named \"{}\" already exists", _0)]
+    ListExists(CustomListName),
+
+    /// The custom list does not exist
+    #[error(display = "There is no custom list named \"{}\"", _0)]
+    ListNotFound(CustomListName),
+
+    /// The custom list is referenced by the current relay or bridge constraints
+    #[error(display = "The custom list \"{}\" is currently in use", _0)]
+    ListInUse(CustomListName),
+
+    /// Custom list names may not be empty
+    #[error(display = "Custom list names must not be empty")]
+    InvalidName,
+
+    /// Custom lists may only contain countries, cities and hostnames
+    #[error(display = "A custom list cannot contain another custom list")]
+    NestedList,
+}
+
+/// A named set of locations. It can be referenced by a
+/// [`LocationConstraint::CustomList`] to select relays from all of its locations.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+pub struct CustomList {
+    pub name: CustomListName,
+    pub locations: Vec<LocationConstraint>,
+}
+
+impl CustomList {
+    pub fn new(name: CustomListName) -> Self {
+        CustomList {
+            name,
+            locations: Vec::new(),
+        }
+    }
+}
+
+/// Contains all custom lists created by the user.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CustomListsSettings {
+    pub custom_lists: Vec<CustomList>,
+}
+
+impl CustomListsSettings {
+    /// Returns the custom list with the given name, if it exists.
+    pub fn get(&self, name: &str) -> Option<&CustomList> {
+        self.custom_lists.iter().find(|list| list.name == name)
+    }
+
+    /// Adds a new, empty custom list.
+    pub fn create(&mut self, name: CustomListName) -> Result<(), Error> {
+        if name.trim().is_empty() {
+            return Err(Error::InvalidName);
+        }
+        if self.get(&name).is_some() {
+            return Err(Error::ListExists(name));
+        }
+        self.custom_lists.push(CustomList::new(name));
+        Ok(())
+    }
+
+    /// Removes the custom list with the given name.
+    pub fn delete(&mut self, name: &str) -> Result<(), Error> {
+        let index = self
+            .custom_lists
+            .iter()
+            .position(|list| list.name == name)
+            .ok_or_else(|| Error::ListNotFound(name.to_owned()))?;
+        self.custom_lists.remove(index);
+        Ok(())
+    }
+
+    /// Replaces the locations of an existing custom list with those of `new_list`.
+    pub fn update(&mut self, new_list: CustomList) -> Result<(), Error> {
+        if new_list
+            .locations
+            .iter()
+            .any(|location| matches!(location, LocationConstraint::CustomList(..)))
+        {
+            return Err(Error::NestedList);
+        }
+        let list = self
+            .custom_lists
+            .iter_mut()
+            .find(|list| list.name == new_list.name)
+            .ok_or_else(|| Error::ListNotFound(new_list.name.clone()))?;
+        *list = new_list;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_create_and_delete() {
+        let mut lists = CustomListsSettings::default();
+        lists.create("eu".to_owned()).unwrap();
+        assert_eq!(
+            lists.create("eu".to_owned()),
+            Err(Error::ListExists("eu".to_owned()))
+        );
+        assert_eq!(lists.create(" ".to_owned()), Err(Error::InvalidName));
+
+        lists.delete("eu").unwrap();
+        assert_eq!(lists.delete("eu"), Err(Error::ListNotFound("eu".to_owned())));
+    }
+
+    #[test]
+    fn test_update_rejects_nested_lists() {
+        let mut lists = CustomListsSettings::default();
+        lists.create("eu".to_owned()).unwrap();
+
+        let nested = CustomList {
+            name: "eu".to_owned(),
+            locations: vec![LocationConstraint::CustomList("eu".to_owned())],
+        };
+        assert_eq!(lists.update(nested), Err(Error::NestedList));
+
+        let list = CustomList {
+            name: "eu".to_owned(),
+            locations: vec![
+                LocationConstraint::Country("se".to_owned()),
+                LocationConstraint::City("de".to_owned(), "fra".to_owned()),
+            ],
+        };
+        lists.update(list.clone()).unwrap();
+        assert_eq!(lists.get("eu"), Some(&list));
+    }
+}
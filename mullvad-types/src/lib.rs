@@ -2,14 +2,20 @@
 
 pub mod account;
 pub mod auth_failed;
+pub mod connection_history;
+pub mod custom_list;
 pub mod device;
 pub mod endpoint;
 pub mod location;
+pub mod network_rules;
 pub mod relay_constraints;
 pub mod relay_list;
+pub mod relay_rotation;
 pub mod settings;
 pub mod states;
+pub mod tunnel_stats;
 pub mod version;
+pub mod wg_quick;
 pub mod wireguard;
 
 mod custom_tunnel;
@@ -0,0 +1,301 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+#[derive(err_derive::Error, Debug, Clone, PartialEq, Eq)]
+#[error(display = "Invalid MAC address: {}", _0)]
+pub struct ParseMacAddressError(String);
+
+#[derive(err_derive::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkRuleError {
+    #[error(display = "A network rule must match at least one network property")]
+    NoNetwork,
+
+    /// Anyone can broadcast a given SSID, so it cannot on its own identify a network where
+    /// traffic may leave the tunnel.
+    #[error(
+        display = "A trusted network must be identified by a connection or gateway MAC address, \
+        not only by its SSID"
+    )]
+    TrustedBySsidOnly,
+}
+
+/// A 48-bit hardware address, written as six colon-separated hexadecimal octets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct MacAddress(pub [u8; 6]);
+
+impl FromStr for MacAddress {
+    type Err = ParseMacAddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseMacAddressError(s.to_owned());
+
+        let mut octets = [0u8; 6];
+        let mut parts = s.split([':', '-']);
+        for octet in octets.iter_mut() {
+            let part = parts.next().ok_or_else(invalid)?;
+            if part.len() != 2 || !part.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(invalid());
+            }
+            *octet = u8::from_str_radix(part, 16).map_err(|_| invalid())?;
+        }
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(MacAddress(octets))
+    }
+}
+
+impl TryFrom<String> for MacAddress {
+    type Error = ParseMacAddressError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<MacAddress> for String {
+    fn from(address: MacAddress) -> Self {
+        address.to_string()
+    }
+}
+
+impl fmt::Display for MacAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let octets: Vec<String> = self.0.iter().map(|octet| format!("{octet:02x}")).collect();
+        f.write_str(&octets.join(":"))
+    }
+}
+
+/// The network that the device is currently connected to.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct NetworkInfo {
+    /// UUID of the NetworkManager connection profile.
+    pub connection_uuid: String,
+    /// Name of the NetworkManager connection profile.
+    pub connection_name: String,
+    /// SSID of the access point, if this is a Wi-Fi network.
+    pub ssid: Option<String>,
+    /// Hardware address of the default gateway, if it is known.
+    pub gateway_mac: Option<MacAddress>,
+}
+
+/// Identifies the networks that a [`NetworkRule`] applies to.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkMatch {
+    /// Wi-Fi networks with the given SSID.
+    Ssid(String),
+    /// The NetworkManager connection profile with the given UUID.
+    ConnectionUuid(String),
+    /// Networks whose default gateway has the given hardware address.
+    GatewayMac(MacAddress),
+}
+
+impl NetworkMatch {
+    pub fn matches(&self, network: &NetworkInfo) -> bool {
+        match self {
+            NetworkMatch::Ssid(ssid) => network.ssid.as_ref() == Some(ssid),
+            NetworkMatch::ConnectionUuid(uuid) => {
+                network.connection_uuid.eq_ignore_ascii_case(uuid)
+            }
+            NetworkMatch::GatewayMac(mac) => network.gateway_mac.as_ref() == Some(mac),
+        }
+    }
+}
+
+impl fmt::Display for NetworkMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkMatch::Ssid(ssid) => write!(f, "SSID \"{ssid}\""),
+            NetworkMatch::ConnectionUuid(uuid) => write!(f, "connection {uuid}"),
+            NetworkMatch::GatewayMac(mac) => write!(f, "gateway {mac}"),
+        }
+    }
+}
+
+/// How the daemon treats a network when the device joins it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkTrust {
+    /// Disconnect the tunnel, and do not block traffic while disconnected, even if
+    /// `block_when_disconnected` is enabled. Since an SSID can be spoofed, a trusted network must
+    /// also be matched by its connection or gateway MAC address.
+    Trusted,
+    /// Connect the tunnel.
+    Untrusted,
+}
+
+impl fmt::Display for NetworkTrust {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkTrust::Trusted => f.write_str("trusted"),
+            NetworkTrust::Untrusted => f.write_str("untrusted"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct NetworkRule {
+    /// The rule applies to networks that match all of these.
+    pub networks: Vec<NetworkMatch>,
+    pub trust: NetworkTrust,
+}
+
+impl NetworkRule {
+    pub fn validate(&self) -> Result<(), NetworkRuleError> {
+        if self.networks.is_empty() {
+            return Err(NetworkRuleError::NoNetwork);
+        }
+        let ssid_only = self
+            .networks
+            .iter()
+            .all(|network| matches!(network, NetworkMatch::Ssid(_)));
+        if self.trust == NetworkTrust::Trusted && ssid_only {
+            return Err(NetworkRuleError::TrustedBySsidOnly);
+        }
+        Ok(())
+    }
+
+    pub fn matches(&self, network: &NetworkInfo) -> bool {
+        !self.networks.is_empty() && self.networks.iter().all(|m| m.matches(network))
+    }
+}
+
+impl fmt::Display for NetworkRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let networks: Vec<String> = self.networks.iter().map(|m| m.to_string()).collect();
+        write!(f, "{} is {}", networks.join(" and "), self.trust)
+    }
+}
+
+/// Rules that connect or disconnect the tunnel depending on which network the device joins.
+/// The rules are evaluated when the network changes and when the rules themselves are changed.
+/// The tunnel can still be connected or disconnected manually in between.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NetworkRulesSettings {
+    /// Evaluated in order. The first rule that matches the network decides its trust.
+    pub rules: Vec<NetworkRule>,
+    /// Trust of networks that no rule matches. If `None`, joining such a network does not
+    /// change the target state.
+    pub unknown_network_trust: Option<NetworkTrust>,
+}
+
+impl NetworkRulesSettings {
+    pub fn validate(&self) -> Result<(), NetworkRuleError> {
+        self.rules.iter().try_for_each(NetworkRule::validate)
+    }
+
+    /// Returns how `network` should be treated, or `None` if the target state should be left
+    /// unchanged. Invalid rules are ignored.
+    pub fn evaluate(&self, network: &NetworkInfo) -> Option<NetworkTrust> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.validate().is_ok())
+            .find(|rule| rule.matches(network))
+            .map(|rule| rule.trust)
+            .or(self.unknown_network_trust)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn office_wifi() -> NetworkInfo {
+        NetworkInfo {
+            connection_uuid: "5f1b2d3e-0c7a-4f7e-9a59-1d2c3b4a5f6e".to_owned(),
+            connection_name: "Office".to_owned(),
+            ssid: Some("office".to_owned()),
+            gateway_mac: Some("00:11:22:aa:bb:cc".parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_parse_mac_address() {
+        let mac: MacAddress = "00:11:22:AA:bb:CC".parse().unwrap();
+        assert_eq!(mac, MacAddress([0x00, 0x11, 0x22, 0xaa, 0xbb, 0xcc]));
+        assert_eq!(mac.to_string(), "00:11:22:aa:bb:cc");
+        assert_eq!("00-11-22-aa-bb-cc".parse::<MacAddress>().unwrap(), mac);
+
+        assert!("00:11:22:aa:bb".parse::<MacAddress>().is_err());
+        assert!("00:11:22:aa:bb:cc:dd".parse::<MacAddress>().is_err());
+        assert!("00:11:22:aa:bb:c".parse::<MacAddress>().is_err());
+        assert!("00:11:22:aa:bb:zz".parse::<MacAddress>().is_err());
+        assert!("+1:11:22:aa:bb:cc".parse::<MacAddress>().is_err());
+    }
+
+    #[test]
+    fn test_evaluate_rules() {
+        let mut settings = NetworkRulesSettings {
+            rules: vec![
+                NetworkRule {
+                    networks: vec![
+                        NetworkMatch::Ssid("office".to_owned()),
+                        NetworkMatch::GatewayMac("00:11:22:aa:bb:cc".parse().unwrap()),
+                    ],
+                    trust: NetworkTrust::Trusted,
+                },
+                NetworkRule {
+                    networks: vec![NetworkMatch::GatewayMac(
+                        "00:11:22:aa:bb:cc".parse().unwrap(),
+                    )],
+                    trust: NetworkTrust::Untrusted,
+                },
+            ],
+            unknown_network_trust: None,
+        };
+
+        // The first matching rule wins
+        assert_eq!(
+            settings.evaluate(&office_wifi()),
+            Some(NetworkTrust::Trusted)
+        );
+
+        let wired = NetworkInfo {
+            ssid: None,
+            ..office_wifi()
+        };
+        assert_eq!(settings.evaluate(&wired), Some(NetworkTrust::Untrusted));
+
+        let cafe = NetworkInfo {
+            connection_uuid: "0b7a6c5d-4e3f-4a2b-8c1d-0e9f8a7b6c5d".to_owned(),
+            connection_name: "Cafe".to_owned(),
+            ssid: Some("cafe".to_owned()),
+            gateway_mac: None,
+        };
+        assert_eq!(settings.evaluate(&cafe), None);
+
+        settings.unknown_network_trust = Some(NetworkTrust::Untrusted);
+        assert_eq!(settings.evaluate(&cafe), Some(NetworkTrust::Untrusted));
+    }
+
+    #[test]
+    fn test_trusted_by_ssid_only() {
+        let rule = NetworkRule {
+            networks: vec![NetworkMatch::Ssid("office".to_owned())],
+            trust: NetworkTrust::Trusted,
+        };
+        assert_eq!(rule.validate(), Err(NetworkRuleError::TrustedBySsidOnly));
+
+        // Such a rule, e.g. from an edited settings file, never applies
+        let settings = NetworkRulesSettings {
+            rules: vec![rule],
+            unknown_network_trust: None,
+        };
+        assert_eq!(settings.evaluate(&office_wifi()), None);
+
+        let untrusted = NetworkRule {
+            networks: vec![NetworkMatch::Ssid("office".to_owned())],
+            trust: NetworkTrust::Untrusted,
+        };
+        assert_eq!(untrusted.validate(), Ok(()));
+
+        let no_network = NetworkRule {
+            networks: vec![],
+            trust: NetworkTrust::Untrusted,
+        };
+        assert_eq!(no_network.validate(), Err(NetworkRuleError::NoNetwork));
+    }
+}
@@ -2,6 +2,7 @@
 //! updated as well.
 
 use crate::{
+    custom_list::{CustomListName, CustomListsSettings},
     location::{CityCode, CountryCode, Hostname},
     relay_list::Relay,
     CustomTunnelEndpoint,
@@ -285,6 +286,8 @@ pub enum LocationConstraint {
     City(CountryCode, CityCode),
     /// An single hostname in a given city.
     Hostname(CountryCode, CityCode, Hostname),
+    /// All locations in the custom list with the given name.
+    CustomList(CustomListName),
 }
 
 impl LocationConstraint {
@@ -309,6 +312,8 @@ impl LocationConstraint {
                         && relay.hostname == *hostname
                 })
             }
+            // Custom lists must be resolved using `ResolvedLocationConstraint` before matching
+            LocationConstraint::CustomList(..) => false,
         }
     }
 }
@@ -346,11 +351,75 @@ impl Set<LocationConstraint> for LocationConstraint {
                     country == other_country && city == other_city
                 }
                 LocationConstraint::Hostname(..) => self == other,
+                LocationConstraint::CustomList(..) => false,
             },
+            LocationConstraint::CustomList(..) => self == other,
         }
     }
 }
 
+/// A [`LocationConstraint`] where any reference to a custom list has been replaced by the
+/// locations in that list.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ResolvedLocationConstraint(Vec<LocationConstraint>);
+
+impl ResolvedLocationConstraint {
+    pub fn from_constraint(
+        location: Constraint<LocationConstraint>,
+        custom_lists: &CustomListsSettings,
+    ) -> Constraint<ResolvedLocationConstraint> {
+        location.map(|location| match location {
+            LocationConstraint::CustomList(name) => {
+                let locations = match custom_lists.get(&name) {
+                    Some(list) => list.locations.clone(),
+                    None => {
+                        log::warn!("Custom list \"{name}\" does not exist");
+                        vec![]
+                    }
+                };
+                ResolvedLocationConstraint(locations)
+            }
+            location => ResolvedLocationConstraint(vec![location]),
+        })
+    }
+
+    pub fn matches_with_opts(&self, relay: &Relay, ignore_include_in_country: bool) -> bool {
+        self.0
+            .iter()
+            .any(|location| location.matches_with_opts(relay, ignore_include_in_country))
+    }
+}
+
+impl Constraint<ResolvedLocationConstraint> {
+    pub fn matches_with_opts(&self, relay: &Relay, ignore_include_in_country: bool) -> bool {
+        match self {
+            Constraint::Only(constraint) => {
+                constraint.matches_with_opts(relay, ignore_include_in_country)
+            }
+            Constraint::Any => true,
+        }
+    }
+}
+
+impl Match<Relay> for ResolvedLocationConstraint {
+    fn matches(&self, relay: &Relay) -> bool {
+        self.matches_with_opts(relay, false)
+    }
+}
+
+impl Set<ResolvedLocationConstraint> for ResolvedLocationConstraint {
+    /// Returns whether every location in `self` is equal to or a subset of some location in
+    /// `other`.
+    fn is_subset(&self, other: &Self) -> bool {
+        self.0.iter().all(|location| {
+            other
+                .0
+                .iter()
+                .any(|other_location| location.is_subset(other_location))
+        })
+    }
+}
+
 /// Limits the set of servers to choose based on ownership.
 #[derive(Copy, Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
 pub enum Ownership {
@@ -439,6 +508,7 @@ impl fmt::Display for LocationConstraint {
             LocationConstraint::Hostname(country, city, hostname) => {
                 write!(f, "city {city}, {country}, hostname {hostname}")
             }
+            LocationConstraint::CustomList(name) => write!(f, "custom list {name}"),
         }
     }
 }
@@ -478,6 +548,11 @@ pub struct WireguardConstraints {
     pub ip_version: Constraint<IpVersion>,
     pub use_multihop: bool,
     pub entry_location: Constraint<LocationConstraint>,
+    /// Locations of the hops between the entry and exit relays, in order, when using multihop.
+    /// The tunnel only supports an entry and an exit relay, so this must be empty for now.
+    pub middle_locations: Vec<Constraint<LocationConstraint>>,
+    /// How the entry and exit relays must differ when using multihop.
+    pub diversity: MultihopDiversity,
 }
 
 impl fmt::Display for WireguardConstraints {
@@ -493,15 +568,69 @@ impl fmt::Display for WireguardConstraints {
         }
         if self.use_multihop {
             match &self.entry_location {
-                Constraint::Any => write!(f, " (via any location)"),
-                Constraint::Only(location) => write!(f, " (via {location})"),
+                Constraint::Any => write!(f, " (via any location")?,
+                Constraint::Only(location) => write!(f, " (via {location}")?,
+            }
+            if self.diversity.is_enabled() {
+                write!(f, ", with different {} for entry and exit", self.diversity)?;
             }
+            write!(f, ")")
         } else {
             Ok(())
         }
     }
 }
 
+/// Properties that the entry and exit relays of a multihop tunnel must not share.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MultihopDiversity {
+    /// The relays must be located in different countries.
+    pub country: bool,
+    /// The relays must be hosted by different providers.
+    pub provider: bool,
+    /// One of the relays must be owned by Mullvad and the other one rented.
+    pub ownership: bool,
+}
+
+impl MultihopDiversity {
+    pub fn is_enabled(&self) -> bool {
+        self.country || self.provider || self.ownership
+    }
+
+    /// Returns whether `entry` and `exit` may be used together. Relays without a known location
+    /// are never considered to be in different countries.
+    pub fn is_satisfied_by(&self, entry: &Relay, exit: &Relay) -> bool {
+        let different_country = match (&entry.location, &exit.location) {
+            (Some(entry_location), Some(exit_location)) => {
+                entry_location.country_code != exit_location.country_code
+            }
+            _ => false,
+        };
+        (!self.country || different_country)
+            && (!self.provider || entry.provider != exit.provider)
+            && (!self.ownership || entry.owned != exit.owned)
+    }
+}
+
+impl fmt::Display for MultihopDiversity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let properties: Vec<_> = [
+            (self.country, "country"),
+            (self.provider, "provider"),
+            (self.ownership, "ownership"),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, name)| enabled.then_some(name))
+        .collect();
+        if properties.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", properties.join(", "))
+        }
+    }
+}
+
 /// Specifies a specific endpoint or [`BridgeConstraints`] to use when `mullvad-daemon` selects a
 /// bridge server.
 #[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
@@ -519,6 +648,7 @@ pub enum SelectedObfuscation {
     #[default]
     Off,
     Udp2Tcp,
+    Shadowsocks,
 }
 
 impl fmt::Display for SelectedObfuscation {
@@ -527,6 +657,7 @@ impl fmt::Display for SelectedObfuscation {
             SelectedObfuscation::Auto => "auto".fmt(f),
             SelectedObfuscation::Off => "off".fmt(f),
             SelectedObfuscation::Udp2Tcp => "udp2tcp".fmt(f),
+            SelectedObfuscation::Shadowsocks => "shadowsocks".fmt(f),
         }
     }
 }
@@ -546,6 +677,21 @@ impl fmt::Display for Udp2TcpObfuscationSettings {
     }
 }
 
+#[derive(Default, Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ShadowsocksObfuscationSettings {
+    pub port: Constraint<u16>,
+}
+
+impl fmt::Display for ShadowsocksObfuscationSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.port {
+            Constraint::Any => write!(f, "any port"),
+            Constraint::Only(port) => write!(f, "port {port}"),
+        }
+    }
+}
+
 /// Contains obfuscation settings
 #[derive(Default, Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
@@ -553,6 +699,7 @@ impl fmt::Display for Udp2TcpObfuscationSettings {
 pub struct ObfuscationSettings {
     pub selected_obfuscation: SelectedObfuscation,
     pub udp2tcp: Udp2TcpObfuscationSettings,
+    pub shadowsocks: ShadowsocksObfuscationSettings,
 }
 
 /// Limits the set of bridge servers to use in `mullvad-daemon`.
@@ -608,6 +755,27 @@ impl fmt::Display for BridgeState {
     }
 }
 
+/// Determines how a relay is picked among all relays that match the current constraints.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelaySelectionStrategy {
+    /// Pick a random relay, biased by the weights in the relay list.
+    #[default]
+    Weighted,
+    /// Prefer the relays with the lowest measured latency. Falls back to `Weighted` if no
+    /// latency has been measured for any of the matching relays.
+    LowestLatency,
+}
+
+impl fmt::Display for RelaySelectionStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RelaySelectionStrategy::Weighted => "weighted".fmt(f),
+            RelaySelectionStrategy::LowestLatency => "lowest latency".fmt(f),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
 pub struct InternalBridgeConstraints {
     pub location: Constraint<LocationConstraint>,
@@ -1,8 +1,12 @@
 use crate::location::{CityCode, CountryCode, Location};
+use chrono::{DateTime, Utc};
 #[cfg(target_os = "android")]
 use jnix::IntoJava;
 use serde::{Deserialize, Serialize};
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::PathBuf,
+};
 use talpid_types::net::{
     openvpn::{ProxySettings, ShadowsocksProxySettings},
     wireguard, TransportProtocol,
@@ -178,3 +182,15 @@ impl ShadowsocksEndpointData {
         })
     }
 }
+
+/// The result of verifying the signature of a relay list stored on disk.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RelayListVerification {
+    pub path: PathBuf,
+    /// Why the relay list was rejected, or `None` if its signature is valid.
+    pub error: Option<String>,
+    /// The number of relays in the list. Zero unless the list could be verified.
+    pub relay_count: usize,
+    /// When the list was last updated, if it could be verified.
+    pub last_updated: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,159 @@
+use chrono::{DateTime, NaiveTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::{fmt, time::Duration};
+
+/// Rotating more often than this would mostly keep the tunnel reconnecting.
+pub const MIN_RELAY_ROTATION_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(err_derive::Error, Debug, Clone, PartialEq, Eq)]
+pub enum RelayRotationError {
+    #[error(display = "Relay rotation interval must be at least 10 minutes")]
+    IntervalTooSmall,
+
+    #[error(display = "At least one time of day must be given")]
+    NoTimesOfDay,
+}
+
+/// When to reconnect to a newly selected relay. The relay is selected using the current relay
+/// constraints, but the previous exit relay is avoided if there are other matching relays.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelayRotation {
+    /// Rotate after being connected for the given duration.
+    Interval(Duration),
+    /// Rotate every day at the given times, in local time.
+    Daily(Vec<NaiveTime>),
+}
+
+impl RelayRotation {
+    pub fn interval(interval: Duration) -> Result<Self, RelayRotationError> {
+        if interval < MIN_RELAY_ROTATION_INTERVAL {
+            return Err(RelayRotationError::IntervalTooSmall);
+        }
+        Ok(RelayRotation::Interval(interval))
+    }
+
+    pub fn daily(mut times: Vec<NaiveTime>) -> Result<Self, RelayRotationError> {
+        if times.is_empty() {
+            return Err(RelayRotationError::NoTimesOfDay);
+        }
+        times.sort();
+        times.dedup();
+        Ok(RelayRotation::Daily(times))
+    }
+
+    /// Checks that the rotation could have been created using [`RelayRotation::interval`] or
+    /// [`RelayRotation::daily`]. This is useful for rotations that were deserialized.
+    pub fn validate(&self) -> Result<(), RelayRotationError> {
+        match self {
+            RelayRotation::Interval(interval) => Self::interval(*interval).map(|_| ()),
+            RelayRotation::Daily(times) if times.is_empty() => {
+                Err(RelayRotationError::NoTimesOfDay)
+            }
+            RelayRotation::Daily(_) => Ok(()),
+        }
+    }
+
+    /// Returns how long to wait until the next rotation, given that the tunnel was just
+    /// connected at `now`.
+    pub fn time_until_rotation<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> Option<Duration> {
+        match self {
+            RelayRotation::Interval(interval) => Some(*interval),
+            RelayRotation::Daily(times) => {
+                times.iter().filter_map(|time| time_until(now, *time)).min()
+            }
+        }
+    }
+}
+
+/// Returns the time until the clock next shows `time`.
+fn time_until<Tz: TimeZone>(now: &DateTime<Tz>, time: NaiveTime) -> Option<Duration> {
+    let now_local = now.naive_local();
+    let mut next = now_local.date().and_time(time);
+    if next <= now_local {
+        next += chrono::Duration::days(1);
+    }
+    // Non-existent local times, which may occur when daylight saving time starts, are skipped
+    // over by measuring in local time instead.
+    let until_next = match now.timezone().from_local_datetime(&next).earliest() {
+        Some(next) => next.signed_duration_since(now.clone()),
+        None => next - now_local,
+    };
+    until_next.to_std().ok()
+}
+
+impl fmt::Display for RelayRotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RelayRotation::Interval(interval) => {
+                write!(f, "every {} minute(s)", interval.as_secs() / 60)
+            }
+            RelayRotation::Daily(times) => {
+                let times: Vec<String> = times
+                    .iter()
+                    .map(|time| time.format("%H:%M").to_string())
+                    .collect();
+                write!(f, "daily at {}", times.join(", "))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Utc;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_validate_relay_rotation() {
+        assert_eq!(
+            RelayRotation::interval(Duration::from_secs(60)),
+            Err(RelayRotationError::IntervalTooSmall)
+        );
+        assert_eq!(
+            RelayRotation::daily(vec![]),
+            Err(RelayRotationError::NoTimesOfDay)
+        );
+        assert_eq!(
+            RelayRotation::daily(vec![time(20, 0), time(8, 0), time(20, 0)]),
+            Ok(RelayRotation::Daily(vec![time(8, 0), time(20, 0)]))
+        );
+        assert_eq!(
+            RelayRotation::Interval(Duration::from_secs(60)).validate(),
+            Err(RelayRotationError::IntervalTooSmall)
+        );
+        assert_eq!(
+            RelayRotation::Daily(vec![]).validate(),
+            Err(RelayRotationError::NoTimesOfDay)
+        );
+    }
+
+    #[test]
+    fn test_time_until_rotation() {
+        let now = Utc.with_ymd_and_hms(2023, 3, 1, 12, 30, 0).unwrap();
+
+        let interval = RelayRotation::interval(Duration::from_secs(60 * 60)).unwrap();
+        assert_eq!(
+            interval.time_until_rotation(&now),
+            Some(Duration::from_secs(60 * 60))
+        );
+
+        // The next time today is picked
+        let daily = RelayRotation::daily(vec![time(8, 0), time(20, 0)]).unwrap();
+        assert_eq!(
+            daily.time_until_rotation(&now),
+            Some(Duration::from_secs(7 * 60 * 60 + 30 * 60))
+        );
+
+        // If all times have passed today, the first one tomorrow is picked
+        let daily = RelayRotation::daily(vec![time(8, 0), time(12, 30)]).unwrap();
+        assert_eq!(
+            daily.time_until_rotation(&now),
+            Some(Duration::from_secs(19 * 60 * 60 + 30 * 60))
+        );
+    }
+}
@@ -1,7 +1,8 @@
 #[cfg(target_os = "android")]
 use jnix::{FromJava, IntoJava};
 use serde::{Deserialize, Serialize};
-use std::net::IpAddr;
+use std::{net::IpAddr, path::PathBuf};
+use talpid_types::net::dns::{EncryptedDnsServer, SplitDnsRule};
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
 #[serde(rename_all = "snake_case")]
@@ -22,6 +23,12 @@ pub struct DnsOptions {
     pub state: DnsState,
     pub default_options: DefaultDnsOptions,
     pub custom_options: CustomDnsOptions,
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub blocklist_options: DnsBlocklistOptions,
+    /// Domains whose queries are sent to specific resolvers, such as resolvers on the local
+    /// network, instead of the default or custom DNS servers.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub split_dns_rules: Vec<SplitDnsRule>,
 }
 
 /// Default DNS config
@@ -43,4 +50,21 @@ pub struct DefaultDnsOptions {
 #[cfg_attr(target_os = "android", jnix(package = "net.mullvad.mullvadvpn.model"))]
 pub struct CustomDnsOptions {
     pub addresses: Vec<IpAddr>,
+    /// DNS-over-HTTPS and DNS-over-TLS servers. If any are given, these are used instead of
+    /// `addresses`, through a local forwarding resolver.
+    #[serde(default)]
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub encrypted_servers: Vec<EncryptedDnsServer>,
+}
+
+/// Blocklists that are enforced by a local resolver, in addition to the default or custom DNS
+/// options. Queries for blocked domains are answered locally and all other queries are forwarded
+/// to the DNS servers that would otherwise be used.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+#[serde(default)]
+pub struct DnsBlocklistOptions {
+    /// Paths of hosts files or domain lists. Blocklisting is enabled if this is non-empty.
+    pub lists: Vec<PathBuf>,
+    /// Domains that are never blocked, including their subdomains.
+    pub allowlist: Vec<String>,
 }
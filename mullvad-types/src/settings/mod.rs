@@ -1,18 +1,27 @@
+#[cfg(target_os = "linux")]
+use crate::network_rules::NetworkRulesSettings;
 use crate::{
+    custom_list::CustomListsSettings,
     relay_constraints::{
         BridgeConstraints, BridgeSettings, BridgeState, Constraint, LocationConstraint,
-        ObfuscationSettings, RelayConstraints, RelaySettings, RelaySettingsUpdate,
-        SelectedObfuscation, WireguardConstraints,
+        ObfuscationSettings, RelayConstraints, RelaySelectionStrategy, RelaySettings,
+        RelaySettingsUpdate, SelectedObfuscation, WireguardConstraints,
     },
+    relay_rotation::RelayRotation,
     wireguard,
 };
+use ipnetwork::IpNetwork;
 #[cfg(target_os = "android")]
 use jnix::IntoJava;
 use rand::Rng;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-#[cfg(target_os = "windows")]
+#[cfg(target_os = "linux")]
+use std::collections::BTreeSet;
+#[cfg(any(windows, target_os = "linux"))]
 use std::{collections::HashSet, path::PathBuf};
-use talpid_types::net::{openvpn, GenericTunnelOptions};
+#[cfg(target_os = "linux")]
+use talpid_types::net::InboundRule;
+use talpid_types::net::{default_lan_networks, openvpn, AllowListEndpoint, GenericTunnelOptions};
 
 mod dns;
 
@@ -72,21 +81,50 @@ pub struct Settings {
     pub obfuscation_settings: ObfuscationSettings,
     #[cfg_attr(target_os = "android", jnix(skip))]
     pub bridge_state: BridgeState,
+    /// Named sets of locations that can be used as location constraints.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub custom_lists: CustomListsSettings,
+    /// How to pick a relay among all relays that match the relay constraints.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub relay_selection_strategy: RelaySelectionStrategy,
+    /// When to reconnect to a newly selected relay. If `None`, the relay is only changed when
+    /// reconnecting for other reasons.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub relay_rotation: Option<RelayRotation>,
     /// If the daemon should allow communication with private (LAN) networks.
     pub allow_lan: bool,
+    /// Networks that are considered local when `allow_lan` is set. Defaults to the private
+    /// address ranges.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub lan_networks: Vec<IpNetwork>,
     /// Extra level of kill switch. When this setting is on, the disconnected state will block
     /// the firewall to not allow any traffic in or out.
     #[cfg_attr(target_os = "android", jnix(skip))]
     pub block_when_disconnected: bool,
+    /// Endpoints that may be reached outside the tunnel in every tunnel state, e.g. an internal
+    /// update server.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub firewall_allow_list: Vec<AllowListEndpoint>,
+    /// Local ports that may be reached from outside the tunnel while connected, e.g. SSH from a
+    /// management network.
+    #[cfg(target_os = "linux")]
+    pub firewall_inbound_rules: Vec<InboundRule>,
     /// If the daemon should connect the VPN tunnel directly on start or not.
     pub auto_connect: bool,
+    /// Rules that connect or disconnect the tunnel when joining specific networks.
+    #[cfg(target_os = "linux")]
+    pub network_rules: NetworkRulesSettings,
     /// Options that should be applied to tunnels of a specific type regardless of where the relays
     /// might be located.
     pub tunnel_options: TunnelOptions,
     /// Whether to notify users of beta updates.
     pub show_beta_releases: bool,
+    /// Whether to keep a history of tunnel states, including the relays connected to, in the
+    /// cache directory.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub record_connection_history: bool,
     /// Split tunneling settings
-    #[cfg(windows)]
+    #[cfg(any(windows, target_os = "linux"))]
     pub split_tunnel: SplitTunnelSettings,
     /// Temporary variable for a random number between 0 and 1 that determines if the user should
     /// use wireguard or openvpn when the automatic feature is set. This variable will be removed
@@ -114,6 +152,27 @@ pub struct SplitTunnelSettings {
     pub apps: HashSet<PathBuf>,
 }
 
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct SplitTunnelSettings {
+    /// Toggles exclusion of the applications in `apps` on or off
+    pub enable_exclusions: bool,
+    /// List of executables whose processes are excluded from the tunnel.
+    pub apps: HashSet<PathBuf>,
+    /// Users and groups whose traffic is excluded from the tunnel.
+    pub excluded_users: ExcludedUsers,
+}
+
+/// Users and groups, by UID and GID, whose traffic is excluded from the tunnel.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct ExcludedUsers {
+    pub uids: BTreeSet<u32>,
+    pub gids: BTreeSet<u32>,
+}
+
 impl Default for Settings {
     fn default() -> Self {
         Settings {
@@ -131,13 +190,23 @@ impl Default for Settings {
                 ..Default::default()
             },
             bridge_state: BridgeState::Auto,
+            custom_lists: CustomListsSettings::default(),
+            relay_selection_strategy: RelaySelectionStrategy::default(),
+            relay_rotation: None,
             allow_lan: false,
+            lan_networks: default_lan_networks(),
             block_when_disconnected: false,
+            firewall_allow_list: vec![],
+            #[cfg(target_os = "linux")]
+            firewall_inbound_rules: vec![],
             auto_connect: false,
+            #[cfg(target_os = "linux")]
+            network_rules: NetworkRulesSettings::default(),
             tunnel_options: TunnelOptions::default(),
             show_beta_releases: false,
+            record_connection_history: false,
             wg_migration_rand_num: rand::thread_rng().gen_range(0.0..=1.0),
-            #[cfg(windows)]
+            #[cfg(any(windows, target_os = "linux"))]
             split_tunnel: SplitTunnelSettings::default(),
             settings_version: CURRENT_SETTINGS_VERSION,
         }
@@ -149,6 +218,10 @@ impl Settings {
         self.relay_settings.clone()
     }
 
+    pub fn set_relay_settings(&mut self, relay_settings: RelaySettings) {
+        self.relay_settings = relay_settings;
+    }
+
     pub fn update_relay_settings(&mut self, update: RelaySettingsUpdate) {
         let update_supports_bridge = update.supports_bridge();
         let new_settings = self.relay_settings.merge(update);
@@ -189,7 +262,7 @@ pub struct TunnelOptions {
     pub dns_options: DnsOptions,
 }
 
-pub use dns::{CustomDnsOptions, DefaultDnsOptions, DnsOptions, DnsState};
+pub use dns::{CustomDnsOptions, DefaultDnsOptions, DnsBlocklistOptions, DnsOptions, DnsState};
 
 impl Default for TunnelOptions {
     fn default() -> Self {
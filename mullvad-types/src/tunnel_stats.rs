@@ -0,0 +1,78 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Traffic statistics of the active tunnel.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TunnelStats {
+    pub exit: PeerStats,
+    /// Stats of the entry relay, if multihop is used.
+    pub entry: Option<PeerStats>,
+}
+
+/// Traffic statistics of a single WireGuard peer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerStats {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    /// Bytes per second received since the previous sample.
+    pub rx_rate: u64,
+    /// Bytes per second sent since the previous sample.
+    pub tx_rate: u64,
+    /// Time of the most recent handshake with the peer.
+    pub last_handshake: Option<DateTime<Utc>>,
+}
+
+impl TunnelStats {
+    /// Computes the rates of each peer from a sample that was taken `elapsed` earlier.
+    pub fn set_rates(&mut self, previous: &TunnelStats, elapsed: Duration) {
+        self.exit.set_rates(&previous.exit, elapsed);
+        if let (Some(entry), Some(previous_entry)) = (&mut self.entry, &previous.entry) {
+            entry.set_rates(previous_entry, elapsed);
+        }
+    }
+}
+
+impl PeerStats {
+    fn set_rates(&mut self, previous: &PeerStats, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        if secs <= 0.0 {
+            return;
+        }
+        // The counters start over if the tunnel has been reconnected
+        let rate =
+            |current: u64, previous: u64| (current.saturating_sub(previous) as f64 / secs) as u64;
+        self.rx_rate = rate(self.rx_bytes, previous.rx_bytes);
+        self.tx_rate = rate(self.tx_bytes, previous.tx_bytes);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn peer_stats(rx_bytes: u64, tx_bytes: u64) -> PeerStats {
+        PeerStats {
+            rx_bytes,
+            tx_bytes,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_set_rates() {
+        let previous = TunnelStats {
+            exit: peer_stats(1000, 500),
+            entry: Some(peer_stats(4000, 2000)),
+        };
+        let mut stats = TunnelStats {
+            exit: peer_stats(3000, 100),
+            entry: Some(peer_stats(8000, 3000)),
+        };
+        stats.set_rates(&previous, Duration::from_secs(2));
+
+        assert_eq!((stats.exit.rx_rate, stats.exit.tx_rate), (1000, 0));
+        let entry = stats.entry.unwrap();
+        assert_eq!((entry.rx_rate, entry.tx_rate), (2000, 500));
+    }
+}
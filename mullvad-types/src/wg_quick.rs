@@ -0,0 +1,450 @@
+//! Parsing and formatting of WireGuard configuration files in the format used by `wg-quick`.
+
+use crate::{ConnectionConfig, CustomTunnelEndpoint};
+use ipnetwork::IpNetwork;
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+};
+use talpid_types::net::wireguard;
+
+/// Keys that `wg-quick` accepts but which have no equivalent in a custom tunnel. These are
+/// ignored rather than rejected, so that most existing configs can be imported as they are.
+const IGNORED_KEYS: &[&str] = &[
+    "listenport",
+    "fwmark",
+    "table",
+    "preup",
+    "postup",
+    "predown",
+    "postdown",
+    "saveconfig",
+];
+
+#[derive(err_derive::Error, Debug, PartialEq, Eq)]
+pub enum Error {
+    #[error(display = "Invalid syntax on line {}", _0)]
+    InvalidSyntax(usize),
+
+    #[error(display = "Unknown section on line {}: {}", _0, _1)]
+    UnknownSection(usize, String),
+
+    #[error(display = "Unknown key on line {}: {}", _0, _1)]
+    UnknownKey(usize, String),
+
+    #[error(display = "Invalid value for {} on line {}", _1, _0)]
+    InvalidValue(usize, String),
+
+    #[error(display = "Preshared keys are not supported")]
+    PresharedKey,
+
+    #[error(display = "The config must contain exactly one peer")]
+    PeerCount,
+
+    #[error(display = "Missing {} in the {} section", _0, _1)]
+    MissingKey(&'static str, &'static str),
+
+    #[error(display = "No IPv4 gateway was given, and there is no IPv4 DNS server to use instead")]
+    NoIpv4Gateway,
+}
+
+/// A WireGuard config with a single peer, as read from or written to a `wg-quick` config file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WgQuickConfig {
+    pub tunnel: wireguard::TunnelConfig,
+    pub peer: wireguard::PeerConfig,
+    /// Host of the peer endpoint. This may be a hostname, in which case the IP of
+    /// `peer.endpoint` is unspecified.
+    pub host: String,
+    pub dns_servers: Vec<IpAddr>,
+    pub mtu: Option<u16>,
+    /// Keys that were present in the config but ignored.
+    pub ignored_keys: Vec<String>,
+}
+
+impl WgQuickConfig {
+    /// Creates a config that reproduces the given connection. DNS servers and MTU are not part
+    /// of the connection config, so they are left for the caller to fill in.
+    pub fn from_connection_config(config: &wireguard::ConnectionConfig) -> Self {
+        WgQuickConfig {
+            tunnel: config.tunnel.clone(),
+            peer: config.peer.clone(),
+            host: config.peer.endpoint.ip().to_string(),
+            dns_servers: vec![],
+            mtu: None,
+            ignored_keys: vec![],
+        }
+    }
+
+    /// Converts the config to a custom tunnel endpoint. `wg-quick` configs do not specify any
+    /// gateways, so unless they are given, the first DNS server of each IP version is used. This
+    /// matches how configs are generated by Mullvad and most other providers.
+    pub fn into_custom_tunnel_endpoint(
+        self,
+        ipv4_gateway: Option<Ipv4Addr>,
+        ipv6_gateway: Option<Ipv6Addr>,
+    ) -> Result<CustomTunnelEndpoint, Error> {
+        let ipv4_gateway = ipv4_gateway
+            .or_else(|| {
+                self.dns_servers.iter().find_map(|server| match server {
+                    IpAddr::V4(server) => Some(*server),
+                    IpAddr::V6(_) => None,
+                })
+            })
+            .ok_or(Error::NoIpv4Gateway)?;
+        let ipv6_gateway = ipv6_gateway.or_else(|| {
+            self.dns_servers.iter().find_map(|server| match server {
+                IpAddr::V4(_) => None,
+                IpAddr::V6(server) => Some(*server),
+            })
+        });
+
+        Ok(CustomTunnelEndpoint::new(
+            self.host,
+            ConnectionConfig::Wireguard(wireguard::ConnectionConfig {
+                tunnel: self.tunnel,
+                peer: self.peer,
+                exit_peer: None,
+                ipv4_gateway,
+                ipv6_gateway,
+                #[cfg(target_os = "linux")]
+                fwmark: Some(crate::TUNNEL_FWMARK),
+            }),
+        ))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Section {
+    Interface,
+    Peer,
+}
+
+#[derive(Default)]
+struct PeerSection {
+    public_key: Option<wireguard::PublicKey>,
+    allowed_ips: Vec<IpNetwork>,
+    endpoint: Option<(String, u16)>,
+    persistent_keepalive: Option<u16>,
+}
+
+impl FromStr for WgQuickConfig {
+    type Err = Error;
+
+    fn from_str(config: &str) -> Result<Self, Self::Err> {
+        let mut section = None;
+        let mut private_key = None;
+        let mut addresses = vec![];
+        let mut dns_servers = vec![];
+        let mut mtu = None;
+        let mut ignored_keys = vec![];
+        let mut peers: Vec<PeerSection> = vec![];
+
+        for (index, line) in config.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                section = match name.trim().to_ascii_lowercase().as_str() {
+                    "interface" => Some(Section::Interface),
+                    "peer" => {
+                        peers.push(PeerSection::default());
+                        Some(Section::Peer)
+                    }
+                    _ => return Err(Error::UnknownSection(line_number, name.to_owned())),
+                };
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(Error::InvalidSyntax(line_number))?;
+            let (key, value) = (key.trim(), value.trim());
+            let invalid_value = || Error::InvalidValue(line_number, key.to_owned());
+
+            match (section, key.to_ascii_lowercase().as_str()) {
+                (None, _) => return Err(Error::InvalidSyntax(line_number)),
+                (Some(Section::Interface), "privatekey") => {
+                    private_key = Some(
+                        wireguard::PrivateKey::from_base64(value).map_err(|_| invalid_value())?,
+                    );
+                }
+                (Some(Section::Interface), "address") => {
+                    for address in split_list(value) {
+                        let address: IpNetwork = address.parse().map_err(|_| invalid_value())?;
+                        addresses.push(address.ip());
+                    }
+                }
+                (Some(Section::Interface), "dns") => {
+                    for server in split_list(value) {
+                        dns_servers.push(server.parse().map_err(|_| invalid_value())?);
+                    }
+                }
+                (Some(Section::Interface), "mtu") => {
+                    mtu = Some(value.parse().map_err(|_| invalid_value())?);
+                }
+                (Some(Section::Interface), lowercase_key)
+                    if IGNORED_KEYS.contains(&lowercase_key) =>
+                {
+                    ignored_keys.push(key.to_owned());
+                }
+                (Some(Section::Peer), lowercase_key) => {
+                    // There is always a peer if the current section is a peer section
+                    let peer = peers.last_mut().unwrap();
+                    match lowercase_key {
+                        "publickey" => {
+                            peer.public_key = Some(
+                                wireguard::PublicKey::from_base64(value)
+                                    .map_err(|_| invalid_value())?,
+                            );
+                        }
+                        "allowedips" => {
+                            for network in split_list(value) {
+                                peer.allowed_ips
+                                    .push(network.parse().map_err(|_| invalid_value())?);
+                            }
+                        }
+                        "endpoint" => {
+                            peer.endpoint = Some(parse_endpoint(value).ok_or_else(invalid_value)?);
+                        }
+                        "persistentkeepalive" => {
+                            peer.persistent_keepalive = match value {
+                                "off" => None,
+                                value => match value.parse().map_err(|_| invalid_value())? {
+                                    0 => None,
+                                    interval => Some(interval),
+                                },
+                            };
+                        }
+                        "presharedkey" => return Err(Error::PresharedKey),
+                        _ => return Err(Error::UnknownKey(line_number, key.to_owned())),
+                    }
+                }
+                (Some(Section::Interface), _) => {
+                    return Err(Error::UnknownKey(line_number, key.to_owned()))
+                }
+            }
+        }
+
+        let private_key = private_key.ok_or(Error::MissingKey("PrivateKey", "Interface"))?;
+        if addresses.is_empty() {
+            return Err(Error::MissingKey("Address", "Interface"));
+        }
+        if peers.len() != 1 {
+            return Err(Error::PeerCount);
+        }
+        let peer = peers.remove(0);
+        let public_key = peer
+            .public_key
+            .ok_or(Error::MissingKey("PublicKey", "Peer"))?;
+        let (host, port) = peer.endpoint.ok_or(Error::MissingKey("Endpoint", "Peer"))?;
+        if peer.allowed_ips.is_empty() {
+            return Err(Error::MissingKey("AllowedIPs", "Peer"));
+        }
+        let endpoint_ip = host.parse().unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+
+        Ok(WgQuickConfig {
+            tunnel: wireguard::TunnelConfig {
+                private_key,
+                addresses,
+            },
+            peer: wireguard::PeerConfig {
+                public_key,
+                allowed_ips: peer.allowed_ips,
+                endpoint: SocketAddr::new(endpoint_ip, port),
+                psk: None,
+                persistent_keepalive: peer.persistent_keepalive,
+            },
+            host,
+            dns_servers,
+            mtu,
+            ignored_keys,
+        })
+    }
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+/// Splits an endpoint such as `example.com:51820` or `[::1]:51820` into its host and port.
+fn parse_endpoint(endpoint: &str) -> Option<(String, u16)> {
+    let (host, port) = endpoint.rsplit_once(':')?;
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    if host.is_empty() {
+        return None;
+    }
+    Some((host.to_owned(), port.parse().ok()?))
+}
+
+impl fmt::Display for WgQuickConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |items: Vec<String>| items.join(", ");
+
+        writeln!(f, "[Interface]")?;
+        writeln!(f, "PrivateKey = {}", self.tunnel.private_key.to_base64())?;
+        writeln!(
+            f,
+            "Address = {}",
+            join(
+                self.tunnel
+                    .addresses
+                    .iter()
+                    .map(|address| IpNetwork::from(*address).to_string())
+                    .collect()
+            )
+        )?;
+        if !self.dns_servers.is_empty() {
+            writeln!(
+                f,
+                "DNS = {}",
+                join(self.dns_servers.iter().map(IpAddr::to_string).collect())
+            )?;
+        }
+        if let Some(mtu) = self.mtu {
+            writeln!(f, "MTU = {mtu}")?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "[Peer]")?;
+        writeln!(f, "PublicKey = {}", self.peer.public_key.to_base64())?;
+        writeln!(
+            f,
+            "AllowedIPs = {}",
+            join(
+                self.peer
+                    .allowed_ips
+                    .iter()
+                    .map(IpNetwork::to_string)
+                    .collect()
+            )
+        )?;
+        match self.host.parse() {
+            Ok(IpAddr::V6(host)) => {
+                writeln!(f, "Endpoint = [{host}]:{}", self.peer.endpoint.port())?
+            }
+            _ => writeln!(f, "Endpoint = {}:{}", self.host, self.peer.endpoint.port())?,
+        }
+        if let Some(interval) = self.peer.persistent_keepalive {
+            writeln!(f, "PersistentKeepalive = {interval}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIVATE_KEY: &str = "mK7xRf3VjB1Y5QrgFm0bXvKk5Dh7rHqMQs4lJ5sPHVw=";
+    const PUBLIC_KEY: &str = "hb5I2BsFGEuB8CbY7BWS8tSSdaklFHYfYFsJWGa5oSw=";
+
+    fn config_text() -> String {
+        format!(
+            "[Interface]
+# Device: Nimble Otter
+PrivateKey = {PRIVATE_KEY}
+Address = 10.64.12.34/32, fc00:bbbb:bbbb:bb01::1:c22/128
+DNS = 10.64.0.1
+ListenPort = 51820
+
+[Peer]
+PublicKey = {PUBLIC_KEY}
+AllowedIPs = 0.0.0.0/0,::0/0
+Endpoint = se-got-wg-001.relays.mullvad.net:51820
+PersistentKeepalive = 25
+"
+        )
+    }
+
+    #[test]
+    fn test_parse() {
+        let config: WgQuickConfig = config_text().parse().unwrap();
+
+        assert_eq!(
+            config.tunnel.private_key,
+            wireguard::PrivateKey::from_base64(PRIVATE_KEY).unwrap()
+        );
+        assert_eq!(
+            config.tunnel.addresses,
+            vec![
+                "10.64.12.34".parse::<IpAddr>().unwrap(),
+                "fc00:bbbb:bbbb:bb01::1:c22".parse().unwrap()
+            ]
+        );
+        assert_eq!(
+            config.dns_servers,
+            vec!["10.64.0.1".parse::<IpAddr>().unwrap()]
+        );
+        assert_eq!(config.ignored_keys, vec!["ListenPort".to_owned()]);
+        assert_eq!(
+            config.peer.public_key,
+            wireguard::PublicKey::from_base64(PUBLIC_KEY).unwrap()
+        );
+        assert_eq!(config.peer.allowed_ips.len(), 2);
+        assert_eq!(config.host, "se-got-wg-001.relays.mullvad.net");
+        assert_eq!(config.peer.endpoint.port(), 51820);
+        assert_eq!(config.peer.persistent_keepalive, Some(25));
+
+        let endpoint = config.into_custom_tunnel_endpoint(None, None).unwrap();
+        match endpoint.config {
+            ConnectionConfig::Wireguard(config) => {
+                assert_eq!(config.ipv4_gateway, Ipv4Addr::new(10, 64, 0, 1));
+                assert_eq!(config.ipv6_gateway, None);
+            }
+            ConnectionConfig::OpenVpn(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_format_round_trip() {
+        let mut config: WgQuickConfig = config_text().parse().unwrap();
+        config.host = "2001:db8::1".to_owned();
+        config.peer.endpoint = "[2001:db8::1]:51820".parse().unwrap();
+        config.ignored_keys.clear();
+
+        let formatted = config.to_string();
+        assert!(formatted.contains("Endpoint = [2001:db8::1]:51820"));
+        assert_eq!(formatted.parse::<WgQuickConfig>().unwrap(), config);
+    }
+
+    #[test]
+    fn test_invalid_configs() {
+        let with_psk = config_text().replace(
+            "PersistentKeepalive = 25",
+            &format!("PresharedKey = {PUBLIC_KEY}"),
+        );
+        assert_eq!(with_psk.parse::<WgQuickConfig>(), Err(Error::PresharedKey));
+
+        let two_peers = format!("{}\n[Peer]\nPublicKey = {PUBLIC_KEY}\n", config_text());
+        assert_eq!(two_peers.parse::<WgQuickConfig>(), Err(Error::PeerCount));
+
+        let unknown_key = config_text().replace("DNS", "Dns-Server");
+        assert_eq!(
+            unknown_key.parse::<WgQuickConfig>(),
+            Err(Error::UnknownKey(5, "Dns-Server".to_owned()))
+        );
+
+        let no_dns = config_text().replace("DNS = 10.64.0.1", "");
+        assert_eq!(
+            no_dns
+                .parse::<WgQuickConfig>()
+                .unwrap()
+                .into_custom_tunnel_endpoint(None, None),
+            Err(Error::NoIpv4Gateway)
+        );
+    }
+}
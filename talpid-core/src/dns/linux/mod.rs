@@ -40,6 +40,16 @@ pub struct DnsMonitor {
     route_manager: RouteManagerHandle,
     handle: tokio::runtime::Handle,
     inner: Option<DnsMonitorHolder>,
+    routing_domains: Vec<String>,
+}
+
+impl DnsMonitor {
+    /// Sets domains that should be resolved using the DNS servers of the tunnel interface, even
+    /// if another interface has a routing domain for them. This only affects systemd-resolved
+    /// and takes effect the next time DNS is set.
+    pub fn set_routing_domains(&mut self, domains: Vec<String>) {
+        self.routing_domains = domains;
+    }
 }
 
 impl super::DnsMonitorT for DnsMonitor {
@@ -50,6 +60,7 @@ impl super::DnsMonitorT for DnsMonitor {
             route_manager,
             handle,
             inner: None,
+            routing_domains: vec![],
         })
     }
 
@@ -58,7 +69,13 @@ impl super::DnsMonitorT for DnsMonitor {
         // Creating a new DNS monitor for each set, in case the system changed how it manages DNS.
         let mut inner = DnsMonitorHolder::new(&self.handle)?;
         if !servers.is_empty() {
-            inner.set(&self.handle, &self.route_manager, interface, servers)?;
+            inner.set(
+                &self.handle,
+                &self.route_manager,
+                interface,
+                servers,
+                &self.routing_domains,
+            )?;
             self.inner = Some(inner);
         }
         Ok(())
@@ -138,6 +155,7 @@ impl DnsMonitorHolder {
         route_manager: &RouteManagerHandle,
         interface: &str,
         servers: &[IpAddr],
+        routing_domains: &[String],
     ) -> Result<()> {
         use self::DnsMonitorHolder::*;
         match self {
@@ -145,8 +163,12 @@ impl DnsMonitorHolder {
             StaticResolvConf(ref mut static_resolv_conf) => {
                 static_resolv_conf.set_dns(servers.to_vec())?
             }
-            SystemdResolved(ref mut systemd_resolved) => handle
-                .block_on(systemd_resolved.set_dns(route_manager.clone(), interface, servers))?,
+            SystemdResolved(ref mut systemd_resolved) => handle.block_on(systemd_resolved.set_dns(
+                route_manager.clone(),
+                interface,
+                servers,
+                routing_domains,
+            ))?,
             NetworkManager(ref mut network_manager) => {
                 network_manager.set_dns(interface, servers)?
             }
@@ -39,6 +39,7 @@ impl SystemdResolved {
         _route_manager: RouteManagerHandle,
         interface_name: &str,
         servers: &[IpAddr],
+        routing_domains: &[String],
     ) -> Result<()> {
         let tunnel_index = iface_index(interface_name)?;
         self.tunnel_index = tunnel_index;
@@ -47,9 +48,15 @@ impl SystemdResolved {
             log::error!("Failed to disable DoT: {}", error.display_chain());
         }
 
+        // Route all queries to the tunnel interface. Routing domains that are more specific than
+        // "." must be added too, or other interfaces that have them would take precedence.
+        let domains: Vec<(&str, bool)> = std::iter::once(".")
+            .chain(routing_domains.iter().map(String::as_str))
+            .map(|domain| (domain, true))
+            .collect();
         if let Err(error) = self
             .dbus_interface
-            .set_domains(tunnel_index, &[(".", true)])
+            .set_domains(tunnel_index, &domains)
             .await
         {
             log::error!("Failed to set search domains: {}", error.display_chain());
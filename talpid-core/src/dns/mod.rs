@@ -60,6 +60,14 @@ impl DnsMonitor {
         self.inner.get_system_config()
     }
 
+    /// Sets domains that should be resolved using the DNS servers of the tunnel interface, even
+    /// if another interface has a routing domain for them. Takes effect on the next call to
+    /// [`DnsMonitor::set`].
+    #[cfg(target_os = "linux")]
+    pub fn set_routing_domains(&mut self, domains: Vec<String>) {
+        self.inner.set_routing_domains(domains)
+    }
+
     /// Set DNS to the given servers. And start monitoring the system for changes.
     pub fn set(&mut self, interface: &str, servers: &[IpAddr]) -> Result<(), Error> {
         log::info!(
@@ -0,0 +1,186 @@
+//! Blocklists of domain names that are enforced by the DNS forwarder. Lists are read from hosts
+//! files, such as `0.0.0.0 ads.example.com`, or from plain lists with one domain per line.
+use std::{
+    collections::HashSet,
+    fs, io,
+    net::IpAddr,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use talpid_types::net::dns::DnsBlocklistStats;
+
+/// Names that appear in most hosts files, but which should never be blocked.
+const IGNORED_HOSTS: &[&str] = &["localhost.localdomain"];
+
+/// A set of blocklists. A domain is blocked if it or any of its parent domains is in one of the
+/// lists, unless it or any of its parent domains is in the allowlist.
+#[derive(Debug, Default)]
+pub struct DnsBlocklist {
+    lists: Vec<List>,
+    allowlist: HashSet<String>,
+}
+
+#[derive(Debug)]
+struct List {
+    path: PathBuf,
+    domains: HashSet<String>,
+    hits: AtomicU64,
+}
+
+impl DnsBlocklist {
+    /// Creates a blocklist without any lists, which never blocks anything.
+    pub fn new(allowlist: impl IntoIterator<Item = String>) -> Self {
+        DnsBlocklist {
+            lists: vec![],
+            allowlist: allowlist
+                .into_iter()
+                .map(|domain| normalize(&domain))
+                .collect(),
+        }
+    }
+
+    /// Reads and adds the list in the given file.
+    pub fn add_list(&mut self, path: PathBuf) -> io::Result<()> {
+        let contents = fs::read_to_string(&path)?;
+        self.lists.push(List {
+            path,
+            domains: parse_list(&contents),
+            hits: AtomicU64::new(0),
+        });
+        Ok(())
+    }
+
+    /// Returns whether queries for `name` should be blocked. The hit counter of the first list
+    /// that contains the name is incremented.
+    pub fn is_blocked(&self, name: &str) -> bool {
+        let name = normalize(name);
+        if domain_and_parents(&name).any(|domain| self.allowlist.contains(domain)) {
+            return false;
+        }
+        for list in &self.lists {
+            if domain_and_parents(&name).any(|domain| list.domains.contains(domain)) {
+                list.hits.fetch_add(1, Ordering::Relaxed);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns the number of entries and hits of each list.
+    pub fn stats(&self) -> Vec<DnsBlocklistStats> {
+        self.lists
+            .iter()
+            .map(|list| DnsBlocklistStats {
+                path: list.path.clone(),
+                entries: list.domains.len() as u64,
+                hits: list.hits.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+/// Parses a hosts file or a list of domains. Comments, addresses and names without a dot, such
+/// as `localhost`, are ignored.
+fn parse_list(contents: &str) -> HashSet<String> {
+    let mut domains = HashSet::new();
+    for line in contents.lines() {
+        let line = match line.split_once('#') {
+            Some((line, _comment)) => line,
+            None => line,
+        };
+        for token in line.split_whitespace() {
+            if token.parse::<IpAddr>().is_ok() {
+                continue;
+            }
+            // Also accept `*.example.com` and the `||example.com^` syntax of adblock lists
+            let domain = token.trim_start_matches("*.");
+            let domain = domain
+                .strip_prefix("||")
+                .and_then(|domain| domain.strip_suffix('^'))
+                .unwrap_or(domain);
+            let domain = normalize(domain);
+            if domain.contains('.') && !IGNORED_HOSTS.contains(&domain.as_str()) {
+                domains.insert(domain);
+            }
+        }
+    }
+    domains
+}
+
+fn normalize(domain: &str) -> String {
+    domain.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Returns `domain` followed by each of its parent domains, e.g. `a.example.com`, `example.com`
+/// and `com`.
+fn domain_and_parents(domain: &str) -> impl Iterator<Item = &str> {
+    std::iter::successors(Some(domain), |domain| {
+        domain.split_once('.').map(|(_, parent)| parent)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const HOSTS_FILE: &str = r#"
+# Blocked domains
+127.0.0.1 localhost localhost.localdomain
+::1 localhost
+0.0.0.0 ads.example.com tracker.example.net # inline comment
+0.0.0.0 0.0.0.0
+"#;
+
+    const DOMAIN_LIST: &str = r#"
+malware.example.org
+||Adult.Example.com^
+*.gambling.example.
+"#;
+
+    fn blocklist(allowlist: &[&str]) -> DnsBlocklist {
+        let mut blocklist = DnsBlocklist::new(allowlist.iter().map(|domain| domain.to_string()));
+        for (path, contents) in [("hosts", HOSTS_FILE), ("domains", DOMAIN_LIST)] {
+            blocklist.lists.push(List {
+                path: PathBuf::from(path),
+                domains: parse_list(contents),
+                hits: AtomicU64::new(0),
+            });
+        }
+        blocklist
+    }
+
+    #[test]
+    fn test_parse_list() {
+        assert_eq!(
+            parse_list(HOSTS_FILE),
+            HashSet::from([
+                "ads.example.com".to_owned(),
+                "tracker.example.net".to_owned()
+            ])
+        );
+        assert_eq!(
+            parse_list(DOMAIN_LIST),
+            HashSet::from([
+                "malware.example.org".to_owned(),
+                "adult.example.com".to_owned(),
+                "gambling.example".to_owned(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_is_blocked() {
+        let blocklist = blocklist(&["allowed.ads.example.com"]);
+
+        assert!(blocklist.is_blocked("ads.example.com."));
+        assert!(blocklist.is_blocked("cdn.ADS.example.com"));
+        assert!(blocklist.is_blocked("www.gambling.example"));
+        assert!(!blocklist.is_blocked("example.com"));
+        assert!(!blocklist.is_blocked("localhost"));
+        assert!(!blocklist.is_blocked("allowed.ads.example.com"));
+        assert!(!blocklist.is_blocked("www.allowed.ads.example.com"));
+
+        let hits: Vec<_> = blocklist.stats().iter().map(|stats| stats.hits).collect();
+        assert_eq!(hits, vec![2, 1]);
+    }
+}
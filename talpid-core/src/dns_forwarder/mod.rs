@@ -0,0 +1,332 @@
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use talpid_types::net::dns::{EncryptedDnsProtocol, EncryptedDnsServer, SplitDnsRule};
+use trust_dns_server::{
+    authority::MessageResponseBuilder,
+    client::rr::RecordType,
+    proto::{
+        op::{header::MessageType, op_code::OpCode, Header, ResponseCode},
+        rr::{domain::Name, record_data::RData, Record},
+    },
+    resolver::{
+        config::{NameServerConfig, NameServerConfigGroup, Protocol, ResolverConfig, ResolverOpts},
+        error::{ResolveError, ResolveErrorKind},
+        TokioAsyncResolver,
+    },
+    server::{Request, RequestHandler, ResponseHandler, ResponseInfo},
+    ServerFuture,
+};
+
+mod blocklist;
+
+pub use blocklist::DnsBlocklist;
+
+/// Address that the forwarder listens on. The system DNS should be set to this address.
+///
+/// A dedicated loopback address is used so that the forwarder does not conflict with local stub
+/// resolvers, such as dnsmasq, that often listen on `127.0.0.1:53`. macOS only assigns
+/// `127.0.0.1` to the loopback interface, so it is used there.
+#[cfg(not(target_os = "macos"))]
+pub const LISTEN_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 66));
+/// Address that the forwarder listens on. The system DNS should be set to this address.
+#[cfg(target_os = "macos")]
+pub const LISTEN_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// Time after which idle TCP connections to the forwarder are closed.
+const TCP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// TTL of the responses to blocked queries.
+const BLOCKED_TTL_SECONDS: u32 = 60;
+
+/// DNS forwarder errors
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// No upstream servers were given
+    #[error(display = "No upstream DNS servers were given")]
+    NoServers,
+
+    /// Failed to create the upstream resolver
+    #[error(display = "Failed to create the upstream resolver")]
+    CreateResolver(#[error(source)] ResolveError),
+
+    /// Another DNS server is listening on the forwarder's address
+    #[error(display = "Another process is already listening on {}", _0)]
+    AddressInUse(SocketAddr),
+
+    /// Failed to bind UDP socket
+    #[error(display = "Failed to bind UDP socket")]
+    UdpBindError(#[error(source)] io::Error),
+
+    /// Failed to bind TCP socket
+    #[error(display = "Failed to bind TCP socket")]
+    TcpBindError(#[error(source)] io::Error),
+}
+
+/// DNS servers that the forwarder sends queries to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Upstream {
+    /// Plain DNS servers, which are queried on port 53.
+    Plain(Vec<IpAddr>),
+    /// DNS-over-HTTPS or DNS-over-TLS servers.
+    Encrypted(Vec<EncryptedDnsServer>),
+}
+
+impl Upstream {
+    fn resolver_config(&self) -> Result<ResolverConfig, Error> {
+        match self {
+            Upstream::Plain(servers) if servers.is_empty() => Err(Error::NoServers),
+            Upstream::Plain(servers) => Ok(ResolverConfig::from_parts(
+                None,
+                vec![],
+                NameServerConfigGroup::from_ips_clear(servers, 53, true),
+            )),
+            Upstream::Encrypted(servers) if servers.is_empty() => Err(Error::NoServers),
+            Upstream::Encrypted(servers) => {
+                let mut config = ResolverConfig::new();
+                for server in servers {
+                    config.add_name_server(NameServerConfig {
+                        socket_addr: server.address,
+                        protocol: match server.protocol {
+                            EncryptedDnsProtocol::Https => Protocol::Https,
+                            EncryptedDnsProtocol::Tls => Protocol::Tls,
+                        },
+                        tls_dns_name: Some(server.tls_name.clone()),
+                        trust_nx_responses: true,
+                        tls_config: None,
+                        bind_addr: None,
+                    });
+                }
+                Ok(config)
+            }
+        }
+    }
+}
+
+/// Starts a DNS server on [`LISTEN_ADDR`] that forwards queries to `upstream`. Queries for
+/// domains in `blocklist` are answered without being forwarded, and queries for domains that
+/// match one of `split_rules` are forwarded to the resolvers of the most specific rule. The
+/// server stops when the returned handle is dropped.
+pub async fn start(
+    upstream: Upstream,
+    blocklist: Option<Arc<DnsBlocklist>>,
+    split_rules: Vec<SplitDnsRule>,
+) -> Result<DnsForwarderHandle, Error> {
+    let resolver = TokioAsyncResolver::tokio(upstream.resolver_config()?, ResolverOpts::default())
+        .map_err(Error::CreateResolver)?;
+
+    let mut split_resolvers = vec![];
+    for rule in split_rules.iter().filter(|rule| !rule.resolvers.is_empty()) {
+        let config = Upstream::Plain(rule.resolvers.clone()).resolver_config()?;
+        let resolver = TokioAsyncResolver::tokio(config, ResolverOpts::default())
+            .map_err(Error::CreateResolver)?;
+        split_resolvers.push((rule.clone(), resolver));
+    }
+    // Try the most specific rules first
+    split_resolvers.sort_by_key(|(rule, _)| std::cmp::Reverse(rule.domain.len()));
+
+    let mut server = ServerFuture::new(Forwarder {
+        resolver,
+        split_resolvers,
+        blocklist: blocklist.clone(),
+    });
+
+    let listen_addr = SocketAddr::new(LISTEN_ADDR, 53);
+    let udp_socket = tokio::net::UdpSocket::bind(listen_addr)
+        .await
+        .map_err(|error| bind_error(error, listen_addr, Error::UdpBindError))?;
+    server.register_socket(udp_socket);
+    let tcp_listener = tokio::net::TcpListener::bind(listen_addr)
+        .await
+        .map_err(|error| bind_error(error, listen_addr, Error::TcpBindError))?;
+    server.register_listener(tcp_listener, TCP_TIMEOUT);
+
+    let server_handle = tokio::spawn(async move {
+        if let Err(err) = server.block_until_done().await {
+            log::error!("DNS forwarder stopped: {}", err);
+        }
+    });
+
+    log::debug!("Started DNS forwarder on {}", listen_addr);
+
+    Ok(DnsForwarderHandle {
+        upstream,
+        blocklist,
+        split_rules,
+        server_handle,
+    })
+}
+
+fn bind_error(error: io::Error, addr: SocketAddr, other: fn(io::Error) -> Error) -> Error {
+    if error.kind() == io::ErrorKind::AddrInUse {
+        Error::AddressInUse(addr)
+    } else {
+        other(error)
+    }
+}
+
+/// Handle to a running DNS forwarder. The forwarder is stopped when this is dropped.
+pub struct DnsForwarderHandle {
+    upstream: Upstream,
+    blocklist: Option<Arc<DnsBlocklist>>,
+    split_rules: Vec<SplitDnsRule>,
+    server_handle: tokio::task::JoinHandle<()>,
+}
+
+impl DnsForwarderHandle {
+    /// Returns whether the forwarder was started using the given arguments.
+    pub fn is_started_with(
+        &self,
+        upstream: &Upstream,
+        blocklist: &Option<Arc<DnsBlocklist>>,
+        split_rules: &[SplitDnsRule],
+    ) -> bool {
+        let same_blocklist = match (&self.blocklist, blocklist) {
+            (Some(current), Some(new)) => Arc::ptr_eq(current, new),
+            (None, None) => true,
+            _ => false,
+        };
+        &self.upstream == upstream && same_blocklist && self.split_rules == split_rules
+    }
+
+    /// Stops the forwarder and waits until its sockets have been closed.
+    pub async fn stop(mut self) {
+        self.server_handle.abort();
+        let _ = (&mut self.server_handle).await;
+    }
+}
+
+impl Drop for DnsForwarderHandle {
+    fn drop(&mut self) {
+        log::debug!("Stopping DNS forwarder");
+        self.server_handle.abort();
+    }
+}
+
+/// An implementation of [trust_dns_server::server::RequestHandler] that resolves queries using
+/// an upstream resolver.
+struct Forwarder {
+    resolver: TokioAsyncResolver,
+    /// Resolvers for split DNS rules, ordered from the most to the least specific rule.
+    split_resolvers: Vec<(SplitDnsRule, TokioAsyncResolver)>,
+    blocklist: Option<Arc<DnsBlocklist>>,
+}
+
+impl Forwarder {
+    /// Returns the resolver that queries for `name` should be sent to.
+    fn resolver_for(&self, name: &str) -> &TokioAsyncResolver {
+        self.split_resolvers
+            .iter()
+            .find(|(rule, _)| rule.matches(name))
+            .map(|(_, resolver)| resolver)
+            .unwrap_or(&self.resolver)
+    }
+
+    async fn lookup<R: ResponseHandler>(&self, request: &Request, mut response_handler: R) {
+        let query = request.query();
+        let name = Name::from(query.name().clone());
+        let builder = MessageResponseBuilder::from_message_request(request);
+
+        let mut response_header = Header::new();
+        response_header.set_id(request.id());
+        response_header.set_op_code(OpCode::Query);
+        response_header.set_message_type(MessageType::Response);
+        response_header.set_authoritative(false);
+        response_header.set_recursion_desired(request.header().recursion_desired());
+        response_header.set_recursion_available(true);
+
+        let is_blocked = self
+            .blocklist
+            .as_ref()
+            .map(|blocklist| blocklist.is_blocked(&name.to_utf8()))
+            .unwrap_or(false);
+
+        let result = if is_blocked {
+            // Address queries are answered with an unspecified address, and everything else
+            // with NXDOMAIN
+            let rdata = match query.query_type() {
+                RecordType::A => Some(RData::A(Ipv4Addr::UNSPECIFIED)),
+                RecordType::AAAA => Some(RData::AAAA(Ipv6Addr::UNSPECIFIED)),
+                _ => None,
+            };
+            match rdata {
+                Some(rdata) => {
+                    let record = Record::from_rdata(name, BLOCKED_TTL_SECONDS, rdata);
+                    let response = builder.build(
+                        response_header,
+                        std::iter::once(&record),
+                        std::iter::empty(),
+                        std::iter::empty(),
+                        std::iter::empty(),
+                    );
+                    response_handler.send_response(response).await
+                }
+                None => {
+                    let response = builder.error_msg(request.header(), ResponseCode::NXDomain);
+                    response_handler.send_response(response).await
+                }
+            }
+        } else {
+            let resolver = self.resolver_for(&name.to_utf8());
+            match resolver.lookup(name, query.query_type()).await {
+                Ok(lookup) => {
+                    let response = builder.build(
+                        response_header,
+                        lookup.record_iter(),
+                        // forwarder responses only contain query answers, no ns,soa or
+                        // additionals
+                        std::iter::empty(),
+                        std::iter::empty(),
+                        std::iter::empty(),
+                    );
+                    response_handler.send_response(response).await
+                }
+                Err(error) => {
+                    let response_code = match error.kind() {
+                        ResolveErrorKind::NoRecordsFound { response_code, .. } => *response_code,
+                        _ => {
+                            log::debug!("Failed to forward DNS query: {}", error);
+                            ResponseCode::ServFail
+                        }
+                    };
+                    let response = builder.error_msg(request.header(), response_code);
+                    response_handler.send_response(response).await
+                }
+            }
+        };
+
+        if let Err(err) = result {
+            log::error!("Failed to send response: {}", err);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RequestHandler for Forwarder {
+    async fn handle_request<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: R,
+    ) -> ResponseInfo {
+        if !request.src().ip().is_loopback() {
+            log::error!("Dropping a stray request from outside: {}", request.src());
+            return Header::new().into();
+        }
+        if let MessageType::Query = request.message_type() {
+            match request.op_code() {
+                OpCode::Query => {
+                    self.lookup(request, response_handle).await;
+                }
+                _ => {
+                    log::trace!("Dropping non-query request: {:?}", request);
+                }
+            };
+        }
+
+        Header::new().into()
+    }
+}
@@ -5,16 +5,20 @@ use lazy_static::lazy_static;
 use libc;
 use nftnl::{
     self,
-    expr::{self, IcmpCode, Payload, RejectionType, Verdict},
-    nft_expr, table, Batch, Chain, FinalizedBatch, ProtoFamily, Rule, Table,
+    expr::{self, IcmpCode, Payload, RejectionType},
+    nft_expr, nftnl_sys, table, Batch, Chain, FinalizedBatch, ProtoFamily, Rule, Table,
 };
 use std::{
+    collections::BTreeSet,
     env,
     ffi::{CStr, CString},
-    fs, io,
+    fmt, fs, io,
     net::{IpAddr, Ipv4Addr},
 };
-use talpid_types::net::{AllowedTunnelTraffic, Endpoint, TransportProtocol};
+use talpid_types::{
+    net::{AllowListEndpoint, AllowedTunnelTraffic, Endpoint, InboundRule, TransportProtocol},
+    ErrorExt,
+};
 
 /// Priority for rules that tag split tunneling packets. Equals NF_IP_PRI_MANGLE.
 const MANGLE_CHAIN_PRIORITY: i32 = libc::NF_IP_PRI_MANGLE;
@@ -84,35 +88,140 @@ enum Direction {
     Out,
 }
 
+impl Direction {
+    fn iface_keyword(self) -> &'static str {
+        match self {
+            Direction::In => "iif",
+            Direction::Out => "oif",
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 enum End {
     Src,
     Dst,
 }
 
+impl End {
+    fn addr_keyword(self) -> &'static str {
+        match self {
+            End::Src => "saddr",
+            End::Dst => "daddr",
+        }
+    }
+
+    fn port_keyword(self) -> &'static str {
+        match self {
+            End::Src => "sport",
+            End::Dst => "dport",
+        }
+    }
+}
+
+/// Users and groups whose traffic is excluded from the tunnel.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct ExcludedUsers {
+    uids: BTreeSet<u32>,
+    gids: BTreeSet<u32>,
+}
+
+/// State of the host that the rules depend on.
+#[derive(Clone, Copy)]
+struct HostState {
+    /// Translates the name of a network interface into its index.
+    iface_index: fn(&str) -> Result<u32>,
+    /// The ID of the cgroup v2 group for excluded processes, if it exists.
+    cgroup2_exclusion_id: Option<u64>,
+}
+
+impl HostState {
+    /// Looks up the current state of the host. This must be done each time the rules are built,
+    /// since the cgroup ID changes if the group is recreated.
+    fn current() -> Self {
+        HostState {
+            iface_index: lookup_iface_index,
+            cgroup2_exclusion_id: split_tunnel::cgroup2_exclusion_id(),
+        }
+    }
+}
+
+fn lookup_iface_index(iface: &str) -> Result<u32> {
+    crate::linux::iface_index(iface).map_err(|e| Error::LookupIfaceIndexError(iface.to_owned(), e))
+}
+
 /// The Linux implementation for the firewall and DNS.
 pub struct Firewall {
     fwmark: u32,
+    excluded_users: ExcludedUsers,
+    /// The currently enforced policy, if any. Used to reapply the rules when the excluded users
+    /// change.
+    last_policy: Option<FirewallPolicy>,
 }
 
 impl Firewall {
     pub fn from_args(args: FirewallArguments) -> Result<Self> {
-        Firewall::new(args.fwmark)
+        let mut firewall = Firewall::new(args.fwmark)?;
+        firewall.excluded_users = ExcludedUsers {
+            uids: args.excluded_uids.into_iter().collect(),
+            gids: args.excluded_gids.into_iter().collect(),
+        };
+        Ok(firewall)
     }
 
     pub fn new(fwmark: u32) -> Result<Self> {
-        Ok(Firewall { fwmark })
+        Ok(Firewall {
+            fwmark,
+            excluded_users: ExcludedUsers::default(),
+            last_policy: None,
+        })
     }
 
     pub fn apply_policy(&mut self, policy: FirewallPolicy) -> Result<()> {
         let table = Table::new(&*TABLE_NAME, ProtoFamily::Inet);
-        let batch = PolicyBatch::new(&table).finalize(&policy, self.fwmark)?;
+        let batch = PolicyBatch::new(&table, HostState::current()).finalize(
+            &policy,
+            self.fwmark,
+            &self.excluded_users,
+        )?;
         Self::send_and_process(&batch)?;
         Self::apply_kernel_config(&policy);
-        self.verify_tables(&[&TABLE_NAME])
+        self.verify_tables(&[&TABLE_NAME])?;
+        self.last_policy = Some(policy);
+        Ok(())
+    }
+
+    /// Returns the ruleset that `apply_policy` would apply for the given policy, in nft syntax,
+    /// without applying it. Interface names are not translated into indices, so the policy may
+    /// refer to interfaces that do not exist.
+    pub fn dump_policy(&self, policy: &FirewallPolicy) -> Result<String> {
+        let table = Table::new(&*TABLE_NAME, ProtoFamily::Inet);
+        let host = HostState {
+            iface_index: |_| Ok(0),
+            ..HostState::current()
+        };
+        PolicyBatch::new(&table, host).render(policy, self.fwmark, &self.excluded_users)
+    }
+
+    /// Sets the users and groups whose traffic is excluded from the tunnel, and updates the
+    /// currently enforced policy, if any.
+    pub fn set_excluded_users(&mut self, uids: Vec<u32>, gids: Vec<u32>) -> Result<()> {
+        let excluded_users = ExcludedUsers {
+            uids: uids.into_iter().collect(),
+            gids: gids.into_iter().collect(),
+        };
+        if excluded_users == self.excluded_users {
+            return Ok(());
+        }
+        self.excluded_users = excluded_users;
+        match self.last_policy.take() {
+            Some(policy) => self.apply_policy(policy),
+            None => Ok(()),
+        }
     }
 
     pub fn reset_policy(&mut self) -> Result<()> {
+        self.last_policy = None;
         let table = Table::new(&*TABLE_NAME, ProtoFamily::Inet);
         let mut batch = Batch::new();
 
@@ -214,19 +323,19 @@ impl Firewall {
 }
 
 struct PolicyBatch<'a> {
-    batch: Batch,
-    in_chain: Chain<'a>,
-    out_chain: Chain<'a>,
-    forward_chain: Chain<'a>,
-    prerouting_chain: Chain<'a>,
-    mangle_chain: Chain<'a>,
-    nat_chain: Chain<'a>,
+    batch: RuleBatch,
+    in_chain: PolicyChain<'a>,
+    out_chain: PolicyChain<'a>,
+    forward_chain: PolicyChain<'a>,
+    prerouting_chain: PolicyChain<'a>,
+    mangle_chain: PolicyChain<'a>,
+    nat_chain: PolicyChain<'a>,
 }
 
 impl<'a> PolicyBatch<'a> {
     /// Bootstrap a new nftnl message batch object and add the initial messages creating the
     /// table and chains.
-    pub fn new(table: &'a Table) -> Self {
+    pub fn new(table: &'a Table, host: HostState) -> Self {
         let mut batch = Batch::new();
 
         batch_deprecated_tables(&mut batch);
@@ -236,40 +345,43 @@ impl<'a> PolicyBatch<'a> {
         batch.add(table, nftnl::MsgType::Del);
         batch.add(table, nftnl::MsgType::Add);
 
-        let mut prerouting_chain = Chain::new(&*PREROUTING_CHAIN_NAME, table);
+        let mut prerouting_chain = PolicyChain::new(&PREROUTING_CHAIN_NAME, table, host);
         prerouting_chain.set_hook(nftnl::Hook::PreRouting, PREROUTING_CHAIN_PRIORITY);
         prerouting_chain.set_type(nftnl::ChainType::Filter);
-        batch.add(&prerouting_chain, nftnl::MsgType::Add);
+        batch.add(&prerouting_chain.chain, nftnl::MsgType::Add);
 
-        let mut out_chain = Chain::new(&*OUT_CHAIN_NAME, table);
+        let mut out_chain = PolicyChain::new(&OUT_CHAIN_NAME, table, host);
         out_chain.set_hook(nftnl::Hook::Out, 0);
         out_chain.set_policy(nftnl::Policy::Drop);
-        batch.add(&out_chain, nftnl::MsgType::Add);
+        batch.add(&out_chain.chain, nftnl::MsgType::Add);
 
-        let mut in_chain = Chain::new(&*IN_CHAIN_NAME, table);
+        let mut in_chain = PolicyChain::new(&IN_CHAIN_NAME, table, host);
         in_chain.set_hook(nftnl::Hook::In, 0);
         in_chain.set_policy(nftnl::Policy::Drop);
-        batch.add(&in_chain, nftnl::MsgType::Add);
+        batch.add(&in_chain.chain, nftnl::MsgType::Add);
 
-        let mut forward_chain = Chain::new(&*FORWARD_CHAIN_NAME, table);
+        let mut forward_chain = PolicyChain::new(&FORWARD_CHAIN_NAME, table, host);
         forward_chain.set_hook(nftnl::Hook::Forward, 0);
         forward_chain.set_policy(nftnl::Policy::Drop);
-        batch.add(&forward_chain, nftnl::MsgType::Add);
+        batch.add(&forward_chain.chain, nftnl::MsgType::Add);
 
-        let mut mangle_chain = Chain::new(&*MANGLE_CHAIN_NAME, table);
+        let mut mangle_chain = PolicyChain::new(&MANGLE_CHAIN_NAME, table, host);
         mangle_chain.set_hook(nftnl::Hook::Out, MANGLE_CHAIN_PRIORITY);
         mangle_chain.set_type(nftnl::ChainType::Route);
         mangle_chain.set_policy(nftnl::Policy::Accept);
-        batch.add(&mangle_chain, nftnl::MsgType::Add);
+        batch.add(&mangle_chain.chain, nftnl::MsgType::Add);
 
-        let mut nat_chain = Chain::new(&*NAT_CHAIN_NAME, table);
+        let mut nat_chain = PolicyChain::new(&NAT_CHAIN_NAME, table, host);
         nat_chain.set_hook(nftnl::Hook::PostRouting, libc::NF_IP_PRI_NAT_SRC);
         nat_chain.set_type(nftnl::ChainType::Nat);
         nat_chain.set_policy(nftnl::Policy::Accept);
-        batch.add(&nat_chain, nftnl::MsgType::Add);
+        batch.add(&nat_chain.chain, nftnl::MsgType::Add);
 
         PolicyBatch {
-            batch,
+            batch: RuleBatch {
+                batch,
+                rules: Vec::new(),
+            },
             in_chain,
             out_chain,
             forward_chain,
@@ -281,27 +393,85 @@ impl<'a> PolicyBatch<'a> {
 
     /// Finalize the nftnl message batch by adding every firewall rule needed to satisfy the given
     /// policy.
-    pub fn finalize(mut self, policy: &FirewallPolicy, fwmark: u32) -> Result<FinalizedBatch> {
+    pub fn finalize(
+        mut self,
+        policy: &FirewallPolicy,
+        fwmark: u32,
+        excluded_users: &ExcludedUsers,
+    ) -> Result<FinalizedBatch> {
+        self.add_rules(policy, fwmark, excluded_users)?;
+        Ok(self.batch.batch.finalize())
+    }
+
+    /// Render every firewall rule needed to satisfy the given policy as an nft ruleset, in the
+    /// same order as `finalize` adds them to the batch.
+    pub fn render(
+        mut self,
+        policy: &FirewallPolicy,
+        fwmark: u32,
+        excluded_users: &ExcludedUsers,
+    ) -> Result<String> {
+        self.add_rules(policy, fwmark, excluded_users)?;
+
+        let chains = [
+            &self.prerouting_chain,
+            &self.out_chain,
+            &self.in_chain,
+            &self.forward_chain,
+            &self.mangle_chain,
+            &self.nat_chain,
+        ];
+        let mut lines = vec![format!("table inet {} {{", TABLE_NAME.to_string_lossy())];
+        for (i, chain) in chains.into_iter().enumerate() {
+            if i > 0 {
+                lines.push(String::new());
+            }
+            lines.push(format!("\tchain {} {{", chain.name.to_string_lossy()));
+            lines.push(format!("\t\t{}", chain.declaration()));
+            for (_, rule) in self
+                .batch
+                .rules
+                .iter()
+                .filter(|(chain_name, _)| *chain_name == chain.name)
+            {
+                lines.push(format!("\t\t{}", rule));
+            }
+            lines.push("\t}".to_owned());
+        }
+        lines.push("}\n".to_owned());
+        Ok(lines.join("\n"))
+    }
+
+    fn add_rules(
+        &mut self,
+        policy: &FirewallPolicy,
+        fwmark: u32,
+        excluded_users: &ExcludedUsers,
+    ) -> Result<()> {
         self.add_loopback_rules()?;
-        self.add_split_tunneling_rules(policy, fwmark)?;
+        self.add_split_tunneling_rules(policy, fwmark, excluded_users)?;
         self.add_dhcp_client_rules();
         self.add_ndp_rules();
-        self.add_policy_specific_rules(policy, fwmark)?;
-
-        Ok(self.batch.finalize())
+        self.add_policy_specific_rules(policy, fwmark)
     }
 
-    fn add_split_tunneling_rules(&mut self, policy: &FirewallPolicy, fwmark: u32) -> Result<()> {
+    fn add_split_tunneling_rules(
+        &mut self,
+        policy: &FirewallPolicy,
+        fwmark: u32,
+        excluded_users: &ExcludedUsers,
+    ) -> Result<()> {
         // Send select DNS requests in the tunnel
         if let FirewallPolicy::Connected {
             tunnel,
+            lan_networks,
             dns_servers,
             ..
         } = policy
         {
             for server in dns_servers
                 .iter()
-                .filter(|server| !is_local_dns_address(tunnel, server))
+                .filter(|server| !is_local_dns_address(tunnel, lan_networks, server))
             {
                 let allow_rule = allow_tunnel_dns_rule(
                     &self.mangle_chain,
@@ -320,63 +490,80 @@ impl<'a> PolicyBatch<'a> {
             }
         }
 
-        let mut rule = Rule::new(&self.mangle_chain);
-        rule.add_expr(&nft_expr!(meta cgroup));
-        rule.add_expr(&nft_expr!(cmp == split_tunnel::NET_CLS_CLASSID));
-        rule.add_expr(&nft_expr!(immediate data split_tunnel::MARK));
-        rule.add_expr(&nft_expr!(ct mark set));
-        rule.add_expr(&nft_expr!(immediate data fwmark));
-        rule.add_expr(&nft_expr!(meta mark set));
+        let mut rule = PolicyRule::new(&self.mangle_chain);
+        rule.add(Statement::Cgroup(split_tunnel::NET_CLS_CLASSID));
+        add_exclusion_marks(&mut rule, fwmark);
         self.batch.add(&rule, nftnl::MsgType::Add);
 
+        // Processes in the cgroup v2 exclusion group are identified by their sockets.
+        if let Some(cgroup_id) = self.mangle_chain.host.cgroup2_exclusion_id {
+            let mut rule = PolicyRule::new(&self.mangle_chain);
+            rule.add(Statement::SocketCgroupV2 {
+                level: split_tunnel::CGROUP2_EXCLUSION_LEVEL,
+                path: split_tunnel::CGROUP2_EXCLUSION_PATH,
+                id: cgroup_id,
+            });
+            add_exclusion_marks(&mut rule, fwmark);
+            self.batch.add(&rule, nftnl::MsgType::Add);
+        }
+
+        for uid in &excluded_users.uids {
+            let mut rule = PolicyRule::new(&self.mangle_chain);
+            rule.add(Statement::SkUid(*uid));
+            add_exclusion_marks(&mut rule, fwmark);
+            self.batch.add(&rule, nftnl::MsgType::Add);
+        }
+        for gid in &excluded_users.gids {
+            let mut rule = PolicyRule::new(&self.mangle_chain);
+            rule.add(Statement::SkGid(*gid));
+            add_exclusion_marks(&mut rule, fwmark);
+            self.batch.add(&rule, nftnl::MsgType::Add);
+        }
+
+        // Traffic to the allow list is routed outside the tunnel, like that of excluded processes.
+        // This includes DNS requests to allowed endpoints, which bypass the DNS leak protection.
+        for endpoint in policy.allow_list() {
+            let mut rule = PolicyRule::new(&self.mangle_chain);
+            check_net(&mut rule, End::Dst, allow_list_network(endpoint));
+            check_port(&mut rule, endpoint.protocol, End::Dst, endpoint.port);
+            add_exclusion_marks(&mut rule, fwmark);
+            self.batch.add(&rule, nftnl::MsgType::Add);
+        }
+
         for chain in &[&self.in_chain, &self.out_chain] {
-            let mut rule = Rule::new(chain);
-            rule.add_expr(&nft_expr!(ct mark));
-            rule.add_expr(&nft_expr!(cmp == split_tunnel::MARK));
+            let mut rule = PolicyRule::new(chain);
+            check_ct_mark(&mut rule);
             add_verdict(&mut rule, &Verdict::Accept);
             self.batch.add(&rule, nftnl::MsgType::Add);
         }
 
         // Block remaining marked outgoing in-tunnel traffic
         if let FirewallPolicy::Connected { tunnel, .. } = policy {
-            let mut block_tunnel_rule = Rule::new(&self.nat_chain);
+            let mut block_tunnel_rule = PolicyRule::new(&self.nat_chain);
             check_iface(&mut block_tunnel_rule, Direction::Out, &tunnel.interface)?;
-            block_tunnel_rule.add_expr(&nft_expr!(ct mark));
-            block_tunnel_rule.add_expr(&nft_expr!(cmp == split_tunnel::MARK));
+            check_ct_mark(&mut block_tunnel_rule);
             add_verdict(&mut block_tunnel_rule, &Verdict::Drop);
             self.batch.add(&block_tunnel_rule, nftnl::MsgType::Add);
         }
 
         // Fix source IP address in rerouted packets using masquerade.
         // Don't masquerade packets on the loopback device.
-        let mut rule = Rule::new(&self.nat_chain);
-
-        let iface_index = crate::linux::iface_index("lo")
-            .map_err(|e| Error::LookupIfaceIndexError("lo".to_string(), e))?;
-        rule.add_expr(&nft_expr!(meta oif));
-        rule.add_expr(&nft_expr!(cmp != iface_index));
-
-        rule.add_expr(&nft_expr!(ct mark));
-        rule.add_expr(&nft_expr!(cmp == split_tunnel::MARK));
+        let mut rule = PolicyRule::new(&self.nat_chain);
+        check_not_iface(&mut rule, Direction::Out, "lo")?;
+        check_ct_mark(&mut rule);
 
-        rule.add_expr(&nft_expr!(masquerade));
-        if *ADD_COUNTERS {
-            rule.add_expr(&nft_expr!(counter));
-        }
+        rule.add(Statement::Masquerade);
+        add_counter(&mut rule);
         self.batch.add(&rule, nftnl::MsgType::Add);
 
         // Route incoming traffic correctly to prevent strict rpf from rejecting packets
         // for excluded processes
         if let FirewallPolicy::Connected { tunnel, .. } = policy {
-            let mut prerouting_rule = Rule::new(&self.prerouting_chain);
+            let mut prerouting_rule = PolicyRule::new(&self.prerouting_chain);
             check_not_iface(&mut prerouting_rule, Direction::In, &tunnel.interface)?;
-            prerouting_rule.add_expr(&nft_expr!(ct mark));
-            prerouting_rule.add_expr(&nft_expr!(cmp == split_tunnel::MARK));
-            prerouting_rule.add_expr(&nft_expr!(immediate data fwmark));
-            prerouting_rule.add_expr(&nft_expr!(meta mark set));
-            if *ADD_COUNTERS {
-                prerouting_rule.add_expr(&nft_expr!(counter));
-            }
+            check_ct_mark(&mut prerouting_rule);
+            set_mark(&mut prerouting_rule, fwmark);
+            add_counter(&mut prerouting_rule);
             self.batch.add(&prerouting_rule, nftnl::MsgType::Add);
         }
 
@@ -400,7 +587,7 @@ impl<'a> PolicyBatch<'a> {
         use self::TransportProtocol::Udp;
         // Outgoing DHCPv4 request
         for chain in &[&self.out_chain, &self.forward_chain] {
-            let mut out_v4 = Rule::new(chain);
+            let mut out_v4 = PolicyRule::new(chain);
             check_port(&mut out_v4, Udp, End::Src, super::DHCPV4_CLIENT_PORT);
             check_ip(&mut out_v4, End::Dst, IpAddr::V4(Ipv4Addr::BROADCAST));
             check_port(&mut out_v4, Udp, End::Dst, super::DHCPV4_SERVER_PORT);
@@ -409,7 +596,7 @@ impl<'a> PolicyBatch<'a> {
         }
         // Incoming DHCPv4 response
         for chain in &[&self.in_chain, &self.forward_chain] {
-            let mut in_v4 = Rule::new(chain);
+            let mut in_v4 = PolicyRule::new(chain);
             check_port(&mut in_v4, Udp, End::Src, super::DHCPV4_SERVER_PORT);
             check_port(&mut in_v4, Udp, End::Dst, super::DHCPV4_CLIENT_PORT);
             add_verdict(&mut in_v4, &Verdict::Accept);
@@ -418,7 +605,7 @@ impl<'a> PolicyBatch<'a> {
 
         for chain in &[&self.out_chain, &self.forward_chain] {
             for dhcpv6_server in &*super::DHCPV6_SERVER_ADDRS {
-                let mut out_v6 = Rule::new(chain);
+                let mut out_v6 = PolicyRule::new(chain);
                 check_net(&mut out_v6, End::Src, *super::IPV6_LINK_LOCAL);
                 check_port(&mut out_v6, Udp, End::Src, super::DHCPV6_CLIENT_PORT);
                 check_ip(&mut out_v6, End::Dst, *dhcpv6_server);
@@ -428,7 +615,7 @@ impl<'a> PolicyBatch<'a> {
             }
         }
         for chain in &[&self.in_chain, &self.forward_chain] {
-            let mut in_v6 = Rule::new(chain);
+            let mut in_v6 = PolicyRule::new(chain);
             check_net(&mut in_v6, End::Src, *super::IPV6_LINK_LOCAL);
             check_port(&mut in_v6, Udp, End::Src, super::DHCPV6_SERVER_PORT);
             check_net(&mut in_v6, End::Dst, *super::IPV6_LINK_LOCAL);
@@ -441,7 +628,7 @@ impl<'a> PolicyBatch<'a> {
     fn add_ndp_rules(&mut self) {
         // Outgoing Router solicitation (part of NDP)
         for chain in &[&self.out_chain, &self.forward_chain] {
-            let mut rule = Rule::new(chain);
+            let mut rule = PolicyRule::new(chain);
             check_ip(
                 &mut rule,
                 End::Dst,
@@ -453,7 +640,7 @@ impl<'a> PolicyBatch<'a> {
         }
         // Incoming Router advertisement (part of NDP)
         for chain in &[&self.in_chain, &self.forward_chain] {
-            let mut rule = Rule::new(chain);
+            let mut rule = PolicyRule::new(chain);
             check_net(&mut rule, End::Src, *super::IPV6_LINK_LOCAL);
             check_icmpv6(&mut rule, 134, 0);
             add_verdict(&mut rule, &Verdict::Accept);
@@ -461,7 +648,7 @@ impl<'a> PolicyBatch<'a> {
         }
         // Incoming Redirect (part of NDP)
         for chain in &[&self.in_chain, &self.forward_chain] {
-            let mut rule = Rule::new(chain);
+            let mut rule = PolicyRule::new(chain);
             check_net(&mut rule, End::Src, *super::IPV6_LINK_LOCAL);
             check_icmpv6(&mut rule, 137, 0);
             add_verdict(&mut rule, &Verdict::Accept);
@@ -469,14 +656,14 @@ impl<'a> PolicyBatch<'a> {
         }
         // Outgoing Neighbor solicitation (part of NDP)
         for chain in &[&self.out_chain, &self.forward_chain] {
-            let mut rule = Rule::new(chain);
+            let mut rule = PolicyRule::new(chain);
             check_net(&mut rule, End::Dst, *super::SOLICITED_NODE_MULTICAST);
             check_icmpv6(&mut rule, 135, 0);
             add_verdict(&mut rule, &Verdict::Accept);
             self.batch.add(&rule, nftnl::MsgType::Add);
         }
         for chain in &[&self.out_chain, &self.forward_chain] {
-            let mut rule = Rule::new(chain);
+            let mut rule = PolicyRule::new(chain);
             check_net(&mut rule, End::Dst, *super::IPV6_LINK_LOCAL);
             check_icmpv6(&mut rule, 135, 0);
             add_verdict(&mut rule, &Verdict::Accept);
@@ -484,7 +671,7 @@ impl<'a> PolicyBatch<'a> {
         }
         // Incoming Neighbor solicitation (part of NDP)
         for chain in &[&self.in_chain, &self.forward_chain] {
-            let mut rule = Rule::new(chain);
+            let mut rule = PolicyRule::new(chain);
             check_net(&mut rule, End::Src, *super::IPV6_LINK_LOCAL);
             check_icmpv6(&mut rule, 135, 0);
             add_verdict(&mut rule, &Verdict::Accept);
@@ -492,7 +679,7 @@ impl<'a> PolicyBatch<'a> {
         }
         // Outgoing Neighbor advertisement (part of NDP)
         for chain in &[&self.out_chain, &self.forward_chain] {
-            let mut rule = Rule::new(chain);
+            let mut rule = PolicyRule::new(chain);
             check_net(&mut rule, End::Dst, *super::IPV6_LINK_LOCAL);
             check_icmpv6(&mut rule, 136, 0);
             add_verdict(&mut rule, &Verdict::Accept);
@@ -500,7 +687,7 @@ impl<'a> PolicyBatch<'a> {
         }
         // Incoming Neighbor advertisement (part of NDP)
         for chain in &[&self.in_chain, &self.forward_chain] {
-            let mut rule = Rule::new(chain);
+            let mut rule = PolicyRule::new(chain);
             check_icmpv6(&mut rule, 136, 0);
             add_verdict(&mut rule, &Verdict::Accept);
             self.batch.add(&rule, nftnl::MsgType::Add);
@@ -508,6 +695,9 @@ impl<'a> PolicyBatch<'a> {
     }
 
     fn add_policy_specific_rules(&mut self, policy: &FirewallPolicy, fwmark: u32) -> Result<()> {
+        // Allowed before DNS is dropped, so that the allow list may contain DNS servers
+        self.add_allow_list_rules(policy.allow_list());
+
         let allow_lan = match policy {
             FirewallPolicy::Connecting {
                 peer_endpoint,
@@ -515,6 +705,7 @@ impl<'a> PolicyBatch<'a> {
                 allow_lan,
                 allowed_endpoint,
                 allowed_tunnel_traffic,
+                ..
             } => {
                 self.add_allow_tunnel_endpoint_rules(peer_endpoint, fwmark);
                 self.add_allow_endpoint_rules(&allowed_endpoint.endpoint);
@@ -547,11 +738,24 @@ impl<'a> PolicyBatch<'a> {
                 peer_endpoint,
                 tunnel,
                 allow_lan,
+                lan_networks,
                 dns_servers,
+                inbound_rules,
+                ..
             } => {
                 self.add_allow_tunnel_endpoint_rules(peer_endpoint, fwmark);
-                self.add_allow_dns_rules(tunnel, dns_servers, TransportProtocol::Udp)?;
-                self.add_allow_dns_rules(tunnel, dns_servers, TransportProtocol::Tcp)?;
+                self.add_allow_dns_rules(
+                    tunnel,
+                    lan_networks,
+                    dns_servers,
+                    TransportProtocol::Udp,
+                )?;
+                self.add_allow_dns_rules(
+                    tunnel,
+                    lan_networks,
+                    dns_servers,
+                    TransportProtocol::Tcp,
+                )?;
                 // Important to block DNS *before* we allow the tunnel and allow LAN. So DNS
                 // can't leak to the wrong IPs in the tunnel or on the LAN.
                 self.add_drop_dns_rule();
@@ -559,11 +763,15 @@ impl<'a> PolicyBatch<'a> {
                 if *allow_lan {
                     self.add_block_cve_2019_14899(tunnel);
                 }
+                // Must come after the CVE-2019-14899 rules, so that the tunnel IP cannot be
+                // reached through an exposed port
+                self.add_inbound_rules(inbound_rules);
                 *allow_lan
             }
             FirewallPolicy::Blocked {
                 allow_lan,
                 allowed_endpoint,
+                ..
             } => {
                 if let Some(endpoint) = allowed_endpoint {
                     self.add_allow_endpoint_rules(&endpoint.endpoint);
@@ -576,16 +784,13 @@ impl<'a> PolicyBatch<'a> {
         };
 
         if allow_lan {
-            self.add_allow_lan_rules();
+            self.add_allow_lan_rules(policy.lan_networks());
         }
 
         // Reject any remaining outgoing traffic
         for chain in &[&self.out_chain, &self.forward_chain] {
-            let mut reject_rule = Rule::new(chain);
-            add_verdict(
-                &mut reject_rule,
-                &Verdict::Reject(RejectionType::Icmp(IcmpCode::PortUnreach)),
-            );
+            let mut reject_rule = PolicyRule::new(chain);
+            add_verdict(&mut reject_rule, &Verdict::Reject);
             self.batch.add(&reject_rule, nftnl::MsgType::Add);
         }
 
@@ -593,32 +798,23 @@ impl<'a> PolicyBatch<'a> {
     }
 
     fn add_allow_tunnel_endpoint_rules(&mut self, endpoint: &Endpoint, fwmark: u32) {
-        let mut prerouting_rule = Rule::new(&self.prerouting_chain);
+        let mut prerouting_rule = PolicyRule::new(&self.prerouting_chain);
         check_endpoint(&mut prerouting_rule, End::Src, endpoint);
-        prerouting_rule.add_expr(&nft_expr!(immediate data fwmark));
-        prerouting_rule.add_expr(&nft_expr!(meta mark set));
-
-        if *ADD_COUNTERS {
-            prerouting_rule.add_expr(&nft_expr!(counter));
-        }
+        set_mark(&mut prerouting_rule, fwmark);
+        add_counter(&mut prerouting_rule);
 
         self.batch.add(&prerouting_rule, nftnl::MsgType::Add);
 
-        let mut in_rule = Rule::new(&self.in_chain);
+        let mut in_rule = PolicyRule::new(&self.in_chain);
         check_endpoint(&mut in_rule, End::Src, endpoint);
-
-        in_rule.add_expr(&nft_expr!(ct state));
-        let allowed_states = nftnl::expr::ct::States::ESTABLISHED.bits();
-        in_rule.add_expr(&nft_expr!(bitwise mask allowed_states, xor 0u32));
-        in_rule.add_expr(&nft_expr!(cmp != 0u32));
+        check_established(&mut in_rule);
         add_verdict(&mut in_rule, &Verdict::Accept);
 
         self.batch.add(&in_rule, nftnl::MsgType::Add);
 
-        let mut out_rule = Rule::new(&self.out_chain);
+        let mut out_rule = PolicyRule::new(&self.out_chain);
         check_endpoint(&mut out_rule, End::Dst, endpoint);
-        out_rule.add_expr(&nft_expr!(meta mark));
-        out_rule.add_expr(&nft_expr!(cmp == fwmark));
+        out_rule.add(Statement::Mark(fwmark));
         add_verdict(&mut out_rule, &Verdict::Accept);
 
         self.batch.add(&out_rule, nftnl::MsgType::Add);
@@ -627,37 +823,104 @@ impl<'a> PolicyBatch<'a> {
     /// Adds firewall rules allow traffic to flow to the API. Allows the app to reach the API in
     /// blocked states.
     fn add_allow_endpoint_rules(&mut self, endpoint: &Endpoint) {
-        let mut in_rule = Rule::new(&self.in_chain);
+        let mut in_rule = PolicyRule::new(&self.in_chain);
         check_endpoint(&mut in_rule, End::Src, endpoint);
-        let allowed_states = nftnl::expr::ct::States::ESTABLISHED.bits();
-        in_rule.add_expr(&nft_expr!(ct state));
-        in_rule.add_expr(&nft_expr!(bitwise mask allowed_states, xor 0u32));
-        in_rule.add_expr(&nft_expr!(cmp != 0u32));
-        in_rule.add_expr(&nft_expr!(meta skuid));
-        in_rule.add_expr(&nft_expr!(cmp == super::ROOT_UID));
+        check_established(&mut in_rule);
+        check_root_user(&mut in_rule);
 
         add_verdict(&mut in_rule, &Verdict::Accept);
 
         self.batch.add(&in_rule, nftnl::MsgType::Add);
 
-        let mut out_rule = Rule::new(&self.out_chain);
+        let mut out_rule = PolicyRule::new(&self.out_chain);
         check_endpoint(&mut out_rule, End::Dst, endpoint);
-        out_rule.add_expr(&nft_expr!(meta skuid));
-        out_rule.add_expr(&nft_expr!(cmp == super::ROOT_UID));
+        check_root_user(&mut out_rule);
+        add_verdict(&mut out_rule, &Verdict::Accept);
+
+        self.batch.add(&out_rule, nftnl::MsgType::Add);
+    }
+
+    /// Adds rules allowing traffic to and from the endpoints in the user's allow list, over any
+    /// interface and by any process.
+    fn add_allow_list_rules(&mut self, allow_list: &[AllowListEndpoint]) {
+        for endpoint in allow_list {
+            let network = allow_list_network(endpoint);
+
+            let mut out_rule = PolicyRule::new(&self.out_chain);
+            check_net(&mut out_rule, End::Dst, network);
+            check_port(&mut out_rule, endpoint.protocol, End::Dst, endpoint.port);
+            add_verdict(&mut out_rule, &Verdict::Accept);
+            self.batch.add(&out_rule, nftnl::MsgType::Add);
+
+            let mut in_rule = PolicyRule::new(&self.in_chain);
+            check_net(&mut in_rule, End::Src, network);
+            check_port(&mut in_rule, endpoint.protocol, End::Src, endpoint.port);
+            check_established(&mut in_rule);
+            add_verdict(&mut in_rule, &Verdict::Accept);
+            self.batch.add(&in_rule, nftnl::MsgType::Add);
+        }
+    }
+
+    /// Adds rules accepting connections to exposed local ports. A rule whose interface does not
+    /// exist is skipped, so that its port stays unreachable.
+    fn add_inbound_rules(&mut self, inbound_rules: &[InboundRule]) {
+        for inbound_rule in inbound_rules {
+            if let Err(error) = self.add_inbound_rule(inbound_rule) {
+                log::warn!(
+                    "{}",
+                    error.display_chain_with_msg(&format!(
+                        "Not applying inbound rule \"{}\"",
+                        inbound_rule
+                    ))
+                );
+            }
+        }
+    }
+
+    fn add_inbound_rule(&mut self, inbound_rule: &InboundRule) -> Result<()> {
+        // Host bits must be cleared, or the masked address would never match
+        let source = IpNetwork::new(inbound_rule.source.network(), inbound_rule.source.prefix())
+            .expect("prefix of a valid network is valid");
+
+        let mut in_rule = PolicyRule::new(&self.in_chain);
+        check_iface(&mut in_rule, Direction::In, &inbound_rule.interface)?;
+        check_net(&mut in_rule, End::Src, source);
+        check_port(
+            &mut in_rule,
+            inbound_rule.protocol,
+            End::Dst,
+            inbound_rule.port,
+        );
+        add_verdict(&mut in_rule, &Verdict::Accept);
+
+        // Replies to accepted connections
+        let mut out_rule = PolicyRule::new(&self.out_chain);
+        check_iface(&mut out_rule, Direction::Out, &inbound_rule.interface)?;
+        check_net(&mut out_rule, End::Dst, source);
+        check_port(
+            &mut out_rule,
+            inbound_rule.protocol,
+            End::Src,
+            inbound_rule.port,
+        );
+        check_established(&mut out_rule);
         add_verdict(&mut out_rule, &Verdict::Accept);
 
+        self.batch.add(&in_rule, nftnl::MsgType::Add);
         self.batch.add(&out_rule, nftnl::MsgType::Add);
+        Ok(())
     }
 
     fn add_allow_dns_rules(
         &mut self,
         tunnel: &tunnel::TunnelMetadata,
+        lan_networks: &[IpNetwork],
         dns_servers: &[IpAddr],
         protocol: TransportProtocol,
     ) -> Result<()> {
         let (local_resolvers, remote_resolvers): (Vec<IpAddr>, Vec<IpAddr>) = dns_servers
             .iter()
-            .partition(|server| is_local_dns_address(tunnel, server));
+            .partition(|server| is_local_dns_address(tunnel, lan_networks, server));
 
         for resolver in &local_resolvers {
             self.add_allow_local_dns_rule(&tunnel.interface, protocol, *resolver)?;
@@ -697,25 +960,15 @@ impl<'a> PolicyBatch<'a> {
         ];
 
         for (chain, direction) in &chains {
-            let mut allow_rule = Rule::new(chain);
-            let addr = match (host, direction) {
-                (IpAddr::V4(_), Direction::Out) => nft_expr!(payload ipv4 daddr),
-                (IpAddr::V6(_), Direction::Out) => nft_expr!(payload ipv6 daddr),
-                (IpAddr::V4(_), Direction::In) => nft_expr!(payload ipv4 saddr),
-                (IpAddr::V6(_), Direction::In) => nft_expr!(payload ipv6 saddr),
-            };
-
-            let port_dir = match direction {
+            let mut allow_rule = PolicyRule::new(chain);
+            let end = match direction {
                 Direction::In => End::Src,
                 Direction::Out => End::Dst,
             };
 
             check_not_iface(&mut allow_rule, *direction, tunnel_interface)?;
-            check_port(&mut allow_rule, protocol, port_dir, 53);
-            check_l3proto(&mut allow_rule, host);
-
-            allow_rule.add_expr(&addr);
-            allow_rule.add_expr(&nft_expr!(cmp == host));
+            check_port(&mut allow_rule, protocol, end, 53);
+            check_ip(&mut allow_rule, end, host);
             add_verdict(&mut allow_rule, &Verdict::Accept);
 
             self.batch.add(&allow_rule, nftnl::MsgType::Add);
@@ -727,17 +980,14 @@ impl<'a> PolicyBatch<'a> {
     /// Blocks all outgoing DNS (port 53) on both TCP and UDP
     fn add_drop_dns_rule(&mut self) {
         for chain in &[&self.out_chain, &self.forward_chain] {
-            let mut block_udp_rule = Rule::new(chain);
+            let mut block_udp_rule = PolicyRule::new(chain);
             check_port(&mut block_udp_rule, TransportProtocol::Udp, End::Dst, 53);
-            add_verdict(
-                &mut block_udp_rule,
-                &Verdict::Reject(RejectionType::Icmp(IcmpCode::PortUnreach)),
-            );
+            add_verdict(&mut block_udp_rule, &Verdict::Reject);
             self.batch.add(&block_udp_rule, nftnl::MsgType::Add);
 
-            let mut block_tcp_rule = Rule::new(chain);
+            let mut block_tcp_rule = PolicyRule::new(chain);
             check_port(&mut block_tcp_rule, TransportProtocol::Tcp, End::Dst, 53);
-            add_verdict(&mut block_tcp_rule, &Verdict::Reject(RejectionType::TcpRst));
+            add_verdict(&mut block_tcp_rule, &Verdict::RejectTcpRst);
             self.batch.add(&block_tcp_rule, nftnl::MsgType::Add);
         }
     }
@@ -751,7 +1001,7 @@ impl<'a> PolicyBatch<'a> {
             (&self.out_chain, Direction::Out, End::Dst),
             (&self.in_chain, Direction::In, End::Src),
         ] {
-            let mut rule = Rule::new(chain);
+            let mut rule = PolicyRule::new(chain);
             check_iface(&mut rule, dir, tunnel_interface)?;
             check_ip(&mut rule, end, endpoint.address.ip());
             check_port(&mut rule, endpoint.protocol, end, endpoint.address.port());
@@ -775,12 +1025,9 @@ impl<'a> PolicyBatch<'a> {
             nftnl::MsgType::Add,
         );
 
-        let mut interface_rule = Rule::new(&self.forward_chain);
+        let mut interface_rule = PolicyRule::new(&self.forward_chain);
         check_iface(&mut interface_rule, Direction::In, tunnel_interface)?;
-        interface_rule.add_expr(&nft_expr!(ct state));
-        let allowed_states = nftnl::expr::ct::States::ESTABLISHED.bits();
-        interface_rule.add_expr(&nft_expr!(bitwise mask allowed_states, xor 0u32));
-        interface_rule.add_expr(&nft_expr!(cmp != 0u32));
+        check_established(&mut interface_rule);
         add_verdict(&mut interface_rule, &Verdict::Accept);
         self.batch.add(&interface_rule, nftnl::MsgType::Add);
 
@@ -794,19 +1041,28 @@ impl<'a> PolicyBatch<'a> {
     /// after the rule allowing the tunnel, otherwise even the tunnel can't talk to that IP.
     fn add_block_cve_2019_14899(&mut self, tunnel: &tunnel::TunnelMetadata) {
         for tunnel_ip in &tunnel.ips {
-            let mut rule = Rule::new(&self.in_chain);
+            let mut rule = PolicyRule::new(&self.in_chain);
             check_ip(&mut rule, End::Dst, *tunnel_ip);
             add_verdict(&mut rule, &Verdict::Drop);
             self.batch.add(&rule, nftnl::MsgType::Add);
         }
     }
 
-    fn add_allow_lan_rules(&mut self) {
+    fn add_allow_lan_rules(&mut self, lan_networks: &[IpNetwork]) {
+        // Host bits must be cleared, or the masked address would never match
+        let lan_networks: Vec<IpNetwork> = lan_networks
+            .iter()
+            .map(|net| {
+                IpNetwork::new(net.network(), net.prefix())
+                    .expect("prefix of a valid network is valid")
+            })
+            .collect();
+
         // Output and forward chains
         for chain in &[&self.out_chain, &self.forward_chain] {
             // LAN -> LAN
-            for net in &*super::ALLOWED_LAN_NETS {
-                let mut out_rule = Rule::new(chain);
+            for net in &lan_networks {
+                let mut out_rule = PolicyRule::new(chain);
                 check_net(&mut out_rule, End::Dst, *net);
                 add_verdict(&mut out_rule, &Verdict::Accept);
                 self.batch.add(&out_rule, nftnl::MsgType::Add);
@@ -814,7 +1070,7 @@ impl<'a> PolicyBatch<'a> {
 
             // LAN -> Multicast
             for net in &*super::ALLOWED_LAN_MULTICAST_NETS {
-                let mut rule = Rule::new(chain);
+                let mut rule = PolicyRule::new(chain);
                 check_net(&mut rule, End::Dst, *net);
                 add_verdict(&mut rule, &Verdict::Accept);
                 self.batch.add(&rule, nftnl::MsgType::Add);
@@ -823,8 +1079,8 @@ impl<'a> PolicyBatch<'a> {
 
         // Input chain
         // LAN -> LAN
-        for net in &*super::ALLOWED_LAN_NETS {
-            let mut in_rule = Rule::new(&self.in_chain);
+        for net in &lan_networks {
+            let mut in_rule = PolicyRule::new(&self.in_chain);
             check_net(&mut in_rule, End::Src, *net);
             add_verdict(&mut in_rule, &Verdict::Accept);
             self.batch.add(&in_rule, nftnl::MsgType::Add);
@@ -836,7 +1092,7 @@ impl<'a> PolicyBatch<'a> {
         use TransportProtocol::Udp;
         // Outgoing DHCPv4 response
         {
-            let mut out_v4 = Rule::new(&self.out_chain);
+            let mut out_v4 = PolicyRule::new(&self.out_chain);
             check_port(&mut out_v4, Udp, End::Src, super::DHCPV4_SERVER_PORT);
             check_port(&mut out_v4, Udp, End::Dst, super::DHCPV4_CLIENT_PORT);
             add_verdict(&mut out_v4, &Verdict::Accept);
@@ -844,7 +1100,7 @@ impl<'a> PolicyBatch<'a> {
         }
         // Incoming DHCPv4 request
         {
-            let mut in_v4 = Rule::new(&self.in_chain);
+            let mut in_v4 = PolicyRule::new(&self.in_chain);
             check_port(&mut in_v4, Udp, End::Src, super::DHCPV4_CLIENT_PORT);
             check_endpoint(
                 &mut in_v4,
@@ -857,139 +1113,448 @@ impl<'a> PolicyBatch<'a> {
     }
 }
 
-fn is_local_dns_address(tunnel: &tunnel::TunnelMetadata, server: &IpAddr) -> bool {
-    super::is_local_address(server)
+/// A chain in the table, along with what is needed to render its declaration in nft syntax.
+struct PolicyChain<'a> {
+    chain: Chain<'a>,
+    name: &'static CStr,
+    host: HostState,
+    chain_type: &'static str,
+    hook: &'static str,
+    priority: i32,
+    policy: &'static str,
+}
+
+impl<'a> PolicyChain<'a> {
+    fn new(name: &'static CStr, table: &'a Table, host: HostState) -> Self {
+        PolicyChain {
+            chain: Chain::new(&name, table),
+            name,
+            host,
+            chain_type: "filter",
+            hook: "",
+            priority: 0,
+            policy: "accept",
+        }
+    }
+
+    fn set_hook(&mut self, hook: nftnl::Hook, priority: i32) {
+        self.hook = match hook {
+            nftnl::Hook::PreRouting => "prerouting",
+            nftnl::Hook::In => "input",
+            nftnl::Hook::Forward => "forward",
+            nftnl::Hook::Out => "output",
+            nftnl::Hook::PostRouting => "postrouting",
+        };
+        self.priority = priority;
+        self.chain.set_hook(hook, priority);
+    }
+
+    fn set_type(&mut self, chain_type: nftnl::ChainType) {
+        self.chain_type = match chain_type {
+            nftnl::ChainType::Filter => "filter",
+            nftnl::ChainType::Route => "route",
+            nftnl::ChainType::Nat => "nat",
+        };
+        self.chain.set_type(chain_type);
+    }
+
+    fn set_policy(&mut self, policy: nftnl::Policy) {
+        self.policy = match policy {
+            nftnl::Policy::Accept => "accept",
+            nftnl::Policy::Drop => "drop",
+        };
+        self.chain.set_policy(policy);
+    }
+
+    fn declaration(&self) -> String {
+        format!(
+            "type {} hook {} priority {}; policy {};",
+            self.chain_type, self.hook, self.priority, self.policy
+        )
+    }
+}
+
+/// A rule, along with the statements it consists of. Every statement is added to the rule as
+/// expressions and kept, so that the rule can be rendered in nft syntax.
+struct PolicyRule<'a> {
+    rule: Rule<'a>,
+    chain: &'a PolicyChain<'a>,
+    statements: Vec<Statement>,
+}
+
+impl<'a> PolicyRule<'a> {
+    fn new(chain: &'a PolicyChain<'a>) -> Self {
+        PolicyRule {
+            rule: Rule::new(&chain.chain),
+            chain,
+            statements: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, statement: Statement) {
+        statement.add_to(&mut self.rule);
+        self.statements.push(statement);
+    }
+}
+
+impl fmt::Display for PolicyRule<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut statements = self.statements.iter();
+        if let Some(statement) = statements.next() {
+            write!(f, "{}", statement)?;
+        }
+        for statement in statements {
+            write!(f, " {}", statement)?;
+        }
+        Ok(())
+    }
+}
+
+/// A match or an action of a rule, which is both translated into nftnl expressions and rendered
+/// in nft syntax.
+enum Statement {
+    Iface {
+        direction: Direction,
+        name: String,
+        index: u32,
+        negate: bool,
+    },
+    Net {
+        end: End,
+        net: IpNetwork,
+    },
+    Ip {
+        end: End,
+        ip: IpAddr,
+    },
+    Port {
+        protocol: TransportProtocol,
+        end: End,
+        port: u16,
+    },
+    Icmpv6 {
+        r#type: u8,
+        code: u8,
+    },
+    Established,
+    Cgroup(u32),
+    SocketCgroupV2 {
+        level: u32,
+        path: &'static str,
+        id: u64,
+    },
+    SkUid(u32),
+    SkGid(u32),
+    CtMark(i32),
+    Mark(u32),
+    SetCtMark(i32),
+    SetMark(u32),
+    Masquerade,
+    Counter,
+    Verdict(Verdict),
+}
+
+impl Statement {
+    fn add_to(&self, rule: &mut Rule<'_>) {
+        match *self {
+            Statement::Iface {
+                direction,
+                index,
+                negate,
+                ..
+            } => {
+                rule.add_expr(&match direction {
+                    Direction::In => nft_expr!(meta iif),
+                    Direction::Out => nft_expr!(meta oif),
+                });
+                if negate {
+                    rule.add_expr(&nft_expr!(cmp != index));
+                } else {
+                    rule.add_expr(&nft_expr!(cmp == index));
+                }
+            }
+            Statement::Net { end, net } => {
+                // Must check network layer protocol before loading network layer payload
+                add_l3proto(rule, net.ip());
+
+                rule.add_expr(&match (net, end) {
+                    (IpNetwork::V4(_), End::Src) => nft_expr!(payload ipv4 saddr),
+                    (IpNetwork::V4(_), End::Dst) => nft_expr!(payload ipv4 daddr),
+                    (IpNetwork::V6(_), End::Src) => nft_expr!(payload ipv6 saddr),
+                    (IpNetwork::V6(_), End::Dst) => nft_expr!(payload ipv6 daddr),
+                });
+                match net {
+                    IpNetwork::V4(_) => {
+                        rule.add_expr(&nft_expr!(bitwise mask net.mask(), xor 0u32))
+                    }
+                    IpNetwork::V6(_) => {
+                        rule.add_expr(&nft_expr!(bitwise mask net.mask(), xor &[0u16; 8][..]))
+                    }
+                };
+                rule.add_expr(&nft_expr!(cmp == net.ip()));
+            }
+            Statement::Ip { end, ip } => {
+                // Must check network layer protocol before loading network layer payload
+                add_l3proto(rule, ip);
+
+                rule.add_expr(&match (ip, end) {
+                    (IpAddr::V4(..), End::Src) => nft_expr!(payload ipv4 saddr),
+                    (IpAddr::V4(..), End::Dst) => nft_expr!(payload ipv4 daddr),
+                    (IpAddr::V6(..), End::Src) => nft_expr!(payload ipv6 saddr),
+                    (IpAddr::V6(..), End::Dst) => nft_expr!(payload ipv6 daddr),
+                });
+                match ip {
+                    IpAddr::V4(addr) => rule.add_expr(&nft_expr!(cmp == addr)),
+                    IpAddr::V6(addr) => rule.add_expr(&nft_expr!(cmp == addr)),
+                }
+            }
+            Statement::Port {
+                protocol,
+                end,
+                port,
+            } => {
+                // Must check transport layer protocol before loading transport layer payload
+                rule.add_expr(&nft_expr!(meta l4proto));
+                rule.add_expr(&nft_expr!(cmp == l4proto(protocol)));
+
+                rule.add_expr(&match (protocol, end) {
+                    (TransportProtocol::Udp, End::Src) => nft_expr!(payload udp sport),
+                    (TransportProtocol::Udp, End::Dst) => nft_expr!(payload udp dport),
+                    (TransportProtocol::Tcp, End::Src) => nft_expr!(payload tcp sport),
+                    (TransportProtocol::Tcp, End::Dst) => nft_expr!(payload tcp dport),
+                });
+                rule.add_expr(&nft_expr!(cmp == port.to_be()));
+            }
+            Statement::Icmpv6 { r#type, code } => {
+                rule.add_expr(&nft_expr!(meta l4proto));
+                rule.add_expr(&nft_expr!(cmp == libc::IPPROTO_ICMPV6 as u8));
+
+                rule.add_expr(&Payload::Transport(
+                    nftnl::expr::TransportHeaderField::Icmpv6(nftnl::expr::Icmpv6HeaderField::Type),
+                ));
+                rule.add_expr(&nft_expr!(cmp == r#type));
+                rule.add_expr(&nftnl::expr::Payload::Transport(
+                    nftnl::expr::TransportHeaderField::Icmpv6(nftnl::expr::Icmpv6HeaderField::Code),
+                ));
+                rule.add_expr(&nft_expr!(cmp == code));
+            }
+            Statement::Established => {
+                rule.add_expr(&nft_expr!(ct state));
+                let allowed_states = nftnl::expr::ct::States::ESTABLISHED.bits();
+                rule.add_expr(&nft_expr!(bitwise mask allowed_states, xor 0u32));
+                rule.add_expr(&nft_expr!(cmp != 0u32));
+            }
+            Statement::Cgroup(classid) => {
+                rule.add_expr(&nft_expr!(meta cgroup));
+                rule.add_expr(&nft_expr!(cmp == classid));
+            }
+            Statement::SocketCgroupV2 { level, id, .. } => {
+                rule.add_expr(&SocketCgroupV2 { level });
+                rule.add_expr(&nft_expr!(cmp == &id.to_ne_bytes()[..]));
+            }
+            Statement::SkUid(uid) => {
+                rule.add_expr(&nft_expr!(meta skuid));
+                rule.add_expr(&nft_expr!(cmp == uid));
+            }
+            Statement::SkGid(gid) => {
+                rule.add_expr(&expr::Meta::SkGid);
+                rule.add_expr(&nft_expr!(cmp == gid));
+            }
+            Statement::CtMark(mark) => {
+                rule.add_expr(&nft_expr!(ct mark));
+                rule.add_expr(&nft_expr!(cmp == mark));
+            }
+            Statement::Mark(mark) => {
+                rule.add_expr(&nft_expr!(meta mark));
+                rule.add_expr(&nft_expr!(cmp == mark));
+            }
+            Statement::SetCtMark(mark) => {
+                rule.add_expr(&nft_expr!(immediate data mark));
+                rule.add_expr(&nft_expr!(ct mark set));
+            }
+            Statement::SetMark(mark) => {
+                rule.add_expr(&nft_expr!(immediate data mark));
+                rule.add_expr(&nft_expr!(meta mark set));
+            }
+            Statement::Masquerade => rule.add_expr(&nft_expr!(masquerade)),
+            Statement::Counter => rule.add_expr(&nft_expr!(counter)),
+            Statement::Verdict(verdict) => rule.add_expr(&match verdict {
+                Verdict::Accept => expr::Verdict::Accept,
+                Verdict::Drop => expr::Verdict::Drop,
+                Verdict::Reject => {
+                    expr::Verdict::Reject(RejectionType::Icmp(IcmpCode::PortUnreach))
+                }
+                Verdict::RejectTcpRst => expr::Verdict::Reject(RejectionType::TcpRst),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Statement::Iface {
+                direction,
+                name,
+                negate,
+                ..
+            } => {
+                let operator = if *negate { "!= " } else { "" };
+                write!(f, "{} {}\"{}\"", direction.iface_keyword(), operator, name)
+            }
+            Statement::Net { end, net } => {
+                write!(f, "{} {} {}", ip_keyword(net.ip()), end.addr_keyword(), net)
+            }
+            Statement::Ip { end, ip } => {
+                write!(f, "{} {} {}", ip_keyword(*ip), end.addr_keyword(), ip)
+            }
+            Statement::Port {
+                protocol,
+                end,
+                port,
+            } => write!(
+                f,
+                "{} {} {}",
+                l4proto_keyword(*protocol),
+                end.port_keyword(),
+                port
+            ),
+            Statement::Icmpv6 { r#type, code } => {
+                write!(f, "icmpv6 type {} icmpv6 code {}", r#type, code)
+            }
+            Statement::Established => f.write_str("ct state established"),
+            Statement::Cgroup(classid) => write!(f, "meta cgroup {}", classid),
+            Statement::SocketCgroupV2 { level, path, .. } => {
+                write!(f, "socket cgroupv2 level {} \"{}\"", level, path)
+            }
+            Statement::SkUid(uid) => write!(f, "meta skuid {}", uid),
+            Statement::SkGid(gid) => write!(f, "meta skgid {}", gid),
+            Statement::CtMark(mark) => write!(f, "ct mark {:#010x}", mark),
+            Statement::Mark(mark) => write!(f, "meta mark {:#010x}", mark),
+            Statement::SetCtMark(mark) => write!(f, "ct mark set {:#010x}", mark),
+            Statement::SetMark(mark) => write!(f, "meta mark set {:#010x}", mark),
+            Statement::Masquerade => f.write_str("masquerade"),
+            Statement::Counter => f.write_str("counter"),
+            Statement::Verdict(Verdict::Accept) => f.write_str("accept"),
+            Statement::Verdict(Verdict::Drop) => f.write_str("drop"),
+            Statement::Verdict(Verdict::Reject) => f.write_str("reject"),
+            Statement::Verdict(Verdict::RejectTcpRst) => f.write_str("reject with tcp reset"),
+        }
+    }
+}
+
+/// The verdicts that rules end with. `Reject` replies with ICMP port unreachable.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Verdict {
+    Accept,
+    Drop,
+    Reject,
+    RejectTcpRst,
+}
+
+/// A message batch that keeps the added rules in nft syntax, so that they can be rendered.
+struct RuleBatch {
+    batch: Batch,
+    rules: Vec<(&'static CStr, String)>,
+}
+
+impl RuleBatch {
+    fn add(&mut self, rule: &PolicyRule<'_>, msg_type: nftnl::MsgType) {
+        self.batch.add(&rule.rule, msg_type);
+        self.rules.push((rule.chain.name, rule.to_string()));
+    }
+}
+
+fn is_local_dns_address(
+    tunnel: &tunnel::TunnelMetadata,
+    lan_networks: &[IpNetwork],
+    server: &IpAddr,
+) -> bool {
+    super::is_local_address(server, lan_networks)
         && server != &tunnel.ipv4_gateway
         && Some(server) != tunnel.ipv6_gateway.map(IpAddr::from).as_ref()
 }
 
 fn allow_tunnel_dns_rule<'a>(
-    chain: &'a Chain<'_>,
+    chain: &'a PolicyChain<'a>,
     iface: &str,
     protocol: TransportProtocol,
     host: IpAddr,
-) -> Result<Rule<'a>> {
-    let mut rule = Rule::new(chain);
+) -> Result<PolicyRule<'a>> {
+    let mut rule = PolicyRule::new(chain);
     check_iface(&mut rule, Direction::Out, iface)?;
     check_port(&mut rule, protocol, End::Dst, 53);
-
-    let daddr = match host {
-        IpAddr::V4(_) => nft_expr!(payload ipv4 daddr),
-        IpAddr::V6(_) => nft_expr!(payload ipv6 daddr),
-    };
-    if chain.get_table().get_family() == ProtoFamily::Inet {
-        check_l3proto(&mut rule, host);
-    }
-
-    rule.add_expr(&daddr);
-    rule.add_expr(&nft_expr!(cmp == host));
+    check_ip(&mut rule, End::Dst, host);
     add_verdict(&mut rule, &Verdict::Accept);
 
     Ok(rule)
 }
 
 fn allow_interface_rule<'a>(
-    chain: &'a Chain<'_>,
+    chain: &'a PolicyChain<'a>,
     direction: Direction,
     iface: &str,
-) -> Result<Rule<'a>> {
-    let mut rule = Rule::new(chain);
+) -> Result<PolicyRule<'a>> {
+    let mut rule = PolicyRule::new(chain);
     check_iface(&mut rule, direction, iface)?;
     add_verdict(&mut rule, &Verdict::Accept);
 
     Ok(rule)
 }
 
-fn check_iface(rule: &mut Rule<'_>, direction: Direction, iface: &str) -> Result<()> {
-    let iface_index = crate::linux::iface_index(iface)
-        .map_err(|e| Error::LookupIfaceIndexError(iface.to_owned(), e))?;
-    rule.add_expr(&match direction {
-        Direction::In => nft_expr!(meta iif),
-        Direction::Out => nft_expr!(meta oif),
-    });
-    rule.add_expr(&nft_expr!(cmp == iface_index));
-    Ok(())
+fn check_iface(rule: &mut PolicyRule<'_>, direction: Direction, iface: &str) -> Result<()> {
+    add_iface(rule, direction, iface, false)
+}
+
+fn check_not_iface(rule: &mut PolicyRule<'_>, direction: Direction, iface: &str) -> Result<()> {
+    add_iface(rule, direction, iface, true)
 }
 
-fn check_not_iface(rule: &mut Rule<'_>, direction: Direction, iface: &str) -> Result<()> {
-    let iface_index = crate::linux::iface_index(iface)
-        .map_err(|e| Error::LookupIfaceIndexError(iface.to_owned(), e))?;
-    rule.add_expr(&match direction {
-        Direction::In => nft_expr!(meta iif),
-        Direction::Out => nft_expr!(meta oif),
+fn add_iface(
+    rule: &mut PolicyRule<'_>,
+    direction: Direction,
+    iface: &str,
+    negate: bool,
+) -> Result<()> {
+    let index = (rule.chain.host.iface_index)(iface)?;
+    rule.add(Statement::Iface {
+        direction,
+        name: iface.to_owned(),
+        index,
+        negate,
     });
-    rule.add_expr(&nft_expr!(cmp != iface_index));
     Ok(())
 }
 
-fn check_net(rule: &mut Rule<'_>, end: End, net: impl Into<IpNetwork>) {
-    let net = net.into();
-    // Must check network layer protocol before loading network layer payload
-    check_l3proto(rule, net.ip());
-
-    rule.add_expr(&match (net, end) {
-        (IpNetwork::V4(_), End::Src) => nft_expr!(payload ipv4 saddr),
-        (IpNetwork::V4(_), End::Dst) => nft_expr!(payload ipv4 daddr),
-        (IpNetwork::V6(_), End::Src) => nft_expr!(payload ipv6 saddr),
-        (IpNetwork::V6(_), End::Dst) => nft_expr!(payload ipv6 daddr),
+fn check_net(rule: &mut PolicyRule<'_>, end: End, net: impl Into<IpNetwork>) {
+    rule.add(Statement::Net {
+        end,
+        net: net.into(),
     });
-    match net {
-        IpNetwork::V4(_) => rule.add_expr(&nft_expr!(bitwise mask net.mask(), xor 0u32)),
-        IpNetwork::V6(_) => rule.add_expr(&nft_expr!(bitwise mask net.mask(), xor &[0u16; 8][..])),
-    };
-    rule.add_expr(&nft_expr!(cmp == net.ip()));
 }
 
-fn check_icmpv6(rule: &mut Rule<'_>, r#type: u8, code: u8) {
-    rule.add_expr(&nft_expr!(meta l4proto));
-    rule.add_expr(&nft_expr!(cmp == libc::IPPROTO_ICMPV6 as u8));
-
-    rule.add_expr(&Payload::Transport(
-        nftnl::expr::TransportHeaderField::Icmpv6(nftnl::expr::Icmpv6HeaderField::Type),
-    ));
-    rule.add_expr(&nft_expr!(cmp == r#type));
-    rule.add_expr(&nftnl::expr::Payload::Transport(
-        nftnl::expr::TransportHeaderField::Icmpv6(nftnl::expr::Icmpv6HeaderField::Code),
-    ));
-    rule.add_expr(&nft_expr!(cmp == code));
+fn check_icmpv6(rule: &mut PolicyRule<'_>, r#type: u8, code: u8) {
+    rule.add(Statement::Icmpv6 { r#type, code });
 }
 
-fn check_endpoint(rule: &mut Rule<'_>, end: End, endpoint: &Endpoint) {
+fn check_endpoint(rule: &mut PolicyRule<'_>, end: End, endpoint: &Endpoint) {
     check_ip(rule, end, endpoint.address.ip());
     check_port(rule, endpoint.protocol, end, endpoint.address.port());
 }
 
-fn check_ip(rule: &mut Rule<'_>, end: End, ip: impl Into<IpAddr>) {
-    let ip = ip.into();
-    // Must check network layer protocol before loading network layer payload
-    check_l3proto(rule, ip);
-
-    rule.add_expr(&match (ip, end) {
-        (IpAddr::V4(..), End::Src) => nft_expr!(payload ipv4 saddr),
-        (IpAddr::V4(..), End::Dst) => nft_expr!(payload ipv4 daddr),
-        (IpAddr::V6(..), End::Src) => nft_expr!(payload ipv6 saddr),
-        (IpAddr::V6(..), End::Dst) => nft_expr!(payload ipv6 daddr),
-    });
-    match ip {
-        IpAddr::V4(addr) => rule.add_expr(&nft_expr!(cmp == addr)),
-        IpAddr::V6(addr) => rule.add_expr(&nft_expr!(cmp == addr)),
-    }
+fn check_ip(rule: &mut PolicyRule<'_>, end: End, ip: impl Into<IpAddr>) {
+    rule.add(Statement::Ip { end, ip: ip.into() });
 }
 
-fn check_port(rule: &mut Rule<'_>, protocol: TransportProtocol, end: End, port: u16) {
-    // Must check transport layer protocol before loading transport layer payload
-    check_l4proto(rule, protocol);
-
-    rule.add_expr(&match (protocol, end) {
-        (TransportProtocol::Udp, End::Src) => nft_expr!(payload udp sport),
-        (TransportProtocol::Udp, End::Dst) => nft_expr!(payload udp dport),
-        (TransportProtocol::Tcp, End::Src) => nft_expr!(payload tcp sport),
-        (TransportProtocol::Tcp, End::Dst) => nft_expr!(payload tcp dport),
+fn check_port(rule: &mut PolicyRule<'_>, protocol: TransportProtocol, end: End, port: u16) {
+    rule.add(Statement::Port {
+        protocol,
+        end,
+        port,
     });
-    rule.add_expr(&nft_expr!(cmp == port.to_be()));
 }
 
-fn check_l3proto(rule: &mut Rule<'_>, ip: IpAddr) {
+fn add_l3proto(rule: &mut Rule<'_>, ip: IpAddr) {
     rule.add_expr(&nft_expr!(meta nfproto));
     rule.add_expr(&nft_expr!(cmp == l3proto(ip)));
 }
@@ -1001,9 +1566,11 @@ fn l3proto(addr: IpAddr) -> u8 {
     }
 }
 
-fn check_l4proto(rule: &mut Rule<'_>, protocol: TransportProtocol) {
-    rule.add_expr(&nft_expr!(meta l4proto));
-    rule.add_expr(&nft_expr!(cmp == l4proto(protocol)));
+fn ip_keyword(addr: IpAddr) -> &'static str {
+    match addr {
+        IpAddr::V4(_) => "ip",
+        IpAddr::V6(_) => "ip6",
+    }
 }
 
 fn l4proto(protocol: TransportProtocol) -> u8 {
@@ -1013,11 +1580,88 @@ fn l4proto(protocol: TransportProtocol) -> u8 {
     }
 }
 
-fn add_verdict(rule: &mut Rule<'_>, verdict: &expr::Verdict) {
+fn l4proto_keyword(protocol: TransportProtocol) -> &'static str {
+    match protocol {
+        TransportProtocol::Udp => "udp",
+        TransportProtocol::Tcp => "tcp",
+    }
+}
+
+/// Matches packets belonging to established connections.
+fn check_established(rule: &mut PolicyRule<'_>) {
+    rule.add(Statement::Established);
+}
+
+/// Matches packets belonging to connections that are excluded from the tunnel.
+fn check_ct_mark(rule: &mut PolicyRule<'_>) {
+    rule.add(Statement::CtMark(split_tunnel::MARK));
+}
+
+fn check_root_user(rule: &mut PolicyRule<'_>) {
+    rule.add(Statement::SkUid(super::ROOT_UID));
+}
+
+fn set_mark(rule: &mut PolicyRule<'_>, fwmark: u32) {
+    rule.add(Statement::SetMark(fwmark));
+}
+
+/// Marks the connection of a matched packet as excluded from the tunnel, and routes it outside of
+/// the tunnel.
+fn add_exclusion_marks(rule: &mut PolicyRule<'_>, fwmark: u32) {
+    rule.add(Statement::SetCtMark(split_tunnel::MARK));
+    set_mark(rule, fwmark);
+}
+
+fn allow_list_network(endpoint: &AllowListEndpoint) -> IpNetwork {
+    // Host bits must be cleared, or the masked address would never match
+    IpNetwork::new(endpoint.network.network(), endpoint.network.prefix())
+        .expect("prefix of a valid network is valid")
+}
+
+/// Loads the ID of the cgroup v2 ancestor at the given level of the cgroup that owns the socket
+/// of a packet into register 1. This is `socket cgroupv2 level <level>` in nft syntax, which
+/// `nftnl` has no expression for.
+struct SocketCgroupV2 {
+    level: u32,
+}
+
+impl SocketCgroupV2 {
+    // Attribute and key values from libnftnl and the kernel UAPI headers
+    const NFTNL_EXPR_SOCKET_KEY: u16 = 1;
+    const NFTNL_EXPR_SOCKET_DREG: u16 = 2;
+    const NFTNL_EXPR_SOCKET_LEVEL: u16 = 3;
+    const NFT_SOCKET_CGROUPV2: u32 = 3;
+    const NFT_REG_1: u32 = 1;
+}
+
+impl expr::Expression for SocketCgroupV2 {
+    fn to_expr(&self, _rule: &Rule<'_>) -> *mut nftnl_sys::nftnl_expr {
+        unsafe {
+            let expr = nftnl_sys::nftnl_expr_alloc(b"socket\0" as *const _ as *const libc::c_char);
+            if expr.is_null() {
+                panic!("libnftnl failed to allocate memory");
+            }
+            nftnl_sys::nftnl_expr_set_u32(
+                expr,
+                Self::NFTNL_EXPR_SOCKET_KEY,
+                Self::NFT_SOCKET_CGROUPV2,
+            );
+            nftnl_sys::nftnl_expr_set_u32(expr, Self::NFTNL_EXPR_SOCKET_DREG, Self::NFT_REG_1);
+            nftnl_sys::nftnl_expr_set_u32(expr, Self::NFTNL_EXPR_SOCKET_LEVEL, self.level);
+            expr
+        }
+    }
+}
+
+fn add_counter(rule: &mut PolicyRule<'_>) {
     if *ADD_COUNTERS {
-        rule.add_expr(&nft_expr!(counter));
+        rule.add(Statement::Counter);
     }
-    rule.add_expr(verdict);
+}
+
+fn add_verdict(rule: &mut PolicyRule<'_>, verdict: &Verdict) {
+    add_counter(rule);
+    rule.add(Statement::Verdict(*verdict));
 }
 
 fn set_src_valid_mark_sysctl() -> io::Result<()> {
@@ -1040,3 +1684,238 @@ fn batch_deprecated_tables(batch: &mut Batch) {
         batch.add(table, nftnl::MsgType::Del);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::Path;
+    use talpid_types::net::{default_lan_networks, AllowedEndpoint};
+
+    const FWMARK: u32 = 0x6d6f6c65;
+
+    /// Renders the ruleset for `policy` and compares it to the golden file `testdata/<name>.nft`.
+    /// Set `UPDATE_GOLDEN_FILES` to update the golden file instead.
+    fn assert_ruleset(name: &str, policy: &FirewallPolicy, excluded_users: &ExcludedUsers) {
+        let table = Table::new(&*TABLE_NAME, ProtoFamily::Inet);
+        let host = HostState {
+            iface_index: |_| Ok(1),
+            cgroup2_exclusion_id: Some(4242),
+        };
+        let ruleset = PolicyBatch::new(&table, host)
+            .render(policy, FWMARK, excluded_users)
+            .unwrap();
+
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/firewall/testdata")
+            .join(format!("{}.nft", name));
+        if env::var_os("UPDATE_GOLDEN_FILES").is_some() {
+            fs::write(&path, ruleset).unwrap();
+            return;
+        }
+        let expected = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            ruleset,
+            expected,
+            "Ruleset does not match {}",
+            path.display()
+        );
+    }
+
+    fn tunnel() -> tunnel::TunnelMetadata {
+        tunnel::TunnelMetadata {
+            interface: "wg0-mullvad".to_owned(),
+            ips: vec![
+                IpAddr::V4(Ipv4Addr::new(10, 64, 0, 2)),
+                "fc00:bbbb:bbbb:bb01::1:2".parse().unwrap(),
+            ],
+            ipv4_gateway: Ipv4Addr::new(10, 64, 0, 1),
+            ipv6_gateway: Some("fc00:bbbb:bbbb:bb01::1".parse().unwrap()),
+        }
+    }
+
+    fn peer_endpoint() -> Endpoint {
+        Endpoint::new(
+            Ipv4Addr::new(185, 213, 154, 68),
+            51820,
+            TransportProtocol::Udp,
+        )
+    }
+
+    fn api_endpoint() -> AllowedEndpoint {
+        AllowedEndpoint {
+            endpoint: Endpoint::new(Ipv4Addr::new(45, 83, 223, 196), 443, TransportProtocol::Tcp),
+        }
+    }
+
+    #[test]
+    fn test_render_blocked() {
+        let policy = FirewallPolicy::Blocked {
+            allow_lan: false,
+            lan_networks: default_lan_networks(),
+            allowed_endpoint: Some(api_endpoint()),
+            allow_list: vec![],
+        };
+        assert_ruleset("blocked", &policy, &ExcludedUsers::default());
+    }
+
+    #[test]
+    fn test_render_blocked_allow_lan() {
+        let policy = FirewallPolicy::Blocked {
+            allow_lan: true,
+            lan_networks: default_lan_networks(),
+            allowed_endpoint: None,
+            allow_list: vec![],
+        };
+        assert_ruleset("blocked_allow_lan", &policy, &ExcludedUsers::default());
+    }
+
+    #[test]
+    fn test_render_blocked_custom_lan_networks() {
+        let policy = FirewallPolicy::Blocked {
+            allow_lan: true,
+            lan_networks: vec![
+                "192.168.0.0/16".parse().unwrap(),
+                "100.64.0.0/10".parse().unwrap(),
+                "198.51.100.23/24".parse().unwrap(),
+            ],
+            allowed_endpoint: None,
+            allow_list: vec![],
+        };
+        assert_ruleset(
+            "blocked_custom_lan_networks",
+            &policy,
+            &ExcludedUsers::default(),
+        );
+    }
+
+    #[test]
+    fn test_render_blocked_allow_list() {
+        let policy = FirewallPolicy::Blocked {
+            allow_lan: false,
+            lan_networks: default_lan_networks(),
+            allowed_endpoint: Some(api_endpoint()),
+            allow_list: vec![
+                AllowListEndpoint {
+                    network: "203.0.113.7/24".parse().unwrap(),
+                    port: 8443,
+                    protocol: TransportProtocol::Tcp,
+                },
+                AllowListEndpoint {
+                    network: "2001:db8::/32".parse().unwrap(),
+                    port: 53,
+                    protocol: TransportProtocol::Udp,
+                },
+            ],
+        };
+        assert_ruleset("blocked_allow_list", &policy, &ExcludedUsers::default());
+    }
+
+    #[test]
+    fn test_render_connecting() {
+        let policy = FirewallPolicy::Connecting {
+            peer_endpoint: peer_endpoint(),
+            tunnel: None,
+            allow_lan: false,
+            lan_networks: default_lan_networks(),
+            allowed_endpoint: api_endpoint(),
+            allowed_tunnel_traffic: AllowedTunnelTraffic::None,
+            allow_list: vec![],
+        };
+        assert_ruleset("connecting", &policy, &ExcludedUsers::default());
+    }
+
+    #[test]
+    fn test_render_connecting_with_tunnel() {
+        let policy = FirewallPolicy::Connecting {
+            peer_endpoint: peer_endpoint(),
+            tunnel: Some(tunnel()),
+            allow_lan: true,
+            lan_networks: default_lan_networks(),
+            allowed_endpoint: api_endpoint(),
+            allowed_tunnel_traffic: AllowedTunnelTraffic::One(Endpoint::new(
+                Ipv4Addr::new(10, 64, 0, 1),
+                1337,
+                TransportProtocol::Tcp,
+            )),
+            allow_list: vec![],
+        };
+        assert_ruleset("connecting_with_tunnel", &policy, &ExcludedUsers::default());
+    }
+
+    #[test]
+    fn test_render_connected() {
+        let policy = FirewallPolicy::Connected {
+            peer_endpoint: peer_endpoint(),
+            tunnel: tunnel(),
+            allow_lan: false,
+            lan_networks: default_lan_networks(),
+            dns_servers: vec![
+                IpAddr::V4(Ipv4Addr::new(10, 64, 0, 1)),
+                IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)),
+            ],
+            allow_list: vec![],
+            inbound_rules: vec![],
+        };
+        let excluded_users = ExcludedUsers {
+            uids: BTreeSet::from([1001]),
+            gids: BTreeSet::from([1002]),
+        };
+        assert_ruleset("connected", &policy, &excluded_users);
+    }
+
+    #[test]
+    fn test_render_connected_allow_list() {
+        let policy = FirewallPolicy::Connected {
+            peer_endpoint: peer_endpoint(),
+            tunnel: tunnel(),
+            allow_lan: false,
+            lan_networks: default_lan_networks(),
+            dns_servers: vec![IpAddr::V4(Ipv4Addr::new(10, 64, 0, 1))],
+            allow_list: vec![
+                AllowListEndpoint {
+                    network: "203.0.113.7/24".parse().unwrap(),
+                    port: 8443,
+                    protocol: TransportProtocol::Tcp,
+                },
+                AllowListEndpoint {
+                    network: "2001:db8::/32".parse().unwrap(),
+                    port: 53,
+                    protocol: TransportProtocol::Udp,
+                },
+            ],
+            inbound_rules: vec![],
+        };
+        assert_ruleset("connected_allow_list", &policy, &ExcludedUsers::default());
+    }
+
+    #[test]
+    fn test_render_connected_inbound_rules() {
+        let policy = FirewallPolicy::Connected {
+            peer_endpoint: peer_endpoint(),
+            tunnel: tunnel(),
+            allow_lan: false,
+            lan_networks: default_lan_networks(),
+            dns_servers: vec![IpAddr::V4(Ipv4Addr::new(10, 64, 0, 1))],
+            allow_list: vec![],
+            inbound_rules: vec![
+                InboundRule {
+                    port: 22,
+                    protocol: TransportProtocol::Tcp,
+                    source: "192.0.2.10/24".parse().unwrap(),
+                    interface: "eth0".to_owned(),
+                },
+                InboundRule {
+                    port: 5353,
+                    protocol: TransportProtocol::Udp,
+                    source: "fd00::/8".parse().unwrap(),
+                    interface: "eth1".to_owned(),
+                },
+            ],
+        };
+        assert_ruleset(
+            "connected_inbound_rules",
+            &policy,
+            &ExcludedUsers::default(),
+        );
+    }
+}
@@ -67,6 +67,7 @@ impl Firewall {
         new_filter_rules.append(&mut self.get_allow_loopback_rules()?);
         new_filter_rules.append(&mut self.get_allow_dhcp_client_rules()?);
         new_filter_rules.append(&mut self.get_allow_ndp_rules()?);
+        new_filter_rules.append(&mut self.get_allow_list_rules(policy.allow_list())?);
         new_filter_rules.append(&mut self.get_policy_specific_rules(&policy)?);
 
         let return_out_rule = self
@@ -118,8 +119,10 @@ impl Firewall {
                 peer_endpoint,
                 tunnel,
                 allow_lan,
+                lan_networks,
                 allowed_endpoint,
                 allowed_tunnel_traffic,
+                ..
             } => {
                 let mut rules = vec![self.get_allow_relay_rule(*peer_endpoint)?];
                 rules.push(self.get_allowed_endpoint_rule(allowed_endpoint.endpoint)?);
@@ -136,7 +139,7 @@ impl Firewall {
                 }
 
                 if *allow_lan {
-                    rules.append(&mut self.get_allow_lan_rules()?);
+                    rules.append(&mut self.get_allow_lan_rules(lan_networks)?);
                 }
                 Ok(rules)
             }
@@ -144,12 +147,18 @@ impl Firewall {
                 peer_endpoint,
                 tunnel,
                 allow_lan,
+                lan_networks,
                 dns_servers,
+                ..
             } => {
                 let mut rules = vec![];
 
                 for server in dns_servers.iter() {
-                    rules.append(&mut self.get_allow_dns_rules_when_connected(&tunnel, *server)?);
+                    rules.append(&mut self.get_allow_dns_rules_when_connected(
+                        &tunnel,
+                        lan_networks,
+                        *server,
+                    )?);
                 }
 
                 rules.push(self.get_allow_relay_rule(*peer_endpoint)?);
@@ -167,13 +176,14 @@ impl Firewall {
                 );
 
                 if *allow_lan {
-                    rules.append(&mut self.get_allow_lan_rules()?);
+                    rules.append(&mut self.get_allow_lan_rules(lan_networks)?);
                 }
 
                 Ok(rules)
             }
             FirewallPolicy::Blocked {
                 allow_lan,
+                lan_networks,
                 allowed_endpoint,
                 ..
             } => {
@@ -185,7 +195,7 @@ impl Firewall {
                 if *allow_lan {
                     // Important to block DNS before allow LAN (so DNS does not leak to the LAN)
                     rules.append(&mut self.get_block_dns_rules()?);
-                    rules.append(&mut self.get_allow_lan_rules()?);
+                    rules.append(&mut self.get_allow_lan_rules(lan_networks)?);
                 }
 
                 Ok(rules)
@@ -196,11 +206,12 @@ impl Firewall {
     fn get_allow_dns_rules_when_connected(
         &self,
         tunnel: &crate::tunnel::TunnelMetadata,
+        lan_networks: &[IpNetwork],
         server: IpAddr,
     ) -> Result<Vec<pfctl::FilterRule>> {
         let mut rules = Vec::with_capacity(4);
 
-        let is_local = super::is_local_address(&server)
+        let is_local = super::is_local_address(&server, lan_networks)
             && server != tunnel.ipv4_gateway
             && !tunnel
                 .ipv6_gateway
@@ -311,6 +322,32 @@ impl Firewall {
             .build()?)
     }
 
+    /// Produces rules that allow traffic to flow to the endpoints in the user's allow list, over
+    /// any interface and by any process. These come before DNS is blocked, so that the allow list
+    /// may contain DNS servers.
+    fn get_allow_list_rules(
+        &self,
+        allow_list: &[net::AllowListEndpoint],
+    ) -> Result<Vec<pfctl::FilterRule>> {
+        let mut rules = Vec::with_capacity(allow_list.len());
+        for endpoint in allow_list {
+            let rule = self
+                .create_rule_builder(FilterRuleAction::Pass)
+                .direction(pfctl::Direction::Out)
+                .quick(true)
+                .to(pfctl::Endpoint::new(
+                    pfctl::Ip::from(endpoint.network),
+                    endpoint.port,
+                ))
+                .proto(as_pfctl_proto(endpoint.protocol))
+                .keep_state(pfctl::StatePolicy::Keep)
+                .tcp_flags(Self::get_tcp_flags())
+                .build()?;
+            rules.push(rule);
+        }
+        Ok(rules)
+    }
+
     fn get_block_dns_rules(&self) -> Result<Vec<pfctl::FilterRule>> {
         let block_tcp_dns_rule = self
             .create_rule_builder(FilterRuleAction::Drop(DropAction::Return))
@@ -391,9 +428,9 @@ impl Firewall {
         Ok(vec![lo0_rule])
     }
 
-    fn get_allow_lan_rules(&self) -> Result<Vec<pfctl::FilterRule>> {
+    fn get_allow_lan_rules(&self, lan_networks: &[IpNetwork]) -> Result<Vec<pfctl::FilterRule>> {
         let mut rules = vec![];
-        for net in &*super::ALLOWED_LAN_NETS {
+        for net in lan_networks {
             let mut rule_builder = self.create_rule_builder(FilterRuleAction::Pass);
             rule_builder.quick(true);
             let allow_out = rule_builder
@@ -6,6 +6,10 @@ use std::{
     fmt,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
 };
+#[cfg(not(target_os = "android"))]
+use talpid_types::net::AllowListEndpoint;
+#[cfg(target_os = "linux")]
+use talpid_types::net::InboundRule;
 use talpid_types::net::{AllowedEndpoint, AllowedTunnelTraffic, Endpoint};
 
 #[cfg(target_os = "macos")]
@@ -27,15 +31,6 @@ mod imp;
 pub use self::imp::Error;
 
 lazy_static! {
-    /// When "allow local network" is enabled the app will allow traffic to and from these networks.
-    pub(crate) static ref ALLOWED_LAN_NETS: [IpNetwork; 6] = [
-        IpNetwork::V4(Ipv4Network::new(Ipv4Addr::new(10, 0, 0, 0), 8).unwrap()),
-        IpNetwork::V4(Ipv4Network::new(Ipv4Addr::new(172, 16, 0, 0), 12).unwrap()),
-        IpNetwork::V4(Ipv4Network::new(Ipv4Addr::new(192, 168, 0, 0), 16).unwrap()),
-        IpNetwork::V4(Ipv4Network::new(Ipv4Addr::new(169, 254, 0, 0), 16).unwrap()),
-        IpNetwork::V6(Ipv6Network::new(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0), 10).unwrap()),
-        IpNetwork::V6(Ipv6Network::new(Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0), 7).unwrap()),
-    ];
     /// When "allow local network" is enabled the app will allow traffic to these networks.
     pub(crate) static ref ALLOWED_LAN_MULTICAST_NETS: [IpNetwork; 8] = [
         // Local network broadcast. Not routable
@@ -79,10 +74,10 @@ const DHCPV6_CLIENT_PORT: u16 = 546;
 #[cfg(all(unix, not(target_os = "android")))]
 const ROOT_UID: u32 = 0;
 
-/// Returns whether an address belongs to a private subnet.
-pub fn is_local_address(address: &IpAddr) -> bool {
+/// Returns whether an address belongs to one of the given LAN networks or is a loopback address.
+pub fn is_local_address(address: &IpAddr, lan_networks: &[IpNetwork]) -> bool {
     let address = *address;
-    (*ALLOWED_LAN_NETS)
+    lan_networks
         .iter()
         .chain(&*LOOPBACK_NETS)
         .any(|net| net.contains(address))
@@ -104,10 +99,15 @@ pub enum FirewallPolicy {
         tunnel: Option<crate::tunnel::TunnelMetadata>,
         /// Flag setting if communication with LAN networks should be possible.
         allow_lan: bool,
+        /// Networks that are considered local.
+        lan_networks: Vec<IpNetwork>,
         /// Host that should be reachable while connecting.
         allowed_endpoint: AllowedEndpoint,
         /// Networks for which to permit in-tunnel traffic.
         allowed_tunnel_traffic: AllowedTunnelTraffic,
+        /// Endpoints that the user has allowed to be reached outside the tunnel.
+        #[cfg(not(target_os = "android"))]
+        allow_list: Vec<AllowListEndpoint>,
         /// A process that is allowed to send packets to the relay.
         #[cfg(windows)]
         relay_client: PathBuf,
@@ -121,9 +121,17 @@ pub enum FirewallPolicy {
         tunnel: crate::tunnel::TunnelMetadata,
         /// Flag setting if communication with LAN networks should be possible.
         allow_lan: bool,
+        /// Networks that are considered local.
+        lan_networks: Vec<IpNetwork>,
         /// Servers that are allowed to respond to DNS requests.
         #[cfg(not(target_os = "android"))]
         dns_servers: Vec<IpAddr>,
+        /// Endpoints that the user has allowed to be reached outside the tunnel.
+        #[cfg(not(target_os = "android"))]
+        allow_list: Vec<AllowListEndpoint>,
+        /// Local ports that may be reached from outside the tunnel.
+        #[cfg(target_os = "linux")]
+        inbound_rules: Vec<InboundRule>,
         /// A process that is allowed to send packets to the relay.
         #[cfg(windows)]
         relay_client: PathBuf,
@@ -133,8 +141,13 @@ pub enum FirewallPolicy {
     Blocked {
         /// Flag setting if communication with LAN networks should be possible.
         allow_lan: bool,
+        /// Networks that are considered local.
+        lan_networks: Vec<IpNetwork>,
         /// Host that should be reachable while in the blocked state.
         allowed_endpoint: Option<AllowedEndpoint>,
+        /// Endpoints that the user has allowed to be reached outside the tunnel.
+        #[cfg(not(target_os = "android"))]
+        allow_list: Vec<AllowListEndpoint>,
         /// Destination port for DNS traffic redirection. Traffic destined to `127.0.0.1:53` will
         /// be redirected to `127.0.0.1:$dns_redirect_port`.
         #[cfg(target_os = "macos")]
@@ -142,6 +155,27 @@ pub enum FirewallPolicy {
     },
 }
 
+impl FirewallPolicy {
+    /// Returns the networks that are considered local.
+    pub fn lan_networks(&self) -> &[IpNetwork] {
+        match self {
+            FirewallPolicy::Connecting { lan_networks, .. }
+            | FirewallPolicy::Connected { lan_networks, .. }
+            | FirewallPolicy::Blocked { lan_networks, .. } => lan_networks,
+        }
+    }
+
+    /// Returns the endpoints that the user has allowed to be reached outside the tunnel.
+    #[cfg(not(target_os = "android"))]
+    pub fn allow_list(&self) -> &[AllowListEndpoint] {
+        match self {
+            FirewallPolicy::Connecting { allow_list, .. }
+            | FirewallPolicy::Connected { allow_list, .. }
+            | FirewallPolicy::Blocked { allow_list, .. } => allow_list,
+        }
+    }
+}
+
 impl fmt::Display for FirewallPolicy {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -214,7 +248,34 @@ impl fmt::Display for FirewallPolicy {
                     .map(|endpoint| -> &dyn std::fmt::Display { endpoint })
                     .unwrap_or(&"none"),
             ),
+        }?;
+        #[cfg(not(target_os = "android"))]
+        if !self.allow_list().is_empty() {
+            write!(
+                f,
+                ". Allow list: {}",
+                self.allow_list()
+                    .iter()
+                    .map(|endpoint| endpoint.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?;
         }
+        #[cfg(target_os = "linux")]
+        if let FirewallPolicy::Connected { inbound_rules, .. } = self {
+            if !inbound_rules.is_empty() {
+                write!(
+                    f,
+                    ". Inbound rules: {}",
+                    inbound_rules
+                        .iter()
+                        .map(|rule| rule.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )?;
+            }
+        }
+        Ok(())
     }
 }
 
@@ -230,10 +291,22 @@ pub struct FirewallArguments {
     pub initial_state: InitialFirewallState,
     /// This argument is required for the blocked state to configure the firewall correctly.
     pub allow_lan: bool,
+    /// Networks that are considered local. Required for the blocked state.
+    pub lan_networks: Vec<IpNetwork>,
+    /// Endpoints that the user has allowed to be reached outside the tunnel. Required for the
+    /// blocked state.
+    #[cfg(not(target_os = "android"))]
+    pub allow_list: Vec<AllowListEndpoint>,
     /// Specifies the firewall mark used to identify traffic that is allowed to be excluded from
     /// the tunnel and _leaked_ during blocked states.
     #[cfg(target_os = "linux")]
     pub fwmark: u32,
+    /// Users whose traffic is excluded from the tunnel.
+    #[cfg(target_os = "linux")]
+    pub excluded_uids: Vec<u32>,
+    /// Groups whose traffic is excluded from the tunnel.
+    #[cfg(target_os = "linux")]
+    pub excluded_gids: Vec<u32>,
 }
 
 /// State to enter during firewall init.
@@ -275,4 +348,19 @@ impl Firewall {
         log::info!("Resetting firewall policy");
         self.inner.reset_policy()
     }
+
+    /// Sets the users and groups whose traffic is excluded from the tunnel. The currently
+    /// enforced `FirewallPolicy`, if any, is updated to match.
+    #[cfg(target_os = "linux")]
+    pub fn set_excluded_users(&mut self, uids: Vec<u32>, gids: Vec<u32>) -> Result<(), Error> {
+        log::info!("Setting excluded users: {:?}, groups: {:?}", uids, gids);
+        self.inner.set_excluded_users(uids, gids)
+    }
+
+    /// Returns the nftables ruleset that `apply_policy` would apply for the given
+    /// `FirewallPolicy`, in nft syntax. Nothing is applied.
+    #[cfg(target_os = "linux")]
+    pub fn dump_policy(&self, policy: &FirewallPolicy) -> Result<String, Error> {
+        self.inner.dump_policy(policy)
+    }
 }
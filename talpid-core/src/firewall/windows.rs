@@ -4,8 +4,9 @@ use std::{ffi::CStr, io, net::IpAddr, path::Path, ptr};
 
 use self::winfw::*;
 use super::{FirewallArguments, FirewallPolicy, InitialFirewallState};
+use ipnetwork::IpNetwork;
 use talpid_types::{
-    net::{AllowedEndpoint, AllowedTunnelTraffic, Endpoint},
+    net::{AllowListEndpoint, AllowedEndpoint, AllowedTunnelTraffic, Endpoint},
     tunnel::FirewallPolicyError,
 };
 use widestring::WideCString;
@@ -51,7 +52,12 @@ pub struct Firewall(());
 impl Firewall {
     pub fn from_args(args: FirewallArguments) -> Result<Self, Error> {
         if let InitialFirewallState::Blocked(allowed_endpoint) = args.initial_state {
-            Self::initialize_blocked(allowed_endpoint, args.allow_lan)
+            Self::initialize_blocked(
+                allowed_endpoint,
+                args.allow_lan,
+                &args.lan_networks,
+                &args.allow_list,
+            )
         } else {
             Self::new()
         }
@@ -74,13 +80,15 @@ impl Firewall {
     fn initialize_blocked(
         allowed_endpoint: AllowedEndpoint,
         allow_lan: bool,
+        lan_networks: &[IpNetwork],
+        allow_list: &[AllowListEndpoint],
     ) -> Result<Self, Error> {
-        let cfg = &WinFwSettings::new(allow_lan);
+        let cfg = WinFwSettingsContainer::new(allow_lan, lan_networks, allow_list);
         let allowed_endpoint = WinFwAllowedEndpointContainer::from(allowed_endpoint);
         unsafe {
             WinFw_InitializeBlocked(
                 WINFW_TIMEOUT_SECONDS,
-                cfg,
+                &cfg.as_settings(),
                 &allowed_endpoint.as_endpoint(),
                 Some(log_sink),
                 LOGGING_CONTEXT.as_ptr(),
@@ -97,15 +105,17 @@ impl Firewall {
                 peer_endpoint,
                 tunnel,
                 allow_lan,
+                lan_networks,
                 allowed_endpoint,
                 allowed_tunnel_traffic,
+                allow_list,
                 relay_client,
             } => {
-                let cfg = &WinFwSettings::new(allow_lan);
+                let cfg = WinFwSettingsContainer::new(allow_lan, &lan_networks, &allow_list);
 
                 self.set_connecting_state(
                     &peer_endpoint,
-                    cfg,
+                    &cfg.as_settings(),
                     &tunnel,
                     &WinFwAllowedEndpointContainer::from(allowed_endpoint).as_endpoint(),
                     &allowed_tunnel_traffic,
@@ -116,19 +126,29 @@ impl Firewall {
                 peer_endpoint,
                 tunnel,
                 allow_lan,
+                lan_networks,
                 dns_servers,
+                allow_list,
                 relay_client,
             } => {
-                let cfg = &WinFwSettings::new(allow_lan);
-                self.set_connected_state(&peer_endpoint, cfg, &tunnel, &dns_servers, &relay_client)
+                let cfg = WinFwSettingsContainer::new(allow_lan, &lan_networks, &allow_list);
+                self.set_connected_state(
+                    &peer_endpoint,
+                    &cfg.as_settings(),
+                    &tunnel,
+                    &dns_servers,
+                    &relay_client,
+                )
             }
             FirewallPolicy::Blocked {
                 allow_lan,
+                lan_networks,
                 allowed_endpoint,
+                allow_list,
             } => {
-                let cfg = &WinFwSettings::new(allow_lan);
+                let cfg = WinFwSettingsContainer::new(allow_lan, &lan_networks, &allow_list);
                 self.set_blocked_state(
-                    cfg,
+                    &cfg.as_settings(),
                     allowed_endpoint.map(WinFwAllowedEndpointContainer::from),
                 )
             }
@@ -143,7 +163,7 @@ impl Firewall {
     fn set_connecting_state(
         &mut self,
         endpoint: &Endpoint,
-        winfw_settings: &WinFwSettings,
+        winfw_settings: &WinFwSettings<'_>,
         tunnel_metadata: &Option<TunnelMetadata>,
         allowed_endpoint: &WinFwAllowedEndpoint<'_>,
         allowed_tunnel_traffic: &AllowedTunnelTraffic,
@@ -241,7 +261,7 @@ impl Firewall {
     fn set_connected_state(
         &mut self,
         endpoint: &Endpoint,
-        winfw_settings: &WinFwSettings,
+        winfw_settings: &WinFwSettings<'_>,
         tunnel_metadata: &TunnelMetadata,
         dns_servers: &[IpAddr],
         relay_client: &Path,
@@ -291,7 +311,7 @@ impl Firewall {
 
     fn set_blocked_state(
         &mut self,
-        winfw_settings: &WinFwSettings,
+        winfw_settings: &WinFwSettings<'_>,
         allowed_endpoint: Option<WinFwAllowedEndpointContainer>,
     ) -> Result<(), Error> {
         log::trace!("Applying 'blocked' firewall policy");
@@ -408,7 +428,10 @@ fn multibyte_to_wide(mb_string: &CStr, codepage: u32) -> Result<Vec<u16>, io::Er
 
 #[allow(non_snake_case)]
 mod winfw {
-    use super::{widestring_ip, AllowedEndpoint, AllowedTunnelTraffic, Error, WideCString};
+    use super::{
+        widestring_ip, AllowListEndpoint, AllowedEndpoint, AllowedTunnelTraffic, Error, IpNetwork,
+        WideCString,
+    };
     use libc;
     use talpid_types::net::TransportProtocol;
 
@@ -521,21 +544,97 @@ mod winfw {
         }
     }
 
-    #[repr(C)]
-    pub struct WinFwSettings {
-        permitDhcp: bool,
-        permitLan: bool,
+    pub struct WinFwSettingsContainer {
+        permit_lan: bool,
+        _lan_network_ips: Box<[WideCString]>,
+        lan_networks: Box<[WinFwNetwork]>,
+        _allow_list_ips: Box<[WideCString]>,
+        allow_list: Box<[WinFwAllowListEndpoint]>,
     }
 
-    impl WinFwSettings {
-        pub fn new(permit_lan: bool) -> WinFwSettings {
+    impl WinFwSettingsContainer {
+        pub fn new(
+            permit_lan: bool,
+            lan_networks: &[IpNetwork],
+            allow_list: &[AllowListEndpoint],
+        ) -> Self {
+            let lan_network_ips = lan_networks
+                .iter()
+                .map(|network| widestring_ip(network.network()))
+                .collect::<Box<_>>();
+            let lan_networks = lan_networks
+                .iter()
+                .zip(lan_network_ips.iter())
+                .map(|(network, ip)| WinFwNetwork {
+                    ip: ip.as_ptr(),
+                    prefixLength: network.prefix(),
+                })
+                .collect::<Box<_>>();
+
+            let allow_list_ips = allow_list
+                .iter()
+                .map(|endpoint| widestring_ip(endpoint.network.ip()))
+                .collect::<Box<_>>();
+            let allow_list = allow_list
+                .iter()
+                .zip(allow_list_ips.iter())
+                .map(|(endpoint, ip)| WinFwAllowListEndpoint {
+                    ip: ip.as_ptr(),
+                    prefixLength: endpoint.network.prefix(),
+                    port: endpoint.port,
+                    protocol: WinFwProt::from(endpoint.protocol),
+                })
+                .collect::<Box<_>>();
+
+            WinFwSettingsContainer {
+                permit_lan,
+                _lan_network_ips: lan_network_ips,
+                lan_networks,
+                _allow_list_ips: allow_list_ips,
+                allow_list,
+            }
+        }
+
+        pub fn as_settings(&self) -> WinFwSettings<'_> {
             WinFwSettings {
                 permitDhcp: true,
-                permitLan: permit_lan,
+                permitLan: self.permit_lan,
+                lanNetworks: self.lan_networks.as_ptr(),
+                numLanNetworks: self.lan_networks.len() as u32,
+                allowList: self.allow_list.as_ptr(),
+                numAllowList: self.allow_list.len() as u32,
+
+                _phantom: std::marker::PhantomData,
             }
         }
     }
 
+    #[repr(C)]
+    pub struct WinFwNetwork {
+        ip: *const libc::wchar_t,
+        prefixLength: u8,
+    }
+
+    #[repr(C)]
+    pub struct WinFwAllowListEndpoint {
+        ip: *const libc::wchar_t,
+        prefixLength: u8,
+        port: u16,
+        protocol: WinFwProt,
+    }
+
+    #[repr(C)]
+    pub struct WinFwSettings<'a> {
+        permitDhcp: bool,
+        permitLan: bool,
+        lanNetworks: *const WinFwNetwork,
+        numLanNetworks: u32,
+        allowList: *const WinFwAllowListEndpoint,
+        numAllowList: u32,
+
+        _phantom: std::marker::PhantomData<&'a WinFwSettingsContainer>,
+    }
+
     #[allow(dead_code)]
     #[repr(u32)]
     #[derive(Clone, Copy)]
@@ -586,7 +685,7 @@ mod winfw {
         #[link_name = "WinFw_InitializeBlocked"]
         pub fn WinFw_InitializeBlocked(
             timeout: libc::c_uint,
-            settings: &WinFwSettings,
+            settings: &WinFwSettings<'_>,
             allowed_endpoint: *const WinFwAllowedEndpoint<'_>,
             sink: Option<LogSink>,
             sink_context: *const u8,
@@ -597,7 +696,7 @@ mod winfw {
 
         #[link_name = "WinFw_ApplyPolicyConnecting"]
         pub fn WinFw_ApplyPolicyConnecting(
-            settings: &WinFwSettings,
+            settings: &WinFwSettings<'_>,
             relay: &WinFwEndpoint,
             relayClient: *const libc::wchar_t,
             tunnelIfaceAlias: *const libc::wchar_t,
@@ -607,7 +706,7 @@ mod winfw {
 
         #[link_name = "WinFw_ApplyPolicyConnected"]
         pub fn WinFw_ApplyPolicyConnected(
-            settings: &WinFwSettings,
+            settings: &WinFwSettings<'_>,
             relay: &WinFwEndpoint,
             relayClient: *const libc::wchar_t,
             tunnelIfaceAlias: *const libc::wchar_t,
@@ -619,7 +718,7 @@ mod winfw {
 
         #[link_name = "WinFw_ApplyPolicyBlocked"]
         pub fn WinFw_ApplyPolicyBlocked(
-            settings: &WinFwSettings,
+            settings: &WinFwSettings<'_>,
             allowed_endpoint: *const WinFwAllowedEndpoint<'_>,
         ) -> WinFwPolicyStatus;
 
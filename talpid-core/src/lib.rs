@@ -43,6 +43,10 @@ pub mod future_retry;
 #[cfg(target_os = "linux")]
 mod linux;
 
+/// A local DNS server that forwards queries to encrypted DNS servers
+#[cfg(not(target_os = "android"))]
+pub mod dns_forwarder;
+
 /// A resolver that's controlled by the tunnel state machine
 #[cfg(target_os = "macos")]
 pub mod resolver;
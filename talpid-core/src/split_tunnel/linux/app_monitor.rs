@@ -0,0 +1,292 @@
+//! Used to exclude processes from the tunnel based on the paths of their executables. New
+//! processes are found through exec events from the kernel, and by periodically scanning `/proc`
+//! in case events are lost or unavailable. Subprocesses of excluded processes are excluded as
+//! well, since they inherit the cgroup of their parent.
+use super::{proc_events::ExecEvents, PidManager};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    fs, io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+use talpid_types::ErrorExt;
+
+/// How often to look for new processes to exclude if exec events are unavailable.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How often to look for new processes to exclude that exec events may have missed.
+const SCAN_INTERVAL: Duration = Duration::from_secs(30);
+
+enum Message {
+    SetPaths(HashSet<PathBuf>),
+    /// The given processes have executed a new program.
+    Exec(Vec<i32>),
+    /// Exec events were lost, so running processes must be scanned.
+    Scan,
+    /// No more exec events will be received.
+    ExecEventsStopped,
+}
+
+/// Moves processes started from a set of executables into the cgroup for excluded processes.
+/// The monitor is stopped when this is dropped.
+pub struct ExcludedAppsMonitor {
+    tx: mpsc::Sender<Message>,
+}
+
+impl ExcludedAppsMonitor {
+    /// Starts excluding processes started from any of the given executables, including those
+    /// that are already running.
+    pub fn spawn(pid_manager: PidManager, paths: HashSet<PathBuf>) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let scan_interval = match ExecEvents::subscribe() {
+            Ok(events) => {
+                let tx = tx.clone();
+                thread::spawn(move || forward_exec_events(events, tx));
+                SCAN_INTERVAL
+            }
+            Err(error) => {
+                log::warn!(
+                    "{}",
+                    error.display_chain_with_msg(
+                        "Failed to subscribe to exec events. Polling for new processes"
+                    )
+                );
+                POLL_INTERVAL
+            }
+        };
+        let monitor = Monitor {
+            pid_manager,
+            paths: resolve_paths(paths),
+            excluded: HashMap::new(),
+            scan_interval,
+        };
+        thread::spawn(move || monitor.run(rx));
+        ExcludedAppsMonitor { tx }
+    }
+
+    /// Sets the executables to exclude. Processes that were excluded because their executables
+    /// were in the previous set are moved back into the tunnel.
+    pub fn set_paths(&self, paths: HashSet<PathBuf>) {
+        if self.tx.send(Message::SetPaths(paths)).is_err() {
+            log::error!("Excluded apps monitor has stopped");
+        }
+    }
+}
+
+struct Monitor {
+    pid_manager: PidManager,
+    paths: HashSet<PathBuf>,
+    /// Processes that were excluded by the monitor, and their executables.
+    excluded: HashMap<i32, PathBuf>,
+    scan_interval: Duration,
+}
+
+impl Monitor {
+    fn run(mut self, rx: mpsc::Receiver<Message>) {
+        loop {
+            self.exclude_new_processes();
+            loop {
+                match rx.recv_timeout(self.scan_interval) {
+                    Ok(Message::Exec(pids)) => {
+                        self.exclude_executed_processes(pids);
+                        continue;
+                    }
+                    Ok(Message::SetPaths(paths)) => self.set_paths(paths),
+                    Ok(Message::Scan) => (),
+                    Ok(Message::ExecEventsStopped) => self.scan_interval = POLL_INTERVAL,
+                    Err(mpsc::RecvTimeoutError::Timeout) => (),
+                    Err(mpsc::RecvTimeoutError::Disconnected) => {
+                        log::trace!("Stopping excluded apps monitor");
+                        return;
+                    }
+                }
+                break;
+            }
+        }
+    }
+
+    fn exclude_executed_processes(&mut self, pids: Vec<i32>) {
+        if self.paths.is_empty() {
+            return;
+        }
+        for pid in pids {
+            // This fails if the process has already exited
+            if let Ok(exe) = process_executable(&Path::new("/proc").join(pid.to_string())) {
+                self.exclude(pid, exe);
+            }
+        }
+    }
+
+    fn exclude_new_processes(&mut self) {
+        if self.paths.is_empty() {
+            return;
+        }
+        let processes = match list_processes() {
+            Ok(processes) => processes,
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to list running processes")
+                );
+                return;
+            }
+        };
+
+        // Forget processes that have exited, since their PIDs may be reused
+        self.excluded
+            .retain(|pid, exe| processes.get(pid) == Some(exe));
+
+        for (pid, exe) in processes {
+            self.exclude(pid, exe);
+        }
+    }
+
+    /// Excludes a process if it runs one of the executables and has not already been excluded.
+    fn exclude(&mut self, pid: i32, exe: PathBuf) {
+        if self.excluded.get(&pid) == Some(&exe) || !self.paths.contains(&exe) {
+            return;
+        }
+        match self.pid_manager.add(pid) {
+            Ok(()) => log::debug!("Excluding process {} ({})", pid, exe.display()),
+            Err(error) => log::error!(
+                "{}",
+                error.display_chain_with_msg(&format!("Failed to exclude process {pid}"))
+            ),
+        }
+        // Failures are not retried, since they are unlikely to succeed on the next attempt
+        self.excluded.insert(pid, exe);
+    }
+
+    fn set_paths(&mut self, paths: HashSet<PathBuf>) {
+        self.paths = resolve_paths(paths);
+
+        let paths = &self.paths;
+        let pid_manager = &self.pid_manager;
+        self.excluded.retain(|pid, exe| {
+            if paths.contains(exe) {
+                return true;
+            }
+            if let Err(error) = pid_manager.remove(*pid) {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg(&format!("Failed to include process {pid}"))
+                );
+            }
+            false
+        });
+    }
+}
+
+/// Returns the PIDs and executables of all running processes.
+fn list_processes() -> io::Result<HashMap<i32, PathBuf>> {
+    let mut processes = HashMap::new();
+    for entry in fs::read_dir("/proc")? {
+        let entry = entry?;
+        let pid = match entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            Some(pid) => pid,
+            None => continue,
+        };
+        // This fails for kernel threads and for processes that have exited
+        if let Ok(exe) = process_executable(&entry.path()) {
+            processes.insert(pid, exe);
+        }
+    }
+    Ok(processes)
+}
+
+/// Returns the executable of the process with the given `/proc/<pid>` directory. If the file
+/// has been removed or replaced since the process started, e.g. by an upgrade, the kernel
+/// appends " (deleted)" to the path. That is removed so that the process still matches.
+fn process_executable(proc_dir: &Path) -> io::Result<PathBuf> {
+    fs::read_link(proc_dir.join("exe")).map(strip_deleted_suffix)
+}
+
+fn strip_deleted_suffix(exe: PathBuf) -> PathBuf {
+    match exe.as_os_str().as_bytes().strip_suffix(b" (deleted)") {
+        Some(path) => PathBuf::from(OsStr::from_bytes(path)),
+        None => exe,
+    }
+}
+
+/// Forwards exec events to the monitor until it stops.
+fn forward_exec_events(events: ExecEvents, tx: mpsc::Sender<Message>) {
+    loop {
+        let message = match events.recv() {
+            Ok(pids) if pids.is_empty() => continue,
+            Ok(pids) => Message::Exec(pids),
+            Err(error) if error.raw_os_error() == Some(libc::ENOBUFS) => {
+                log::debug!("Exec events were lost");
+                Message::Scan
+            }
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to receive exec events")
+                );
+                let _ = tx.send(Message::ExecEventsStopped);
+                return;
+            }
+        };
+        if tx.send(message).is_err() {
+            return;
+        }
+    }
+}
+
+/// Resolves symbolic links in the given paths, since `/proc/<pid>/exe` refers to the actual
+/// executable.
+fn resolve_paths(paths: HashSet<PathBuf>) -> HashSet<PathBuf> {
+    paths
+        .into_iter()
+        .map(|path| fs::canonicalize(&path).unwrap_or(path))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_strip_deleted_suffix() {
+        assert_eq!(
+            strip_deleted_suffix(PathBuf::from("/usr/bin/app (deleted)")),
+            Path::new("/usr/bin/app")
+        );
+        assert_eq!(
+            strip_deleted_suffix(PathBuf::from("/usr/bin/app")),
+            Path::new("/usr/bin/app")
+        );
+        assert_eq!(
+            strip_deleted_suffix(PathBuf::from("/opt/app (deleted)/app")),
+            Path::new("/opt/app (deleted)/app")
+        );
+    }
+
+    #[test]
+    fn test_match_process_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("app");
+        fs::write(&exe, b"").unwrap();
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&exe, &link).unwrap();
+
+        // Paths are compared with the target of /proc/<pid>/exe, so links must be resolved
+        let paths = resolve_paths(HashSet::from([link]));
+        let exe = fs::canonicalize(&exe).unwrap();
+        assert!(paths.contains(&exe));
+
+        let proc_dir = dir.path().join("proc");
+        fs::create_dir(&proc_dir).unwrap();
+        let mut deleted_exe = exe.into_os_string();
+        deleted_exe.push(" (deleted)");
+        std::os::unix::fs::symlink(&deleted_exe, proc_dir.join("exe")).unwrap();
+        assert!(paths.contains(&process_executable(&proc_dir).unwrap()));
+    }
+}
@@ -0,0 +1,291 @@
+mod app_monitor;
+mod proc_events;
+
+pub use app_monitor::ExcludedAppsMonitor;
+
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use talpid_types::cgroup::{find_cgroup2_mount, find_net_cls_mount, SPLIT_TUNNEL_CGROUP_NAME};
+
+const DEFAULT_NET_CLS_DIR: &str = "/sys/fs/cgroup/net_cls";
+const NET_CLS_DIR_OVERRIDE_ENV_VAR: &str = "TALPID_NET_CLS_MOUNT_DIR";
+
+/// Identifies packets coming from the cgroup.
+/// This should be an arbitrary but unique integer.
+pub const NET_CLS_CLASSID: u32 = 0x4d9f41;
+/// Value used to mark packets and associated connections.
+/// This should be an arbitrary but unique integer.
+pub const MARK: i32 = 0xf41;
+/// Depth of the group for excluded processes in the cgroup v2 hierarchy.
+pub const CGROUP2_EXCLUSION_LEVEL: u32 = 1;
+/// Path of the group for excluded processes, relative to the root of the cgroup v2 hierarchy.
+pub const CGROUP2_EXCLUSION_PATH: &str = SPLIT_TUNNEL_CGROUP_NAME;
+
+/// Errors related to split tunneling.
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Unable to create cgroup.
+    #[error(display = "Unable to initialize net_cls cgroup instance")]
+    InitNetClsCGroup(#[error(source)] nix::Error),
+
+    /// Unable to create cgroup.
+    #[error(display = "Unable to create cgroup for excluded processes")]
+    CreateCGroup(#[error(source)] io::Error),
+
+    /// Unable to set class ID for cgroup.
+    #[error(display = "Unable to set cgroup class ID")]
+    SetCGroupClassId(#[error(source)] io::Error),
+
+    /// Unable to add PID to cgroup.procs.
+    #[error(display = "Unable to add PID to cgroup.procs")]
+    AddCGroupPid(#[error(source)] io::Error),
+
+    /// Unable to remove PID to cgroup.procs.
+    #[error(display = "Unable to remove PID from cgroup")]
+    RemoveCGroupPid(#[error(source)] io::Error),
+
+    /// Unable to read cgroup.procs.
+    #[error(display = "Unable to obtain PIDs from cgroup.procs")]
+    ListCGroupPids(#[error(source)] io::Error),
+
+    /// Unable to read /proc/mounts
+    #[error(display = "Failed to read /proc/mounts")]
+    ListMounts(#[error(source)] io::Error),
+}
+
+/// The cgroup hierarchy that excluded processes are tracked in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CGroupVersion {
+    /// Packets are identified by the class ID of the `net_cls` controller.
+    V1NetCls,
+    /// Packets are identified by the cgroup v2 path of their socket.
+    V2,
+}
+
+/// Manages PIDs in the Linux Cgroup excluded from the VPN tunnel.
+#[derive(Clone)]
+pub struct PidManager {
+    cgroup_root: PathBuf,
+    version: CGroupVersion,
+    /// The cgroups that excluded processes were in before they were added, relative to
+    /// `cgroup_root`. They are moved back there when they are removed.
+    original_cgroups: Arc<Mutex<HashMap<i32, PathBuf>>>,
+}
+
+impl PidManager {
+    /// Creates a new PID Cgroup manager.
+    ///
+    /// Finds the corresponding Cgroup to use. An existing `net_cls` controller is preferred, and
+    /// the cgroup v2 hierarchy is used if there is none. If neither is mounted, a `net_cls`
+    /// filesystem is mounted.
+    pub fn new() -> Result<PidManager, Error> {
+        let manager = Self::create_cgroup()?;
+        log::debug!(
+            "Using {:?} cgroup at {} for split tunneling",
+            manager.version,
+            manager.cgroup_root.display()
+        );
+        manager.setup_exclusion_group()?;
+        Ok(manager)
+    }
+
+    /// Set up cgroup used to track PIDs for split tunneling.
+    fn create_cgroup() -> Result<PidManager, Error> {
+        if let Some(net_cls_path) = find_net_cls_mount().map_err(Error::ListMounts)? {
+            return Ok(PidManager {
+                cgroup_root: net_cls_path,
+                version: CGroupVersion::V1NetCls,
+                original_cgroups: Arc::default(),
+            });
+        }
+        if let Some(cgroup2_path) = find_cgroup2_mount().map_err(Error::ListMounts)? {
+            return Ok(PidManager {
+                cgroup_root: cgroup2_path,
+                version: CGroupVersion::V2,
+                original_cgroups: Arc::default(),
+            });
+        }
+
+        let net_cls_dir = env::var(NET_CLS_DIR_OVERRIDE_ENV_VAR)
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| PathBuf::from(DEFAULT_NET_CLS_DIR));
+
+        if !net_cls_dir.exists() {
+            fs::create_dir_all(&net_cls_dir).map_err(Error::CreateCGroup)?;
+        }
+
+        // https://www.kernel.org/doc/Documentation/cgroup-v1/net_cls.txt
+        nix::mount::mount(
+            Some("net_cls"),
+            &net_cls_dir,
+            Some("cgroup"),
+            nix::mount::MsFlags::empty(),
+            Some("net_cls"),
+        )
+        .map_err(Error::InitNetClsCGroup)?;
+
+        Ok(PidManager {
+            cgroup_root: net_cls_dir,
+            version: CGroupVersion::V1NetCls,
+            original_cgroups: Arc::default(),
+        })
+    }
+
+    fn setup_exclusion_group(&self) -> Result<(), Error> {
+        let exclusions_dir = self.cgroup_root.join(SPLIT_TUNNEL_CGROUP_NAME);
+        if !exclusions_dir.exists() {
+            fs::create_dir(exclusions_dir.clone()).map_err(Error::CreateCGroup)?;
+        }
+
+        if self.version == CGroupVersion::V2 {
+            return Ok(());
+        }
+
+        let classid_path = exclusions_dir.join("net_cls.classid");
+        fs::write(classid_path, NET_CLS_CLASSID.to_string().as_bytes())
+            .map_err(Error::SetCGroupClassId)
+    }
+
+    /// Add a PID to the Cgroup to have it excluded from the tunnel.
+    pub fn add(&self, pid: i32) -> Result<(), Error> {
+        let original_cgroup = self
+            .cgroup_of_process(pid)
+            .filter(|cgroup| cgroup != Path::new(SPLIT_TUNNEL_CGROUP_NAME));
+
+        Self::write_pid(&self.cgroup_root.join(SPLIT_TUNNEL_CGROUP_NAME), pid)
+            .map_err(Error::AddCGroupPid)?;
+
+        if let Some(cgroup) = original_cgroup {
+            self.original_cgroups.lock().insert(pid, cgroup);
+        }
+        Ok(())
+    }
+
+    /// Remove a PID from the Cgroup to have it included in the tunnel. The process is moved back
+    /// to the cgroup it was in when it was added, or to the root cgroup if that is unknown.
+    pub fn remove(&self, pid: i32) -> Result<(), Error> {
+        let original_cgroup = self.original_cgroups.lock().remove(&pid);
+        if let Some(cgroup) = original_cgroup {
+            match Self::write_pid(&self.cgroup_root.join(&cgroup), pid) {
+                Ok(()) => return Ok(()),
+                // The cgroup may have been removed, or may no longer accept processes
+                Err(error) => log::debug!(
+                    "Failed to restore cgroup {} of process {}: {}",
+                    cgroup.display(),
+                    pid,
+                    error
+                ),
+            }
+        }
+        Self::write_pid(&self.cgroup_root, pid).map_err(Error::RemoveCGroupPid)
+    }
+
+    fn write_pid(cgroup_dir: &Path, pid: i32) -> io::Result<()> {
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .open(cgroup_dir.join("cgroup.procs"))?;
+        file.write_all(pid.to_string().as_bytes())
+    }
+
+    /// Returns the cgroup of a process in the hierarchy that is used, relative to its root.
+    fn cgroup_of_process(&self, pid: i32) -> Option<PathBuf> {
+        let cgroups = fs::read_to_string(format!("/proc/{pid}/cgroup")).ok()?;
+        parse_cgroup_path(&cgroups, self.version)
+    }
+
+    /// Return a list of all PIDs currently in the Cgroup excluded from the tunnel.
+    pub fn list(&self) -> Result<Vec<i32>, Error> {
+        let exclusions_path = self
+            .cgroup_root
+            .join(SPLIT_TUNNEL_CGROUP_NAME)
+            .join("cgroup.procs");
+
+        let file = fs::File::open(exclusions_path).map_err(Error::ListCGroupPids)?;
+
+        let result: Result<Vec<i32>, io::Error> = BufReader::new(file)
+            .lines()
+            .map(|line| {
+                line.and_then(|v| {
+                    v.parse()
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+                })
+            })
+            .collect();
+        result.map_err(Error::ListCGroupPids)
+    }
+
+    /// Removes all PIDs from the Cgroup.
+    pub fn clear(&self) -> Result<(), Error> {
+        // TODO: reuse file handle
+        let pids = self.list()?;
+
+        for pid in pids {
+            self.remove(pid)?;
+        }
+        // Forget processes that have left the cgroup by other means
+        self.original_cgroups.lock().clear();
+
+        Ok(())
+    }
+}
+
+/// Finds the path of the cgroup in the given hierarchy in the contents of `/proc/<pid>/cgroup`.
+/// Each line of it has the format `hierarchy-ID:controllers:path`, where the controllers are
+/// empty for the cgroup v2 hierarchy.
+fn parse_cgroup_path(cgroups: &str, version: CGroupVersion) -> Option<PathBuf> {
+    cgroups.lines().find_map(|line| {
+        let mut fields = line.splitn(3, ':');
+        let _hierarchy_id = fields.next()?;
+        let controllers = fields.next()?;
+        let path = fields.next()?;
+        let in_hierarchy = match version {
+            CGroupVersion::V1NetCls => controllers.split(',').any(|c| c == "net_cls"),
+            CGroupVersion::V2 => controllers.is_empty(),
+        };
+        in_hierarchy.then(|| PathBuf::from(path.trim_start_matches('/')))
+    })
+}
+
+/// Returns the ID of the cgroup v2 group for excluded processes, if it exists. This is used to
+/// match sockets of excluded processes in the firewall.
+pub fn cgroup2_exclusion_id() -> Option<u64> {
+    let cgroup_root = find_cgroup2_mount().ok()??;
+    // The ID of a cgroup v2 group is the inode number of its directory
+    fs::metadata(cgroup_root.join(SPLIT_TUNNEL_CGROUP_NAME))
+        .ok()
+        .map(|metadata| metadata.ino())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CGROUPS: &str = "12:net_cls,net_prio:/\n\
+        3:cpu,cpuacct:/user.slice\n\
+        0::/user.slice/user-1000.slice/session-2.scope\n";
+
+    #[test]
+    fn test_parse_cgroup_path() {
+        assert_eq!(
+            parse_cgroup_path(CGROUPS, CGroupVersion::V2),
+            Some(PathBuf::from("user.slice/user-1000.slice/session-2.scope"))
+        );
+        assert_eq!(
+            parse_cgroup_path(CGROUPS, CGroupVersion::V1NetCls),
+            Some(PathBuf::from(""))
+        );
+        assert_eq!(
+            parse_cgroup_path("3:cpu,cpuacct:/user.slice\n", CGroupVersion::V2),
+            None
+        );
+    }
+}
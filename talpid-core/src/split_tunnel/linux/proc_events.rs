@@ -0,0 +1,185 @@
+//! Receives notifications when processes execute a new program, from the process events
+//! connector of the kernel. See `linux/connector.h` and `linux/cn_proc.h`.
+use std::{io, mem, os::unix::io::RawFd};
+
+const CN_IDX_PROC: u32 = 1;
+const CN_VAL_PROC: u32 = 1;
+const PROC_CN_MCAST_LISTEN: u32 = 1;
+const PROC_EVENT_EXEC: u32 = 2;
+
+/// Size of `struct nlmsghdr`.
+const NLMSG_HDR_LEN: usize = 16;
+/// Size of `struct cn_msg`, which precedes the `struct proc_event`.
+const CN_MSG_LEN: usize = 20;
+/// Offset of `event_data.exec.process_tgid` in `struct proc_event`.
+const EXEC_TGID_OFFSET: usize = 20;
+
+/// A subscription to exec events. This requires `CAP_NET_ADMIN`.
+pub struct ExecEvents {
+    fd: RawFd,
+}
+
+impl ExecEvents {
+    pub fn subscribe() -> io::Result<Self> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+                libc::NETLINK_CONNECTOR,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let events = ExecEvents { fd };
+
+        let mut address: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        address.nl_groups = CN_IDX_PROC;
+        let result = unsafe {
+            libc::bind(
+                fd,
+                &address as *const libc::sockaddr_nl as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let request = listen_request();
+        let result = unsafe {
+            libc::send(
+                fd,
+                request.as_ptr() as *const libc::c_void,
+                request.len(),
+                0,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(events)
+    }
+
+    /// Blocks until one or more process events are received, and returns the PIDs of the
+    /// processes that executed a new program, if any. Fails with `ENOBUFS` if events were lost.
+    pub fn recv(&self) -> io::Result<Vec<i32>> {
+        let mut buffer = [0u8; 4096];
+        let len = unsafe {
+            libc::recv(
+                self.fd,
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+                0,
+            )
+        };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(parse_exec_events(&buffer[..len as usize]))
+    }
+}
+
+impl Drop for ExecEvents {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+/// Returns a netlink message that subscribes to process events.
+fn listen_request() -> Vec<u8> {
+    let len = NLMSG_HDR_LEN + CN_MSG_LEN + mem::size_of::<u32>();
+    let mut request = Vec::with_capacity(len);
+    // struct nlmsghdr
+    request.extend_from_slice(&(len as u32).to_ne_bytes());
+    request.extend_from_slice(&(libc::NLMSG_DONE as u16).to_ne_bytes());
+    request.extend_from_slice(&0u16.to_ne_bytes());
+    request.extend_from_slice(&0u32.to_ne_bytes());
+    request.extend_from_slice(&0u32.to_ne_bytes());
+    // struct cn_msg
+    request.extend_from_slice(&CN_IDX_PROC.to_ne_bytes());
+    request.extend_from_slice(&CN_VAL_PROC.to_ne_bytes());
+    request.extend_from_slice(&0u32.to_ne_bytes());
+    request.extend_from_slice(&0u32.to_ne_bytes());
+    request.extend_from_slice(&(mem::size_of::<u32>() as u16).to_ne_bytes());
+    request.extend_from_slice(&0u16.to_ne_bytes());
+    // enum proc_cn_mcast_op
+    request.extend_from_slice(&PROC_CN_MCAST_LISTEN.to_ne_bytes());
+    request
+}
+
+/// Returns the PIDs of all exec events in a buffer of netlink messages.
+fn parse_exec_events(buffer: &[u8]) -> Vec<i32> {
+    let mut pids = vec![];
+    let mut offset = 0;
+    while let Some(message_len) = read_u32(buffer, offset) {
+        let message_len = message_len as usize;
+        if message_len < NLMSG_HDR_LEN || offset + message_len > buffer.len() {
+            break;
+        }
+        let message = &buffer[offset + NLMSG_HDR_LEN..offset + message_len];
+        if let Some(pid) = parse_exec_event(message) {
+            pids.push(pid);
+        }
+        // Messages are aligned to 4 bytes
+        offset += (message_len + 3) & !3;
+    }
+    pids
+}
+
+fn parse_exec_event(message: &[u8]) -> Option<i32> {
+    if read_u32(message, 0)? != CN_IDX_PROC || read_u32(message, 4)? != CN_VAL_PROC {
+        return None;
+    }
+    let event = message.get(CN_MSG_LEN..)?;
+    if read_u32(event, 0)? != PROC_EVENT_EXEC {
+        return None;
+    }
+    // Events are reported for threads, so the thread group ID identifies the process
+    read_u32(event, EXEC_TGID_OFFSET).map(|tgid| tgid as i32)
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> Option<u32> {
+    let bytes = buffer.get(offset..offset + mem::size_of::<u32>())?;
+    Some(u32::from_ne_bytes(bytes.try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn event_message(what: u32, pid: u32, tgid: u32) -> Vec<u8> {
+        let mut event = vec![];
+        event.extend_from_slice(&what.to_ne_bytes());
+        // cpu and timestamp_ns
+        event.extend_from_slice(&[0u8; 12]);
+        event.extend_from_slice(&pid.to_ne_bytes());
+        event.extend_from_slice(&tgid.to_ne_bytes());
+
+        let len = NLMSG_HDR_LEN + CN_MSG_LEN + event.len();
+        let mut message = vec![];
+        message.extend_from_slice(&(len as u32).to_ne_bytes());
+        message.extend_from_slice(&[0u8; 12]);
+        message.extend_from_slice(&CN_IDX_PROC.to_ne_bytes());
+        message.extend_from_slice(&CN_VAL_PROC.to_ne_bytes());
+        message.extend_from_slice(&[0u8; 8]);
+        message.extend_from_slice(&(event.len() as u16).to_ne_bytes());
+        message.extend_from_slice(&[0u8; 2]);
+        message.extend_from_slice(&event);
+        message
+    }
+
+    #[test]
+    fn test_parse_exec_events() {
+        const PROC_EVENT_FORK: u32 = 1;
+
+        let mut buffer = event_message(PROC_EVENT_EXEC, 1001, 1000);
+        buffer.extend(event_message(PROC_EVENT_FORK, 1002, 1002));
+        buffer.extend(event_message(PROC_EVENT_EXEC, 1003, 1003));
+        assert_eq!(parse_exec_events(&buffer), [1000, 1003]);
+
+        // Truncated messages are ignored
+        assert_eq!(parse_exec_events(&buffer[..buffer.len() - 1]), [1000]);
+    }
+}
@@ -10,6 +10,7 @@ pub use talpid_tunnel::{TunnelArgs, TunnelEvent, TunnelMetadata};
 #[cfg(not(target_os = "android"))]
 use talpid_types::net::openvpn as openvpn_types;
 use talpid_types::net::{wireguard as wireguard_types, TunnelParameters};
+pub use talpid_wireguard::{Stats, StatsHandle, StatsMap};
 
 /// A module for all WireGuard related tunnel management.
 use talpid_wireguard;
@@ -281,6 +282,16 @@ impl TunnelMonitor {
         }
     }
 
+    /// Returns a handle for reading the traffic stats of the tunnel. Only WireGuard tunnels
+    /// provide stats.
+    pub fn stats_handle(&self) -> Option<talpid_wireguard::StatsHandle> {
+        match &self.monitor {
+            #[cfg(not(target_os = "android"))]
+            InternalTunnelMonitor::OpenVpn(_) => None,
+            InternalTunnelMonitor::Wireguard(monitor) => Some(monitor.stats_handle()),
+        }
+    }
+
     /// Consumes the monitor and blocks until the tunnel exits or there is an error.
     pub fn wait(self) -> Result<()> {
         self.monitor.wait().map_err(Error::from)
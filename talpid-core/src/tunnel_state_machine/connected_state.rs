@@ -3,6 +3,8 @@ use super::{
     EventResult, SharedTunnelStateValues, TunnelCommand, TunnelCommandReceiver, TunnelState,
     TunnelStateTransition, TunnelStateWrapper,
 };
+#[cfg(not(target_os = "android"))]
+use crate::dns_forwarder;
 use crate::{
     firewall::FirewallPolicy,
     tunnel::{TunnelEvent, TunnelMetadata},
@@ -81,8 +83,10 @@ impl ConnectedState {
             })
     }
 
+    /// Returns the DNS servers to use, or the servers that the DNS forwarder sends queries to if
+    /// it is used.
     #[allow(unused_variables)]
-    fn get_dns_servers(&self, shared_values: &SharedTunnelStateValues) -> Vec<IpAddr> {
+    fn get_upstream_dns_servers(&self, shared_values: &SharedTunnelStateValues) -> Vec<IpAddr> {
         #[cfg(not(target_os = "android"))]
         if let Some(ref servers) = shared_values.dns_servers {
             servers.clone()
@@ -104,13 +108,72 @@ impl ConnectedState {
         }
     }
 
+    /// Returns the servers that the DNS forwarder should send queries to, or `None` if the
+    /// forwarder should not be used.
+    #[cfg(not(target_os = "android"))]
+    fn get_dns_forwarder_upstream(
+        &self,
+        shared_values: &SharedTunnelStateValues,
+    ) -> Option<dns_forwarder::Upstream> {
+        if !shared_values.encrypted_dns_servers.is_empty() {
+            Some(dns_forwarder::Upstream::Encrypted(
+                shared_values.encrypted_dns_servers.clone(),
+            ))
+        } else if shared_values.dns_blocklist.is_some() || !shared_values.split_dns_rules.is_empty()
+        {
+            Some(dns_forwarder::Upstream::Plain(
+                self.get_upstream_dns_servers(shared_values),
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the DNS servers that the system should use.
+    fn get_dns_servers(&self, shared_values: &SharedTunnelStateValues) -> Vec<IpAddr> {
+        #[cfg(not(target_os = "android"))]
+        if self.get_dns_forwarder_upstream(shared_values).is_some() {
+            return vec![dns_forwarder::LISTEN_ADDR];
+        }
+        self.get_upstream_dns_servers(shared_values)
+    }
+
+    /// Returns the DNS servers that the firewall should allow queries to.
+    #[cfg(not(target_os = "android"))]
+    fn get_allowed_dns_servers(&self, shared_values: &SharedTunnelStateValues) -> Vec<IpAddr> {
+        let mut servers = match self.get_dns_forwarder_upstream(shared_values) {
+            // The forwarder itself sends plain queries on port 53
+            Some(dns_forwarder::Upstream::Plain(mut servers)) => {
+                servers.insert(0, dns_forwarder::LISTEN_ADDR);
+                servers
+            }
+            Some(dns_forwarder::Upstream::Encrypted(_)) => vec![dns_forwarder::LISTEN_ADDR],
+            None => self.get_upstream_dns_servers(shared_values),
+        };
+        // The forwarder also sends plain queries to the resolvers of split DNS rules. Resolvers
+        // on the local network are reached outside the tunnel.
+        for rule in &shared_values.split_dns_rules {
+            for resolver in &rule.resolvers {
+                if !servers.contains(resolver) {
+                    servers.push(*resolver);
+                }
+            }
+        }
+        servers
+    }
+
     fn get_firewall_policy(&self, shared_values: &SharedTunnelStateValues) -> FirewallPolicy {
         FirewallPolicy::Connected {
             peer_endpoint: self.tunnel_parameters.get_next_hop_endpoint(),
             tunnel: self.metadata.clone(),
             allow_lan: shared_values.allow_lan,
+            lan_networks: shared_values.lan_networks.clone(),
             #[cfg(not(target_os = "android"))]
-            dns_servers: self.get_dns_servers(shared_values),
+            dns_servers: self.get_allowed_dns_servers(shared_values),
+            #[cfg(not(target_os = "android"))]
+            allow_list: shared_values.allow_list.clone(),
+            #[cfg(target_os = "linux")]
+            inbound_rules: shared_values.inbound_rules.clone(),
             #[cfg(windows)]
             relay_client: TunnelMonitor::get_relay_client(
                 &shared_values.resource_dir,
@@ -120,13 +183,32 @@ impl ConnectedState {
     }
 
     fn set_dns(&self, shared_values: &mut SharedTunnelStateValues) -> Result<(), BoxedError> {
+        #[cfg(not(target_os = "android"))]
+        {
+            let upstream = self.get_dns_forwarder_upstream(shared_values);
+            let runtime = shared_values.runtime.clone();
+            runtime
+                .block_on(shared_values.update_dns_forwarder(upstream))
+                .map_err(BoxedError::new)?;
+        }
+
         let dns_ips = self.get_dns_servers(shared_values);
 
+        #[cfg(target_os = "linux")]
+        shared_values.dns_monitor.set_routing_domains(
+            shared_values
+                .split_dns_rules
+                .iter()
+                .map(|rule| rule.domain.clone())
+                .collect(),
+        );
+
         #[cfg(any(target_os = "linux", target_os = "windows"))]
         let dns_ips = dns_ips
             .into_iter()
             .filter(|ip| {
-                !crate::firewall::is_local_address(ip)
+                !crate::firewall::is_local_address(ip, &shared_values.lan_networks)
+                    || *ip == dns_forwarder::LISTEN_ADDR
                     || IpAddr::V4(self.metadata.ipv4_gateway) == *ip
                     || self.metadata.ipv6_gateway.map(IpAddr::V6) == Some(*ip)
             })
@@ -144,6 +226,11 @@ impl ConnectedState {
         if let Err(error) = shared_values.dns_monitor.reset_before_interface_removal() {
             log::error!("{}", error.display_chain_with_msg("Unable to reset DNS"));
         }
+        #[cfg(not(target_os = "android"))]
+        {
+            let runtime = shared_values.runtime.clone();
+            runtime.block_on(shared_values.stop_dns_forwarder());
+        }
     }
 
     fn reset_routes(shared_values: &mut SharedTunnelStateValues) {
@@ -162,6 +249,30 @@ impl ConnectedState {
         }
     }
 
+    /// Applies the firewall policy and DNS config after the DNS settings have changed.
+    fn update_dns(self, shared_values: &mut SharedTunnelStateValues) -> EventConsequence {
+        if let Err(error) = self.set_firewall_policy(shared_values) {
+            return self.disconnect(
+                shared_values,
+                AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
+            );
+        }
+
+        match self.set_dns(shared_values) {
+            #[cfg(target_os = "android")]
+            Ok(()) => self.disconnect(shared_values, AfterDisconnect::Reconnect(0)),
+            #[cfg(not(target_os = "android"))]
+            Ok(()) => EventConsequence::SameState(self.into()),
+            Err(error) => {
+                log::error!("{}", error.display_chain_with_msg("Failed to set DNS"));
+                self.disconnect(
+                    shared_values,
+                    AfterDisconnect::Block(ErrorStateCause::SetDnsError),
+                )
+            }
+        }
+    }
+
     fn disconnect(
         self,
         shared_values: &mut SharedTunnelStateValues,
@@ -188,8 +299,8 @@ impl ConnectedState {
         use self::EventConsequence::*;
 
         match command {
-            Some(TunnelCommand::AllowLan(allow_lan)) => {
-                if let Err(error_cause) = shared_values.set_allow_lan(allow_lan) {
+            Some(TunnelCommand::AllowLan(allow_lan, lan_networks)) => {
+                if let Err(error_cause) = shared_values.set_allow_lan(allow_lan, lan_networks) {
                     self.disconnect(shared_values, AfterDisconnect::Block(error_cause))
                 } else {
                     match self.set_firewall_policy(shared_values) {
@@ -214,34 +325,61 @@ impl ConnectedState {
                 let _ = tx.send(());
                 SameState(self.into())
             }
-            Some(TunnelCommand::Dns(servers)) => match shared_values.set_dns_servers(servers) {
-                Ok(true) => {
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::AllowList(allow_list)) => {
+                if shared_values.set_allow_list(allow_list) {
                     if let Err(error) = self.set_firewall_policy(shared_values) {
                         return self.disconnect(
                             shared_values,
                             AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
                         );
                     }
-
-                    match self.set_dns(shared_values) {
-                        #[cfg(target_os = "android")]
-                        Ok(()) => self.disconnect(shared_values, AfterDisconnect::Reconnect(0)),
-                        #[cfg(not(target_os = "android"))]
-                        Ok(()) => SameState(self.into()),
-                        Err(error) => {
-                            log::error!("{}", error.display_chain_with_msg("Failed to set DNS"));
-                            self.disconnect(
-                                shared_values,
-                                AfterDisconnect::Block(ErrorStateCause::SetDnsError),
-                            )
-                        }
+                }
+                SameState(self.into())
+            }
+            #[cfg(target_os = "linux")]
+            Some(TunnelCommand::InboundRules(inbound_rules)) => {
+                if shared_values.set_inbound_rules(inbound_rules) {
+                    if let Err(error) = self.set_firewall_policy(shared_values) {
+                        return self.disconnect(
+                            shared_values,
+                            AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
+                        );
                     }
                 }
+                SameState(self.into())
+            }
+            Some(TunnelCommand::Dns(servers)) => match shared_values.set_dns_servers(servers) {
+                Ok(true) => self.update_dns(shared_values),
                 Ok(false) => SameState(self.into()),
                 Err(error_cause) => {
                     self.disconnect(shared_values, AfterDisconnect::Block(error_cause))
                 }
             },
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::EncryptedDns(servers)) => {
+                if shared_values.set_encrypted_dns_servers(servers) {
+                    self.update_dns(shared_values)
+                } else {
+                    SameState(self.into())
+                }
+            }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::DnsBlocklist(blocklist)) => {
+                if shared_values.set_dns_blocklist(blocklist) {
+                    self.update_dns(shared_values)
+                } else {
+                    SameState(self.into())
+                }
+            }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::SplitDns(rules)) => {
+                if shared_values.set_split_dns_rules(rules) {
+                    self.update_dns(shared_values)
+                } else {
+                    SameState(self.into())
+                }
+            }
             Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 SameState(self.into())
@@ -276,6 +414,11 @@ impl ConnectedState {
                 shared_values.split_tunnel.set_paths(&paths, result_tx);
                 SameState(self.into())
             }
+            #[cfg(target_os = "linux")]
+            Some(TunnelCommand::SetExcludedUsers(uids, gids)) => {
+                shared_values.set_excluded_users(uids, gids);
+                SameState(self.into())
+            }
         }
     }
 
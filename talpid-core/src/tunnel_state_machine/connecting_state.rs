@@ -69,8 +69,11 @@ impl ConnectingState {
             peer_endpoint,
             tunnel: tunnel_metadata.clone(),
             allow_lan: shared_values.allow_lan,
+            lan_networks: shared_values.lan_networks.clone(),
             allowed_endpoint: shared_values.allowed_endpoint.clone(),
             allowed_tunnel_traffic,
+            #[cfg(not(target_os = "android"))]
+            allow_list: shared_values.allow_list.clone(),
             #[cfg(windows)]
             relay_client: TunnelMonitor::get_relay_client(&shared_values.resource_dir, params),
         };
@@ -99,6 +102,7 @@ impl ConnectingState {
         resource_dir: &Path,
         tun_provider: Arc<Mutex<TunProvider>>,
         route_manager: &mut RouteManager,
+        tunnel_stats: Arc<Mutex<Option<tunnel::StatsHandle>>>,
         retry_attempt: u32,
     ) -> Self {
         let (event_tx, event_rx) = mpsc::unbounded();
@@ -154,6 +158,7 @@ impl ConnectingState {
 
             let block_reason = match TunnelMonitor::start(&mut tunnel_parameters, &log_dir, args) {
                 Ok(monitor) => {
+                    *tunnel_stats.lock().unwrap() = monitor.stats_handle();
                     let reason = Self::wait_for_tunnel_monitor(monitor, retry_attempt);
                     log::debug!("Tunnel monitor exited with block reason: {:?}", reason);
                     reason
@@ -324,8 +329,8 @@ impl ConnectingState {
         use self::EventConsequence::*;
 
         match command {
-            Some(TunnelCommand::AllowLan(allow_lan)) => {
-                if let Err(error_cause) = shared_values.set_allow_lan(allow_lan) {
+            Some(TunnelCommand::AllowLan(allow_lan, lan_networks)) => {
+                if let Err(error_cause) = shared_values.set_allow_lan(allow_lan, lan_networks) {
                     self.disconnect(shared_values, AfterDisconnect::Block(error_cause))
                 } else {
                     self.reset_firewall(shared_values)
@@ -350,12 +355,42 @@ impl ConnectingState {
                 let _ = tx.send(());
                 SameState(self.into())
             }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::AllowList(allow_list)) => {
+                if shared_values.set_allow_list(allow_list) {
+                    self.reset_firewall(shared_values)
+                } else {
+                    SameState(self.into())
+                }
+            }
+            #[cfg(target_os = "linux")]
+            Some(TunnelCommand::InboundRules(inbound_rules)) => {
+                // Only enforced in the connected state
+                let _ = shared_values.set_inbound_rules(inbound_rules);
+                SameState(self.into())
+            }
             Some(TunnelCommand::Dns(servers)) => match shared_values.set_dns_servers(servers) {
                 #[cfg(target_os = "android")]
                 Ok(true) => self.disconnect(shared_values, AfterDisconnect::Reconnect(0)),
                 Ok(_) => SameState(self.into()),
                 Err(cause) => self.disconnect(shared_values, AfterDisconnect::Block(cause)),
             },
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::EncryptedDns(servers)) => {
+                // The DNS config is applied once connected
+                shared_values.set_encrypted_dns_servers(servers);
+                SameState(self.into())
+            }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::DnsBlocklist(blocklist)) => {
+                shared_values.set_dns_blocklist(blocklist);
+                SameState(self.into())
+            }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::SplitDns(rules)) => {
+                shared_values.set_split_dns_rules(rules);
+                SameState(self.into())
+            }
             Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 SameState(self.into())
@@ -390,6 +425,11 @@ impl ConnectingState {
                 shared_values.split_tunnel.set_paths(&paths, result_tx);
                 SameState(self.into())
             }
+            #[cfg(target_os = "linux")]
+            Some(TunnelCommand::SetExcludedUsers(uids, gids)) => {
+                shared_values.set_excluded_users(uids, gids);
+                SameState(self.into())
+            }
         }
     }
 
@@ -594,6 +634,7 @@ impl TunnelState for ConnectingState {
                         &shared_values.resource_dir,
                         shared_values.tun_provider.clone(),
                         &mut shared_values.route_manager,
+                        shared_values.tunnel_stats.clone(),
                         retry_attempt,
                     );
                     let params = connecting_state.tunnel_parameters.clone();
@@ -23,7 +23,10 @@ impl DisconnectedState {
         let result = if shared_values.block_when_disconnected {
             let policy = FirewallPolicy::Blocked {
                 allow_lan: shared_values.allow_lan,
+                lan_networks: shared_values.lan_networks.clone(),
                 allowed_endpoint: Some(shared_values.allowed_endpoint.clone()),
+                #[cfg(not(target_os = "android"))]
+                allow_list: shared_values.allow_list.clone(),
                 #[cfg(target_os = "macos")]
                 dns_redirect_port: shared_values.filtering_resolver.listening_port(),
             };
@@ -132,12 +135,14 @@ impl TunnelState for DisconnectedState {
         use self::EventConsequence::*;
 
         match runtime.block_on(commands.next()) {
-            Some(TunnelCommand::AllowLan(allow_lan)) => {
-                if shared_values.allow_lan != allow_lan {
+            Some(TunnelCommand::AllowLan(allow_lan, lan_networks)) => {
+                if shared_values.allow_lan != allow_lan
+                    || shared_values.lan_networks != lan_networks
+                {
                     // The only platform that can fail is Android, but Android doesn't support the
                     // "block when disconnected" option, so the following call never fails.
                     shared_values
-                        .set_allow_lan(allow_lan)
+                        .set_allow_lan(allow_lan, lan_networks)
                         .expect("Failed to set allow LAN parameter");
 
                     Self::set_firewall_policy(shared_values, false);
@@ -152,6 +157,18 @@ impl TunnelState for DisconnectedState {
                 let _ = tx.send(());
                 SameState(self.into())
             }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::AllowList(allow_list)) => {
+                if shared_values.set_allow_list(allow_list) {
+                    Self::set_firewall_policy(shared_values, false);
+                }
+                SameState(self.into())
+            }
+            #[cfg(target_os = "linux")]
+            Some(TunnelCommand::InboundRules(inbound_rules)) => {
+                let _ = shared_values.set_inbound_rules(inbound_rules);
+                SameState(self.into())
+            }
             Some(TunnelCommand::Dns(servers)) => {
                 // Same situation as allow LAN above.
                 shared_values
@@ -160,6 +177,21 @@ impl TunnelState for DisconnectedState {
 
                 SameState(self.into())
             }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::EncryptedDns(servers)) => {
+                shared_values.set_encrypted_dns_servers(servers);
+                SameState(self.into())
+            }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::DnsBlocklist(blocklist)) => {
+                shared_values.set_dns_blocklist(blocklist);
+                SameState(self.into())
+            }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::SplitDns(rules)) => {
+                shared_values.set_split_dns_rules(rules);
+                SameState(self.into())
+            }
             Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
                 if shared_values.block_when_disconnected != block_when_disconnected {
                     shared_values.block_when_disconnected = block_when_disconnected;
@@ -203,6 +235,11 @@ impl TunnelState for DisconnectedState {
                 shared_values.split_tunnel.set_paths(&paths, result_tx);
                 SameState(self.into())
             }
+            #[cfg(target_os = "linux")]
+            Some(TunnelCommand::SetExcludedUsers(uids, gids)) => {
+                shared_values.set_excluded_users(uids, gids);
+                SameState(self.into())
+            }
             None => {
                 Self::reset_dns(shared_values);
                 Finished
@@ -23,8 +23,8 @@ impl DisconnectingState {
 
         self.after_disconnect = match after_disconnect {
             AfterDisconnect::Nothing => match command {
-                Some(TunnelCommand::AllowLan(allow_lan)) => {
-                    let _ = shared_values.set_allow_lan(allow_lan);
+                Some(TunnelCommand::AllowLan(allow_lan, lan_networks)) => {
+                    let _ = shared_values.set_allow_lan(allow_lan, lan_networks);
                     AfterDisconnect::Nothing
                 }
                 Some(TunnelCommand::AllowEndpoint(endpoint, tx)) => {
@@ -32,10 +32,35 @@ impl DisconnectingState {
                     let _ = tx.send(());
                     AfterDisconnect::Nothing
                 }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::AllowList(allow_list)) => {
+                    let _ = shared_values.set_allow_list(allow_list);
+                    AfterDisconnect::Nothing
+                }
+                #[cfg(target_os = "linux")]
+                Some(TunnelCommand::InboundRules(inbound_rules)) => {
+                    let _ = shared_values.set_inbound_rules(inbound_rules);
+                    AfterDisconnect::Nothing
+                }
                 Some(TunnelCommand::Dns(servers)) => {
                     let _ = shared_values.set_dns_servers(servers);
                     AfterDisconnect::Nothing
                 }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::EncryptedDns(servers)) => {
+                    shared_values.set_encrypted_dns_servers(servers);
+                    AfterDisconnect::Nothing
+                }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::DnsBlocklist(blocklist)) => {
+                    shared_values.set_dns_blocklist(blocklist);
+                    AfterDisconnect::Nothing
+                }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::SplitDns(rules)) => {
+                    shared_values.set_split_dns_rules(rules);
+                    AfterDisconnect::Nothing
+                }
                 Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
                     shared_values.block_when_disconnected = block_when_disconnected;
                     AfterDisconnect::Nothing
@@ -57,10 +82,15 @@ impl DisconnectingState {
                     shared_values.split_tunnel.set_paths(&paths, result_tx);
                     AfterDisconnect::Nothing
                 }
+                #[cfg(target_os = "linux")]
+                Some(TunnelCommand::SetExcludedUsers(uids, gids)) => {
+                    shared_values.set_excluded_users(uids, gids);
+                    AfterDisconnect::Nothing
+                }
             },
             AfterDisconnect::Block(reason) => match command {
-                Some(TunnelCommand::AllowLan(allow_lan)) => {
-                    let _ = shared_values.set_allow_lan(allow_lan);
+                Some(TunnelCommand::AllowLan(allow_lan, lan_networks)) => {
+                    let _ = shared_values.set_allow_lan(allow_lan, lan_networks);
                     AfterDisconnect::Block(reason)
                 }
                 Some(TunnelCommand::AllowEndpoint(endpoint, tx)) => {
@@ -68,10 +98,35 @@ impl DisconnectingState {
                     let _ = tx.send(());
                     AfterDisconnect::Block(reason)
                 }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::AllowList(allow_list)) => {
+                    let _ = shared_values.set_allow_list(allow_list);
+                    AfterDisconnect::Block(reason)
+                }
+                #[cfg(target_os = "linux")]
+                Some(TunnelCommand::InboundRules(inbound_rules)) => {
+                    let _ = shared_values.set_inbound_rules(inbound_rules);
+                    AfterDisconnect::Block(reason)
+                }
                 Some(TunnelCommand::Dns(servers)) => {
                     let _ = shared_values.set_dns_servers(servers);
                     AfterDisconnect::Block(reason)
                 }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::EncryptedDns(servers)) => {
+                    shared_values.set_encrypted_dns_servers(servers);
+                    AfterDisconnect::Block(reason)
+                }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::DnsBlocklist(blocklist)) => {
+                    shared_values.set_dns_blocklist(blocklist);
+                    AfterDisconnect::Block(reason)
+                }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::SplitDns(rules)) => {
+                    shared_values.set_split_dns_rules(rules);
+                    AfterDisconnect::Block(reason)
+                }
                 Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
                     shared_values.block_when_disconnected = block_when_disconnected;
                     AfterDisconnect::Block(reason)
@@ -97,11 +152,16 @@ impl DisconnectingState {
                     shared_values.split_tunnel.set_paths(&paths, result_tx);
                     AfterDisconnect::Block(reason)
                 }
+                #[cfg(target_os = "linux")]
+                Some(TunnelCommand::SetExcludedUsers(uids, gids)) => {
+                    shared_values.set_excluded_users(uids, gids);
+                    AfterDisconnect::Block(reason)
+                }
                 None => AfterDisconnect::Block(reason),
             },
             AfterDisconnect::Reconnect(retry_attempt) => match command {
-                Some(TunnelCommand::AllowLan(allow_lan)) => {
-                    let _ = shared_values.set_allow_lan(allow_lan);
+                Some(TunnelCommand::AllowLan(allow_lan, lan_networks)) => {
+                    let _ = shared_values.set_allow_lan(allow_lan, lan_networks);
                     AfterDisconnect::Reconnect(retry_attempt)
                 }
                 Some(TunnelCommand::AllowEndpoint(endpoint, tx)) => {
@@ -109,10 +169,35 @@ impl DisconnectingState {
                     let _ = tx.send(());
                     AfterDisconnect::Reconnect(retry_attempt)
                 }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::AllowList(allow_list)) => {
+                    let _ = shared_values.set_allow_list(allow_list);
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                #[cfg(target_os = "linux")]
+                Some(TunnelCommand::InboundRules(inbound_rules)) => {
+                    let _ = shared_values.set_inbound_rules(inbound_rules);
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
                 Some(TunnelCommand::Dns(servers)) => {
                     let _ = shared_values.set_dns_servers(servers);
                     AfterDisconnect::Reconnect(retry_attempt)
                 }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::EncryptedDns(servers)) => {
+                    shared_values.set_encrypted_dns_servers(servers);
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::DnsBlocklist(blocklist)) => {
+                    shared_values.set_dns_blocklist(blocklist);
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::SplitDns(rules)) => {
+                    shared_values.set_split_dns_rules(rules);
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
                 Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
                     shared_values.block_when_disconnected = block_when_disconnected;
                     AfterDisconnect::Reconnect(retry_attempt)
@@ -138,6 +223,11 @@ impl DisconnectingState {
                     shared_values.split_tunnel.set_paths(&paths, result_tx);
                     AfterDisconnect::Reconnect(retry_attempt)
                 }
+                #[cfg(target_os = "linux")]
+                Some(TunnelCommand::SetExcludedUsers(uids, gids)) => {
+                    shared_values.set_excluded_users(uids, gids);
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
             },
         };
 
@@ -22,7 +22,10 @@ impl ErrorState {
     ) -> Result<(), FirewallPolicyError> {
         let policy = FirewallPolicy::Blocked {
             allow_lan: shared_values.allow_lan,
+            lan_networks: shared_values.lan_networks.clone(),
             allowed_endpoint: Some(shared_values.allowed_endpoint.clone()),
+            #[cfg(not(target_os = "android"))]
+            allow_list: shared_values.allow_list.clone(),
             #[cfg(target_os = "macos")]
             dns_redirect_port: shared_values.filtering_resolver.listening_port(),
         };
@@ -140,8 +143,9 @@ impl TunnelState for ErrorState {
         use self::EventConsequence::*;
 
         match runtime.block_on(commands.next()) {
-            Some(TunnelCommand::AllowLan(allow_lan)) => {
-                if let Err(error_state_cause) = shared_values.set_allow_lan(allow_lan) {
+            Some(TunnelCommand::AllowLan(allow_lan, lan_networks)) => {
+                if let Err(error_state_cause) = shared_values.set_allow_lan(allow_lan, lan_networks)
+                {
                     NewState(Self::enter(shared_values, error_state_cause))
                 } else {
                     let _ = Self::set_firewall_policy(shared_values);
@@ -165,6 +169,18 @@ impl TunnelState for ErrorState {
                 let _ = tx.send(());
                 SameState(self.into())
             }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::AllowList(allow_list)) => {
+                if shared_values.set_allow_list(allow_list) {
+                    let _ = Self::set_firewall_policy(shared_values);
+                }
+                SameState(self.into())
+            }
+            #[cfg(target_os = "linux")]
+            Some(TunnelCommand::InboundRules(inbound_rules)) => {
+                let _ = shared_values.set_inbound_rules(inbound_rules);
+                SameState(self.into())
+            }
             Some(TunnelCommand::Dns(servers)) => {
                 if let Err(error_state_cause) = shared_values.set_dns_servers(servers) {
                     NewState(Self::enter(shared_values, error_state_cause))
@@ -172,6 +188,21 @@ impl TunnelState for ErrorState {
                     SameState(self.into())
                 }
             }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::EncryptedDns(servers)) => {
+                shared_values.set_encrypted_dns_servers(servers);
+                SameState(self.into())
+            }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::DnsBlocklist(blocklist)) => {
+                shared_values.set_dns_blocklist(blocklist);
+                SameState(self.into())
+            }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::SplitDns(rules)) => {
+                shared_values.set_split_dns_rules(rules);
+                SameState(self.into())
+            }
             Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 SameState(self.into())
@@ -209,6 +240,11 @@ impl TunnelState for ErrorState {
                 shared_values.split_tunnel.set_paths(&paths, result_tx);
                 SameState(self.into())
             }
+            #[cfg(target_os = "linux")]
+            Some(TunnelCommand::SetExcludedUsers(uids, gids)) => {
+                shared_values.set_excluded_users(uids, gids);
+                SameState(self.into())
+            }
         }
     }
 }
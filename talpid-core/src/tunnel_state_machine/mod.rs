@@ -18,6 +18,7 @@ use crate::{
     firewall::{Firewall, FirewallArguments, InitialFirewallState},
     mpsc::Sender,
     offline,
+    tunnel::StatsHandle,
 };
 #[cfg(windows)]
 use std::ffi::OsString;
@@ -28,6 +29,7 @@ use futures::{
     channel::{mpsc, oneshot},
     stream, StreamExt,
 };
+use ipnetwork::IpNetwork;
 #[cfg(target_os = "android")]
 use std::os::unix::io::RawFd;
 use std::{
@@ -41,11 +43,23 @@ use std::{
     time::Duration,
 };
 #[cfg(target_os = "android")]
-use talpid_types::{android::AndroidContext, ErrorExt};
+use talpid_types::android::AndroidContext;
+#[cfg(target_os = "linux")]
+use talpid_types::net::InboundRule;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use talpid_types::ErrorExt;
 use talpid_types::{
     net::{AllowedEndpoint, TunnelParameters},
     tunnel::{ErrorStateCause, ParameterGenerationError, TunnelStateTransition},
 };
+#[cfg(not(target_os = "android"))]
+use {
+    crate::dns_forwarder::{self, DnsBlocklist},
+    talpid_types::net::{
+        dns::{EncryptedDnsServer, SplitDnsRule},
+        AllowListEndpoint,
+    },
+};
 
 const TUNNEL_STATE_MACHINE_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
@@ -91,18 +105,41 @@ pub enum Error {
 pub struct InitialTunnelState {
     /// Whether to allow LAN traffic when not in the (non-blocking) disconnected state.
     pub allow_lan: bool,
+    /// Networks that are considered local.
+    pub lan_networks: Vec<IpNetwork>,
     /// Block traffic unless connected to the VPN.
     pub block_when_disconnected: bool,
     /// DNS servers to use. If `None`, the tunnel gateway is used.
     pub dns_servers: Option<Vec<IpAddr>>,
+    /// DNS-over-HTTPS and DNS-over-TLS servers to use. These take precedence over `dns_servers`.
+    #[cfg(not(target_os = "android"))]
+    pub encrypted_dns_servers: Vec<EncryptedDnsServer>,
+    /// Domains to block using a local resolver.
+    #[cfg(not(target_os = "android"))]
+    pub dns_blocklist: Option<Arc<DnsBlocklist>>,
+    /// Domains whose queries are sent to specific resolvers.
+    #[cfg(not(target_os = "android"))]
+    pub split_dns_rules: Vec<SplitDnsRule>,
     /// A single endpoint that is allowed to communicate outside the tunnel, i.e.
     /// in any of the blocking states.
     pub allowed_endpoint: AllowedEndpoint,
+    /// Endpoints that the user has allowed to communicate outside the tunnel, in any state.
+    #[cfg(not(target_os = "android"))]
+    pub allow_list: Vec<AllowListEndpoint>,
+    /// Local ports that may be reached from outside the tunnel while connected.
+    #[cfg(target_os = "linux")]
+    pub inbound_rules: Vec<InboundRule>,
     /// Whether to reset any existing firewall rules when initializing the disconnected state.
     pub reset_firewall: bool,
     /// Programs to exclude from the tunnel using the split tunnel driver.
     #[cfg(windows)]
     pub exclude_paths: Vec<OsString>,
+    /// Users whose traffic is excluded from the tunnel.
+    #[cfg(target_os = "linux")]
+    pub excluded_uids: Vec<u32>,
+    /// Groups whose traffic is excluded from the tunnel.
+    #[cfg(target_os = "linux")]
+    pub excluded_gids: Vec<u32>,
 }
 
 /// Identifiers for various network resources that should be unique to a given instance of a tunnel
@@ -139,11 +176,7 @@ pub async fn spawn(
         #[cfg(target_os = "android")]
         initial_settings.dns_servers.clone(),
         #[cfg(target_os = "android")]
-        crate::firewall::ALLOWED_LAN_NETS
-            .iter()
-            .chain(crate::firewall::ALLOWED_LAN_MULTICAST_NETS.iter())
-            .cloned()
-            .collect(),
+        android_allowed_lan_networks(&initial_settings.lan_networks),
     );
 
     let (shutdown_tx, shutdown_rx) = oneshot::channel();
@@ -171,6 +204,7 @@ pub async fn spawn(
 
     #[cfg(windows)]
     let split_tunnel = state_machine.shared_values.split_tunnel.handle();
+    let tunnel_stats = state_machine.shared_values.tunnel_stats.clone();
 
     tokio::task::spawn_blocking(move || {
         state_machine.run(state_change_listener);
@@ -184,19 +218,46 @@ pub async fn spawn(
         shutdown_rx,
         #[cfg(windows)]
         split_tunnel,
+        tunnel_stats,
     })
 }
 
+/// Networks that the tunnel device on Android should not route, when LAN access is allowed.
+#[cfg(target_os = "android")]
+fn android_allowed_lan_networks(lan_networks: &[IpNetwork]) -> Vec<IpNetwork> {
+    lan_networks
+        .iter()
+        .chain(crate::firewall::ALLOWED_LAN_MULTICAST_NETS.iter())
+        .cloned()
+        .collect()
+}
+
 /// Representation of external commands for the tunnel state machine.
 pub enum TunnelCommand {
-    /// Enable or disable LAN access in the firewall.
-    AllowLan(bool),
+    /// Enable or disable LAN access in the firewall, and set the networks that are considered
+    /// local.
+    AllowLan(bool, Vec<IpNetwork>),
     /// Endpoint that should never be blocked. `()` is sent to the
     /// channel after attempting to set the firewall policy, regardless
     /// of whether it succeeded.
     AllowEndpoint(AllowedEndpoint, oneshot::Sender<()>),
+    /// Set endpoints that the user has allowed to communicate outside the tunnel.
+    #[cfg(not(target_os = "android"))]
+    AllowList(Vec<AllowListEndpoint>),
+    /// Set local ports that may be reached from outside the tunnel while connected.
+    #[cfg(target_os = "linux")]
+    InboundRules(Vec<InboundRule>),
     /// Set DNS servers to use.
     Dns(Option<Vec<IpAddr>>),
+    /// Set DNS-over-HTTPS and DNS-over-TLS servers to use.
+    #[cfg(not(target_os = "android"))]
+    EncryptedDns(Vec<EncryptedDnsServer>),
+    /// Set domains to block using a local resolver.
+    #[cfg(not(target_os = "android"))]
+    DnsBlocklist(Option<Arc<DnsBlocklist>>),
+    /// Set domains whose queries are sent to specific resolvers.
+    #[cfg(not(target_os = "android"))]
+    SplitDns(Vec<SplitDnsRule>),
     /// Enable or disable the block_when_disconnected feature.
     BlockWhenDisconnected(bool),
     /// Notify the state machine of the connectivity of the device.
@@ -216,6 +277,9 @@ pub enum TunnelCommand {
         oneshot::Sender<Result<(), split_tunnel::Error>>,
         Vec<OsString>,
     ),
+    /// Set users and groups, by UID and GID, whose traffic is excluded from the tunnel.
+    #[cfg(target_os = "linux")]
+    SetExcludedUsers(Vec<u32>, Vec<u32>),
 }
 
 type TunnelCommandReceiver = stream::Fuse<mpsc::UnboundedReceiver<TunnelCommand>>;
@@ -300,8 +364,15 @@ impl TunnelStateMachine {
                 InitialFirewallState::None
             },
             allow_lan: args.settings.allow_lan,
+            lan_networks: args.settings.lan_networks.clone(),
+            #[cfg(not(target_os = "android"))]
+            allow_list: args.settings.allow_list.clone(),
             #[cfg(target_os = "linux")]
             fwmark: args.linux_ids.fwmark,
+            #[cfg(target_os = "linux")]
+            excluded_uids: args.settings.excluded_uids.clone(),
+            #[cfg(target_os = "linux")]
+            excluded_gids: args.settings.excluded_gids.clone(),
         };
 
         let firewall = Firewall::from_args(fw_args).map_err(Error::InitFirewallError)?;
@@ -362,14 +433,28 @@ impl TunnelStateMachine {
             route_manager,
             _offline_monitor: offline_monitor,
             allow_lan: args.settings.allow_lan,
+            lan_networks: args.settings.lan_networks,
             block_when_disconnected: args.settings.block_when_disconnected,
             is_offline,
             dns_servers: args.settings.dns_servers,
+            #[cfg(not(target_os = "android"))]
+            encrypted_dns_servers: args.settings.encrypted_dns_servers,
+            #[cfg(not(target_os = "android"))]
+            dns_blocklist: args.settings.dns_blocklist,
+            #[cfg(not(target_os = "android"))]
+            split_dns_rules: args.settings.split_dns_rules,
+            #[cfg(not(target_os = "android"))]
+            dns_forwarder: None,
             allowed_endpoint: args.settings.allowed_endpoint,
+            #[cfg(not(target_os = "android"))]
+            allow_list: args.settings.allow_list,
+            #[cfg(target_os = "linux")]
+            inbound_rules: args.settings.inbound_rules,
             tunnel_parameters_generator: Box::new(args.tunnel_parameters_generator),
             tun_provider: Arc::new(Mutex::new(args.tun_provider)),
             log_dir: args.log_dir,
             resource_dir: args.resource_dir,
+            tunnel_stats: Arc::new(Mutex::new(None)),
             #[cfg(target_os = "linux")]
             connectivity_check_was_enabled: None,
             #[cfg(target_os = "macos")]
@@ -445,14 +530,35 @@ struct SharedTunnelStateValues {
     _offline_monitor: offline::MonitorHandle,
     /// Should LAN access be allowed outside the tunnel.
     allow_lan: bool,
+    /// Networks that are considered local.
+    lan_networks: Vec<IpNetwork>,
     /// Should network access be allowed when in the disconnected state.
     block_when_disconnected: bool,
     /// True when the computer is known to be offline.
     is_offline: bool,
     /// DNS servers to use (overriding default).
     dns_servers: Option<Vec<IpAddr>>,
+    /// Encrypted DNS servers to use (overriding `dns_servers`).
+    #[cfg(not(target_os = "android"))]
+    encrypted_dns_servers: Vec<EncryptedDnsServer>,
+    /// Domains to block using the DNS forwarder.
+    #[cfg(not(target_os = "android"))]
+    dns_blocklist: Option<Arc<DnsBlocklist>>,
+    /// Domains whose queries are sent to specific resolvers by the DNS forwarder.
+    #[cfg(not(target_os = "android"))]
+    split_dns_rules: Vec<SplitDnsRule>,
+    /// Local resolver that forwards queries to `encrypted_dns_servers`, filters queries using
+    /// `dns_blocklist` or applies `split_dns_rules`, while connected.
+    #[cfg(not(target_os = "android"))]
+    dns_forwarder: Option<dns_forwarder::DnsForwarderHandle>,
     /// Endpoint that should not be blocked by the firewall.
     allowed_endpoint: AllowedEndpoint,
+    /// Endpoints that the user has allowed to communicate outside the tunnel.
+    #[cfg(not(target_os = "android"))]
+    allow_list: Vec<AllowListEndpoint>,
+    /// Local ports that may be reached from outside the tunnel while connected.
+    #[cfg(target_os = "linux")]
+    inbound_rules: Vec<InboundRule>,
     /// The generator of new `TunnelParameter`s
     tunnel_parameters_generator: Box<dyn TunnelParametersGenerator>,
     /// The provider of tunnel devices.
@@ -461,6 +567,8 @@ struct SharedTunnelStateValues {
     log_dir: Option<PathBuf>,
     /// Resource directory path.
     resource_dir: PathBuf,
+    /// Traffic stats of the most recently started tunnel.
+    tunnel_stats: Arc<Mutex<Option<StatsHandle>>>,
 
     /// NetworkManager's connecitivity check state.
     #[cfg(target_os = "linux")]
@@ -472,13 +580,24 @@ struct SharedTunnelStateValues {
 }
 
 impl SharedTunnelStateValues {
-    pub fn set_allow_lan(&mut self, allow_lan: bool) -> Result<(), ErrorStateCause> {
-        if self.allow_lan != allow_lan {
+    pub fn set_allow_lan(
+        &mut self,
+        allow_lan: bool,
+        lan_networks: Vec<IpNetwork>,
+    ) -> Result<(), ErrorStateCause> {
+        if self.allow_lan != allow_lan || self.lan_networks != lan_networks {
             self.allow_lan = allow_lan;
+            self.lan_networks = lan_networks;
 
             #[cfg(target_os = "android")]
             {
-                if let Err(error) = self.tun_provider.lock().unwrap().set_allow_lan(allow_lan) {
+                let allowed_lan_networks = android_allowed_lan_networks(&self.lan_networks);
+                if let Err(error) = self
+                    .tun_provider
+                    .lock()
+                    .unwrap()
+                    .set_allow_lan(allow_lan, allowed_lan_networks)
+                {
                     log::error!(
                         "{}",
                         error.display_chain_with_msg(&format!(
@@ -525,6 +644,92 @@ impl SharedTunnelStateValues {
         }
     }
 
+    #[cfg(not(target_os = "android"))]
+    pub fn set_encrypted_dns_servers(&mut self, servers: Vec<EncryptedDnsServer>) -> bool {
+        if self.encrypted_dns_servers != servers {
+            self.encrypted_dns_servers = servers;
+            true
+        } else {
+            false
+        }
+    }
+
+    #[cfg(not(target_os = "android"))]
+    pub fn set_dns_blocklist(&mut self, blocklist: Option<Arc<DnsBlocklist>>) -> bool {
+        let changed = match (&self.dns_blocklist, &blocklist) {
+            (Some(current), Some(new)) => !Arc::ptr_eq(current, new),
+            (None, None) => false,
+            _ => true,
+        };
+        self.dns_blocklist = blocklist;
+        changed
+    }
+
+    #[cfg(not(target_os = "android"))]
+    pub fn set_split_dns_rules(&mut self, rules: Vec<SplitDnsRule>) -> bool {
+        if self.split_dns_rules != rules {
+            self.split_dns_rules = rules;
+            true
+        } else {
+            false
+        }
+    }
+
+    #[cfg(not(target_os = "android"))]
+    pub fn set_allow_list(&mut self, allow_list: Vec<AllowListEndpoint>) -> bool {
+        if self.allow_list != allow_list {
+            self.allow_list = allow_list;
+            true
+        } else {
+            false
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn set_inbound_rules(&mut self, inbound_rules: Vec<InboundRule>) -> bool {
+        if self.inbound_rules != inbound_rules {
+            self.inbound_rules = inbound_rules;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Starts the DNS forwarder if `upstream` is given, or stops it otherwise. A running
+    /// forwarder is restarted if the upstream servers, the blocklist or the split DNS rules have
+    /// changed.
+    #[cfg(not(target_os = "android"))]
+    pub async fn update_dns_forwarder(
+        &mut self,
+        upstream: Option<dns_forwarder::Upstream>,
+    ) -> Result<(), dns_forwarder::Error> {
+        if let (Some(forwarder), Some(upstream)) = (&self.dns_forwarder, &upstream) {
+            if forwarder.is_started_with(upstream, &self.dns_blocklist, &self.split_dns_rules) {
+                return Ok(());
+            }
+        }
+        // The old forwarder must be stopped before a new one can bind to the same address
+        self.stop_dns_forwarder().await;
+        if let Some(upstream) = upstream {
+            self.dns_forwarder = Some(
+                dns_forwarder::start(
+                    upstream,
+                    self.dns_blocklist.clone(),
+                    self.split_dns_rules.clone(),
+                )
+                .await?,
+            );
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "android"))]
+    pub async fn stop_dns_forwarder(&mut self) {
+        if let Some(forwarder) = self.dns_forwarder.take() {
+            forwarder.stop().await;
+        }
+    }
+
     /// NetworkManager's connectivity check can get hung when DNS requests fail, thus the TSM
     /// should always disable it before applying firewall rules. The connectivity check should be
     /// reset whenever the firewall is cleared.
@@ -551,6 +756,17 @@ impl SharedTunnelStateValues {
         }
     }
 
+    /// Updates the users and groups whose traffic is excluded from the tunnel.
+    #[cfg(target_os = "linux")]
+    pub fn set_excluded_users(&mut self, uids: Vec<u32>, gids: Vec<u32>) {
+        if let Err(error) = self.firewall.set_excluded_users(uids, gids) {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to update excluded users in the firewall")
+            );
+        }
+    }
+
     #[cfg(target_os = "android")]
     pub fn bypass_socket(&mut self, fd: RawFd, tx: oneshot::Sender<()>) {
         if let Err(err) = self.tun_provider.lock().unwrap().bypass(fd) {
@@ -652,6 +868,7 @@ pub struct TunnelStateMachineHandle {
     shutdown_rx: oneshot::Receiver<()>,
     #[cfg(windows)]
     split_tunnel: split_tunnel::SplitTunnelHandle,
+    tunnel_stats: Arc<Mutex<Option<StatsHandle>>>,
 }
 
 impl TunnelStateMachineHandle {
@@ -671,6 +888,12 @@ impl TunnelStateMachineHandle {
         &self.command_tx
     }
 
+    /// Returns a handle for reading the traffic stats of the most recently started tunnel, if it
+    /// provides any.
+    pub fn tunnel_stats(&self) -> Option<StatsHandle> {
+        self.tunnel_stats.lock().unwrap().clone()
+    }
+
     /// Returns split tunnel object handle.
     #[cfg(windows)]
     pub fn split_tunnel(&self) -> &split_tunnel::SplitTunnelHandle {
@@ -3,17 +3,20 @@ use super::systemd_resolved;
 pub use dbus::arg::{RefArg, Variant};
 use dbus::{
     arg,
-    blocking::{stdintf::org_freedesktop_dbus::Properties, Proxy, SyncConnection},
-    message::MatchRule,
+    blocking::{
+        stdintf::org_freedesktop_dbus::{Properties, PropertiesPropertiesChanged},
+        Proxy, SyncConnection,
+    },
+    message::{MatchRule, SignalArgs},
 };
 use std::{
     collections::HashMap,
     fs::File,
     io::{BufRead, BufReader},
-    net::IpAddr,
+    net::{IpAddr, Ipv4Addr},
     path::Path,
     sync::{
-        atomic::{AtomicU32, Ordering},
+        atomic::{AtomicBool, AtomicU32, Ordering},
         Arc,
     },
     time::{Duration, Instant},
@@ -27,6 +30,10 @@ const CONNECTIVITY_CHECK_KEY: &str = "ConnectivityCheckEnabled";
 const NM_DNS_MANAGER: &str = "org.freedesktop.NetworkManager.DnsManager";
 const NM_DNS_MANAGER_PATH: &str = "/org/freedesktop/NetworkManager/DnsManager";
 const NM_DEVICE: &str = "org.freedesktop.NetworkManager.Device";
+const NM_DEVICE_WIRELESS: &str = "org.freedesktop.NetworkManager.Device.Wireless";
+const NM_ACCESS_POINT: &str = "org.freedesktop.NetworkManager.AccessPoint";
+const NM_PRIMARY_CONNECTION_KEY: &str = "PrimaryConnection";
+const NM_WIRELESS_CONNECTION_TYPE: &str = "802-11-wireless";
 
 const NM_IP4_CONFIG: &str = "org.freedesktop.NetworkManager.IP4Config";
 const NM_IP6_CONFIG: &str = "org.freedesktop.NetworkManager.IP6Config";
@@ -47,6 +54,10 @@ const NM_CONNECTION_ACTIVE: &str = "org.freedesktop.NetworkManager.Connection.Ac
 const NM_ADD_CONNECTION_VOLATILE: u32 = 0x2;
 
 const RPC_TIMEOUT: std::time::Duration = Duration::from_secs(3);
+/// Number of consecutive failures to process DBus messages after which
+/// [`NetworkManager::watch_primary_connection`] gives up.
+const MAX_WATCH_PROCESS_ERRORS: u32 = 5;
+const WATCH_PROCESS_RETRY_DELAY: Duration = Duration::from_secs(1);
 
 const DBUS_UNKNOWN_METHOD: &str = "org.freedesktop.DBus.Error.UnknownMethod";
 
@@ -114,6 +125,19 @@ pub type VariantMap = HashMap<String, VariantRefArg>;
 // settings are a{sa{sv}}
 pub type DeviceConfig = HashMap<String, VariantMap>;
 
+/// The connection that NetworkManager uses for the default route.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrimaryConnection {
+    /// UUID of the connection profile.
+    pub uuid: String,
+    /// Name of the connection profile.
+    pub id: String,
+    /// SSID of the access point, if this is a Wi-Fi connection.
+    pub ssid: Option<String>,
+    /// Default IPv4 gateway of the connection, if it has one.
+    pub ipv4_gateway: Option<Ipv4Addr>,
+}
+
 /// Implements functionality to control NetworkManager over DBus.
 pub struct NetworkManager {
     connection: Arc<SyncConnection>,
@@ -126,6 +150,14 @@ impl NetworkManager {
         })
     }
 
+    /// Creates an instance with a dedicated DBus connection, for watching signals without
+    /// processing messages on the shared connection.
+    pub fn new_connection() -> Result<Self> {
+        Ok(Self {
+            connection: Arc::new(SyncConnection::new_system()?),
+        })
+    }
+
     pub fn create_wg_tunnel(&self, config: &DeviceConfig) -> Result<WireguardTunnel> {
         self.nm_supports_wireguard()?;
         let tunnel = self.create_wg_tunnel_inner(config)?;
@@ -376,6 +408,151 @@ impl NetworkManager {
         }
     }
 
+    /// Returns the connection that NetworkManager uses for the default route, or `None` if there
+    /// is no such connection.
+    pub fn get_primary_connection(&self) -> Result<Option<PrimaryConnection>> {
+        let connection_path: dbus::Path<'static> = self
+            .as_manager()
+            .get(NM_MANAGER, NM_PRIMARY_CONNECTION_KEY)
+            .map_err(Error::Dbus)?;
+        if &*connection_path == "/" {
+            return Ok(None);
+        }
+
+        let connection = self.as_path(&connection_path);
+        let uuid: String = connection
+            .get(NM_CONNECTION_ACTIVE, "Uuid")
+            .map_err(Error::Dbus)?;
+        let id: String = connection
+            .get(NM_CONNECTION_ACTIVE, "Id")
+            .map_err(Error::Dbus)?;
+        let connection_type: String = connection
+            .get(NM_CONNECTION_ACTIVE, "Type")
+            .map_err(Error::Dbus)?;
+
+        let ssid = if connection_type == NM_WIRELESS_CONNECTION_TYPE {
+            let device_paths: Vec<dbus::Path<'static>> = connection
+                .get(NM_CONNECTION_ACTIVE, "Devices")
+                .map_err(Error::Dbus)?;
+            match device_paths.first() {
+                Some(device_path) => self.get_ssid(device_path)?,
+                None => None,
+            }
+        } else {
+            None
+        };
+
+        let ip4_config: dbus::Path<'static> = connection
+            .get(NM_CONNECTION_ACTIVE, "Ip4Config")
+            .map_err(Error::Dbus)?;
+        let ipv4_gateway = if &*ip4_config == "/" {
+            None
+        } else {
+            let gateway: String = self
+                .as_path(&ip4_config)
+                .get(NM_IP4_CONFIG, "Gateway")
+                .map_err(Error::Dbus)?;
+            gateway.parse().ok()
+        };
+
+        Ok(Some(PrimaryConnection {
+            uuid,
+            id,
+            ssid,
+            ipv4_gateway,
+        }))
+    }
+
+    fn get_ssid(&self, device: &dbus::Path<'_>) -> Result<Option<String>> {
+        let access_point: dbus::Path<'static> = self
+            .as_path(device)
+            .get(NM_DEVICE_WIRELESS, "ActiveAccessPoint")
+            .map_err(Error::Dbus)?;
+        if &*access_point == "/" {
+            return Ok(None);
+        }
+        let ssid: Vec<u8> = self
+            .as_path(&access_point)
+            .get(NM_ACCESS_POINT, "Ssid")
+            .map_err(Error::Dbus)?;
+        Ok(Some(String::from_utf8_lossy(&ssid).into_owned()))
+    }
+
+    /// Calls `callback` with the current primary connection, and again every time NetworkManager
+    /// switches to another primary connection or the gateway of the primary connection changes.
+    /// Blocks until `should_continue` returns false, or until processing DBus messages has failed
+    /// [`MAX_WATCH_PROCESS_ERRORS`] times in a row.
+    /// Should be used with an instance created by [`NetworkManager::new_connection`].
+    pub fn watch_primary_connection<F, S>(&self, mut callback: F, should_continue: S) -> Result<()>
+    where
+        F: FnMut(Option<PrimaryConnection>),
+        S: Fn() -> bool,
+    {
+        let mut match_rule = MatchRule::new_signal(
+            PropertiesPropertiesChanged::INTERFACE,
+            PropertiesPropertiesChanged::NAME,
+        );
+        // Active connections and IP configs live below the manager path. The IPv4 config of a
+        // connection is usually populated after the connection becomes the primary one.
+        match_rule.path = Some(NM_MANAGER_PATH.into());
+        match_rule.path_is_namespace = true;
+
+        // The connection is queried outside of the signal handler, since the handler is invoked
+        // while messages are being processed.
+        let changed = Arc::new(AtomicBool::new(true));
+        let shared_changed = changed.clone();
+        let primary_connection_matcher = self
+            .connection
+            .add_match(
+                match_rule,
+                move |prop_changed: PropertiesPropertiesChanged, _connection, _message| {
+                    let changed_key = match prop_changed.interface_name.as_str() {
+                        NM_MANAGER => NM_PRIMARY_CONNECTION_KEY,
+                        NM_CONNECTION_ACTIVE => "Ip4Config",
+                        NM_IP4_CONFIG => "Gateway",
+                        _ => return true,
+                    };
+                    if prop_changed.changed_properties.contains_key(changed_key) {
+                        shared_changed.store(true, Ordering::Release);
+                    }
+                    true
+                },
+            )
+            .map_err(Error::Dbus)?;
+
+        let mut last_connection = None;
+        let mut process_errors = 0;
+        while should_continue() {
+            if changed.swap(false, Ordering::AcqRel) {
+                match self.get_primary_connection() {
+                    Ok(primary_connection) => {
+                        if last_connection.as_ref() != Some(&primary_connection) {
+                            last_connection = Some(primary_connection.clone());
+                            callback(primary_connection);
+                        }
+                    }
+                    Err(err) => log::error!("Failed to get primary connection: {}", err),
+                }
+            }
+            match self.connection.process(RPC_TIMEOUT) {
+                Ok(_) => process_errors = 0,
+                Err(err) => {
+                    process_errors += 1;
+                    log::error!("Failed to process DBus messages: {}", err);
+                    if process_errors >= MAX_WATCH_PROCESS_ERRORS {
+                        let _ = self.connection.remove_match(primary_connection_matcher);
+                        return Err(Error::Dbus(err));
+                    }
+                    std::thread::sleep(WATCH_PROCESS_RETRY_DELAY * process_errors);
+                }
+            }
+        }
+
+        self.connection
+            .remove_match(primary_connection_matcher)
+            .map_err(Error::Dbus)
+    }
+
     fn nm_manager(&self) -> Proxy<'_, &SyncConnection> {
         Proxy::new(NM_BUS, NM_MANAGER_PATH, RPC_TIMEOUT, &*self.connection)
     }
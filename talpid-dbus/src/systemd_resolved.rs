@@ -563,13 +563,22 @@ impl AsyncHandle {
     pub async fn set_domains(
         &self,
         interface_index: u32,
-        domains: &[(&'static str, bool)],
+        domains: &[(&str, bool)],
     ) -> Result<()> {
         let interface = self.dbus_interface.clone();
-        let domains = domains.to_vec();
-        tokio::task::spawn_blocking(move || interface.set_domains(interface_index, &domains))
-            .await
-            .map_err(Error::AsyncTaskError)?
+        let domains: Vec<(String, bool)> = domains
+            .iter()
+            .map(|(domain, routing_only)| (domain.to_string(), *routing_only))
+            .collect();
+        tokio::task::spawn_blocking(move || {
+            let domains: Vec<(&str, bool)> = domains
+                .iter()
+                .map(|(domain, routing_only)| (domain.as_str(), *routing_only))
+                .collect();
+            interface.set_domains(interface_index, &domains)
+        })
+        .await
+        .map_err(Error::AsyncTaskError)?
     }
 
     pub async fn revert_link(&self, state: DnsState) -> Result<()> {
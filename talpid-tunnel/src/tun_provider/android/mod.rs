@@ -95,9 +95,14 @@ impl AndroidTunProvider {
         }
     }
 
-    pub fn set_allow_lan(&mut self, allow_lan: bool) -> Result<(), Error> {
-        if self.allow_lan != allow_lan {
+    pub fn set_allow_lan(
+        &mut self,
+        allow_lan: bool,
+        allowed_lan_networks: Vec<IpNetwork>,
+    ) -> Result<(), Error> {
+        if self.allow_lan != allow_lan || self.allowed_lan_networks != allowed_lan_networks {
             self.allow_lan = allow_lan;
+            self.allowed_lan_networks = allowed_lan_networks;
             self.recreate_tun_if_open()?;
         }
 
@@ -14,6 +14,25 @@ fn find_net_cls_mount_inner(mounts: &[u8]) -> Option<PathBuf> {
         .find_map(parse_mount_line)
 }
 
+/// Find the path of the cgroup v2 hierarchy if it is mounted
+pub fn find_cgroup2_mount() -> std::io::Result<Option<PathBuf>> {
+    let mounts = fs::read("/proc/mounts")?;
+    Ok(find_cgroup2_mount_inner(&mounts))
+}
+
+fn find_cgroup2_mount_inner(mounts: &[u8]) -> Option<PathBuf> {
+    mounts.split(|byte| *byte == b'\n').find_map(|line| {
+        let mut parts = line.split(|byte| *byte == b' ');
+        let _device_type = parts.next()?;
+        let mount_path = parts.next()?;
+        let filesystem_type = parts.next()?;
+        if filesystem_type != b"cgroup2" {
+            return None;
+        }
+        Some(PathBuf::from(OsStr::from_bytes(mount_path)))
+    })
+}
+
 fn parse_mount_line(line: &[u8]) -> Option<PathBuf> {
     // Each line contains multiple values separated by space.
     // `cgroup /sys/fs/cgroup/net_cls,net_prio cgroup
@@ -72,4 +91,18 @@ cgroup /nope
 
         assert_eq!(find_net_cls_mount_inner(input), None)
     }
+
+    #[test]
+    fn test_find_cgroup2_path() {
+        let input =
+            br#"cgroup /sys/fs/cgroup/net_cls,net_prio cgroup rw,nosuid,nodev,noexec,relatime,net_cls,net_prio 0 0
+cgroup2 /sys/fs/cgroup cgroup2 rw,nosuid,nodev,noexec,relatime,nsdelegate,memory_recursiveprot 0 0
+"#;
+
+        assert_eq!(
+            find_cgroup2_mount_inner(input),
+            Some(PathBuf::from("/sys/fs/cgroup"))
+        );
+        assert_eq!(find_cgroup2_mount_inner(b"cgroup /nope\n"), None);
+    }
 }
@@ -0,0 +1,239 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    str::FromStr,
+};
+
+/// Path that DNS-over-HTTPS queries are sent to.
+const DOH_PATH: &str = "/dns-query";
+
+/// A DNS resolver that is queried over an encrypted transport.
+///
+/// It is written as `https://<name>@<ip>[:<port>]/dns-query` or `tls://<name>@<ip>[:<port>]`,
+/// where `<name>` is used to verify the certificate of the server.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct EncryptedDnsServer {
+    pub protocol: EncryptedDnsProtocol,
+    pub address: SocketAddr,
+    /// Name that the certificate of the server must be valid for.
+    pub tls_name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EncryptedDnsProtocol {
+    /// DNS-over-HTTPS
+    Https,
+    /// DNS-over-TLS
+    Tls,
+}
+
+impl EncryptedDnsProtocol {
+    fn scheme(&self) -> &'static str {
+        match self {
+            EncryptedDnsProtocol::Https => "https",
+            EncryptedDnsProtocol::Tls => "tls",
+        }
+    }
+
+    fn default_port(&self) -> u16 {
+        match self {
+            EncryptedDnsProtocol::Https => 443,
+            EncryptedDnsProtocol::Tls => 853,
+        }
+    }
+}
+
+/// Returned when a string is not a valid [`EncryptedDnsServer`].
+#[derive(err_derive::Error, Debug, Clone, PartialEq, Eq)]
+pub enum EncryptedDnsServerParseError {
+    #[error(display = "The URL must start with https:// or tls://")]
+    UnknownScheme,
+
+    #[error(display = "The server must be given as an IP address, optionally with a port")]
+    InvalidAddress,
+
+    #[error(display = "The server must be given as <name>@<ip>, where <name> is its TLS name")]
+    MissingTlsName,
+
+    #[error(display = "DNS-over-HTTPS servers must use the path {}", DOH_PATH)]
+    UnsupportedPath,
+}
+
+impl FromStr for EncryptedDnsServer {
+    type Err = EncryptedDnsServerParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (protocol, rest) = if let Some(rest) = s.strip_prefix("https://") {
+            (EncryptedDnsProtocol::Https, rest)
+        } else if let Some(rest) = s.strip_prefix("tls://") {
+            (EncryptedDnsProtocol::Tls, rest)
+        } else {
+            return Err(EncryptedDnsServerParseError::UnknownScheme);
+        };
+
+        let authority = match rest.split_once('/') {
+            Some((authority, path)) => {
+                if protocol != EncryptedDnsProtocol::Https || format!("/{path}") != DOH_PATH {
+                    return Err(EncryptedDnsServerParseError::UnsupportedPath);
+                }
+                authority
+            }
+            None => rest,
+        };
+
+        let (tls_name, host) = match authority.rsplit_once('@') {
+            Some((name, host)) if !name.is_empty() => (name.to_owned(), host),
+            _ => return Err(EncryptedDnsServerParseError::MissingTlsName),
+        };
+
+        let address = match SocketAddr::from_str(host) {
+            Ok(address) => address,
+            Err(_) => {
+                let ip = host.trim_start_matches('[').trim_end_matches(']');
+                let ip = IpAddr::from_str(ip)
+                    .map_err(|_| EncryptedDnsServerParseError::InvalidAddress)?;
+                SocketAddr::new(ip, protocol.default_port())
+            }
+        };
+
+        Ok(EncryptedDnsServer {
+            protocol,
+            address,
+            tls_name,
+        })
+    }
+}
+
+impl fmt::Display for EncryptedDnsServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://", self.protocol.scheme())?;
+        write!(f, "{}@", self.tls_name)?;
+        if self.address.port() == self.protocol.default_port() {
+            match self.address.ip() {
+                IpAddr::V4(ip) => write!(f, "{ip}")?,
+                IpAddr::V6(ip) => write!(f, "[{ip}]")?,
+            }
+        } else {
+            write!(f, "{}", self.address)?;
+        }
+        if self.protocol == EncryptedDnsProtocol::Https {
+            write!(f, "{DOH_PATH}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Statistics for a DNS blocklist that is enforced locally.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsBlocklistStats {
+    /// Path of the file that the list was loaded from.
+    pub path: PathBuf,
+    /// Number of domains in the list.
+    pub entries: u64,
+    /// Number of queries that were blocked because of the list.
+    pub hits: u64,
+}
+
+/// Rule that sends queries for a domain and its subdomains to specific resolvers, instead of the
+/// DNS servers that are otherwise used.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SplitDnsRule {
+    /// Domain suffix that the rule applies to, such as `corp.internal`.
+    pub domain: String,
+    /// Plain DNS servers that queries matching the rule are sent to.
+    pub resolvers: Vec<IpAddr>,
+}
+
+impl SplitDnsRule {
+    /// Returns whether the rule applies to queries for `name`.
+    pub fn matches(&self, name: &str) -> bool {
+        let name = name.trim_end_matches('.').as_bytes();
+        let domain = self.domain.trim_end_matches('.').as_bytes();
+        match name.len().checked_sub(domain.len()) {
+            Some(0) => name.eq_ignore_ascii_case(domain),
+            Some(prefix_len) => {
+                name[prefix_len - 1] == b'.' && name[prefix_len..].eq_ignore_ascii_case(domain)
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_encrypted_dns_server() {
+        let server: EncryptedDnsServer = "https://dns.quad9.net@9.9.9.9/dns-query".parse().unwrap();
+        assert_eq!(
+            server,
+            EncryptedDnsServer {
+                protocol: EncryptedDnsProtocol::Https,
+                address: "9.9.9.9:443".parse().unwrap(),
+                tls_name: "dns.quad9.net".to_owned(),
+            }
+        );
+        assert_eq!(
+            server.to_string(),
+            "https://dns.quad9.net@9.9.9.9/dns-query"
+        );
+
+        let server: EncryptedDnsServer = "tls://dns.quad9.net@[2620:fe::fe]:8853".parse().unwrap();
+        assert_eq!(
+            server,
+            EncryptedDnsServer {
+                protocol: EncryptedDnsProtocol::Tls,
+                address: "[2620:fe::fe]:8853".parse().unwrap(),
+                tls_name: "dns.quad9.net".to_owned(),
+            }
+        );
+        assert_eq!(server.to_string(), "tls://dns.quad9.net@[2620:fe::fe]:8853");
+
+        let server: EncryptedDnsServer = "tls://dns.quad9.net@[2620:fe::fe]".parse().unwrap();
+        assert_eq!(server.address.port(), 853);
+        assert_eq!(server.to_string(), "tls://dns.quad9.net@[2620:fe::fe]");
+
+        assert_eq!(
+            "tls://[2620:fe::fe]".parse::<EncryptedDnsServer>(),
+            Err(EncryptedDnsServerParseError::MissingTlsName)
+        );
+        assert_eq!(
+            "https://@9.9.9.9/dns-query".parse::<EncryptedDnsServer>(),
+            Err(EncryptedDnsServerParseError::MissingTlsName)
+        );
+
+        assert_eq!(
+            "udp://9.9.9.9".parse::<EncryptedDnsServer>(),
+            Err(EncryptedDnsServerParseError::UnknownScheme)
+        );
+        assert_eq!(
+            "tls://dns.quad9.net@dns.quad9.net".parse::<EncryptedDnsServer>(),
+            Err(EncryptedDnsServerParseError::InvalidAddress)
+        );
+        assert_eq!(
+            "https://dns.quad9.net@9.9.9.9/query".parse::<EncryptedDnsServer>(),
+            Err(EncryptedDnsServerParseError::UnsupportedPath)
+        );
+        assert_eq!(
+            "tls://dns.quad9.net@9.9.9.9/dns-query".parse::<EncryptedDnsServer>(),
+            Err(EncryptedDnsServerParseError::UnsupportedPath)
+        );
+    }
+
+    #[test]
+    fn test_split_dns_rule_matches() {
+        let rule = SplitDnsRule {
+            domain: "corp.internal".to_owned(),
+            resolvers: vec!["10.0.0.53".parse().unwrap()],
+        };
+        assert!(rule.matches("corp.internal"));
+        assert!(rule.matches("git.Corp.Internal."));
+        assert!(!rule.matches("notcorp.internal"));
+        assert!(!rule.matches("internal"));
+        assert!(!rule.matches("corp.internal.example.com"));
+    }
+}
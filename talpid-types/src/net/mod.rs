@@ -10,6 +10,7 @@ use std::{
     str::FromStr,
 };
 
+pub mod dns;
 pub mod obfuscation;
 pub mod openvpn;
 pub mod proxy;
@@ -74,6 +75,10 @@ impl TunnelParameters {
                 address: *endpoint,
                 protocol: TransportProtocol::Tcp,
             },
+            ObfuscatorConfig::Shadowsocks { endpoint, .. } => Endpoint {
+                address: *endpoint,
+                protocol: TransportProtocol::Udp,
+            },
         }
     }
 
@@ -175,12 +180,15 @@ impl fmt::Display for TunnelEndpoint {
 pub enum ObfuscationType {
     #[serde(rename = "udp2tcp")]
     Udp2Tcp,
+    #[serde(rename = "shadowsocks")]
+    Shadowsocks,
 }
 
 impl fmt::Display for ObfuscationType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         match self {
             ObfuscationType::Udp2Tcp => "Udp2Tcp".fmt(f),
+            ObfuscationType::Shadowsocks => "Shadowsocks".fmt(f),
         }
     }
 }
@@ -202,6 +210,13 @@ impl From<&ObfuscatorConfig> for ObfuscationEndpoint {
                 },
                 ObfuscationType::Udp2Tcp,
             ),
+            ObfuscatorConfig::Shadowsocks { endpoint, .. } => (
+                Endpoint {
+                    address: *endpoint,
+                    protocol: TransportProtocol::Udp,
+                },
+                ObfuscationType::Shadowsocks,
+            ),
         };
 
         ObfuscationEndpoint {
@@ -280,6 +295,149 @@ impl fmt::Display for AllowedEndpoint {
     }
 }
 
+/// Network and port that the user has allowed to be reached outside the tunnel, in any tunnel
+/// state.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct AllowListEndpoint {
+    pub network: ipnetwork::IpNetwork,
+    pub port: u16,
+    pub protocol: TransportProtocol,
+}
+
+impl fmt::Display for AllowListEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self.network {
+            ipnetwork::IpNetwork::V4(network) => write!(f, "{}", network)?,
+            ipnetwork::IpNetwork::V6(network) => write!(f, "[{}]", network)?,
+        }
+        write!(f, ":{}/{}", self.port, self.protocol)
+    }
+}
+
+impl FromStr for AllowListEndpoint {
+    type Err = AllowListEndpointParseError;
+
+    /// Parses an endpoint on the form `NETWORK:PORT/PROTOCOL`, where IPv6 networks may be
+    /// enclosed in brackets. This is the same form as the one produced by `Display`.
+    fn from_str(s: &str) -> std::result::Result<AllowListEndpoint, Self::Err> {
+        let (address, protocol) = s.rsplit_once('/').ok_or(AllowListEndpointParseError)?;
+        let (network, port) = address
+            .rsplit_once(':')
+            .ok_or(AllowListEndpointParseError)?;
+        let network = network
+            .strip_prefix('[')
+            .and_then(|network| network.strip_suffix(']'))
+            .unwrap_or(network);
+
+        Ok(AllowListEndpoint {
+            network: network.parse().map_err(|_| AllowListEndpointParseError)?,
+            port: port.parse().map_err(|_| AllowListEndpointParseError)?,
+            protocol: protocol
+                .to_lowercase()
+                .parse()
+                .map_err(|_| AllowListEndpointParseError)?,
+        })
+    }
+}
+
+/// Returned when `AllowListEndpoint::from_str` fails to convert a string into a
+/// [`AllowListEndpoint`] object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllowListEndpointParseError;
+
+impl fmt::Display for AllowListEndpointParseError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str("Not a valid endpoint, expected NETWORK:PORT/PROTOCOL")
+    }
+}
+
+/// Local port that may be reached from outside the tunnel while connected. Only traffic from
+/// `source` arriving on `interface` is accepted.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct InboundRule {
+    pub port: u16,
+    pub protocol: TransportProtocol,
+    pub source: ipnetwork::IpNetwork,
+    pub interface: String,
+}
+
+impl fmt::Display for InboundRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "{}/{} from {} on {}",
+            self.port, self.protocol, self.source, self.interface
+        )
+    }
+}
+
+/// Networks that are considered local unless the user has configured others. These are the
+/// private IPv4 ranges, IPv4 link-local, IPv6 link-local and IPv6 unique local addresses.
+pub fn default_lan_networks() -> Vec<ipnetwork::IpNetwork> {
+    use ipnetwork::{IpNetwork, Ipv4Network, Ipv6Network};
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    vec![
+        IpNetwork::V4(Ipv4Network::new(Ipv4Addr::new(10, 0, 0, 0), 8).unwrap()),
+        IpNetwork::V4(Ipv4Network::new(Ipv4Addr::new(172, 16, 0, 0), 12).unwrap()),
+        IpNetwork::V4(Ipv4Network::new(Ipv4Addr::new(192, 168, 0, 0), 16).unwrap()),
+        IpNetwork::V4(Ipv4Network::new(Ipv4Addr::new(169, 254, 0, 0), 16).unwrap()),
+        IpNetwork::V6(Ipv6Network::new(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0), 10).unwrap()),
+        IpNetwork::V6(Ipv6Network::new(Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0), 7).unwrap()),
+    ]
+}
+
+/// The shortest prefix of an IPv4 network that is considered local. This is the prefix of the
+/// broadest default IPv4 network.
+const MIN_LAN_NETWORK_PREFIX_V4: u8 = 8;
+/// The shortest prefix of an IPv6 network that is considered local. This is the prefix of the
+/// broadest default IPv6 network.
+const MIN_LAN_NETWORK_PREFIX_V6: u8 = 7;
+
+/// Reasons for a network to not be allowed to be considered local.
+#[derive(err_derive::Error, Debug, Clone, PartialEq, Eq)]
+pub enum LanNetworkError {
+    #[error(display = "The network {} is too broad to be considered local", _0)]
+    TooBroad(ipnetwork::IpNetwork),
+
+    #[error(
+        display = "The network {} contains {}, which is only reachable through the tunnel",
+        _0,
+        _1
+    )]
+    ContainsTunnelAddress(ipnetwork::IpNetwork, IpAddr),
+}
+
+/// Validates a network that is to be considered local and returns it with its host bits cleared.
+///
+/// Traffic to local networks is allowed outside the tunnel, so networks with too short prefixes
+/// are rejected, as are networks that contain any of `tunnel_addresses`. These are the relay
+/// endpoint and the addresses of the tunnel interface. The default networks are exempt from the
+/// latter check, since the tunnel addresses are private addresses.
+pub fn validate_lan_network(
+    network: ipnetwork::IpNetwork,
+    tunnel_addresses: &[IpAddr],
+) -> Result<ipnetwork::IpNetwork, LanNetworkError> {
+    let min_prefix = match network {
+        ipnetwork::IpNetwork::V4(_) => MIN_LAN_NETWORK_PREFIX_V4,
+        ipnetwork::IpNetwork::V6(_) => MIN_LAN_NETWORK_PREFIX_V6,
+    };
+    if network.prefix() < min_prefix {
+        return Err(LanNetworkError::TooBroad(network));
+    }
+    let network = ipnetwork::IpNetwork::new(network.network(), network.prefix())
+        .expect("prefix of a valid network is valid");
+    if !default_lan_networks().contains(&network) {
+        if let Some(address) = tunnel_addresses
+            .iter()
+            .find(|address| network.contains(**address))
+        {
+            return Err(LanNetworkError::ContainsTunnelAddress(network, *address));
+        }
+    }
+    Ok(network)
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum AllowedTunnelTraffic {
     None,
@@ -382,3 +540,83 @@ pub fn all_of_the_internet() -> Vec<ipnetwork::IpNetwork> {
         "::0/0".parse().expect("Failed to parse ipv6 network"),
     ]
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_allow_list_endpoint() {
+        let endpoint: AllowListEndpoint = "203.0.113.0/24:443/tcp".parse().unwrap();
+        assert_eq!(
+            endpoint,
+            AllowListEndpoint {
+                network: "203.0.113.0/24".parse().unwrap(),
+                port: 443,
+                protocol: TransportProtocol::Tcp,
+            }
+        );
+        assert_eq!(endpoint.to_string(), "203.0.113.0/24:443/TCP");
+        assert_eq!(endpoint.to_string().parse(), Ok(endpoint));
+
+        let endpoint: AllowListEndpoint = "[2001:db8::/32]:53/udp".parse().unwrap();
+        assert_eq!(endpoint.network, "2001:db8::/32".parse().unwrap());
+        assert_eq!(endpoint.to_string(), "[2001:db8::/32]:53/UDP");
+
+        let endpoint: AllowListEndpoint = "198.51.100.7:8080/tcp".parse().unwrap();
+        assert_eq!(endpoint.network.prefix(), 32);
+
+        assert_eq!(
+            "198.51.100.7/24:8080".parse::<AllowListEndpoint>(),
+            Err(AllowListEndpointParseError)
+        );
+        assert_eq!(
+            "198.51.100.7:8080/icmp".parse::<AllowListEndpoint>(),
+            Err(AllowListEndpointParseError)
+        );
+        assert_eq!(
+            "198.51.100.7/33:8080/tcp".parse::<AllowListEndpoint>(),
+            Err(AllowListEndpointParseError)
+        );
+    }
+
+    #[test]
+    fn test_validate_lan_network() {
+        let relay: IpAddr = "185.65.134.1".parse().unwrap();
+        let tunnel: IpAddr = "10.64.0.2".parse().unwrap();
+        let validate =
+            |network: &str| validate_lan_network(network.parse().unwrap(), &[relay, tunnel]);
+
+        assert_eq!(
+            validate("198.51.100.23/24"),
+            Ok("198.51.100.0/24".parse().unwrap())
+        );
+        assert_eq!(validate("10.0.0.0/8"), Ok("10.0.0.0/8".parse().unwrap()));
+        assert_eq!(
+            validate("0.0.0.0/0"),
+            Err(LanNetworkError::TooBroad("0.0.0.0/0".parse().unwrap()))
+        );
+        assert_eq!(
+            validate("::/0"),
+            Err(LanNetworkError::TooBroad("::/0".parse().unwrap()))
+        );
+        assert!(matches!(
+            validate("8.0.0.0/7"),
+            Err(LanNetworkError::TooBroad(_))
+        ));
+        assert_eq!(
+            validate("185.65.134.0/24"),
+            Err(LanNetworkError::ContainsTunnelAddress(
+                "185.65.134.0/24".parse().unwrap(),
+                relay
+            ))
+        );
+        assert_eq!(
+            validate("10.64.0.0/10"),
+            Err(LanNetworkError::ContainsTunnelAddress(
+                "10.64.0.0/10".parse().unwrap(),
+                tunnel
+            ))
+        );
+    }
+}
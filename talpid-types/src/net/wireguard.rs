@@ -61,6 +61,9 @@ pub struct PeerConfig {
     /// ephemeral and living in memory only.
     #[serde(skip)]
     pub psk: Option<PresharedKey>,
+    /// Interval in seconds between keepalive packets sent to the peer. Disabled if `None`.
+    #[serde(default)]
+    pub persistent_keepalive: Option<u16>,
 }
 
 #[derive(Clone, Eq, PartialEq, Deserialize, Serialize, Debug)]
@@ -104,6 +107,10 @@ impl PrivateKey {
     pub fn to_base64(&self) -> String {
         base64::encode(self.0.to_bytes())
     }
+
+    pub fn from_base64(key: &str) -> Result<Self, InvalidKeyError> {
+        decode_base64_key(key).map(From::from)
+    }
 }
 
 impl From<[u8; 32]> for PrivateKey {
@@ -169,14 +176,18 @@ impl PublicKey {
     }
 
     pub fn from_base64(key: &str) -> Result<Self, InvalidKeyError> {
-        let bytes = base64::decode(key).map_err(|_| InvalidKeyError(()))?;
-        if bytes.len() != 32 {
-            return Err(InvalidKeyError(()));
-        }
-        let mut key = [0u8; 32];
-        key.copy_from_slice(&bytes);
-        Ok(From::from(key))
+        decode_base64_key(key).map(From::from)
+    }
+}
+
+fn decode_base64_key(key: &str) -> Result<[u8; 32], InvalidKeyError> {
+    let bytes = base64::decode(key).map_err(|_| InvalidKeyError(()))?;
+    if bytes.len() != 32 {
+        return Err(InvalidKeyError(()));
     }
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&bytes);
+    Ok(key)
 }
 
 impl<'a> From<&'a x25519_dalek::StaticSecret> for PublicKey {
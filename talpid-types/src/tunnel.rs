@@ -134,6 +134,9 @@ pub enum ParameterGenerationError {
     /// Failure to resolve the hostname of a custom tunnel configuration
     #[error(display = "Can't resolve hostname for custom tunnel host")]
     CustomTunnelHostResultionError,
+    /// No entry and exit relays satisfy the multihop diversity constraints
+    #[error(display = "No entry and exit relays satisfy the multihop diversity constraints")]
+    NoDiverseMultihopRelays,
 }
 
 /// Application that prevents setting the firewall policy.
@@ -151,6 +151,12 @@ impl Config {
             if let Some(ref psk) = peer.psk {
                 wg_conf.add("preshared_key", psk.as_bytes().as_ref());
             }
+            if let Some(interval) = peer.persistent_keepalive {
+                wg_conf.add(
+                    "persistent_keepalive_interval",
+                    interval.to_string().as_str(),
+                );
+            }
             for addr in &peer.allowed_ips {
                 wg_conf.add("allowed_ip", addr.to_string().as_str());
             }
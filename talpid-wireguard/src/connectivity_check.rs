@@ -440,6 +440,7 @@ mod test {
             Stats {
                 rx_bytes: 1,
                 tx_bytes: 0,
+                last_handshake_time: None,
             },
         );
         conn_state.update(Instant::now(), stats);
@@ -465,6 +466,7 @@ mod test {
             Stats {
                 rx_bytes: 1,
                 tx_bytes: 0,
+                last_handshake_time: None,
             },
         );
         conn_state.update(connect_time, stats);
@@ -489,6 +491,7 @@ mod test {
             Stats {
                 rx_bytes: 1,
                 tx_bytes: 0,
+                last_handshake_time: None,
             },
         );
         conn_state.update(start, stats);
@@ -500,6 +503,7 @@ mod test {
             Stats {
                 rx_bytes: 1,
                 tx_bytes: 1,
+                last_handshake_time: None,
             },
         );
         conn_state.update(update_time, stats);
@@ -543,6 +547,7 @@ mod test {
                 stats::Stats {
                     tx_bytes: 0,
                     rx_bytes: 0,
+                    last_handshake_time: None,
                 },
             );
             let peers = Mutex::new(map);
@@ -567,6 +572,7 @@ mod test {
                         stats::Stats {
                             tx_bytes: 0,
                             rx_bytes: 0,
+                            last_handshake_time: None,
                         },
                     );
                     Ok(map)
@@ -633,6 +639,7 @@ mod test {
             stats::Stats {
                 tx_bytes: 0,
                 rx_bytes: 0,
+                last_handshake_time: None,
             },
         );
         ConnState::Connected {
@@ -733,6 +740,7 @@ mod test {
             stats::Stats {
                 tx_bytes: 0,
                 rx_bytes: 0,
+                last_handshake_time: None,
             },
         );
         let tunnel_stats = Mutex::new(map);
@@ -784,6 +792,7 @@ mod test {
             stats::Stats {
                 tx_bytes: 0,
                 rx_bytes: 0,
+                last_handshake_time: None,
             },
         );
 
@@ -20,7 +20,7 @@ use std::{
     net::IpAddr,
     path::Path,
     pin::Pin,
-    sync::{mpsc as sync_mpsc, Arc, Mutex},
+    sync::{mpsc as sync_mpsc, Arc, Mutex, Weak},
     time::Duration,
 };
 use talpid_routing as routing;
@@ -42,7 +42,8 @@ use talpid_types::{
 };
 use tokio::sync::Mutex as AsyncMutex;
 use tunnel_obfuscation::{
-    create_obfuscator, Error as ObfuscationError, Settings as ObfuscationSettings, Udp2TcpSettings,
+    create_obfuscator, Error as ObfuscationError, Settings as ObfuscationSettings,
+    ShadowsocksSettings, Udp2TcpSettings,
 };
 
 /// WireGuard config data-types
@@ -57,6 +58,7 @@ pub(crate) mod wireguard_kernel;
 #[cfg(windows)]
 mod wireguard_nt;
 
+pub use self::stats::{Stats, StatsMap};
 use self::wireguard_go::WgGoTunnel;
 
 type Result<T> = std::result::Result<T, Error>;
@@ -119,6 +121,34 @@ pub struct WireguardMonitor {
     close_msg_receiver: sync_mpsc::Receiver<CloseMsg>,
     pinger_stop_sender: sync_mpsc::Sender<()>,
     obfuscator: Arc<AsyncMutex<Option<ObfuscatorHandle>>>,
+    /// Stats read just before the tunnel was stopped
+    final_stats: Arc<Mutex<Option<StatsMap>>>,
+}
+
+/// Reads the traffic stats of a tunnel. Once the tunnel has been stopped, the stats read just
+/// before stopping it are returned instead.
+#[derive(Clone)]
+pub struct StatsHandle {
+    tunnel: Weak<Mutex<Option<Box<dyn Tunnel>>>>,
+    final_stats: Arc<Mutex<Option<StatsMap>>>,
+}
+
+impl StatsHandle {
+    /// Returns the stats of each peer, keyed by public key. Returns `None` if the stats could not
+    /// be read.
+    pub fn get(&self) -> Option<StatsMap> {
+        if let Some(tunnel) = self.tunnel.upgrade() {
+            if let Some(tunnel) = &*tunnel.lock().expect("Tunnel lock poisoned") {
+                match tunnel.get_tunnel_stats() {
+                    Ok(stats) => return Some(stats),
+                    Err(error) => {
+                        log::debug!("{}", error.display_chain_with_msg("Failed to read stats"));
+                    }
+                }
+            }
+        }
+        self.final_stats.lock().unwrap().clone()
+    }
 }
 
 const INITIAL_PSK_EXCHANGE_TIMEOUT: Duration = Duration::from_secs(8);
@@ -181,48 +211,63 @@ async fn maybe_create_obfuscator(
     let mut first_peer = config.peers.get_mut(0).expect("missing peer");
 
     if let Some(ref obfuscator_config) = config.obfuscator_config {
-        match obfuscator_config {
+        let settings = match obfuscator_config {
             ObfuscatorConfig::Udp2Tcp { endpoint } => {
                 log::trace!("Connecting to Udp2Tcp endpoint {:?}", *endpoint);
-                let settings = Udp2TcpSettings {
+                ObfuscationSettings::Udp2Tcp(Udp2TcpSettings {
                     peer: *endpoint,
                     #[cfg(target_os = "linux")]
                     fwmark: config.fwmark,
-                };
-                let obfuscator = create_obfuscator(&ObfuscationSettings::Udp2Tcp(settings))
-                    .await
-                    .map_err(Error::CreateObfuscatorError)?;
-                let endpoint = obfuscator.endpoint();
+                })
+            }
+            ObfuscatorConfig::Shadowsocks {
+                endpoint,
+                password,
+                cipher,
+            } => {
+                log::trace!("Connecting to Shadowsocks endpoint {:?}", *endpoint);
+                ObfuscationSettings::Shadowsocks(ShadowsocksSettings {
+                    shadowsocks_endpoint: *endpoint,
+                    wireguard_endpoint: first_peer.endpoint,
+                    password: password.clone(),
+                    cipher: cipher.clone(),
+                    #[cfg(target_os = "linux")]
+                    fwmark: config.fwmark,
+                })
+            }
+        };
+        let obfuscator = create_obfuscator(&settings)
+            .await
+            .map_err(Error::CreateObfuscatorError)?;
+        let endpoint = obfuscator.endpoint();
 
-                log::trace!("Patching first WireGuard peer to become {:?}", endpoint);
-                first_peer.endpoint = endpoint;
+        log::trace!("Patching first WireGuard peer to become {:?}", endpoint);
+        first_peer.endpoint = endpoint;
 
-                #[cfg(target_os = "android")]
-                let remote_socket_fd = obfuscator.remote_socket_fd();
+        #[cfg(target_os = "android")]
+        let remote_socket_fd = obfuscator.remote_socket_fd();
 
-                let (runner, abort_handle) = abortable(async move {
-                    match obfuscator.run().await {
-                        Ok(_) => {
-                            let _ = close_msg_sender.send(CloseMsg::ObfuscatorExpired);
-                        }
-                        Err(error) => {
-                            log::error!(
-                                "{}",
-                                error.display_chain_with_msg("Obfuscation controller failed")
-                            );
-                            let _ = close_msg_sender
-                                .send(CloseMsg::ObfuscatorFailed(Error::ObfuscatorError(error)));
-                        }
-                    }
-                });
-                tokio::spawn(runner);
-                return Ok(Some(ObfuscatorHandle::new(
-                    abort_handle,
-                    #[cfg(target_os = "android")]
-                    remote_socket_fd,
-                )));
+        let (runner, abort_handle) = abortable(async move {
+            match obfuscator.run().await {
+                Ok(_) => {
+                    let _ = close_msg_sender.send(CloseMsg::ObfuscatorExpired);
+                }
+                Err(error) => {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Obfuscation controller failed")
+                    );
+                    let _ = close_msg_sender
+                        .send(CloseMsg::ObfuscatorFailed(Error::ObfuscatorError(error)));
+                }
             }
-        }
+        });
+        tokio::spawn(runner);
+        return Ok(Some(ObfuscatorHandle::new(
+            abort_handle,
+            #[cfg(target_os = "android")]
+            remote_socket_fd,
+        )));
     }
     Ok(None)
 }
@@ -309,6 +354,7 @@ impl WireguardMonitor {
             close_msg_receiver: close_obfs_listener,
             pinger_stop_sender: pinger_tx,
             obfuscator,
+            final_stats: Arc::new(Mutex::new(None)),
         };
 
         let gateway = config.ipv4_gateway;
@@ -799,9 +845,20 @@ impl WireguardMonitor {
         wait_result
     }
 
+    /// Returns a handle for reading the traffic stats of the tunnel.
+    pub fn stats_handle(&self) -> StatsHandle {
+        StatsHandle {
+            tunnel: Arc::downgrade(&self.tunnel),
+            final_stats: self.final_stats.clone(),
+        }
+    }
+
     fn stop_tunnel(&mut self) {
         match self.tunnel.lock().expect("Tunnel lock poisoned").take() {
             Some(tunnel) => {
+                if let Ok(stats) = tunnel.get_tunnel_stats() {
+                    *self.final_stats.lock().unwrap() = Some(stats);
+                }
                 if let Err(e) = tunnel.stop() {
                     log::error!("{}", e.display_chain_with_msg("Failed to stop tunnel"));
                 }
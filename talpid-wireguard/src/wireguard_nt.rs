@@ -1,7 +1,7 @@
 use super::{
     config::Config,
     logging,
-    stats::{Stats, StatsMap},
+    stats::{self, Stats, StatsMap},
     Tunnel,
 };
 use bitflags::bitflags;
@@ -20,6 +20,7 @@ use std::{
     pin::Pin,
     ptr,
     sync::{Arc, Mutex},
+    time::SystemTime,
 };
 use talpid_types::{BoxedError, ErrorExt};
 use talpid_windows_net as net;
@@ -173,6 +174,9 @@ pub struct WgNtTunnel {
 
 const WIREGUARD_KEY_LENGTH: usize = 32;
 
+/// Number of 100-nanosecond intervals between the Windows epoch (1601-01-01) and the Unix epoch.
+const FILETIME_UNIX_EPOCH: u64 = 116_444_736_000_000_000;
+
 /// See `WIREGUARD_ALLOWED_IP` at https://git.zx2c4.com/wireguard-nt/tree/api/wireguard.h.
 #[derive(Clone, Copy)]
 #[repr(C, align(8))]
@@ -825,6 +829,16 @@ fn load_wg_nt_dll(resource_dir: &Path) -> Result<Arc<WgNtDll>> {
     }
 }
 
+/// Converts a handshake time given as a `FILETIME` value. Zero means that there has not been any
+/// handshake.
+fn filetime_to_handshake_time(filetime: u64) -> Option<SystemTime> {
+    let since_unix_epoch = filetime.checked_sub(FILETIME_UNIX_EPOCH)?;
+    stats::handshake_time(
+        since_unix_epoch / 10_000_000,
+        (since_unix_epoch % 10_000_000) as u32 * 100,
+    )
+}
+
 fn serialize_config(config: &Config) -> Result<Vec<MaybeUninit<u8>>> {
     let mut buffer = vec![];
 
@@ -839,11 +853,14 @@ fn serialize_config(config: &Config) -> Result<Vec<MaybeUninit<u8>>> {
     buffer.extend(as_uninit_byte_slice(&header));
 
     for peer in &config.peers {
-        let flags = if peer.psk.is_some() {
+        let mut flags = if peer.psk.is_some() {
             WgPeerFlag::HAS_PRESHARED_KEY | WgPeerFlag::HAS_PUBLIC_KEY | WgPeerFlag::HAS_ENDPOINT
         } else {
             WgPeerFlag::HAS_PUBLIC_KEY | WgPeerFlag::HAS_ENDPOINT
         };
+        if peer.persistent_keepalive.is_some() {
+            flags |= WgPeerFlag::HAS_PERSISTENT_KEEPALIVE;
+        }
         let wg_peer = WgPeer {
             flags,
             reserved: 0,
@@ -853,7 +870,7 @@ fn serialize_config(config: &Config) -> Result<Vec<MaybeUninit<u8>>> {
                 .as_ref()
                 .map(|psk| *psk.as_bytes())
                 .unwrap_or([0u8; WIREGUARD_KEY_LENGTH]),
-            persistent_keepalive: 0,
+            persistent_keepalive: peer.persistent_keepalive.unwrap_or(0),
             endpoint: net::inet_sockaddr_from_socketaddr(peer.endpoint).into(),
             tx_bytes: 0,
             rx_bytes: 0,
@@ -963,6 +980,7 @@ impl Tunnel for WgNtTunnel {
                     Stats {
                         tx_bytes: peer.tx_bytes,
                         rx_bytes: peer.rx_bytes,
+                        last_handshake_time: filetime_to_handshake_time(peer.last_handshake),
                     },
                 );
             }
@@ -1031,6 +1049,7 @@ mod tests {
                     allowed_ips: vec!["1.3.3.0/24".parse().unwrap()],
                     endpoint: "1.2.3.4:1234".parse().unwrap(),
                     psk: None,
+                    persistent_keepalive: None,
                 }],
                 ipv4_gateway: "0.0.0.0".parse().unwrap(),
                 ipv6_gateway: None,
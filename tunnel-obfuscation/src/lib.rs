@@ -1,7 +1,9 @@
 use async_trait::async_trait;
 use std::net::SocketAddr;
 
+mod shadowsocks;
 mod udp2tcp;
+pub use self::shadowsocks::ShadowsocksSettings;
 pub use udp2tcp::Udp2TcpSettings;
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -14,6 +16,12 @@ pub enum Error {
 
     #[error(display = "Failed to run Udp2Tcp obfuscator")]
     RunUdp2TcpObfuscator(#[error(source)] udp2tcp::Error),
+
+    #[error(display = "Failed to create Shadowsocks obfuscator")]
+    CreateShadowsocksObfuscator(#[error(source)] shadowsocks::Error),
+
+    #[error(display = "Failed to run Shadowsocks obfuscator")]
+    RunShadowsocksObfuscator(#[error(source)] shadowsocks::Error),
 }
 
 #[async_trait]
@@ -30,6 +38,7 @@ pub trait Obfuscator: Send {
 
 pub enum Settings {
     Udp2Tcp(Udp2TcpSettings),
+    Shadowsocks(ShadowsocksSettings),
 }
 
 pub async fn create_obfuscator(settings: &Settings) -> Result<Box<dyn Obfuscator>> {
@@ -37,5 +46,8 @@ pub async fn create_obfuscator(settings: &Settings) -> Result<Box<dyn Obfuscator
         Settings::Udp2Tcp(s) => udp2tcp::create_obfuscator(s)
             .await
             .map_err(Error::CreateUdp2TcpObfuscator),
+        Settings::Shadowsocks(s) => shadowsocks::create_obfuscator(s)
+            .await
+            .map_err(Error::CreateShadowsocksObfuscator),
     }
 }
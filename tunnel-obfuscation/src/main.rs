@@ -1,5 +1,7 @@
 use std::{env::args, net::SocketAddr};
-use tunnel_obfuscation::{create_obfuscator, Obfuscator, Settings, Udp2TcpSettings};
+use tunnel_obfuscation::{
+    create_obfuscator, Obfuscator, Settings, ShadowsocksSettings, Udp2TcpSettings,
+};
 
 #[tokio::main]
 async fn main() {
@@ -29,6 +31,20 @@ async fn instantiate_requested(obfuscator_type: &str) -> Box<dyn Obfuscator> {
                 .await
                 .expect("Creating obfuscator failed")
         }
+        "shadowsocks" => {
+            let settings = ShadowsocksSettings {
+                shadowsocks_endpoint: SocketAddr::new("127.0.0.1".parse().unwrap(), 3030),
+                wireguard_endpoint: SocketAddr::new("127.0.0.1".parse().unwrap(), 51820),
+                password: "mullvad".to_string(),
+                cipher: "aes-256-gcm".to_string(),
+                #[cfg(target_os = "linux")]
+                fwmark: Some(1337),
+            };
+
+            create_obfuscator(&Settings::Shadowsocks(settings))
+                .await
+                .expect("Creating obfuscator failed")
+        }
         _ => {
             unimplemented!()
         }
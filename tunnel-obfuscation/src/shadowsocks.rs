@@ -0,0 +1,183 @@
+use crate::Obfuscator;
+use async_trait::async_trait;
+use shadowsocks::{
+    config::ServerType, context::Context, crypto::CipherKind, net::ConnectOpts,
+    relay::socks5::Address, relay::udprelay::ProxySocket, ServerConfig,
+};
+#[cfg(target_os = "android")]
+use std::os::unix::io::AsRawFd;
+use std::{io, net::SocketAddr, str::FromStr};
+use tokio::net::UdpSocket;
+
+/// Large enough to hold any WireGuard packet, plus the Shadowsocks overhead.
+const MAX_DATAGRAM_SIZE: usize = u16::MAX as usize;
+
+pub struct ShadowsocksSettings {
+    /// Address of the Shadowsocks server
+    pub shadowsocks_endpoint: SocketAddr,
+    /// Address that the Shadowsocks server should forward the traffic to
+    pub wireguard_endpoint: SocketAddr,
+    pub password: String,
+    pub cipher: String,
+    #[cfg(target_os = "linux")]
+    pub fwmark: Option<u32>,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// The cipher is not supported
+    #[error(display = "Unsupported cipher: {}", _0)]
+    InvalidCipher(String),
+
+    /// Failed to bind the local UDP socket
+    #[error(display = "Failed to bind local UDP socket")]
+    BindLocalSocket(#[error(source)] io::Error),
+
+    /// Failed to create the socket used to reach the Shadowsocks server
+    #[error(display = "Failed to connect to Shadowsocks server")]
+    ConnectServer(#[error(source)] io::Error),
+
+    /// Failed to receive a datagram from the local client
+    #[error(display = "Failed to receive datagram from local client")]
+    ReceiveLocal(#[error(source)] io::Error),
+
+    /// Failed to send a datagram to the local client
+    #[error(display = "Failed to send datagram to local client")]
+    SendLocal(#[error(source)] io::Error),
+
+    /// Failed to receive a datagram from the Shadowsocks server
+    #[error(display = "Failed to receive datagram from Shadowsocks server")]
+    ReceiveServer(#[error(source)] io::Error),
+
+    /// Failed to send a datagram to the Shadowsocks server
+    #[error(display = "Failed to send datagram to Shadowsocks server")]
+    SendServer(#[error(source)] io::Error),
+}
+
+/// Forwards UDP datagrams between a local socket and a Shadowsocks server. The server relays the
+/// datagrams to and from the WireGuard endpoint.
+struct Shadowsocks {
+    local_socket: UdpSocket,
+    local_addr: SocketAddr,
+    server: ProxySocket,
+    wireguard_endpoint: Address,
+}
+
+impl Shadowsocks {
+    pub async fn new(settings: &ShadowsocksSettings) -> Result<Self> {
+        let cipher = CipherKind::from_str(&settings.cipher)
+            .map_err(|_| Error::InvalidCipher(settings.cipher.clone()))?;
+
+        let listen_addr = if settings.shadowsocks_endpoint.is_ipv4() {
+            SocketAddr::new("127.0.0.1".parse().unwrap(), 0)
+        } else {
+            SocketAddr::new("::1".parse().unwrap(), 0)
+        };
+        let local_socket = UdpSocket::bind(listen_addr)
+            .await
+            .map_err(Error::BindLocalSocket)?;
+        let local_addr = local_socket.local_addr().map_err(Error::BindLocalSocket)?;
+
+        #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+        let mut connect_opts = ConnectOpts::default();
+        #[cfg(target_os = "linux")]
+        {
+            connect_opts.fwmark = settings.fwmark;
+        }
+
+        let server = ProxySocket::connect_with_opts(
+            Context::new_shared(ServerType::Local),
+            &ServerConfig::new(
+                settings.shadowsocks_endpoint,
+                settings.password.clone(),
+                cipher,
+            ),
+            &connect_opts,
+        )
+        .await
+        .map_err(Error::ConnectServer)?;
+
+        Ok(Self {
+            local_socket,
+            local_addr,
+            server,
+            wireguard_endpoint: Address::SocketAddress(settings.wireguard_endpoint),
+        })
+    }
+
+    async fn forward_outbound(&self) -> Result<()> {
+        let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+        loop {
+            let size = self
+                .local_socket
+                .recv(&mut buffer)
+                .await
+                .map_err(Error::ReceiveLocal)?;
+            self.server
+                .send(&self.wireguard_endpoint, &buffer[..size])
+                .await
+                .map_err(Error::SendServer)?;
+        }
+    }
+
+    async fn forward_inbound(&self) -> Result<()> {
+        let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+        loop {
+            let (size, _addr, _received_size) = self
+                .server
+                .recv(&mut buffer)
+                .await
+                .map_err(Error::ReceiveServer)?;
+            self.local_socket
+                .send(&buffer[..size])
+                .await
+                .map_err(Error::SendLocal)?;
+        }
+    }
+}
+
+#[async_trait]
+impl Obfuscator for Shadowsocks {
+    fn endpoint(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    async fn run(self: Box<Self>) -> crate::Result<()> {
+        // Replies can only be forwarded once the address of the client is known, so wait for the
+        // first datagram before doing anything else.
+        let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+        let (size, client_addr) = self
+            .local_socket
+            .recv_from(&mut buffer)
+            .await
+            .map_err(Error::ReceiveLocal)
+            .map_err(crate::Error::RunShadowsocksObfuscator)?;
+        self.local_socket
+            .connect(client_addr)
+            .await
+            .map_err(Error::ReceiveLocal)
+            .map_err(crate::Error::RunShadowsocksObfuscator)?;
+        self.server
+            .send(&self.wireguard_endpoint, &buffer[..size])
+            .await
+            .map_err(Error::SendServer)
+            .map_err(crate::Error::RunShadowsocksObfuscator)?;
+
+        futures::future::try_join(self.forward_outbound(), self.forward_inbound())
+            .await
+            .map(|_| ())
+            .map_err(crate::Error::RunShadowsocksObfuscator)
+    }
+
+    #[cfg(target_os = "android")]
+    fn remote_socket_fd(&self) -> std::os::unix::io::RawFd {
+        self.server.as_raw_fd()
+    }
+}
+
+pub async fn create_obfuscator(settings: &ShadowsocksSettings) -> Result<Box<dyn Obfuscator>> {
+    Ok(Box::new(Shadowsocks::new(settings).await?))
+}
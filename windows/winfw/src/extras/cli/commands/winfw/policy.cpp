@@ -11,12 +11,26 @@ namespace commands::winfw
 namespace detail
 {
 
+const WinFwNetwork g_lanNetworks[] =
+{
+	{ L"10.0.0.0", 8 },
+	{ L"172.16.0.0", 12 },
+	{ L"192.168.0.0", 16 },
+	{ L"169.254.0.0", 16 },
+	{ L"fe80::", 10 },
+	{ L"fc00::", 7 },
+};
+
 WinFwSettings CreateSettings(const std::wstring &dhcp, const std::wstring &lan)
 {
 	WinFwSettings s;
 
 	s.permitDhcp = (0 == _wcsicmp(dhcp.c_str(), L"yes"));
 	s.permitLan = (0 == _wcsicmp(lan.c_str(), L"yes"));
+	s.lanNetworks = g_lanNetworks;
+	s.numLanNetworks = static_cast<uint32_t>(std::size(g_lanNetworks));
+	s.allowList = nullptr;
+	s.numAllowList = 0;
 
 	return s;
 }
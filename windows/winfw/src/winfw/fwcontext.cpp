@@ -19,6 +19,7 @@
 #include "rules/dns/permitloopback.h"
 #include "rules/dns/permittunnel.h"
 #include "rules/dns/permitnontunnel.h"
+#include "rules/multi/permitallowlist.h"
 #include "rules/multi/permitvpnrelay.h"
 #include <libwfp/transaction.h>
 #include <libwfp/filterengine.h>
@@ -60,11 +61,41 @@ void AppendSettingsRules
 
 	if (settings.permitLan)
 	{
-		ruleset.emplace_back(std::make_unique<baseline::PermitLan>());
-		ruleset.emplace_back(std::make_unique<baseline::PermitLanService>());
+		NetworkSet lanNetworks;
+		lanNetworks.reserve(settings.numLanNetworks);
+
+		for (uint32_t i = 0; i < settings.numLanNetworks; ++i)
+		{
+			const auto &network = settings.lanNetworks[i];
+			lanNetworks.push_back(Network{ wfp::IpAddress(network.ip), network.prefixLength });
+		}
+
+		ruleset.emplace_back(std::make_unique<baseline::PermitLan>(lanNetworks));
+		ruleset.emplace_back(std::make_unique<baseline::PermitLanService>(lanNetworks));
 		ruleset.emplace_back(baseline::PermitDhcpServer::WithExtent(baseline::PermitDhcpServer::Extent::IPv4Only));
 	}
 
+	if (0 != settings.numAllowList)
+	{
+		std::vector<multi::PermitAllowList::Endpoint> endpoints;
+		endpoints.reserve(settings.numAllowList);
+
+		for (uint32_t i = 0; i < settings.numAllowList; ++i)
+		{
+			const auto &endpoint = settings.allowList[i];
+
+			endpoints.push_back(multi::PermitAllowList::Endpoint
+			{
+				wfp::IpAddress(endpoint.ip),
+				endpoint.prefixLength,
+				endpoint.port,
+				endpoint.protocol
+			});
+		}
+
+		ruleset.emplace_back(std::make_unique<multi::PermitAllowList>(endpoints));
+	}
+
 	//
 	// DNS management
 	//
@@ -1,6 +1,8 @@
 #include "stdafx.h"
 #include "mullvadguids.h"
+#include <libcommon/error.h>
 #include <algorithm>
+#include <array>
 #include <iterator>
 
 //static
@@ -156,6 +158,11 @@ MullvadGuids::DetailedIdentityRegistry MullvadGuids::DetailedRegistry(IdentityQu
 	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Dns_PermitTunnel_Outbound_Ipv4()));
 	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Dns_PermitTunnel_Outbound_Ipv6()));
 
+	for (size_t i = 0; i < MAX_ALLOW_LIST_FILTERS; ++i)
+	{
+		registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitAllowList(i)));
+	}
+
 	if (IdentityQualifier::IncludePersistent == (qualifier & IdentityQualifier::IncludePersistent))
 	{
 		registry.insert(std::make_pair(WfpObjectType::Provider, ProviderPersistent()));
@@ -666,6 +673,37 @@ const GUID &MullvadGuids::Filter_Baseline_PermitEndpoint()
 	return g;
 }
 
+//static
+const GUID &MullvadGuids::Filter_Baseline_PermitAllowList(size_t index)
+{
+	static_assert(MAX_ALLOW_LIST_FILTERS <= 256, "Allow list filter index must fit in the last GUID byte");
+
+	static const auto guids = []()
+	{
+		std::array<GUID, MAX_ALLOW_LIST_FILTERS> guids;
+
+		for (size_t i = 0; i < guids.size(); ++i)
+		{
+			guids[i] =
+			{
+				0x3a9c8e0f,
+				0x5d27,
+				0x4b61,
+				{ 0x8f, 0x12, 0xc4, 0x6e, 0x0b, 0x93, 0x7a, static_cast<unsigned char>(i) }
+			};
+		}
+
+		return guids;
+	}();
+
+	if (index >= guids.size())
+	{
+		THROW_ERROR("Allow list filter index is out of range");
+	}
+
+	return guids[index];
+}
+
 //static
 const GUID &MullvadGuids::Filter_Baseline_PermitVpnTunnel_Outbound_Ipv4_1()
 {
@@ -71,6 +71,13 @@ public:
 
 	static const GUID &Filter_Baseline_PermitEndpoint();
 
+	//
+	// There is one filter for each endpoint in the allow list. Identifiers are taken
+	// from a fixed pool, which limits the number of endpoints.
+	//
+	static constexpr size_t MAX_ALLOW_LIST_FILTERS = 256;
+	static const GUID &Filter_Baseline_PermitAllowList(size_t index);
+
 	static const GUID &Filter_Baseline_PermitVpnTunnel_Outbound_Ipv4_1();
 	static const GUID &Filter_Baseline_PermitVpnTunnel_Outbound_Ipv6_1();
 	static const GUID &Filter_Baseline_PermitVpnTunnel_Outbound_Ipv4_2();
@@ -12,6 +12,11 @@ using namespace wfp::conditions;
 namespace rules::baseline
 {
 
+PermitLan::PermitLan(const NetworkSet &lanNetworks)
+{
+	SplitNetworks(lanNetworks, m_lanNetworksIpv4, m_lanNetworksIpv6);
+}
+
 bool PermitLan::apply(IObjectInstaller &objectInstaller)
 {
 	return applyIpv4(objectInstaller) && applyIpv6(objectInstaller);
@@ -37,14 +42,21 @@ bool PermitLan::applyIpv4(IObjectInstaller &objectInstaller) const
 
 	wfp::ConditionBuilder conditionBuilder(FWPM_LAYER_ALE_AUTH_CONNECT_V4);
 
-	conditionBuilder.add_condition(ConditionIp::Remote(wfp::IpNetwork(wfp::IpAddress::Literal({ 10, 0, 0, 0 }), 8)));
-	conditionBuilder.add_condition(ConditionIp::Remote(wfp::IpNetwork(wfp::IpAddress::Literal({ 172, 16, 0, 0 }), 12)));
-	conditionBuilder.add_condition(ConditionIp::Remote(wfp::IpNetwork(wfp::IpAddress::Literal({ 192, 168, 0, 0 }), 16)));
-	conditionBuilder.add_condition(ConditionIp::Remote(wfp::IpNetwork(wfp::IpAddress::Literal({ 169, 254, 0, 0 }), 16)));
+	//
+	// A filter without conditions would match all traffic.
+	//
 
-	if (!objectInstaller.addFilter(filterBuilder, conditionBuilder))
+	if (!m_lanNetworksIpv4.empty())
 	{
-		return false;
+		for (const auto &network : m_lanNetworksIpv4)
+		{
+			conditionBuilder.add_condition(ConditionIp::Remote(wfp::IpNetwork(network.address, network.prefixLength)));
+		}
+
+		if (!objectInstaller.addFilter(filterBuilder, conditionBuilder))
+		{
+			return false;
+		}
 	}
 
 	//
@@ -89,15 +101,21 @@ bool PermitLan::applyIpv6(IObjectInstaller &objectInstaller) const
 
 	wfp::ConditionBuilder conditionBuilder(FWPM_LAYER_ALE_AUTH_CONNECT_V6);
 
-	const wfp::IpNetwork linkLocal(wfp::IpAddress::Literal6({ 0xFE80, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0 }), 10);
-	const wfp::IpNetwork uniqueLocal(wfp::IpAddress::Literal6({ 0xFC00, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0 }), 7);
-
-	conditionBuilder.add_condition(ConditionIp::Remote(linkLocal));
-	conditionBuilder.add_condition(ConditionIp::Remote(uniqueLocal));
+	//
+	// A filter without conditions would match all traffic.
+	//
 
-	if (!objectInstaller.addFilter(filterBuilder, conditionBuilder))
+	if (!m_lanNetworksIpv6.empty())
 	{
-		return false;
+		for (const auto &network : m_lanNetworksIpv6)
+		{
+			conditionBuilder.add_condition(ConditionIp::Remote(wfp::IpNetwork(network.address, network.prefixLength)));
+		}
+
+		if (!objectInstaller.addFilter(filterBuilder, conditionBuilder))
+		{
+			return false;
+		}
 	}
 
 	//
@@ -1,6 +1,7 @@
 #pragma once
 
 #include <winfw/rules/ifirewallrule.h>
+#include <winfw/rules/shared.h>
 
 namespace rules::baseline
 {
@@ -9,7 +10,7 @@ class PermitLan : public IFirewallRule
 {
 public:
 
-	PermitLan() = default;
+	PermitLan(const NetworkSet &lanNetworks);
 	~PermitLan() = default;
 	
 	bool apply(IObjectInstaller &objectInstaller) override;
@@ -18,6 +19,9 @@ private:
 
 	bool applyIpv4(IObjectInstaller &objectInstaller) const;
 	bool applyIpv6(IObjectInstaller &objectInstaller) const;
+
+	NetworkSet m_lanNetworksIpv4;
+	NetworkSet m_lanNetworksIpv6;
 };
 
 }
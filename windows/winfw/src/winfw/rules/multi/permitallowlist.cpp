@@ -0,0 +1,95 @@
+#include "stdafx.h"
+#include "permitallowlist.h"
+#include <winfw/mullvadguids.h>
+#include <winfw/winfw.h>
+#include <winfw/rules/ports.h>
+#include <winfw/rules/shared.h>
+#include <libwfp/filterbuilder.h>
+#include <libwfp/conditionbuilder.h>
+#include <libwfp/conditions/conditionprotocol.h>
+#include <libwfp/conditions/conditionip.h>
+#include <libwfp/conditions/conditionport.h>
+#include <libcommon/error.h>
+
+using namespace wfp::conditions;
+
+namespace rules::multi
+{
+
+namespace
+{
+
+const GUID &LayerFromIp(const wfp::IpAddress &ip)
+{
+	switch (ip.type())
+	{
+		case wfp::IpAddress::Type::Ipv4: return FWPM_LAYER_ALE_AUTH_CONNECT_V4;
+		case wfp::IpAddress::Type::Ipv6: return FWPM_LAYER_ALE_AUTH_CONNECT_V6;
+		default:
+		{
+			THROW_ERROR("Missing case handler in switch clause");
+		}
+	};
+}
+
+//
+// DNS traffic is restricted in the DNS sublayer, so endpoints on the DNS port
+// have to be permitted there. Refer comment on `AppendSettingsRules`.
+//
+const GUID &SublayerFromPort(uint16_t port)
+{
+	return DNS_SERVER_PORT == port
+		? MullvadGuids::SublayerDns()
+		: MullvadGuids::SublayerBaseline();
+}
+
+} // anonymous namespace
+
+PermitAllowList::PermitAllowList(const std::vector<Endpoint> &endpoints)
+	: m_endpoints(endpoints)
+{
+}
+
+bool PermitAllowList::apply(IObjectInstaller &objectInstaller)
+{
+	if (m_endpoints.size() > MullvadGuids::MAX_ALLOW_LIST_FILTERS)
+	{
+		THROW_ERROR("Too many endpoints in allow list");
+	}
+
+	for (size_t i = 0; i < m_endpoints.size(); ++i)
+	{
+		const auto &endpoint = m_endpoints[i];
+
+		//
+		// Permit outbound connections to endpoint in allow list.
+		//
+
+		wfp::FilterBuilder filterBuilder;
+
+		filterBuilder
+			.key(MullvadGuids::Filter_Baseline_PermitAllowList(i))
+			.name(L"Permit outbound connections to an endpoint in the allow list")
+			.description(L"This filter is part of a rule that permits traffic to endpoints that the user has allowed")
+			.provider(MullvadGuids::Provider())
+			.layer(LayerFromIp(endpoint.address))
+			.sublayer(SublayerFromPort(endpoint.port))
+			.weight(wfp::FilterBuilder::WeightClass::Max)
+			.permit();
+
+		wfp::ConditionBuilder conditionBuilder(LayerFromIp(endpoint.address));
+
+		conditionBuilder.add_condition(ConditionIp::Remote(wfp::IpNetwork(endpoint.address, endpoint.prefixLength)));
+		conditionBuilder.add_condition(ConditionPort::Remote(endpoint.port));
+		conditionBuilder.add_condition(CreateProtocolCondition(endpoint.protocol));
+
+		if (false == objectInstaller.addFilter(filterBuilder, conditionBuilder))
+		{
+			return false;
+		}
+	}
+
+	return true;
+}
+
+}
@@ -0,0 +1,32 @@
+#pragma once
+
+#include <winfw/rules/ifirewallrule.h>
+#include <winfw/winfw.h>
+#include <libwfp/ipaddress.h>
+#include <vector>
+
+namespace rules::multi
+{
+
+class PermitAllowList : public IFirewallRule
+{
+public:
+
+	struct Endpoint
+	{
+		wfp::IpAddress address;
+		uint8_t prefixLength;
+		uint16_t port;
+		WinFwProtocol protocol;
+	};
+
+	PermitAllowList(const std::vector<Endpoint> &endpoints);
+
+	bool apply(IObjectInstaller &objectInstaller) override;
+
+private:
+
+	const std::vector<Endpoint> m_endpoints;
+};
+
+}
@@ -39,6 +39,33 @@ void SplitAddresses(const IpSet &in, IpSet &outIpv4, IpSet &outIpv6)
 	}
 }
 
+void SplitNetworks(const NetworkSet &in, NetworkSet &outIpv4, NetworkSet &outIpv6)
+{
+	outIpv4.clear();
+	outIpv6.clear();
+
+	for (const auto &network : in)
+	{
+		switch (network.address.type())
+		{
+			case wfp::IpAddress::Type::Ipv4:
+			{
+				outIpv4.push_back(network);
+				break;
+			}
+			case wfp::IpAddress::Type::Ipv6:
+			{
+				outIpv6.push_back(network);
+				break;
+			}
+			default:
+			{
+				THROW_ERROR("Missing case handler in switch clause");
+			}
+		}
+	}
+}
+
 std::unique_ptr<wfp::conditions::ConditionProtocol> CreateProtocolCondition(WinFwProtocol protocol)
 {
 	switch (protocol)
@@ -13,6 +13,16 @@ using IpSet = std::vector<wfp::IpAddress>;
 
 void SplitAddresses(const IpSet &in, IpSet &outIpv4, IpSet &outIpv6);
 
+struct Network
+{
+	wfp::IpAddress address;
+	uint8_t prefixLength;
+};
+
+using NetworkSet = std::vector<Network>;
+
+void SplitNetworks(const NetworkSet &in, NetworkSet &outIpv4, NetworkSet &outIpv6);
+
 std::unique_ptr<wfp::conditions::ConditionProtocol> CreateProtocolCondition(WinFwProtocol protocol);
 
 }
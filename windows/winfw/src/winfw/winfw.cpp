@@ -48,20 +48,12 @@ std::optional<T> MakeOptional(T* object)
 }
 
 //
-// Networks for which DNS requests can be made on all network adapters.
+// Loopback networks. Together with the LAN networks in the settings, these are the
+// networks for which DNS requests can be made on all network adapters.
 //
-// This should be synchronized with `ALLOWED_LAN_NETS` in talpid-core,
-// but it also includes loopback addresses.
-//
-wfp::IpNetwork g_privateIpRanges[] = {
+wfp::IpNetwork g_loopbackIpRanges[] = {
 	wfp::IpNetwork(wfp::IpAddress::Literal{127, 0, 0, 0}, 8),
-	wfp::IpNetwork(wfp::IpAddress::Literal{10, 0, 0, 0}, 8),
-	wfp::IpNetwork(wfp::IpAddress::Literal{172, 16, 0, 0}, 12),
-	wfp::IpNetwork(wfp::IpAddress::Literal{192, 168, 0, 0}, 16),
-	wfp::IpNetwork(wfp::IpAddress::Literal{169, 254, 0, 0}, 16),
-	wfp::IpNetwork(wfp::IpAddress::Literal6{0, 0, 0, 0, 0, 0, 0, 1}, 128),
-	wfp::IpNetwork(wfp::IpAddress::Literal6{0xfe80, 0, 0, 0, 0, 0, 0, 0}, 10),
-	wfp::IpNetwork(wfp::IpAddress::Literal6{0xfc80, 0, 0, 0, 0, 0, 0, 0}, 7)
+	wfp::IpNetwork(wfp::IpAddress::Literal6{0, 0, 0, 0, 0, 0, 0, 1}, 128)
 };
 
 } // anonymous namespace
@@ -346,6 +338,14 @@ WinFw_ApplyPolicyConnected(
 		std::vector<wfp::IpAddress> tunnelDnsServers;
 		std::vector<wfp::IpAddress> nonTunnelDnsServers;
 
+		std::vector<wfp::IpNetwork> localIpRanges(std::begin(g_loopbackIpRanges), std::end(g_loopbackIpRanges));
+
+		for (uint32_t i = 0; i < settings->numLanNetworks; ++i)
+		{
+			const auto &network = settings->lanNetworks[i];
+			localIpRanges.emplace_back(wfp::IpAddress(network.ip), network.prefixLength);
+		}
+
 		const auto v4GatewayIp = wfp::IpAddress(v4Gateway);
 		const auto v6GatewayIp = (nullptr != v6Gateway)
 			? std::make_optional(wfp::IpAddress(v6Gateway))
@@ -360,7 +360,7 @@ WinFw_ApplyPolicyConnected(
 				return;
 			}
 
-			for (const auto &network : g_privateIpRanges)
+			for (const auto &network : localIpRanges)
 			{
 				if (network.includes(ip))
 				{
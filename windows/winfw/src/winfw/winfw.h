@@ -19,22 +19,49 @@
 // Structures
 ///////////////////////////////////////////////////////////////////////////////
 
+enum WinFwProtocol : uint8_t
+{
+	Tcp = 0,
+	Udp = 1,
+};
+
+typedef struct tag_WinFwAllowListEndpoint
+{
+	// Network address. Together with `prefixLength` this describes the
+	// network that is reachable on the given port.
+	const wchar_t *ip;
+	uint8_t prefixLength;
+	uint16_t port;
+	WinFwProtocol protocol;
+}
+WinFwAllowListEndpoint;
+
+typedef struct tag_WinFwNetwork
+{
+	const wchar_t *ip;
+	uint8_t prefixLength;
+}
+WinFwNetwork;
+
 typedef struct tag_WinFwSettings
 {
 	// Permit outbound DHCP requests and inbound DHCP responses on all interfaces.
 	bool permitDhcp;
 
-	// Permit all traffic to and from private address ranges.
+	// Permit all traffic to and from the networks in `lanNetworks`.
 	bool permitLan;
+
+	// Networks that are considered local.
+	const WinFwNetwork *lanNetworks;
+	uint32_t numLanNetworks;
+
+	// Endpoints that the user has allowed to be reached outside the tunnel,
+	// regardless of policy.
+	const WinFwAllowListEndpoint *allowList;
+	uint32_t numAllowList;
 }
 WinFwSettings;
 
-enum WinFwProtocol : uint8_t
-{
-	Tcp = 0,
-	Udp = 1,
-};
-
 typedef struct tag_WinFwEndpoint
 {
 	const wchar_t *ip;